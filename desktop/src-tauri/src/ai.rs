@@ -1,239 +1,197 @@
-use reqwest::{Client, StatusCode};
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use futures_util::future::{BoxFuture, FutureExt};
+use futures_util::stream::{self, StreamExt};
+use serde::Serialize;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::fs;
-use std::time::Duration;
-use tokio::time::sleep;
-
-use crate::storage::{Settings, TabRecord, TabSuggestion};
-
-const DEFAULT_MODEL: &str = "gpt-4o-mini";
-const DEFAULT_BASE_URL: &str = "https://api.openai.com/v1";
-const MAX_RETRIES: u32 = 3;
-const INITIAL_RETRY_DELAY_MS: u64 = 1000;
-
-fn is_retryable_error(status: StatusCode) -> bool {
-    matches!(status.as_u16(), 429 | 500 | 502 | 503 | 504)
-}
-
-#[derive(Debug, Serialize)]
-struct ChatMessage {
-    role: String,
-    content: serde_json::Value,
-}
-
-#[derive(Debug, Serialize)]
-struct ChatRequest {
-    model: String,
-    messages: Vec<ChatMessage>,
-    temperature: f32,
-}
-
-#[derive(Debug, Deserialize)]
-struct ChatChoice {
-    message: ChatMessageResponse,
-}
-
-#[derive(Debug, Deserialize)]
-struct ChatMessageResponse {
-    content: String,
-}
-
-#[derive(Debug, Deserialize)]
-struct ChatResponse {
-    choices: Vec<ChatChoice>,
-}
-
-#[derive(Debug, Deserialize)]
+use std::ops::Range;
+
+use crate::llm::{
+    self, ChunkSink, CompletionOptions, ContentPart, Message, ProviderKind, ResponseSchema, Task,
+};
+use crate::privacy::{self, Exposure, PrivacyFilter};
+use crate::rollup::{Granularity, Period, PeriodStats};
+use crate::storage::{DailyReport, Settings, TabRecord, TabSuggestion};
+use crate::usage::{self, AiCall};
+
+/// Inputs per embeddings request
+pub const EMBEDDING_BATCH_SIZE: usize = 64;
+/// Keeps each input well under the embedding models' token limit
+const MAX_EMBEDDING_CHARS: usize = 8000;
+
+/// Bump when the suggestion prompt or schema changes so cached suggestions are not reused
+const SUGGESTION_PROMPT_VERSION: u32 = 1;
+/// How long a cached suggestion stays valid when settings do not say
+pub const DEFAULT_SUGGESTION_CACHE_TTL_HOURS: u32 = 168;
+
+/// A suggestion item that passed validation
+#[derive(Debug)]
 struct SuggestionItem {
-    #[serde(rename = "tabId")]
     tab_id: i64,
-    category: Option<String>,
+    category: String,
     decision: String,
     reason: String,
     digest: Option<String>,
 }
 
-fn get_api_key(settings: &Settings) -> Result<String, String> {
-    settings
-        .openai_api_key
-        .clone()
-        .filter(|k| !k.is_empty())
-        .ok_or_else(|| "Missing OpenAI API key. Please configure it in settings.".to_string())
+/// Provider and model label used for a task's embeddings, e.g. "openai/text-embedding-3-small".
+/// Part of the embedding cache key, so switching either re-embeds.
+pub fn get_embedding_model(settings: &Settings) -> Result<String, String> {
+    Ok(llm::task_config(settings, Task::Embeddings)?.label())
 }
 
-fn get_base_url(settings: &Settings) -> String {
-    settings
-        .base_url
-        .clone()
-        .filter(|u| !u.is_empty())
-        .unwrap_or_else(|| DEFAULT_BASE_URL.to_string())
+/// Provider and model label used for suggestions; part of the suggestion cache key
+pub fn get_suggestion_model(settings: &Settings) -> Result<String, String> {
+    Ok(llm::task_config(settings, Task::Suggestions)?.label())
 }
 
-fn get_model(settings: &Settings) -> String {
-    settings
-        .model
-        .clone()
-        .filter(|m| !m.is_empty())
-        .unwrap_or_else(|| DEFAULT_MODEL.to_string())
+/// Cache key for a tab's suggestion: a hash of everything the prompt says about the tab
+/// (url, title, description, screenshot bytes) plus the user's context, the privacy
+/// settings, the model and the prompt version. Activity times are left out so a tab that
+/// was merely used again still hits.
+pub fn suggestion_input_hash(tab: &TabRecord, settings: &Settings, model: &str) -> String {
+    let screenshot = screenshot_path(tab).and_then(|path| fs::read(path).ok());
+    let privacy = privacy::settings_key(settings);
+    let fields: [&[u8]; 8] = [
+        &SUGGESTION_PROMPT_VERSION.to_le_bytes(),
+        model.as_bytes(),
+        settings.user_context.as_deref().unwrap_or("").as_bytes(),
+        privacy.as_bytes(),
+        tab.url.as_deref().unwrap_or("").as_bytes(),
+        tab.title.as_deref().unwrap_or("").as_bytes(),
+        tab.description.as_deref().unwrap_or("").as_bytes(),
+        screenshot.as_deref().unwrap_or(&[]),
+    ];
+
+    let mut hasher = Sha256::new();
+    for field in fields {
+        // Length prefixes keep ("ab", "c") and ("a", "bc") apart
+        hasher.update((field.len() as u64).to_le_bytes());
+        hasher.update(field);
+    }
+    hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
 }
 
-async fn call_openai(
+/// Send `messages` to the provider configured for `task`
+async fn complete(
     settings: &Settings,
-    messages: Vec<ChatMessage>,
+    task: Task,
+    messages: &[Message],
     temperature: f32,
+    response_schema: Option<ResponseSchema>,
 ) -> Result<String, String> {
-    let api_key = get_api_key(settings)?;
-    let base_url = get_base_url(settings);
-    let model = get_model(settings);
+    complete_with(settings, task, messages, temperature, response_schema, None).await
+}
 
-    // Log request details
-    let request_body = serde_json::to_string(&messages).unwrap_or_default();
-    let request_size = request_body.len();
-    let image_count = request_body.matches("image_url").count();
+/// `complete`, streaming the reply through `on_chunk` when given
+async fn complete_with(
+    settings: &Settings,
+    task: Task,
+    messages: &[Message],
+    temperature: f32,
+    response_schema: Option<ResponseSchema>,
+    on_chunk: Option<ChunkSink<'_>>,
+) -> Result<String, String> {
+    let config = llm::task_config(settings, task)?;
 
-    // Count words (split by whitespace)
+    // Log request details
     let word_count: usize = messages
         .iter()
-        .map(|msg| {
-            let content_str = serde_json::to_string(&msg.content).unwrap_or_default();
-            // Remove base64 image data before counting words
-            let without_base64 = content_str.split("base64,").next().unwrap_or(&content_str);
-            without_base64.split_whitespace().count()
-        })
+        .map(|msg| msg.text().split_whitespace().count())
         .sum();
+    let image_count: usize = messages.iter().map(Message::image_count).sum();
 
     println!("\n[AI] ========== Request Start ==========");
-    println!("[AI] Model: {}", model);
-    println!("[AI] Base URL: {}", base_url);
+    println!("[AI] Provider: {}", config.provider.kind().name());
+    println!("[AI] Model: {}", config.model);
     println!("[AI] Temperature: {}", temperature);
     println!("[AI] Messages count: {}", messages.len());
-    println!(
-        "[AI] Request body size: {} bytes ({:.2} KB)",
-        request_size,
-        request_size as f64 / 1024.0
-    );
-    println!("[AI] Word count (excluding base64): ~{} words", word_count);
+    println!("[AI] Word count (excluding images): ~{} words", word_count);
     println!("[AI] Image count: {}", image_count);
 
-    // Print full message content
+    // Print full message content; images are summarized
     for (i, msg) in messages.iter().enumerate() {
-        let content_str = serde_json::to_string_pretty(&msg.content).unwrap_or_default();
-        // For content with images, truncate base64 data for readability
-        let display_content = if content_str.contains("base64,") {
-            let mut result = String::new();
-            for part in content_str.split("base64,") {
-                if result.is_empty() {
-                    result.push_str(part);
-                } else {
-                    // Find where the base64 data ends (at the next quote)
-                    if let Some(end_idx) = part.find('"') {
-                        result.push_str("base64,[IMAGE_DATA_TRUNCATED]");
-                        result.push_str(&part[end_idx..]);
-                    } else {
-                        result.push_str("base64,[IMAGE_DATA_TRUNCATED]");
-                    }
+        let display_content: String = msg
+            .content
+            .iter()
+            .map(|part| match part {
+                ContentPart::Text(text) => text.clone(),
+                ContentPart::Image { media_type, data } => {
+                    format!("\n[IMAGE {} {} bytes base64]", media_type, data.len())
                 }
-            }
-            result
-        } else {
-            content_str
-        };
+            })
+            .collect();
         println!(
-            "[AI] Message[{}] role={}:\n{}",
+            "[AI] Message[{}] role={:?}:\n{}",
             i, msg.role, display_content
         );
     }
 
-    let client = Client::builder()
-        .timeout(std::time::Duration::from_secs(120)) // 2 minutes timeout
-        .build()
-        .map_err(|e| format!("Failed to create HTTP client: {}", e))?;
-    let request = ChatRequest {
-        model: model.clone(),
-        messages,
+    let options = CompletionOptions {
+        model: config.model.clone(),
         temperature,
+        max_tokens: None,
+        response_schema,
     };
 
-    let start_time = std::time::Instant::now();
-    let mut delay = Duration::from_millis(INITIAL_RETRY_DELAY_MS);
-
-    for attempt in 1..=MAX_RETRIES {
-        println!("[AI] Sending request (attempt {}/{})...", attempt, MAX_RETRIES);
-
-        let response = client
-            .post(format!("{}/chat/completions", base_url))
-            .header("Authorization", format!("Bearer {}", api_key))
-            .header("Content-Type", "application/json")
-            .json(&request)
-            .send()
-            .await;
-
-        match response {
-            Ok(resp) => {
-                let elapsed = start_time.elapsed();
-                let status = resp.status();
-                println!(
-                    "[AI] Response status: {} (took {:.2}s)",
-                    status,
-                    elapsed.as_secs_f64()
-                );
+    let kind = config.provider.kind();
+    usage::check_budget(settings, kind, &config.model).await?;
 
-                if status.is_success() {
-                    let chat_response: ChatResponse = resp.json().await.map_err(|e| {
-                        println!("[AI] Failed to parse response: {}", e);
-                        format!("Failed to parse response: {}", e)
-                    })?;
-
-                    let result = chat_response
-                        .choices
-                        .first()
-                        .map(|c| c.message.content.clone())
-                        .ok_or_else(|| "No response from API".to_string())?;
-
-                    let response_word_count = result.split_whitespace().count();
-                    println!(
-                        "[AI] Response: {} words, {} bytes",
-                        response_word_count,
-                        result.len()
-                    );
-                    println!(
-                        "[AI] ========== Request End (total {:.2}s) ==========\n",
-                        start_time.elapsed().as_secs_f64()
-                    );
-
-                    return Ok(result);
-                } else if is_retryable_error(status) && attempt < MAX_RETRIES {
-                    let error_text = resp.text().await.unwrap_or_default();
-                    println!(
-                        "[AI] Retryable error ({}): {}. Retry {}/{} after {:?}",
-                        status, error_text, attempt, MAX_RETRIES, delay
-                    );
-                    sleep(delay).await;
-                    delay *= 2;
-                } else {
-                    let error_text = resp.text().await.unwrap_or_default();
-                    println!("[AI] API error: {}", error_text);
-                    return Err(format!("API error ({}): {}", status, error_text));
-                }
-            }
-            Err(e) => {
-                if attempt < MAX_RETRIES {
-                    println!(
-                        "[AI] Network error: {}. Retry {}/{} after {:?}",
-                        e, attempt, MAX_RETRIES, delay
-                    );
-                    sleep(delay).await;
-                    delay *= 2;
-                } else {
-                    println!("[AI] Request failed after {} attempts: {}", MAX_RETRIES, e);
-                    return Err(format!("Request failed: {}", e));
-                }
-            }
+    let start_time = std::time::Instant::now();
+    let completion = match on_chunk {
+        Some(sink) => {
+            config
+                .provider
+                .complete_streaming(messages, &options, sink)
+                .await
         }
-    }
+        None => config.provider.complete(messages, &options).await,
+    };
 
-    unreachable!("Retry loop should have returned")
+    // Providers that omit usage are billed on the same estimate used for batch planning
+    let prompt_estimate = estimate_message_tokens(messages, image_tokens(kind)) as u64;
+    let reported = completion.as_ref().map(|c| c.usage).unwrap_or_default();
+    let completion_tokens = reported.completion_tokens.unwrap_or_else(|| {
+        completion
+            .as_ref()
+            .map(|c| estimate_text_tokens(&c.text) as u64)
+            .unwrap_or(0)
+    });
+    usage::record(
+        settings,
+        AiCall {
+            provider: kind,
+            model: config.model.clone(),
+            task,
+            prompt_tokens: reported.prompt_tokens.unwrap_or(prompt_estimate),
+            completion_tokens,
+            estimated: reported.prompt_tokens.is_none() || reported.completion_tokens.is_none(),
+            image_count,
+            latency_ms: start_time.elapsed().as_millis() as u64,
+            retries: completion.as_ref().ok().map(|c| c.usage.retries),
+            error: completion.as_ref().err().cloned(),
+        },
+    )
+    .await;
+    let result = completion?.text;
+
+    let response_word_count = result.split_whitespace().count();
+    println!(
+        "[AI] Response: {} words, {} bytes",
+        response_word_count,
+        result.len()
+    );
+    println!(
+        "[AI] ========== Request End (total {:.2}s) ==========\n",
+        start_time.elapsed().as_secs_f64()
+    );
+
+    Ok(result)
 }
 
 fn format_tab_for_prompt(tab: &TabRecord) -> String {
@@ -256,14 +214,17 @@ fn format_tab_for_prompt(tab: &TabRecord) -> String {
     lines.join("\n")
 }
 
-fn extract_json_array(content: &str) -> Result<Vec<SuggestionItem>, String> {
-    // Find JSON array in response
-    let start = content.find('[').ok_or("No JSON array found")?;
-    let end = content.rfind(']').ok_or("No closing bracket found")? + 1;
-    let json_str = &content[start..end];
-
-    serde_json::from_str(json_str).map_err(|e| format!("Failed to parse JSON: {}", e))
-}
+/// Categories a suggestion may use
+const CATEGORIES: &[&str] = &[
+    "work",
+    "research",
+    "communication",
+    "entertainment",
+    "shopping",
+    "reference",
+    "utility",
+];
+const DECISIONS: &[&str] = &["keep", "close", "unsure"];
 
 const TAB_CATEGORIES: &str = r#"
 Categories to classify tabs:
@@ -276,17 +237,271 @@ Categories to classify tabs:
 - utility: Settings, admin panels, dev tools
 "#;
 
-pub async fn suggest_tabs(
-    tabs: &[TabRecord],
-    settings: &Settings,
-) -> Result<HashMap<i64, TabSuggestion>, String> {
-    if tabs.is_empty() {
-        return Ok(HashMap::new());
+/// Problems in a suggestion response, reported back to the caller
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SuggestionIssues {
+    /// Requested tabs the model gave no suggestion for
+    pub skipped: Vec<i64>,
+    /// Tab ids in the response that were not part of the request
+    pub unknown: Vec<i64>,
+    /// Items still invalid after the repair round-trip
+    pub invalid: Vec<InvalidSuggestion>,
+    /// Items fixed by the repair round-trip
+    pub repaired: usize,
+    /// Batches that failed outright; their tabs are listed in `skipped`
+    pub errors: Vec<String>,
+    /// Tabs not sent because they match the privacy blocklist
+    pub withheld: Vec<i64>,
+}
+
+impl SuggestionIssues {
+    pub fn is_empty(&self) -> bool {
+        self.skipped.is_empty()
+            && self.unknown.is_empty()
+            && self.invalid.is_empty()
+            && self.errors.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct InvalidSuggestion {
+    pub tab_id: Option<i64>,
+    pub error: String,
+}
+
+#[derive(Debug)]
+pub struct SuggestionOutcome {
+    pub suggestions: HashMap<i64, TabSuggestion>,
+    pub issues: SuggestionIssues,
+}
+
+fn suggestion_schema() -> ResponseSchema {
+    ResponseSchema {
+        name: "tab_suggestions",
+        schema: json!({
+            "type": "object",
+            "additionalProperties": false,
+            "required": ["suggestions"],
+            "properties": {
+                "suggestions": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "additionalProperties": false,
+                        "required": ["tabId", "category", "decision", "reason", "digest"],
+                        "properties": {
+                            "tabId": { "type": "integer" },
+                            "category": { "type": "string", "enum": CATEGORIES },
+                            "decision": { "type": "string", "enum": DECISIONS },
+                            "reason": { "type": "string" },
+                            "digest": { "type": ["string", "null"] }
+                        }
+                    }
+                }
+            }
+        }),
+    }
+}
+
+/// Items of a suggestion response as raw JSON. Accepts `{"suggestions": [...]}` or a bare
+/// array, with or without code fences or surrounding prose. When the output was cut off
+/// mid-array, the items that were complete are returned.
+fn extract_suggestion_items(content: &str) -> Result<Vec<Value>, String> {
+    let mut partial: Option<Vec<Value>> = None;
+
+    // Try every bracket as the start of the JSON, so brackets in prose are skipped
+    for (start, c) in content
+        .char_indices()
+        .filter(|(_, c)| *c == '[' || *c == '{')
+    {
+        let rest = &content[start..];
+        match serde_json::Deserializer::from_str(rest)
+            .into_iter::<Value>()
+            .next()
+        {
+            Some(Ok(Value::Array(items)))
+                if items.is_empty() || items.iter().any(Value::is_object) =>
+            {
+                return Ok(items)
+            }
+            Some(Ok(Value::Object(mut object))) => {
+                if let Some(Value::Array(items)) = object.remove("suggestions") {
+                    return Ok(items);
+                }
+            }
+            Some(Err(_)) if c == '[' && partial.is_none() => {
+                let items = salvage_array(rest);
+                if !items.is_empty() {
+                    partial = Some(items);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    partial.ok_or_else(|| "No JSON array found in response".to_string())
+}
+
+/// Complete objects at the start of a truncated JSON array (`text` starts at the `[`)
+fn salvage_array(text: &str) -> Vec<Value> {
+    let mut items = Vec::new();
+    let mut rest = &text[1..];
+    loop {
+        rest = rest.trim_start_matches(|c: char| c.is_whitespace() || c == ',');
+        let mut stream = serde_json::Deserializer::from_str(rest).into_iter::<Value>();
+        match stream.next() {
+            Some(Ok(value)) if value.is_object() => {
+                items.push(value);
+                rest = &rest[stream.byte_offset()..];
+            }
+            _ => return items,
+        }
+    }
+}
+
+/// Check one item against the schema. Decision and category are matched case-insensitively.
+fn validate_item(value: &Value) -> Result<SuggestionItem, InvalidSuggestion> {
+    let tab_id = value.get("tabId").and_then(Value::as_i64);
+    let invalid = |error: String| InvalidSuggestion { tab_id, error };
+    let got = |field: &str| {
+        value
+            .get(field)
+            .map(Value::to_string)
+            .unwrap_or_else(|| "nothing".to_string())
+    };
+
+    let Some(tab_id) = tab_id else {
+        return Err(invalid(format!(
+            "\"tabId\" must be an integer (got {})",
+            got("tabId")
+        )));
+    };
+
+    let one_of = |field: &str, allowed: &[&str]| {
+        value
+            .get(field)
+            .and_then(Value::as_str)
+            .map(|v| v.trim().to_lowercase())
+            .filter(|v| allowed.contains(&v.as_str()))
+            .ok_or_else(|| {
+                invalid(format!(
+                    "\"{}\" must be one of {} (got {})",
+                    field,
+                    allowed.join(", "),
+                    got(field)
+                ))
+            })
+    };
+    let decision = one_of("decision", DECISIONS)?;
+    let category = one_of("category", CATEGORIES)?;
+
+    let reason = value
+        .get("reason")
+        .and_then(Value::as_str)
+        .ok_or_else(|| {
+            invalid(format!(
+                "\"reason\" must be a string (got {})",
+                got("reason")
+            ))
+        })?
+        .to_string();
+    let digest = match value.get("digest") {
+        None | Some(Value::Null) => None,
+        Some(Value::String(digest)) => Some(digest.clone()),
+        Some(other) => {
+            return Err(invalid(format!(
+                "\"digest\" must be a string or null (got {})",
+                other
+            )))
+        }
+    };
+
+    Ok(SuggestionItem {
+        tab_id,
+        category,
+        decision,
+        reason,
+        digest,
+    })
+}
+
+/// Suggestions collected so far for one batch
+struct SuggestionCollector {
+    expected: HashSet<i64>,
+    accepted: HashMap<i64, SuggestionItem>,
+    unknown: BTreeSet<i64>,
+}
+
+impl SuggestionCollector {
+    /// Take the valid items of one response. Returns the invalid items for requested tabs
+    /// that are still without a suggestion, or the parse error if no items were found.
+    fn absorb(&mut self, content: &str) -> Result<Vec<InvalidSuggestion>, String> {
+        let mut invalid = Vec::new();
+        for value in extract_suggestion_items(content)? {
+            match validate_item(&value) {
+                Ok(item) if !self.expected.contains(&item.tab_id) => {
+                    self.unknown.insert(item.tab_id);
+                }
+                Ok(item) => {
+                    self.accepted.entry(item.tab_id).or_insert(item);
+                }
+                Err(error) => match error.tab_id {
+                    Some(id) if !self.expected.contains(&id) => {
+                        self.unknown.insert(id);
+                    }
+                    _ => invalid.push(error),
+                },
+            }
+        }
+        invalid.retain(|e| e.tab_id.is_none_or(|id| !self.accepted.contains_key(&id)));
+        Ok(invalid)
+    }
+
+    fn skipped(&self, invalid: &[InvalidSuggestion]) -> Vec<i64> {
+        let mut skipped: Vec<i64> = self
+            .expected
+            .iter()
+            .copied()
+            .filter(|id| !self.accepted.contains_key(id))
+            .filter(|id| !invalid.iter().any(|e| e.tab_id == Some(*id)))
+            .collect();
+        skipped.sort_unstable();
+        skipped
+    }
+}
+
+fn repair_prompt(
+    invalid: &[InvalidSuggestion],
+    skipped: &[i64],
+    parse_error: Option<&str>,
+) -> String {
+    if let Some(error) = parse_error {
+        return format!(
+            "Your previous answer could not be read ({}). Return suggestions for every tab as JSON only, in the format described above.",
+            error
+        );
     }
 
-    // Use all tabs passed in - batch size is already controlled by the caller
-    let tabs_to_analyze: Vec<_> = tabs.iter().collect();
+    let mut lines = vec!["Some items in your previous answer could not be used:".to_string()];
+    for error in invalid {
+        match error.tab_id {
+            Some(id) => lines.push(format!("- tabId {}: {}", id, error.error)),
+            None => lines.push(format!("- {}", error.error)),
+        }
+    }
+    for id in skipped {
+        lines.push(format!("- tabId {}: no suggestion given", id));
+    }
+    lines.push(String::new());
+    lines.push(
+        "Return JSON only, in the same format, with corrected items for these tabs only."
+            .to_string(),
+    );
+    lines.join("\n")
+}
 
+/// Instructions that open every suggestion request
+fn suggestion_prompt(settings: &Settings) -> String {
     // Build user context if available
     let user_context_str = settings
         .user_context
@@ -296,89 +511,430 @@ pub async fn suggest_tabs(
         .unwrap_or_default();
 
     // Build content with text and images
-    let prompt = format!(
+    format!(
         r#"Analyze these browser tabs and suggest which to keep or close.
 
 {}
 {}
 
-Return JSON array only. Each item must have:
-- "tabId": number
-- "category": one of [work, research, communication, entertainment, shopping, reference, utility]
-- "decision": "keep" | "close" | "unsure"
+Return JSON only, as {{"suggestions": [...]}} with one item per tab. Each item must have:
+- "tabId": number, as given below
+- "category": one of [{}]
+- "decision": one of [{}]
 - "reason": brief explanation
-- "digest": a concise 1-2 sentence summary of the tab's content/purpose (in the same language as the page content)
+- "digest": a concise 1-2 sentence summary of the tab's content/purpose (in the same language as the page content), or null
 
 Base decisions on:
 1. Tab's relevance to user's current work/goals
 2. How recently it was active
 3. Whether the content is transient or worth keeping
 4. Category - entertainment tabs idle for long are good candidates to close"#,
-        TAB_CATEGORIES, user_context_str
-    );
+        TAB_CATEGORIES,
+        user_context_str,
+        CATEGORIES.join(", "),
+        DECISIONS.join(", ")
+    )
+}
+
+fn suggestion_system_message() -> Message {
+    Message::system("You are a tab cleanup assistant. Classify and decide whether each tab should be kept, closed, or is unsure. Consider the user's context and work habits.")
+}
+
+fn screenshot_path(tab: &TabRecord) -> Option<&str> {
+    tab.snapshot.as_ref()?.screenshot_path.as_deref()
+}
+
+/// The tab's prompt text and screenshot, if the screenshot file can be read
+fn tab_content_parts(tab: &TabRecord) -> Vec<ContentPart> {
+    let mut parts = vec![ContentPart::Text(format!(
+        "\n\n{}",
+        format_tab_for_prompt(tab)
+    ))];
+
+    // Add screenshot if available
+    if let Some(bytes) = screenshot_path(tab).and_then(|path| fs::read(path).ok()) {
+        let base64 = base64::Engine::encode(&base64::engine::general_purpose::STANDARD, &bytes);
+        parts.push(ContentPart::Image {
+            media_type: "image/jpeg".to_string(),
+            data: base64,
+        });
+    }
+    parts
+}
+
+/// The request for one batch of (privacy-filtered) tabs
+fn suggestion_messages(tabs: &[TabRecord], settings: &Settings) -> Vec<Message> {
+    let mut content_parts = vec![ContentPart::Text(suggestion_prompt(settings))];
+    for tab in tabs {
+        content_parts.extend(tab_content_parts(tab));
+    }
+    vec![
+        suggestion_system_message(),
+        Message::user_parts(content_parts),
+    ]
+}
 
-    let mut content_parts: Vec<serde_json::Value> = vec![serde_json::json!({
-        "type": "text",
-        "text": prompt
-    })];
-
-    for tab in &tabs_to_analyze {
-        content_parts.push(serde_json::json!({
-            "type": "text",
-            "text": format!("\n\n{}", format_tab_for_prompt(tab))
-        }));
-
-        // Add screenshot if available
-        if let Some(snapshot) = &tab.snapshot {
-            if let Some(path) = &snapshot.screenshot_path {
-                if let Ok(bytes) = fs::read(path) {
-                    let base64 =
-                        base64::Engine::encode(&base64::engine::general_purpose::STANDARD, &bytes);
-                    content_parts.push(serde_json::json!({
-                        "type": "image_url",
-                        "image_url": {
-                            "url": format!("data:image/jpeg;base64,{}", base64),
-                            "detail": "low"
+/// Analyze one budget-sized batch, with a repair round-trip for invalid or missing items
+async fn suggest_batch(
+    tabs: &[TabRecord],
+    settings: &Settings,
+) -> Result<SuggestionOutcome, String> {
+    let mut messages = suggestion_messages(tabs, settings);
+
+    let mut collector = SuggestionCollector {
+        expected: tabs.iter().map(|t| t.id).collect(),
+        accepted: HashMap::new(),
+        unknown: BTreeSet::new(),
+    };
+
+    let response = complete(
+        settings,
+        Task::Suggestions,
+        &messages,
+        0.2,
+        Some(suggestion_schema()),
+    )
+    .await?;
+    let mut parse_error = None;
+    let mut invalid = collector.absorb(&response).unwrap_or_else(|e| {
+        parse_error = Some(e);
+        Vec::new()
+    });
+    let mut skipped = collector.skipped(&invalid);
+    let mut repaired = 0;
+
+    // One repair round-trip for invalid items and skipped tabs, instead of failing the batch
+    if !invalid.is_empty() || !skipped.is_empty() {
+        println!(
+            "[AI] {} invalid suggestions, {} tabs skipped; asking for a repair",
+            invalid.len(),
+            skipped.len()
+        );
+        messages.push(Message::assistant(response));
+        messages.push(Message::user(repair_prompt(
+            &invalid,
+            &skipped,
+            parse_error.as_deref(),
+        )));
+
+        let before = collector.accepted.len();
+        match complete(
+            settings,
+            Task::Suggestions,
+            &messages,
+            0.2,
+            Some(suggestion_schema()),
+        )
+        .await
+        {
+            Ok(reply) => match collector.absorb(&reply) {
+                Ok(still_invalid) => {
+                    parse_error = None;
+                    // Keep first-round errors for tabs the repair did not mention
+                    let mut merged = still_invalid;
+                    for error in invalid {
+                        let unresolved = error.tab_id.is_some_and(|id| {
+                            !collector.accepted.contains_key(&id)
+                                && !merged.iter().any(|e| e.tab_id == Some(id))
+                        });
+                        if unresolved {
+                            merged.push(error);
                         }
-                    }));
+                    }
+                    invalid = merged;
                 }
-            }
+                Err(e) => println!("[AI] Repair response unusable: {}", e),
+            },
+            Err(e) => println!("[AI] Repair request failed: {}", e),
         }
+        repaired = collector.accepted.len() - before;
+        skipped = collector.skipped(&invalid);
     }
 
-    let messages = vec![
-        ChatMessage {
-            role: "system".to_string(),
-            content: serde_json::json!("You are a tab cleanup assistant. Classify and decide whether each tab should be kept, closed, or is unsure. Consider the user's context and work habits."),
-        },
-        ChatMessage {
-            role: "user".to_string(),
-            content: serde_json::Value::Array(content_parts),
-        },
-    ];
+    if collector.accepted.is_empty() {
+        if let Some(error) = parse_error {
+            return Err(format!("Failed to parse suggestions: {}", error));
+        }
+    }
 
-    let response = call_openai(settings, messages, 0.2).await?;
-    let suggestions = extract_json_array(&response)?;
+    let issues = SuggestionIssues {
+        skipped,
+        unknown: collector.unknown.into_iter().collect(),
+        invalid,
+        repaired,
+        errors: Vec::new(),
+        withheld: Vec::new(),
+    };
+    if !issues.is_empty() {
+        println!(
+            "[AI] Suggestions incomplete: skipped {:?}, unknown {:?}, {} invalid",
+            issues.skipped,
+            issues.unknown,
+            issues.invalid.len()
+        );
+    }
 
     let now = chrono::Utc::now().timestamp_millis();
-    Ok(suggestions
-        .into_iter()
+    let suggestions = collector
+        .accepted
+        .into_values()
         .map(|s| {
             (
                 s.tab_id,
                 TabSuggestion {
                     decision: s.decision,
                     reason: s.reason,
-                    category: s.category,
+                    category: Some(s.category),
                     digest: s.digest,
                     scored_at: now,
                 },
             )
         })
-        .collect())
+        .collect();
+
+    Ok(SuggestionOutcome {
+        suggestions,
+        issues,
+    })
+}
+
+/// Context window of well-known models, matched by prefix (most specific first)
+const MODEL_CONTEXT_WINDOWS: &[(&str, usize)] = &[
+    ("gpt-5", 400_000),
+    ("gpt-4.1", 1_047_576),
+    ("gpt-4o", 128_000),
+    ("gpt-4-turbo", 128_000),
+    ("gpt-4", 8_192),
+    ("gpt-3.5-turbo", 16_385),
+    ("o1", 200_000),
+    ("o3", 200_000),
+    ("o4", 200_000),
+    ("claude", 200_000),
+];
+/// Assumed for models not in the table
+const DEFAULT_CONTEXT_WINDOW: usize = 8_192;
+/// Ollama truncates prompts to its `num_ctx`, whatever the model supports
+const OLLAMA_CONTEXT_WINDOW: usize = 4_096;
+/// Share of the context window a batch may fill; estimates are rough
+const CONTEXT_SAFETY_FACTOR: f64 = 0.8;
+/// Upper bound on a batch's tokens, whatever the context window, to keep each call cheap
+const MAX_BATCH_TOKENS: usize = 32_000;
+/// Room for each tab's suggestion in the reply
+const OUTPUT_TOKENS_PER_TAB: usize = 120;
+/// Role and framing tokens added to every message
+const MESSAGE_OVERHEAD_TOKENS: usize = 4;
+const DEFAULT_ANALYZE_CONCURRENCY: usize = 2;
+const MAX_ANALYZE_CONCURRENCY: usize = 8;
+
+/// Rough token count: about four ASCII characters per token, one token per other character
+pub fn estimate_text_tokens(text: &str) -> usize {
+    let (ascii, other) = text.chars().fold((0usize, 0usize), |(ascii, other), c| {
+        if c.is_ascii() {
+            (ascii + 1, other)
+        } else {
+            (ascii, other + 1)
+        }
+    });
+    ascii.div_ceil(4) + other
+}
+
+/// Tokens one screenshot costs with `provider`. OpenAI bills low-detail images at a flat 85;
+/// Anthropic scales full viewport captures to about 1.2 megapixels (~1,600 tokens).
+fn image_tokens(provider: ProviderKind) -> usize {
+    match provider {
+        ProviderKind::OpenAi => 85,
+        ProviderKind::Anthropic => 1_600,
+        ProviderKind::Ollama => 600,
+        ProviderKind::Mock => 0,
+    }
+}
+
+pub fn estimate_message_tokens(messages: &[Message], image_tokens: usize) -> usize {
+    messages
+        .iter()
+        .map(|m| {
+            MESSAGE_OVERHEAD_TOKENS
+                + estimate_text_tokens(&m.text())
+                + m.image_count() * image_tokens
+        })
+        .sum()
+}
+
+/// Context window for `model` on `provider`; `configured` (from settings) wins
+pub fn context_window(provider: ProviderKind, model: &str, configured: Option<u32>) -> usize {
+    if let Some(tokens) = configured.filter(|t| *t > 0) {
+        return tokens as usize;
+    }
+    match provider {
+        ProviderKind::Ollama => OLLAMA_CONTEXT_WINDOW,
+        ProviderKind::Mock => MAX_BATCH_TOKENS,
+        _ => {
+            let model = model.to_lowercase();
+            let model = model.rsplit('/').next().unwrap_or(&model);
+            MODEL_CONTEXT_WINDOWS
+                .iter()
+                .find(|(prefix, _)| model.starts_with(prefix))
+                .map(|(_, tokens)| *tokens)
+                .unwrap_or(DEFAULT_CONTEXT_WINDOW)
+        }
+    }
+}
+
+/// Token costs used to split an analysis run
+struct SuggestionBudget {
+    /// Tokens a batch may use, prompt and reply together
+    batch_limit: usize,
+    /// Instructions and system message, paid once per batch
+    base_tokens: usize,
+    image_tokens: usize,
+}
+
+impl SuggestionBudget {
+    fn new(settings: &Settings) -> Result<Self, String> {
+        let config = llm::task_config(settings, Task::Suggestions)?;
+        let kind = config.provider.kind();
+        let window = context_window(kind, &config.model, settings.suggestion_context_window);
+        let image_tokens = image_tokens(kind);
+        let base = [
+            suggestion_system_message(),
+            Message::user(suggestion_prompt(settings)),
+        ];
+
+        Ok(Self {
+            batch_limit: ((window as f64 * CONTEXT_SAFETY_FACTOR) as usize).min(MAX_BATCH_TOKENS),
+            base_tokens: estimate_message_tokens(&base, image_tokens),
+            image_tokens,
+        })
+    }
+
+    /// Prompt and reply tokens a tab adds to a batch
+    fn tab_tokens(&self, tab: &TabRecord) -> usize {
+        let screenshot = screenshot_path(tab).is_some_and(|p| std::path::Path::new(p).is_file());
+        estimate_text_tokens(&format_tab_for_prompt(tab))
+            + if screenshot { self.image_tokens } else { 0 }
+            + OUTPUT_TOKENS_PER_TAB
+    }
+
+    /// Split `tabs` into consecutive batches that fit the limit. A tab too large to share a
+    /// batch gets one of its own.
+    fn plan(&self, tabs: &[TabRecord]) -> Vec<Range<usize>> {
+        let mut batches = Vec::new();
+        let mut start = 0;
+        let mut used = self.base_tokens;
+        for (i, tab) in tabs.iter().enumerate() {
+            let cost = self.tab_tokens(tab);
+            if i > start && used + cost > self.batch_limit {
+                batches.push(start..i);
+                start = i;
+                used = self.base_tokens;
+            }
+            used += cost;
+        }
+        if start < tabs.len() {
+            batches.push(start..tabs.len());
+        }
+        batches
+    }
+}
+
+/// Requests sent at once when a task is split into parts (`analyze_concurrency`)
+fn request_concurrency(settings: &Settings) -> usize {
+    settings
+        .analyze_concurrency
+        .map(|n| n as usize)
+        .unwrap_or(DEFAULT_ANALYZE_CONCURRENCY)
+        .clamp(1, MAX_ANALYZE_CONCURRENCY)
+}
+
+/// Suggest keep/close decisions for `tabs`. The tabs are split into batches that fit the
+/// suggestion model's context window, analyzed concurrently (`analyze_concurrency` at a
+/// time) and merged, calling `on_progress(done, total)` as batches finish. Fails only if
+/// every batch fails. Tabs on the privacy blocklist are never sent and come back as
+/// `withheld`.
+pub async fn suggest_tabs(
+    tabs: &[TabRecord],
+    settings: &Settings,
+    on_progress: &(dyn Fn(usize, usize) + Sync),
+) -> Result<SuggestionOutcome, String> {
+    let privacy = PrivacyFilter::new(settings);
+    let mut merged = SuggestionOutcome {
+        suggestions: HashMap::new(),
+        issues: SuggestionIssues {
+            withheld: tabs
+                .iter()
+                .filter(|t| privacy.is_withheld(t))
+                .map(|t| t.id)
+                .collect(),
+            ..SuggestionIssues::default()
+        },
+    };
+    let tabs = &privacy.shareable(tabs)[..];
+    if tabs.is_empty() {
+        return Ok(merged);
+    }
+
+    let budget = SuggestionBudget::new(settings)?;
+    let batches = budget.plan(tabs);
+    let concurrency = request_concurrency(settings);
+    if batches.len() > 1 {
+        println!(
+            "[AI] Splitting {} tabs into {} batches (limit {} tokens each, {} at a time)",
+            tabs.len(),
+            batches.len(),
+            budget.batch_limit,
+            concurrency
+        );
+    }
+
+    let total = batches.len();
+    on_progress(0, total);
+    let mut pending = stream::iter(batches)
+        .map(|range| async move {
+            let result = suggest_batch(&tabs[range.clone()], settings).await;
+            (range, result)
+        })
+        .buffer_unordered(concurrency);
+    let mut results: Vec<(Range<usize>, Result<SuggestionOutcome, String>)> = Vec::new();
+    while let Some(result) = pending.next().await {
+        results.push(result);
+        on_progress(results.len(), total);
+    }
+
+    let mut succeeded = false;
+    let mut unknown = BTreeSet::new();
+    for (range, result) in results {
+        match result {
+            Ok(outcome) => {
+                succeeded = true;
+                merged.suggestions.extend(outcome.suggestions);
+                merged.issues.skipped.extend(outcome.issues.skipped);
+                merged.issues.invalid.extend(outcome.issues.invalid);
+                merged.issues.errors.extend(outcome.issues.errors);
+                merged.issues.repaired += outcome.issues.repaired;
+                unknown.extend(outcome.issues.unknown);
+            }
+            Err(e) => {
+                println!("[AI] Batch of {} tabs failed: {}", range.len(), e);
+                merged
+                    .issues
+                    .skipped
+                    .extend(tabs[range].iter().map(|t| t.id));
+                merged.issues.errors.push(e);
+            }
+        }
+    }
+    if !succeeded {
+        return Err(merged.issues.errors.swap_remove(0));
+    }
+
+    // An id made up in one batch may be a real tab of another
+    unknown.retain(|id| !tabs.iter().any(|t| t.id == *id));
+    merged.issues.unknown = unknown.into_iter().collect();
+    merged.issues.skipped.sort_unstable();
+    Ok(merged)
 }
 
-fn extract_domain(url: &str) -> String {
+pub fn extract_domain(url: &str) -> String {
     url.split("://")
         .nth(1)
         .and_then(|s| s.split('/').next())
@@ -397,126 +953,331 @@ fn truncate_str(s: &str, max_chars: usize) -> String {
     }
 }
 
+/// Shown instead of a report for a day without tabs
+const EMPTY_REPORT: &str = "No tabs to report on.";
+
+/// `date` is the `YYYY-MM-DD` day the report covers
 pub async fn generate_daily_report(
     tabs: &[TabRecord],
     settings: &Settings,
+    date: &str,
+    on_progress: &(dyn Fn(usize, usize) + Sync),
 ) -> Result<String, String> {
     println!("\n[AI Report] ========== Generate Daily Report ==========");
 
+    let tabs = &PrivacyFilter::new(settings).shareable(tabs)[..];
     if tabs.is_empty() {
         println!("[AI Report] No tabs to report on.");
-        return Ok("No tabs to report on.".to_string());
+        return Ok(EMPTY_REPORT.to_string());
     }
 
-    // Stats for logging
-    let tabs_with_suggestion = tabs.iter().filter(|t| t.suggestion.is_some()).count();
-    let tabs_with_digest = tabs
-        .iter()
-        .filter(|t| {
-            t.suggestion
-                .as_ref()
-                .and_then(|s| s.digest.as_ref())
-                .is_some()
-        })
-        .count();
-    let tabs_with_description = tabs.iter().filter(|t| t.description.is_some()).count();
+    let (messages, steps) = prepare_report(tabs, settings, date, on_progress).await?;
 
-    println!("[AI Report] Total tabs: {}", tabs.len());
-    println!("[AI Report] Tabs with suggestion: {}", tabs_with_suggestion);
-    println!("[AI Report] Tabs with digest: {}", tabs_with_digest);
-    println!(
-        "[AI Report] Tabs with description: {}",
-        tabs_with_description
-    );
+    println!("[AI Report] Calling AI provider...");
+    let result = complete(settings, Task::Report, &messages, 0.3, None).await;
+    log_report_result(&result);
+    if result.is_ok() {
+        on_progress(steps, steps);
+    }
+    result
+}
 
-    // Group tabs by domain
-    let mut domain_groups: std::collections::HashMap<String, Vec<&TabRecord>> =
-        std::collections::HashMap::new();
-    for tab in tabs {
-        let domain = tab
-            .url
-            .as_deref()
-            .map(extract_domain)
-            .unwrap_or_else(|| "unknown".to_string());
-        domain_groups.entry(domain).or_default().push(tab);
+/// Like `generate_daily_report`, passing the report to `on_chunk` as it is written; parts
+/// of a large day are summarized before anything streams.
+/// With `resume_from`, the model continues that partial report instead of starting over;
+/// the result is the whole report, partial included.
+pub async fn stream_daily_report(
+    tabs: &[TabRecord],
+    settings: &Settings,
+    date: &str,
+    resume_from: Option<&str>,
+    on_chunk: ChunkSink<'_>,
+    on_progress: &(dyn Fn(usize, usize) + Sync),
+) -> Result<String, String> {
+    println!("\n[AI Report] ========== Stream Daily Report ==========");
+
+    let tabs = &PrivacyFilter::new(settings).shareable(tabs)[..];
+    if tabs.is_empty() {
+        println!("[AI Report] No tabs to report on.");
+        on_chunk(EMPTY_REPORT);
+        return Ok(EMPTY_REPORT.to_string());
     }
 
-    println!("[AI Report] Domains: {}", domain_groups.len());
-    for (domain, domain_tabs) in &domain_groups {
-        println!("[AI Report]   - {}: {} tabs", domain, domain_tabs.len());
+    let (mut messages, steps) = prepare_report(tabs, settings, date, on_progress).await?;
+    let partial = resume_from.filter(|p| !p.trim().is_empty());
+    if let Some(partial) = partial {
+        println!(
+            "[AI Report] Resuming from {} bytes of partial report",
+            partial.len()
+        );
+        messages.push(Message::assistant(partial));
+        messages.push(Message::user(
+            "Your report was cut off. Continue it exactly where it stopped, without repeating anything already written.",
+        ));
     }
 
-    // Format grouped tabs
-    let grouped_list: Vec<String> = domain_groups
-        .iter()
-        .map(|(domain, domain_tabs)| {
-            let tabs_info: Vec<String> = domain_tabs
-                .iter()
-                .map(|tab| {
-                    let title = tab.title.as_deref().unwrap_or("Untitled");
-                    let active_time = tab.total_active_ms;
-
-                    // Use category + digest from suggestion, fallback to description
-                    let content = if let Some(suggestion) = &tab.suggestion {
-                        let category = suggestion.category.as_deref().unwrap_or("uncategorized");
-                        let summary = suggestion
-                            .digest
-                            .as_deref()
-                            .or(tab.description.as_deref())
-                            .map(|d| truncate_str(d, 300))
-                            .unwrap_or_default();
-                        if summary.is_empty() {
-                            format!("[{}]", category)
-                        } else {
-                            format!("[{}] {}", category, summary)
-                        }
-                    } else {
-                        // No suggestion, use description
-                        tab.description
-                            .as_deref()
-                            .map(|d| truncate_str(d, 300))
-                            .unwrap_or_default()
-                    };
-
-                    if content.is_empty() {
-                        format!("  - {} ({}ms)", title, active_time)
-                    } else {
-                        format!("  - {} ({}ms)\n    {}", title, active_time, content)
-                    }
-                })
-                .collect();
+    println!("[AI Report] Streaming from AI provider...");
+    let result = complete_with(settings, Task::Report, &messages, 0.3, None, Some(on_chunk))
+        .await
+        .map(|rest| format!("{}{}", partial.unwrap_or(""), rest));
+    log_report_result(&result);
+    if result.is_ok() {
+        on_progress(steps, steps);
+    }
+    result
+}
 
-            format!("## {}\n{}", domain, tabs_info.join("\n"))
-        })
+/// Shown instead of a rollup for a period without tabs or reports
+const EMPTY_PERIOD_REPORT: &str = "No activity in this period.";
+
+const PERIOD_REPORT_SYSTEM: &str = "You write status updates from a person's browsing activity over a week or month: themes, progress, unfinished threads and trends. Be concise and actionable. Use markdown formatting.";
+
+/// Inputs of a weekly or monthly rollup
+pub struct PeriodActivity<'a> {
+    pub period: Period,
+    pub granularity: Granularity,
+    /// Tabs touched in the period, already passed through `PrivacyFilter::shareable`
+    /// (as were those `stats` were computed from)
+    pub tabs: &'a [TabRecord],
+    /// Daily reports archived for the period
+    pub daily_reports: &'a [DailyReport],
+    pub stats: &'a PeriodStats,
+    /// `stats` as markdown tables compared with the previous period
+    pub stats_markdown: &'a str,
+}
+
+/// Rollup report for a period: the model's summary of the daily reports (and of the tabs
+/// of days without one), followed by the exact time tables
+pub async fn generate_period_report(
+    activity: &PeriodActivity<'_>,
+    settings: &Settings,
+) -> Result<String, String> {
+    let PeriodActivity {
+        period,
+        granularity,
+        tabs,
+        daily_reports,
+        stats,
+        stats_markdown,
+    } = activity;
+    println!(
+        "\n[AI Report] ========== Generate {} Report {} to {} ==========",
+        granularity.adjective(),
+        period.start,
+        period.end
+    );
+    if tabs.is_empty() && daily_reports.is_empty() {
+        println!("[AI Report] No activity in this period.");
+        return Ok(EMPTY_PERIOD_REPORT.to_string());
+    }
+
+    let open_tabs: Vec<String> = stats
+        .open_at_end
+        .iter()
+        .map(|title| format!("- {}", title))
         .collect();
+    let intro = format!(
+        "Write a {} report for {} to {} based on the user's browsing activity.\n\nInclude:\n- Main themes and what moved forward\n- Where the time went, by category and domain\n- Unfinished threads worth picking up\n- Trends compared with the previous period{}\n\nTime statistics (exact; they are appended to your report, so refer to them instead of repeating the tables):\n\n{}\nTabs still open at the end of the period:\n{}",
+        granularity.adjective(),
+        period.start,
+        period.end,
+        user_context_note(settings),
+        stats_markdown,
+        if open_tabs.is_empty() { "- none".to_string() } else { open_tabs.join("\n") }
+    );
 
-    let today = chrono::Local::now().format("%Y-%m-%d").to_string();
+    // Days without a daily report are covered by their tabs
+    let reported: HashSet<&str> = daily_reports.iter().map(|r| r.date.as_str()).collect();
+    let uncovered: Vec<String> = period
+        .dates()
+        .map(crate::storage::format_date)
+        .filter(|date| !reported.contains(date.as_str()))
+        .collect();
+    let mut loose_tabs: Vec<&TabRecord> = if uncovered.is_empty() {
+        Vec::new()
+    } else {
+        tabs.iter().collect()
+    };
+    loose_tabs.sort_by_key(|t| std::cmp::Reverse(t.total_active_ms));
+
+    // Whatever room the intro leaves is shared between reports and tabs
+    let limit = report_prompt_limit(settings)?;
+    let room = limit
+        .saturating_sub(estimate_text_tokens(PERIOD_REPORT_SYSTEM) + estimate_text_tokens(&intro));
+    let shares = usize::from(!daily_reports.is_empty()) + usize::from(!loose_tabs.is_empty());
+    let share = room / shares.max(1);
+
+    let privacy = PrivacyFilter::new(settings);
+    let mut prompt = intro;
+    if !daily_reports.is_empty() {
+        // At least a few hundred characters each, even if that overruns the estimate
+        let per_report = (share * 4 / daily_reports.len()).max(400);
+        prompt.push_str("\n\nDaily reports:");
+        for report in *daily_reports {
+            prompt.push_str(&format!(
+                "\n\n### {}\n{}",
+                report.date,
+                privacy.redact(&truncate_str(report.content.trim(), per_report))
+            ));
+        }
+    }
+    if !loose_tabs.is_empty() {
+        prompt.push_str(&format!(
+            "\n\nMost used tabs (covering days without a daily report: {}):\n",
+            uncovered.join(", ")
+        ));
+        let mut used = 0;
+        for tab in loose_tabs {
+            let line = report_tab_line(tab);
+            used += estimate_text_tokens(&line);
+            if used > share {
+                break;
+            }
+            prompt.push_str(&line);
+            prompt.push('\n');
+        }
+    }
 
-    // Build user context if available
-    let user_context_str = settings
+    println!("[AI Report] User message:\n{}", prompt);
+    let messages = vec![Message::system(PERIOD_REPORT_SYSTEM), Message::user(prompt)];
+    let result = complete(settings, Task::Report, &messages, 0.3, None)
+        .await
+        .map(|summary| format!("{}\n\n{}", summary.trim(), stats_markdown));
+    log_report_result(&result);
+    result
+}
+
+fn log_report_result(result: &Result<String, String>) {
+    match result {
+        Ok(content) => {
+            println!(
+                "[AI Report] Report generated successfully ({} words)",
+                content.split_whitespace().count()
+            );
+            println!("[AI Report] Response:\n{}", content);
+        }
+        Err(e) => {
+            println!("[AI Report] Failed to generate report: {}", e);
+        }
+    }
+    println!("[AI Report] ========== End Daily Report ==========\n");
+}
+
+/// Upper bound on a report prompt's tokens, whatever the context window; long prompts lose detail
+const MAX_REPORT_PROMPT_TOKENS: usize = 24_000;
+/// Room kept in the context window for the report itself
+const REPORT_OUTPUT_TOKENS: usize = 1_000;
+/// Rounds of condensing notes, after which the report is written from whatever notes remain
+const MAX_REPORT_LEVELS: usize = 3;
+
+const REPORT_SYSTEM: &str = "You summarize browsing activity as a daily report with key themes, tasks, and next actions. Be concise and actionable. Use markdown formatting. The input is grouped by domain, each tab has a title, active time, and optionally a category tag with content summary.";
+const REPORT_PART_SYSTEM: &str = "You condense part of a day's browsing activity into notes for a daily report written later from all parts. Keep themes, concrete tasks and progress, open questions and notable pages; drop repetition. Use terse markdown bullets.";
+const ACTIVITY_HEADING: &str = "Browsing activity grouped by domain";
+const NOTES_HEADING: &str = "Notes summarizing each part of the day's browsing activity";
+
+/// A tab as listed in the report prompt: title, active time, and category with summary
+fn report_tab_line(tab: &TabRecord) -> String {
+    let title = tab.title.as_deref().unwrap_or("Untitled");
+    let active_time = tab.total_active_ms;
+
+    // Use category + digest from suggestion, fallback to description
+    let content = if let Some(suggestion) = &tab.suggestion {
+        let category = suggestion.category.as_deref().unwrap_or("uncategorized");
+        let summary = suggestion
+            .digest
+            .as_deref()
+            .or(tab.description.as_deref())
+            .map(|d| truncate_str(d, 300))
+            .unwrap_or_default();
+        if summary.is_empty() {
+            format!("[{}]", category)
+        } else {
+            format!("[{}] {}", category, summary)
+        }
+    } else {
+        // No suggestion, use description
+        tab.description
+            .as_deref()
+            .map(|d| truncate_str(d, 300))
+            .unwrap_or_default()
+    };
+
+    if content.is_empty() {
+        format!("  - {} ({}ms)", title, active_time)
+    } else {
+        format!("  - {} ({}ms)\n    {}", title, active_time, content)
+    }
+}
+
+/// Tab lines grouped by domain, in domain order
+fn domain_sections(tabs: &[TabRecord]) -> Vec<(String, Vec<String>)> {
+    // Stats for logging
+    let tabs_with_suggestion = tabs.iter().filter(|t| t.suggestion.is_some()).count();
+    let tabs_with_digest = tabs
+        .iter()
+        .filter(|t| {
+            t.suggestion
+                .as_ref()
+                .and_then(|s| s.digest.as_ref())
+                .is_some()
+        })
+        .count();
+    let tabs_with_description = tabs.iter().filter(|t| t.description.is_some()).count();
+
+    println!("[AI Report] Total tabs: {}", tabs.len());
+    println!("[AI Report] Tabs with suggestion: {}", tabs_with_suggestion);
+    println!("[AI Report] Tabs with digest: {}", tabs_with_digest);
+    println!(
+        "[AI Report] Tabs with description: {}",
+        tabs_with_description
+    );
+
+    let mut domain_groups: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for tab in tabs {
+        let domain = tab
+            .url
+            .as_deref()
+            .map(extract_domain)
+            .unwrap_or_else(|| "unknown".to_string());
+        domain_groups
+            .entry(domain)
+            .or_default()
+            .push(report_tab_line(tab));
+    }
+
+    println!("[AI Report] Domains: {}", domain_groups.len());
+    for (domain, lines) in &domain_groups {
+        println!("[AI Report]   - {}: {} tabs", domain, lines.len());
+    }
+    domain_groups.into_iter().collect()
+}
+
+fn user_context_note(settings: &Settings) -> String {
+    settings
         .user_context
         .as_ref()
         .filter(|s| !s.is_empty())
         .map(|ctx| format!("\n\nUser's context and work preferences:\n{}", ctx))
-        .unwrap_or_default();
+        .unwrap_or_default()
+}
 
-    // Build prompt content
+/// System and user messages asking for the report on `activity` (domain sections or notes)
+fn report_messages(settings: &Settings, date: &str, heading: &str, activity: &str) -> Vec<Message> {
     let prompt_content = format!(
-        "Generate a concise daily report for {} based on the user's browsing activity.\n\nInclude:\n- Main themes and topics\n- Key activities and progress\n- Open questions or unfinished tasks\n- Suggested follow-ups for tomorrow{}\n\nBrowsing activity grouped by domain:\n\n{}",
-        today,
-        user_context_str,
-        grouped_list.join("\n\n")
+        "Generate a concise daily report for {} based on the user's browsing activity.\n\nInclude:\n- Main themes and topics\n- Key activities and progress\n- Open questions or unfinished tasks\n- Suggested follow-ups for tomorrow{}\n\n{}:\n\n{}",
+        date,
+        user_context_note(settings),
+        heading,
+        activity
     );
 
-    let system_content = "You summarize browsing activity as a daily report with key themes, tasks, and next actions. Be concise and actionable. Use markdown formatting. The input is grouped by domain, each tab has a title, active time, and optionally a category tag with content summary.";
-
     // Log complete messages
     let prompt_word_count = prompt_content.split_whitespace().count();
     println!("[AI Report] -------- Messages --------");
     println!(
         "[AI Report] System message ({} words):\n{}",
-        system_content.split_whitespace().count(),
-        system_content
+        REPORT_SYSTEM.split_whitespace().count(),
+        REPORT_SYSTEM
     );
     println!("[AI Report] --------");
     println!(
@@ -525,33 +1286,1235 @@ pub async fn generate_daily_report(
     );
     println!("[AI Report] -------- End Messages --------");
 
-    let messages = vec![
-        ChatMessage {
-            role: "system".to_string(),
-            content: serde_json::json!(system_content),
-        },
-        ChatMessage {
-            role: "user".to_string(),
-            content: serde_json::json!(prompt_content),
+    vec![
+        Message::system(REPORT_SYSTEM),
+        Message::user(prompt_content),
+    ]
+}
+
+/// Messages asking for notes on part `index` of `count` of the day
+fn report_part_messages(
+    settings: &Settings,
+    date: &str,
+    (index, count): (usize, usize),
+    heading: &str,
+    activity: &str,
+) -> Vec<Message> {
+    vec![
+        Message::system(REPORT_PART_SYSTEM),
+        Message::user(format!(
+            "Summarize part {} of {} of the browsing activity on {} as notes for the daily report.{}\n\n{}:\n\n{}",
+            index,
+            count,
+            date,
+            user_context_note(settings),
+            heading,
+            activity
+        )),
+    ]
+}
+
+/// Prompt tokens one report request may use with the configured report model
+fn report_prompt_limit(settings: &Settings) -> Result<usize, String> {
+    let config = llm::task_config(settings, Task::Report)?;
+    let window = context_window(
+        config.provider.kind(),
+        &config.model,
+        settings.report_context_window,
+    );
+    let limit = ((window as f64 * CONTEXT_SAFETY_FACTOR) as usize).min(MAX_REPORT_PROMPT_TOKENS);
+    Ok(limit
+        .saturating_sub(REPORT_OUTPUT_TOKENS)
+        .max(REPORT_OUTPUT_TOKENS))
+}
+
+/// Domain sections as prompt text, splitting any domain too large for `limit` tokens into
+/// several sections
+fn section_units(sections: &[(String, Vec<String>)], limit: usize) -> Vec<String> {
+    let mut units = Vec::new();
+    for (domain, lines) in sections {
+        let mut unit = format!("## {}", domain);
+        let mut has_lines = false;
+        for line in lines {
+            if has_lines && estimate_text_tokens(&unit) + estimate_text_tokens(line) > limit {
+                units.push(unit);
+                unit = format!("## {} (continued)", domain);
+            }
+            unit.push('\n');
+            unit.push_str(line);
+            has_lines = true;
+        }
+        units.push(unit);
+    }
+    units
+}
+
+/// Join consecutive `units` into as few parts as fit `limit` tokens each
+fn pack_units(units: &[String], limit: usize) -> Vec<String> {
+    let mut parts: Vec<String> = Vec::new();
+    let mut used = 0;
+    for unit in units {
+        let tokens = estimate_text_tokens(unit);
+        match parts.last_mut() {
+            Some(part) if used + tokens <= limit => {
+                part.push_str("\n\n");
+                part.push_str(unit);
+                used += tokens;
+            }
+            _ => {
+                parts.push(unit.clone());
+                used = tokens;
+            }
+        }
+    }
+    parts
+}
+
+/// How a day's report starts
+enum ReportPlan {
+    /// The activity fits one prompt: the report request itself
+    Single(Vec<Message>),
+    /// Domain section units to summarize in parts of at most `part_limit` tokens
+    Parts {
+        units: Vec<String>,
+        part_limit: usize,
+        /// Estimated tokens of the whole activity, and the prompt limit it exceeds
+        tokens: usize,
+        limit: usize,
+    },
+}
+
+fn plan_report(tabs: &[TabRecord], settings: &Settings, date: &str) -> Result<ReportPlan, String> {
+    let sections = domain_sections(tabs);
+    let limit = report_prompt_limit(settings)?;
+    let activity: Vec<String> = sections
+        .iter()
+        .map(|(domain, lines)| format!("## {}\n{}", domain, lines.join("\n")))
+        .collect();
+    let activity = activity.join("\n\n");
+    let tokens = estimate_text_tokens(&activity);
+    if tokens <= limit {
+        return Ok(ReportPlan::Single(report_messages(
+            settings,
+            date,
+            ACTIVITY_HEADING,
+            &activity,
+        )));
+    }
+
+    let overhead = estimate_message_tokens(
+        &report_part_messages(settings, date, (1, 1), NOTES_HEADING, ""),
+        0,
+    );
+    let part_limit = limit.saturating_sub(overhead).max(REPORT_OUTPUT_TOKENS);
+    Ok(ReportPlan::Parts {
+        units: section_units(&sections, part_limit),
+        part_limit,
+        tokens,
+        limit,
+    })
+}
+
+/// Messages for the report on `tabs`, and how many requests the report takes in all.
+/// A day too large for one prompt is summarized in parts first (map), and the report is
+/// written from their notes (reduce); notes still too large are condensed again.
+/// `on_progress` counts the part requests as they finish.
+async fn prepare_report(
+    tabs: &[TabRecord],
+    settings: &Settings,
+    date: &str,
+    on_progress: &(dyn Fn(usize, usize) + Sync),
+) -> Result<(Vec<Message>, usize), String> {
+    let (mut units, part_limit, tokens, limit) = match plan_report(tabs, settings, date)? {
+        ReportPlan::Single(messages) => {
+            on_progress(0, 1);
+            return Ok((messages, 1));
+        }
+        ReportPlan::Parts {
+            units,
+            part_limit,
+            tokens,
+            limit,
+        } => (units, part_limit, tokens, limit),
+    };
+
+    let concurrency = request_concurrency(settings);
+    let mut heading = ACTIVITY_HEADING;
+    let mut done = 0;
+    for level in 1..=MAX_REPORT_LEVELS {
+        let parts = pack_units(&units, part_limit);
+        let count = parts.len();
+        let total = done + count + 1;
+        println!(
+            "[AI Report] Activity of ~{} tokens exceeds {}; summarizing {} parts (round {})",
+            tokens, limit, count, level
+        );
+        on_progress(done, total);
+
+        // Built up front rather than in a `map` closure over borrowed parts: the closure's
+        // higher-ranked signature makes the command futures fail to be `Send`
+        let requests: Vec<BoxFuture<'_, Result<String, String>>> = parts
+            .iter()
+            .enumerate()
+            .map(|(i, part)| {
+                let messages = report_part_messages(settings, date, (i + 1, count), heading, part);
+                async move {
+                    complete(settings, Task::Report, &messages, 0.3, None)
+                        .await
+                        .map_err(|e| {
+                            format!("Failed to summarize part {} of {}: {}", i + 1, count, e)
+                        })
+                }
+                .boxed()
+            })
+            .collect();
+        let mut pending = stream::iter(requests).buffered(concurrency);
+        let mut notes = Vec::with_capacity(count);
+        while let Some(note) = pending.next().await {
+            notes.push(note?);
+            done += 1;
+            on_progress(done, total);
+        }
+
+        units = notes
+            .iter()
+            .enumerate()
+            .map(|(i, note)| format!("### Part {}\n{}", i + 1, note.trim()))
+            .collect();
+        let joined = units.join("\n\n");
+        let tokens = estimate_text_tokens(&joined);
+        if tokens <= limit || count == 1 || level == MAX_REPORT_LEVELS {
+            return Ok((
+                report_messages(settings, date, NOTES_HEADING, &joined),
+                total,
+            ));
+        }
+        heading = NOTES_HEADING;
+    }
+    unreachable!("The last round always returns")
+}
+
+/// Text embedded for a tab: title, AI digest and page description
+pub fn embedding_text(tab: &TabRecord) -> String {
+    let mut parts = Vec::new();
+    if let Some(title) = tab.title.as_deref().filter(|t| !t.is_empty()) {
+        parts.push(title.to_string());
+    }
+    if let Some(digest) = tab
+        .suggestion
+        .as_ref()
+        .and_then(|s| s.digest.as_deref())
+        .filter(|d| !d.is_empty())
+    {
+        parts.push(digest.to_string());
+    }
+    if let Some(description) = tab.description.as_deref().filter(|d| !d.is_empty()) {
+        parts.push(description.to_string());
+    }
+    if parts.is_empty() {
+        if let Some(url) = &tab.url {
+            parts.push(url.clone());
+        }
+    }
+    truncate_str(&parts.join("\n"), MAX_EMBEDDING_CHARS)
+}
+
+/// Embed texts with the provider configured for embeddings.
+/// Returns one vector per input, in input order.
+pub async fn embed_texts(settings: &Settings, texts: &[String]) -> Result<Vec<Vec<f32>>, String> {
+    if texts.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let config = llm::task_config(settings, Task::Embeddings)?;
+    println!(
+        "[AI Embed] Embedding {} texts with {}",
+        texts.len(),
+        config.label()
+    );
+
+    let kind = config.provider.kind();
+    usage::check_budget(settings, kind, &config.model).await?;
+
+    let start_time = std::time::Instant::now();
+    let batch = config.provider.embed(texts, &config.model).await;
+
+    let reported = batch.as_ref().map(|b| b.usage).unwrap_or_default();
+    usage::record(
+        settings,
+        AiCall {
+            provider: kind,
+            model: config.model.clone(),
+            task: Task::Embeddings,
+            prompt_tokens: reported
+                .prompt_tokens
+                .unwrap_or_else(|| texts.iter().map(|t| estimate_text_tokens(t) as u64).sum()),
+            completion_tokens: 0,
+            estimated: reported.prompt_tokens.is_none(),
+            image_count: 0,
+            latency_ms: start_time.elapsed().as_millis() as u64,
+            retries: batch.as_ref().ok().map(|b| b.usage.retries),
+            error: batch.as_ref().err().cloned(),
         },
-    ];
+    )
+    .await;
+    let vectors = batch?.vectors;
+    if vectors.len() != texts.len() {
+        return Err(format!(
+            "Expected {} embeddings, got {}",
+            texts.len(),
+            vectors.len()
+        ));
+    }
+    Ok(vectors)
+}
 
-    println!("[AI Report] Calling OpenAI API...");
-    let result = call_openai(settings, messages, 0.3).await;
+/// One request as a dry run shows it
+#[derive(Debug, Serialize)]
+pub struct RequestPreview {
+    /// Provider and model, e.g. "openai/gpt-4o-mini"
+    pub model: String,
+    /// Chat messages, see `Message::preview`
+    pub messages: Vec<Value>,
+    /// Texts to embed, for embedding requests
+    pub inputs: Vec<String>,
+}
 
-    match &result {
-        Ok(content) => {
-            println!(
-                "[AI Report] Report generated successfully ({} words)",
-                content.split_whitespace().count()
-            );
-            println!("[AI Report] Response:\n{}", content);
+/// What a task would send, computed without sending anything
+#[derive(Debug, Serialize)]
+pub struct PrivacyPreview {
+    /// How much of each tab involved is sent, by tab id
+    pub exposure: BTreeMap<i64, Exposure>,
+    pub requests: Vec<RequestPreview>,
+}
+
+impl PrivacyPreview {
+    fn new(privacy: &PrivacyFilter, tabs: &[TabRecord]) -> Self {
+        Self {
+            exposure: tabs.iter().map(|t| (t.id, privacy.exposure(t))).collect(),
+            requests: Vec::new(),
         }
-        Err(e) => {
-            println!("[AI Report] Failed to generate report: {}", e);
+    }
+
+    fn push_chat(&mut self, model: &str, messages: &[Message]) {
+        self.requests.push(RequestPreview {
+            model: model.to_string(),
+            messages: messages.iter().map(Message::preview).collect(),
+            inputs: Vec::new(),
+        });
+    }
+}
+
+/// The suggestion requests for `tabs`, batched as `suggest_tabs` would (cache not consulted)
+pub fn preview_suggestions(
+    tabs: &[TabRecord],
+    settings: &Settings,
+) -> Result<PrivacyPreview, String> {
+    let privacy = PrivacyFilter::new(settings);
+    let mut preview = PrivacyPreview::new(&privacy, tabs);
+    let model = get_suggestion_model(settings)?;
+    let tabs = privacy.shareable(tabs);
+    let budget = SuggestionBudget::new(settings)?;
+    for range in budget.plan(&tabs) {
+        preview.push_chat(&model, &suggestion_messages(&tabs[range], settings));
+    }
+    Ok(preview)
+}
+
+/// The first requests of the report for `date`: the report itself, or for a large day the
+/// part summaries (the requests after them are written from their replies)
+pub fn preview_daily_report(
+    tabs: &[TabRecord],
+    settings: &Settings,
+    date: &str,
+) -> Result<PrivacyPreview, String> {
+    let privacy = PrivacyFilter::new(settings);
+    let mut preview = PrivacyPreview::new(&privacy, tabs);
+    let model = llm::task_config(settings, Task::Report)?.label();
+    let tabs = privacy.shareable(tabs);
+    if tabs.is_empty() {
+        return Ok(preview);
+    }
+    match plan_report(&tabs, settings, date)? {
+        ReportPlan::Single(messages) => preview.push_chat(&model, &messages),
+        ReportPlan::Parts {
+            units, part_limit, ..
+        } => {
+            let parts = pack_units(&units, part_limit);
+            for (i, part) in parts.iter().enumerate() {
+                let messages = report_part_messages(
+                    settings,
+                    date,
+                    (i + 1, parts.len()),
+                    ACTIVITY_HEADING,
+                    part,
+                );
+                preview.push_chat(&model, &messages);
+            }
         }
     }
-    println!("[AI Report] ========== End Daily Report ==========\n");
+    Ok(preview)
+}
 
-    result
+/// The texts embedded for `tabs`, in batches (texts already cached are not sent again)
+pub fn preview_embeddings(
+    tabs: &[TabRecord],
+    settings: &Settings,
+) -> Result<PrivacyPreview, String> {
+    let privacy = PrivacyFilter::new(settings);
+    let mut preview = PrivacyPreview::new(&privacy, tabs);
+    let model = llm::task_config(settings, Task::Embeddings)?.label();
+    let texts: Vec<String> = privacy
+        .shareable(tabs)
+        .iter()
+        .map(embedding_text)
+        .filter(|text| !text.trim().is_empty())
+        .collect();
+    for batch in texts.chunks(EMBEDDING_BATCH_SIZE) {
+        preview.requests.push(RequestPreview {
+            model: model.clone(),
+            messages: Vec::new(),
+            inputs: batch.to_vec(),
+        });
+    }
+    Ok(preview)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::TabSnapshot;
+    use crate::test_support::{self, StubResponse, StubServer};
+
+    const TWO_SUGGESTIONS: &str = r#"[
+        {"tabId": 1, "category": "research", "decision": "keep", "reason": "docs", "digest": "Tokio docs"},
+        {"tabId": 2, "category": "entertainment", "decision": "close", "reason": "idle", "digest": null}
+    ]"#;
+
+    fn two_tabs() -> Vec<TabRecord> {
+        vec![
+            test_support::tab(1, "https://docs.rs/tokio")
+                .title("Tokio docs")
+                .build(),
+            test_support::tab(2, "https://youtube.com/watch")
+                .title("Cat video")
+                .build(),
+        ]
+    }
+
+    #[test]
+    fn extracts_plain_array() {
+        let items = extract_suggestion_items(TWO_SUGGESTIONS).unwrap();
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0]["tabId"], 1);
+    }
+
+    #[test]
+    fn extracts_wrapped_object() {
+        let wrapped = format!("{{\"suggestions\": {}}}", TWO_SUGGESTIONS);
+        assert_eq!(extract_suggestion_items(&wrapped).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn extracts_array_from_fenced_output() {
+        let fenced = format!(
+            "Here are my suggestions:\n```json\n{}\n```\nLet me know!",
+            TWO_SUGGESTIONS
+        );
+        assert_eq!(extract_suggestion_items(&fenced).unwrap().len(), 2);
+        assert!(extract_suggestion_items("```json\n[]\n```")
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn skips_brackets_in_surrounding_prose() {
+        let output = format!(
+            "Analyzed [2] tabs {{see below}}: {} [done]",
+            TWO_SUGGESTIONS
+        );
+        assert_eq!(extract_suggestion_items(&output).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn salvages_complete_items_from_truncated_output() {
+        let partial = r#"{"suggestions": [{"tabId": 1, "category": "work", "decision": "keep", "reason": "see [docs]"}, {"tabId": 2, "deci"#;
+        let items = extract_suggestion_items(partial).unwrap();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0]["tabId"], 1);
+    }
+
+    #[test]
+    fn rejects_output_without_items() {
+        let err = extract_suggestion_items("I could not analyze these tabs.").unwrap_err();
+        assert!(err.contains("No JSON array"), "{}", err);
+        assert!(extract_suggestion_items(r#"[{"tabId": 2, "deci"#).is_err());
+    }
+
+    #[test]
+    fn validates_items_one_by_one() {
+        let item = |json: &str| validate_item(&serde_json::from_str(json).unwrap());
+
+        let ok = item(r#"{"tabId": 3, "category": " Work ", "decision": "KEEP", "reason": "x"}"#)
+            .unwrap();
+        assert_eq!(
+            (ok.category.as_str(), ok.decision.as_str()),
+            ("work", "keep")
+        );
+        assert_eq!(ok.digest, None);
+
+        let err = item(r#"{"tabId": 3, "category": "work", "decision": "maybe", "reason": "x"}"#)
+            .unwrap_err();
+        assert_eq!(err.tab_id, Some(3));
+        assert!(err
+            .error
+            .contains("\"decision\" must be one of keep, close, unsure (got \"maybe\")"));
+
+        let err = item(r#"{"tabId": 3, "category": "news", "decision": "keep", "reason": "x"}"#)
+            .unwrap_err();
+        assert!(err.error.contains("\"category\""), "{}", err.error);
+
+        let err = item(r#"{"tabId": "3", "category": "work", "decision": "keep", "reason": "x"}"#)
+            .unwrap_err();
+        assert_eq!(err.tab_id, None);
+
+        assert!(item(r#"{"tabId": 3, "category": "work", "decision": "keep"}"#).is_err());
+        assert!(item(
+            r#"{"tabId": 3, "category": "work", "decision": "keep", "reason": "x", "digest": 5}"#
+        )
+        .is_err());
+    }
+
+    #[tokio::test]
+    async fn suggest_tabs_maps_response_to_suggestions() {
+        let server = StubServer::start(vec![StubResponse::chat(TWO_SUGGESTIONS)]).await;
+
+        let outcome = suggest_tabs(&two_tabs(), &server.settings(), &|_, _| {})
+            .await
+            .unwrap();
+        let suggestions = outcome.suggestions;
+
+        assert!(outcome.issues.is_empty());
+        assert_eq!(suggestions.len(), 2);
+        assert_eq!(suggestions[&1].decision, "keep");
+        assert_eq!(suggestions[&1].category.as_deref(), Some("research"));
+        assert_eq!(suggestions[&1].digest.as_deref(), Some("Tokio docs"));
+        assert_eq!(suggestions[&2].decision, "close");
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 1);
+        let request = &requests[0];
+        assert_eq!(request.headers["authorization"], "Bearer test-key");
+        assert_eq!(request.body["model"], "test-model");
+        assert_eq!(request.body["temperature"].as_f64().unwrap() as f32, 0.2);
+        assert_eq!(request.body["response_format"]["type"], "json_schema");
+        assert_eq!(
+            request.body["response_format"]["json_schema"]["schema"]["properties"]["suggestions"]
+                ["items"]["properties"]["decision"]["enum"],
+            json!(["keep", "close", "unsure"])
+        );
+        let prompt = request.body["messages"][1].to_string();
+        assert!(prompt.contains("tabId: 1") && prompt.contains("tabId: 2"));
+        assert!(prompt.contains("https://youtube.com/watch"));
+    }
+
+    #[tokio::test]
+    async fn suggest_tabs_repairs_invalid_and_skipped_items() {
+        let first = r#"{"suggestions": [
+            {"tabId": 1, "category": "research", "decision": "maybe", "reason": "docs", "digest": null},
+            {"tabId": 9, "category": "work", "decision": "keep", "reason": "made up", "digest": null}
+        ]}"#;
+        let repair = r#"{"suggestions": [
+            {"tabId": 1, "category": "research", "decision": "keep", "reason": "docs", "digest": null},
+            {"tabId": 2, "category": "entertainment", "decision": "close", "reason": "idle", "digest": null}
+        ]}"#;
+        let server =
+            StubServer::start(vec![StubResponse::chat(first), StubResponse::chat(repair)]).await;
+
+        let outcome = suggest_tabs(&two_tabs(), &server.settings(), &|_, _| {})
+            .await
+            .unwrap();
+
+        assert_eq!(outcome.suggestions.len(), 2);
+        assert_eq!(outcome.suggestions[&1].decision, "keep");
+        assert_eq!(outcome.issues.repaired, 2);
+        assert_eq!(outcome.issues.unknown, vec![9]);
+        assert!(outcome.issues.skipped.is_empty() && outcome.issues.invalid.is_empty());
+
+        // The repair round-trip carries the first answer and names each problem
+        let messages = server.requests()[1].body["messages"].clone();
+        assert_eq!(messages[2]["role"], "assistant");
+        let prompt = messages[3]["content"].as_str().unwrap();
+        assert!(
+            prompt.contains("tabId 1: \"decision\" must be one of"),
+            "{}",
+            prompt
+        );
+        assert!(
+            prompt.contains("tabId 2: no suggestion given"),
+            "{}",
+            prompt
+        );
+        assert!(!prompt.contains("tabId 9"));
+    }
+
+    #[tokio::test]
+    async fn suggest_tabs_reports_items_the_repair_did_not_fix() {
+        let first = r#"[
+            {"tabId": 1, "category": "research", "decision": "keep", "reason": "docs", "digest": null},
+            {"tabId": 2, "category": "videos", "decision": "close", "reason": "idle", "digest": null}
+        ]"#;
+        let server =
+            StubServer::start(vec![StubResponse::chat(first), StubResponse::chat("[]")]).await;
+        let mut tabs = two_tabs();
+        tabs.push(
+            test_support::tab(3, "https://example.com")
+                .title("Example")
+                .build(),
+        );
+
+        let outcome = suggest_tabs(&tabs, &server.settings(), &|_, _| {})
+            .await
+            .unwrap();
+
+        assert_eq!(outcome.suggestions.len(), 1);
+        assert_eq!(outcome.issues.repaired, 0);
+        assert_eq!(outcome.issues.skipped, vec![3]);
+        assert_eq!(outcome.issues.invalid.len(), 1);
+        assert_eq!(outcome.issues.invalid[0].tab_id, Some(2));
+    }
+
+    #[tokio::test]
+    async fn suggest_tabs_keeps_first_round_when_repair_fails() {
+        let first = r#"[{"tabId": 1, "category": "research", "decision": "keep", "reason": "docs", "digest": null}]"#;
+        let server = StubServer::start(vec![
+            StubResponse::chat(first),
+            StubResponse::error(400, "context length exceeded"),
+        ])
+        .await;
+
+        let outcome = suggest_tabs(&two_tabs(), &server.settings(), &|_, _| {})
+            .await
+            .unwrap();
+
+        assert_eq!(outcome.suggestions.len(), 1);
+        assert_eq!(outcome.issues.skipped, vec![2]);
+    }
+
+    #[tokio::test]
+    async fn suggest_tabs_attaches_screenshots() {
+        let path = std::env::temp_dir().join(format!("tabula-test-{}.jpg", std::process::id()));
+        fs::write(&path, b"jpeg").unwrap();
+        let mut with_shot = test_support::tab(1, "https://example.com")
+            .title("Example")
+            .build();
+        with_shot.snapshot = Some(TabSnapshot {
+            screenshot_path: Some(path.to_string_lossy().into_owned()),
+            captured_at: 0,
+        });
+        let server = StubServer::start(vec![StubResponse::chat(TWO_SUGGESTIONS)]).await;
+
+        suggest_tabs(&[with_shot], &server.settings(), &|_, _| {})
+            .await
+            .unwrap();
+        fs::remove_file(&path).ok();
+
+        let parts = server.requests()[0].body["messages"][1]["content"].clone();
+        let image = parts
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|p| p["type"] == "image_url")
+            .expect("image part");
+        assert_eq!(image["image_url"]["url"], "data:image/jpeg;base64,anBlZw==");
+    }
+
+    #[tokio::test]
+    async fn privacy_settings_filter_what_is_sent() {
+        let path = std::env::temp_dir().join(format!("tabula-private-{}.jpg", std::process::id()));
+        fs::write(&path, b"jpeg").unwrap();
+        let mut docs = test_support::tab(1, "https://docs.rs/tokio")
+            .title("Tokio docs")
+            .build();
+        docs.description = Some("Maintained by jane@example.com".to_string());
+        docs.snapshot = Some(TabSnapshot {
+            screenshot_path: Some(path.to_string_lossy().into_owned()),
+            captured_at: 0,
+        });
+        let bank = test_support::tab(2, "https://online.bank.example/accounts")
+            .title("Balance")
+            .build();
+        let server = StubServer::start(vec![StubResponse::chat(TWO_SUGGESTIONS)]).await;
+        let settings = Settings {
+            privacy_blocklist: Some(vec!["*.bank.example".to_string()]),
+            privacy_no_screenshots: Some(vec!["docs.rs".to_string()]),
+            ..server.settings()
+        };
+        let tabs = vec![docs, bank];
+
+        let preview = preview_suggestions(&tabs, &settings).unwrap();
+        let outcome = suggest_tabs(&tabs, &settings, &|_, _| {}).await.unwrap();
+        fs::remove_file(&path).ok();
+
+        assert_eq!(outcome.issues.withheld, vec![2]);
+        assert_eq!(preview.exposure[&2], Exposure::Withheld);
+        assert_eq!(preview.exposure[&1], Exposure::NoScreenshot);
+        let sent = server.requests()[0].body["messages"][1]["content"].to_string();
+        assert!(sent.contains("Maintained by [email]"), "{}", sent);
+        assert!(!sent.contains("bank.example") && !sent.contains("image_url"));
+        // The dry run shows the same text
+        let previewed = preview.requests[0].messages[1]["content"].to_string();
+        assert!(previewed.contains("Maintained by [email]"));
+        assert!(!previewed.contains("bank.example") && !previewed.contains("image"));
+    }
+
+    #[tokio::test]
+    async fn suggest_tabs_skips_request_for_no_tabs() {
+        let server = StubServer::start(vec![]).await;
+        assert!(suggest_tabs(&[], &server.settings(), &|_, _| {})
+            .await
+            .unwrap()
+            .suggestions
+            .is_empty());
+        assert!(server.requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn suggest_tabs_fails_when_nothing_is_parseable() {
+        let server = StubServer::start(vec![
+            StubResponse::chat("Sorry, I can't help."),
+            StubResponse::chat("Still can't."),
+        ])
+        .await;
+
+        let err = suggest_tabs(&two_tabs(), &server.settings(), &|_, _| {})
+            .await
+            .unwrap_err();
+
+        assert!(err.contains("Failed to parse suggestions"), "{}", err);
+        assert_eq!(server.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn suggest_tabs_retries_transient_errors() {
+        let server = StubServer::start(vec![
+            StubResponse::error(429, "rate limited"),
+            StubResponse::chat(TWO_SUGGESTIONS),
+        ])
+        .await;
+
+        let outcome = suggest_tabs(&two_tabs(), &server.settings(), &|_, _| {})
+            .await
+            .unwrap();
+
+        assert_eq!(outcome.suggestions.len(), 2);
+        assert_eq!(server.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn suggest_tabs_works_offline_with_mock_provider() {
+        let settings = Settings {
+            suggestion_provider: Some("mock".to_string()),
+            ..Settings::default()
+        };
+        let tabs = vec![test_support::tab(4, "https://example.com")
+            .title("Example")
+            .build()];
+
+        let suggestions = suggest_tabs(&tabs, &settings, &|_, _| {})
+            .await
+            .unwrap()
+            .suggestions;
+
+        assert_eq!(suggestions[&4].decision, "keep");
+        assert_eq!(suggestions[&4].digest.as_deref(), Some("Example"));
+    }
+
+    #[test]
+    fn estimates_tokens_for_text_and_images() {
+        assert_eq!(estimate_text_tokens(""), 0);
+        assert_eq!(estimate_text_tokens("abcdefgh"), 2);
+        assert_eq!(estimate_text_tokens("abcdefghi"), 3);
+        assert_eq!(estimate_text_tokens("日本語"), 3);
+
+        let messages = [Message::user_parts(vec![
+            ContentPart::Text("abcd".to_string()),
+            ContentPart::Image {
+                media_type: "image/jpeg".to_string(),
+                data: "x".repeat(10_000),
+            },
+        ])];
+        assert_eq!(
+            estimate_message_tokens(&messages, 85),
+            MESSAGE_OVERHEAD_TOKENS + 1 + 85
+        );
+    }
+
+    #[test]
+    fn knows_model_context_windows() {
+        assert_eq!(
+            context_window(ProviderKind::OpenAi, "gpt-4o-mini", None),
+            128_000
+        );
+        assert_eq!(context_window(ProviderKind::OpenAi, "gpt-4", None), 8_192);
+        assert_eq!(
+            context_window(ProviderKind::OpenAi, "openai/GPT-4.1-mini", None),
+            1_047_576
+        );
+        assert_eq!(
+            context_window(ProviderKind::Anthropic, "claude-3-5-haiku-latest", None),
+            200_000
+        );
+        assert_eq!(
+            context_window(ProviderKind::Ollama, "llama3.2", None),
+            OLLAMA_CONTEXT_WINDOW
+        );
+        assert_eq!(
+            context_window(ProviderKind::OpenAi, "my-finetune", None),
+            DEFAULT_CONTEXT_WINDOW
+        );
+        assert_eq!(
+            context_window(ProviderKind::Ollama, "llama3.2", Some(32_768)),
+            32_768
+        );
+    }
+
+    #[test]
+    fn plans_batches_within_the_budget() {
+        let budget = SuggestionBudget {
+            batch_limit: 2_000,
+            base_tokens: 500,
+            image_tokens: 85,
+        };
+        let tabs: Vec<TabRecord> = (1..=5)
+            .map(|id| {
+                test_support::tab(id, &format!("https://example.com/{}", id))
+                    .title("Example")
+                    .description(&"a".repeat(2_000))
+                    .build()
+            })
+            .collect();
+        let cost = budget.tab_tokens(&tabs[0]);
+        assert!(cost > 500 && cost < 750, "{}", cost);
+
+        assert_eq!(budget.plan(&tabs), vec![0..2, 2..4, 4..5]);
+        assert!(budget.plan(&[]).is_empty());
+
+        // Oversized tabs still get analyzed, one per batch
+        let big: Vec<TabRecord> = (1..=2)
+            .map(|id| {
+                test_support::tab(id, &format!("https://example.com/{}", id))
+                    .title("Example")
+                    .description(&"a".repeat(12_000))
+                    .build()
+            })
+            .collect();
+        assert_eq!(budget.plan(&big), vec![0..1, 1..2]);
+    }
+
+    /// Settings whose context window fits the prompt plus two of `tabs`
+    fn two_tabs_per_batch(server: &StubServer, tabs: &[TabRecord]) -> Settings {
+        let mut settings = Settings {
+            analyze_concurrency: Some(1),
+            ..server.settings()
+        };
+        let budget = SuggestionBudget::new(&settings).unwrap();
+        let fits = budget.base_tokens + budget.tab_tokens(&tabs[0]) * 5 / 2;
+        settings.suggestion_context_window = Some((fits as f64 / CONTEXT_SAFETY_FACTOR) as u32);
+        settings
+    }
+
+    fn suggestions_for(ids: &[i64]) -> String {
+        let items: Vec<Value> = ids
+            .iter()
+            .map(|id| json!({"tabId": id, "category": "work", "decision": "keep", "reason": "x", "digest": null}))
+            .collect();
+        json!({ "suggestions": items }).to_string()
+    }
+
+    #[tokio::test]
+    async fn suggest_tabs_splits_large_runs_and_merges_results() {
+        let tabs: Vec<TabRecord> = (1..=4)
+            .map(|id| {
+                test_support::tab(id, &format!("https://example.com/{}", id))
+                    .title("Example")
+                    .description(&"a".repeat(2_000))
+                    .build()
+            })
+            .collect();
+        let server = StubServer::start(vec![
+            StubResponse::chat(&suggestions_for(&[1, 2])),
+            StubResponse::chat(&suggestions_for(&[3, 4])),
+        ])
+        .await;
+
+        let progress = std::sync::Mutex::new(Vec::new());
+
+        let outcome = suggest_tabs(
+            &tabs,
+            &two_tabs_per_batch(&server, &tabs),
+            &|done, total| progress.lock().unwrap().push((done, total)),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(outcome.suggestions.len(), 4);
+        assert!(outcome.issues.is_empty());
+        assert_eq!(*progress.lock().unwrap(), vec![(0, 2), (1, 2), (2, 2)]);
+        let requests = server.requests();
+        assert_eq!(requests.len(), 2);
+        let second = requests[1].body["messages"][1].to_string();
+        assert!(second.contains("tabId: 3") && !second.contains("tabId: 1"));
+    }
+
+    #[tokio::test]
+    async fn suggest_tabs_keeps_batches_that_succeeded() {
+        let tabs: Vec<TabRecord> = (1..=4)
+            .map(|id| {
+                test_support::tab(id, &format!("https://example.com/{}", id))
+                    .title("Example")
+                    .description(&"a".repeat(2_000))
+                    .build()
+            })
+            .collect();
+        let server = StubServer::start(vec![
+            StubResponse::chat(&suggestions_for(&[1, 2])),
+            StubResponse::error(400, "bad request"),
+        ])
+        .await;
+
+        let outcome = suggest_tabs(&tabs, &two_tabs_per_batch(&server, &tabs), &|_, _| {})
+            .await
+            .unwrap();
+
+        assert_eq!(outcome.suggestions.len(), 2);
+        assert_eq!(outcome.issues.skipped, vec![3, 4]);
+        assert_eq!(outcome.issues.errors.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn suggest_tabs_keeps_at_most_analyze_concurrency_batches_in_flight() {
+        let tabs: Vec<TabRecord> = (1..=6)
+            .map(|id| {
+                test_support::tab(id, &format!("https://example.com/{}", id))
+                    .title("Example")
+                    .description(&"a".repeat(2_000))
+                    .build()
+            })
+            .collect();
+        // Any batch takes its own tabs from a reply naming all six
+        let reply = StubResponse::chat(&suggestions_for(&[1, 2, 3, 4, 5, 6]))
+            .delayed(std::time::Duration::from_millis(200));
+        let server = StubServer::start(vec![reply; 3]).await;
+        let settings = Settings {
+            analyze_concurrency: Some(2),
+            ..two_tabs_per_batch(&server, &tabs)
+        };
+
+        let outcome = suggest_tabs(&tabs, &settings, &|_, _| {}).await.unwrap();
+
+        assert_eq!(outcome.suggestions.len(), 6);
+        assert!(outcome.issues.is_empty());
+        assert_eq!(server.requests().len(), 3);
+        assert_eq!(server.max_in_flight(), 2);
+    }
+
+    #[tokio::test]
+    async fn daily_report_groups_tabs_by_domain() {
+        let server = StubServer::start(vec![StubResponse::chat("# Report\n- Rust")]).await;
+        let docs = test_support::tab(1, "https://docs.rs/tokio")
+            .title("Tokio docs")
+            .suggestion(TabSuggestion {
+                decision: "keep".to_string(),
+                reason: String::new(),
+                category: Some("research".to_string()),
+                digest: Some("Async runtime reference".to_string()),
+                scored_at: 0,
+            })
+            .build();
+        let tabs = vec![
+            docs,
+            test_support::tab(2, "https://docs.rs/serde")
+                .title("Serde docs")
+                .build(),
+            test_support::tab(3, "https://news.ycombinator.com/")
+                .title("Hacker News")
+                .build(),
+        ];
+        let settings = Settings {
+            user_context: Some("Building a Rust app".to_string()),
+            ..server.settings()
+        };
+
+        let report = generate_daily_report(&tabs, &settings, "2026-10-17", &|_, _| {})
+            .await
+            .unwrap();
+
+        assert_eq!(report, "# Report\n- Rust");
+        let request = &server.requests()[0];
+        assert_eq!(request.body["temperature"].as_f64().unwrap() as f32, 0.3);
+        let prompt = request.body["messages"][1]["content"].as_str().unwrap();
+        assert!(prompt.contains("daily report for 2026-10-17"));
+        assert!(prompt.contains("## docs.rs\n"));
+        assert!(prompt.contains("## news.ycombinator.com\n"));
+        assert!(prompt.contains("[research] Async runtime reference"));
+        assert!(prompt.contains("Building a Rust app"));
+    }
+
+    #[tokio::test]
+    async fn large_day_is_summarized_in_parts_then_merged() {
+        let tabs: Vec<TabRecord> = (1..=30)
+            .map(|id| {
+                test_support::tab(id, &format!("https://example.com/{}", id))
+                    .title("Example")
+                    .description(&"a".repeat(300))
+                    .build()
+            })
+            .collect();
+        let mut responses = vec![StubResponse::chat("- part notes"); 3];
+        responses.push(StubResponse::chat("# Merged report"));
+        let server = StubServer::start(responses).await;
+        let settings = Settings {
+            report_context_window: Some(2_500),
+            ..server.settings()
+        };
+        let progress = std::sync::Mutex::new(Vec::new());
+
+        let report = generate_daily_report(&tabs, &settings, "2026-10-17", &|done, total| {
+            progress.lock().unwrap().push((done, total))
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(report, "# Merged report");
+        let requests = server.requests();
+        assert_eq!(requests.len(), 4);
+        let part = requests[0].body["messages"][1]["content"].as_str().unwrap();
+        assert!(part.contains("part 1 of 3"), "{}", part);
+        assert!(part.contains("## example.com\n"));
+        let later = requests[1].body["messages"][1]["content"].as_str().unwrap();
+        assert!(later.contains("## example.com (continued)"));
+        let merge = requests[3].body["messages"][1]["content"].as_str().unwrap();
+        assert!(merge.contains("### Part 3\n- part notes"));
+        assert!(!merge.contains("Example ("));
+        assert_eq!(progress.lock().unwrap().last(), Some(&(4, 4)));
+    }
+
+    // Tauri spawns async commands, so what they await has to be `Send` for any borrow
+    #[test]
+    fn report_futures_are_send() {
+        fn assert_send<T: Send>(_: T) {}
+        fn daily(tabs: &[TabRecord], settings: &Settings, date: &str) {
+            assert_send(generate_daily_report(tabs, settings, date, &|_, _| {}));
+            assert_send(stream_daily_report(
+                tabs,
+                settings,
+                date,
+                None,
+                &mut |_| {},
+                &|_, _| {},
+            ));
+        }
+        daily(&[], &Settings::default(), "2026-10-17");
+    }
+
+    #[test]
+    fn packs_units_in_order_within_the_limit() {
+        let units: Vec<String> = ["a".repeat(40), "b".repeat(40), "c".repeat(80)]
+            .into_iter()
+            .collect();
+        // 10, 10 and 20 tokens
+        let parts = pack_units(&units, 20);
+        assert_eq!(parts.len(), 2);
+        assert!(parts[0].starts_with('a') && parts[0].ends_with('b'));
+        assert_eq!(parts[1], units[2]);
+    }
+
+    #[tokio::test]
+    async fn daily_report_without_tabs_makes_no_request() {
+        let server = StubServer::start(vec![]).await;
+        let report = generate_daily_report(&[], &server.settings(), "2026-10-17", &|_, _| {})
+            .await
+            .unwrap();
+        assert_eq!(report, "No tabs to report on.");
+        assert!(server.requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn daily_report_surfaces_api_errors() {
+        let server = StubServer::start(vec![StubResponse::error(401, "invalid api key")]).await;
+        let tabs = vec![test_support::tab(1, "https://example.com")
+            .title("Example")
+            .build()];
+
+        let err = generate_daily_report(&tabs, &server.settings(), "2026-10-17", &|_, _| {})
+            .await
+            .unwrap_err();
+
+        assert!(
+            err.contains("401") && err.contains("invalid api key"),
+            "{}",
+            err
+        );
+    }
+
+    #[tokio::test]
+    async fn streamed_report_resumes_from_partial() {
+        let delta =
+            |text: &str| json!({ "choices": [{ "delta": { "content": text } }] }).to_string();
+        let server = StubServer::start(vec![StubResponse::sse(&[
+            delta(" and more"),
+            delta("."),
+            "[DONE]".to_string(),
+        ])])
+        .await;
+        let tabs = vec![test_support::tab(1, "https://example.com")
+            .title("Example")
+            .build()];
+        let mut chunks = Vec::new();
+
+        let report = stream_daily_report(
+            &tabs,
+            &server.settings(),
+            "2026-10-17",
+            Some("# Report\nRust"),
+            &mut |chunk: &str| chunks.push(chunk.to_string()),
+            &|_, _| {},
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(report, "# Report\nRust and more.");
+        assert_eq!(chunks, vec![" and more", "."]);
+        let request = &server.requests()[0];
+        assert_eq!(request.body["stream"], true);
+        let messages = request.body["messages"].as_array().unwrap();
+        assert_eq!(messages.len(), 4);
+        assert_eq!(messages[2]["role"], "assistant");
+        assert_eq!(messages[2]["content"], "# Report\nRust");
+    }
+
+    #[tokio::test]
+    async fn period_report_combines_daily_reports_and_uncovered_days() {
+        let server = StubServer::start(vec![StubResponse::chat("# Week summary\n")]).await;
+        let period = Period::new(
+            crate::storage::parse_date("2026-10-12").unwrap(),
+            crate::storage::parse_date("2026-10-13").unwrap(),
+        )
+        .unwrap();
+        let tabs = vec![test_support::tab(1, "https://docs.rs/tokio")
+            .title("Tokio docs")
+            .build()];
+        let daily_reports = vec![DailyReport {
+            date: "2026-10-12".to_string(),
+            content: "Read about async runtimes.".to_string(),
+            generated_at: 0,
+        }];
+        let stats = crate::rollup::period_stats(&tabs, &HashMap::new(), 0);
+        let stats_markdown = crate::rollup::stats_markdown(&stats, &PeriodStats::default());
+        let activity = PeriodActivity {
+            period,
+            granularity: Granularity::Week,
+            tabs: &tabs,
+            daily_reports: &daily_reports,
+            stats: &stats,
+            stats_markdown: &stats_markdown,
+        };
+
+        let report = generate_period_report(&activity, &server.settings())
+            .await
+            .unwrap();
+
+        assert_eq!(report, format!("# Week summary\n\n{}", stats_markdown));
+        let prompt = server.requests()[0].body["messages"][1]["content"]
+            .as_str()
+            .unwrap()
+            .to_string();
+        assert!(prompt.contains("weekly report for 2026-10-12 to 2026-10-13"));
+        assert!(prompt.contains("### 2026-10-12\nRead about async runtimes."));
+        assert!(prompt.contains("days without a daily report: 2026-10-13"));
+        assert!(prompt.contains("Tokio docs"));
+    }
+
+    #[tokio::test]
+    async fn embed_texts_checks_vector_count() {
+        let server = StubServer::start(vec![StubResponse::ok(json!({
+            "data": [{ "index": 0, "embedding": [1.0] }]
+        }))])
+        .await;
+
+        let err = embed_texts(&server.settings(), &["a".to_string(), "b".to_string()])
+            .await
+            .unwrap_err();
+
+        assert!(err.contains("Expected 2 embeddings, got 1"), "{}", err);
+    }
+
+    #[test]
+    fn suggestion_hash_tracks_prompt_inputs_only() {
+        let path = std::env::temp_dir().join(format!("tabula-hash-{}.jpg", std::process::id()));
+        fs::write(&path, b"first").unwrap();
+        let mut base = test_support::tab(1, "https://example.com")
+            .title("Example")
+            .build();
+        base.snapshot = Some(TabSnapshot {
+            screenshot_path: Some(path.to_string_lossy().into_owned()),
+            captured_at: 0,
+        });
+        let settings = Settings::default();
+        let hash = |tab: &TabRecord, settings: &Settings, model: &str| {
+            suggestion_input_hash(tab, settings, model)
+        };
+        let original = hash(&base, &settings, "openai/gpt-4o-mini");
+
+        let mut used_again = base.clone();
+        used_again.id = 9;
+        used_again.total_active_ms += 1000;
+        used_again.last_active_at = Some(1);
+        assert_eq!(hash(&used_again, &settings, "openai/gpt-4o-mini"), original);
+
+        let mut retitled = base.clone();
+        retitled.title = Some("Example 2".to_string());
+        let context = Settings {
+            user_context: Some("I write Rust".to_string()),
+            ..Settings::default()
+        };
+        assert_ne!(hash(&retitled, &settings, "openai/gpt-4o-mini"), original);
+        assert_ne!(hash(&base, &context, "openai/gpt-4o-mini"), original);
+        assert_ne!(hash(&base, &settings, "openai/gpt-4o"), original);
+
+        fs::write(&path, b"second").unwrap();
+        let new_screenshot = hash(&base, &settings, "openai/gpt-4o-mini");
+        fs::remove_file(&path).ok();
+        assert_ne!(new_screenshot, original);
+    }
+
+    #[test]
+    fn suggestion_cache_expires_entries() {
+        let db = crate::db::Database::open_in_memory().unwrap();
+        let suggestion = |scored_at| TabSuggestion {
+            decision: "keep".to_string(),
+            reason: "docs".to_string(),
+            category: Some("reference".to_string()),
+            digest: None,
+            scored_at,
+        };
+        db.save_cached_suggestions(
+            &[
+                ("old".to_string(), suggestion(100)),
+                ("new".to_string(), suggestion(500)),
+            ],
+            0,
+        )
+        .unwrap();
+        let keys = ["old".to_string(), "new".to_string(), "missing".to_string()];
+
+        let fresh = db.load_cached_suggestions(&keys, 200).unwrap();
+        assert_eq!(fresh.len(), 1);
+        assert_eq!(fresh["new"].scored_at, 500);
+
+        // Saving prunes what has expired
+        db.save_cached_suggestions(&[], 200).unwrap();
+        assert_eq!(db.load_cached_suggestions(&keys, 0).unwrap().len(), 1);
+    }
 }
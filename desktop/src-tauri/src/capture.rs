@@ -0,0 +1,166 @@
+//! Capture policies: how much of a tab the desktop server stores, by URL, incognito flag
+//! or window. The extension gets them in the health response so it can skip capturing
+//! what would be dropped anyway.
+
+use serde::{Deserialize, Serialize};
+
+use crate::privacy::Glob;
+
+/// What is stored for a tab, from least to most
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CaptureLevel {
+    /// The tab is not stored at all
+    Nothing,
+    /// URL, title, favicon and activity times
+    Metadata,
+    /// Metadata and the page description
+    Description,
+    /// Metadata, description and screenshot
+    Full,
+}
+
+impl CaptureLevel {
+    pub fn keeps_description(self) -> bool {
+        self >= CaptureLevel::Description
+    }
+
+    pub fn keeps_screenshot(self) -> bool {
+        self == CaptureLevel::Full
+    }
+}
+
+/// A rule matching tabs by URL, incognito flag and window; all given conditions must hold
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CapturePolicy {
+    /// Domain or URL globs, as in the privacy settings; empty matches every URL
+    #[serde(default)]
+    pub patterns: Vec<String>,
+    /// Only incognito tabs (`true`) or only normal ones (`false`)
+    #[serde(default)]
+    pub incognito: Option<bool>,
+    /// Only tabs in these browser windows
+    #[serde(default)]
+    pub window_ids: Vec<i64>,
+    pub capture: CaptureLevel,
+}
+
+impl CapturePolicy {
+    fn matches(&self, tab: &CaptureTarget) -> bool {
+        let url = tab.url.unwrap_or("");
+        let url_matches = self.patterns.is_empty()
+            || self
+                .patterns
+                .iter()
+                .filter_map(|p| Glob::new(p))
+                .any(|glob| glob.matches_url(url));
+        let incognito_matches = self
+            .incognito
+            .is_none_or(|incognito| incognito == tab.incognito);
+        let window_matches = self.window_ids.is_empty()
+            || tab
+                .window_id
+                .is_some_and(|id| self.window_ids.contains(&id));
+        url_matches && incognito_matches && window_matches
+    }
+}
+
+/// What the extension reports about a tab, as far as policies care
+pub struct CaptureTarget<'a> {
+    pub url: Option<&'a str>,
+    pub incognito: bool,
+    pub window_id: Option<i64>,
+}
+
+/// The level of the first policy matching `tab`; tabs no policy matches are stored fully
+pub fn capture_level(policies: &[CapturePolicy], tab: &CaptureTarget) -> CaptureLevel {
+    policies
+        .iter()
+        .find(|policy| policy.matches(tab))
+        .map(|policy| policy.capture)
+        .unwrap_or(CaptureLevel::Full)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(patterns: &[&str], incognito: Option<bool>, capture: CaptureLevel) -> CapturePolicy {
+        CapturePolicy {
+            patterns: patterns.iter().map(|p| p.to_string()).collect(),
+            incognito,
+            window_ids: Vec::new(),
+            capture,
+        }
+    }
+
+    fn target(url: &str, incognito: bool, window_id: i64) -> CaptureTarget<'_> {
+        CaptureTarget {
+            url: Some(url),
+            incognito,
+            window_id: Some(window_id),
+        }
+    }
+
+    #[test]
+    fn first_matching_policy_decides() {
+        let policies = vec![
+            policy(&[], Some(true), CaptureLevel::Nothing),
+            policy(&["*.bank.example"], None, CaptureLevel::Metadata),
+            policy(
+                &[
+                    "mail.example.com",
+                    "chat.example.com/*",
+                    "wiki.example.com/HR/*",
+                ],
+                None,
+                CaptureLevel::Description,
+            ),
+            CapturePolicy {
+                window_ids: vec![7],
+                ..policy(&[], None, CaptureLevel::Metadata)
+            },
+        ];
+        let level =
+            |url, incognito, window| capture_level(&policies, &target(url, incognito, window));
+
+        assert_eq!(level("https://docs.rs/", true, 1), CaptureLevel::Nothing);
+        assert_eq!(
+            level("https://online.bank.example/", false, 1),
+            CaptureLevel::Metadata
+        );
+        assert_eq!(
+            level("https://mail.example.com/inbox", false, 1),
+            CaptureLevel::Description
+        );
+        assert_eq!(
+            level("https://chat.example.com/room/1", false, 1),
+            CaptureLevel::Description
+        );
+        assert_eq!(
+            level("https://wiki.example.com/hr/Salaries", false, 1),
+            CaptureLevel::Description
+        );
+        assert_eq!(level("https://docs.rs/", false, 7), CaptureLevel::Metadata);
+        assert_eq!(level("https://docs.rs/", false, 1), CaptureLevel::Full);
+    }
+
+    #[test]
+    fn levels_keep_less_as_they_go_down() {
+        assert!(CaptureLevel::Full.keeps_screenshot());
+        assert!(!CaptureLevel::Description.keeps_screenshot());
+        assert!(CaptureLevel::Description.keeps_description());
+        assert!(!CaptureLevel::Metadata.keeps_description());
+    }
+
+    #[test]
+    fn policies_parse_from_settings_json() {
+        let policies: Vec<CapturePolicy> = serde_json::from_str(
+            r#"[{"patterns": ["*.health.example"], "capture": "metadata"}, {"incognito": true, "capture": "nothing"}]"#,
+        )
+        .unwrap();
+        assert_eq!(policies[0].capture, CaptureLevel::Metadata);
+        assert_eq!(policies[1].incognito, Some(true));
+        assert!(policies[1].patterns.is_empty());
+    }
+}
@@ -0,0 +1,1273 @@
+use rusqlite::{params, Connection, ErrorCode, OptionalExtension, Row};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard};
+
+use crate::pairing::PairedClient;
+use crate::queue::{CommandStatus, QueuedCommand};
+use crate::search::{self, SearchFilters, SearchHit, MATCH_END, MATCH_START};
+use crate::storage::{
+    DailyReport, PeriodReport, ReportDraft, TabRecord, TabSnapshot, TabSuggestion,
+};
+use crate::usage::{AiCall, UsagePeriod};
+
+const SCHEMA_V1: &str = r#"
+CREATE TABLE IF NOT EXISTS tabs (
+    id INTEGER PRIMARY KEY,
+    window_id INTEGER,
+    url TEXT,
+    title TEXT,
+    fav_icon_url TEXT,
+    created_at INTEGER NOT NULL,
+    last_active_at INTEGER,
+    total_active_ms INTEGER NOT NULL DEFAULT 0,
+    is_active INTEGER NOT NULL DEFAULT 0,
+    closed_at INTEGER,
+    description TEXT
+);
+
+CREATE TABLE IF NOT EXISTS snapshots (
+    tab_id INTEGER PRIMARY KEY REFERENCES tabs(id) ON DELETE CASCADE,
+    screenshot_path TEXT,
+    captured_at INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS suggestions (
+    tab_id INTEGER PRIMARY KEY REFERENCES tabs(id) ON DELETE CASCADE,
+    decision TEXT NOT NULL,
+    reason TEXT NOT NULL,
+    category TEXT,
+    digest TEXT,
+    scored_at INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS reports (
+    date TEXT PRIMARY KEY,
+    content TEXT NOT NULL,
+    generated_at INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS settings (
+    key TEXT PRIMARY KEY,
+    value TEXT NOT NULL
+);
+"#;
+
+const SELECT_TABS: &str = r#"
+SELECT t.id, t.window_id, t.url, t.title, t.fav_icon_url, t.created_at,
+       t.last_active_at, t.total_active_ms, t.is_active, t.closed_at, t.description,
+       sn.screenshot_path, sn.captured_at,
+       su.decision, su.reason, su.category, su.digest, su.scored_at,
+       t.chrome_id, t.session_id, t.tab_index, t.active_ms_base
+FROM tabs t
+LEFT JOIN snapshots sn ON sn.tab_id = t.id
+LEFT JOIN suggestions su ON su.tab_id = t.id
+"#;
+
+const SCHEMA_V2: &str = r#"
+ALTER TABLE tabs ADD COLUMN chrome_id INTEGER;
+ALTER TABLE tabs ADD COLUMN session_id TEXT;
+ALTER TABLE tabs ADD COLUMN tab_index INTEGER;
+ALTER TABLE tabs ADD COLUMN active_ms_base INTEGER NOT NULL DEFAULT 0;
+UPDATE tabs SET chrome_id = id;
+"#;
+
+/// Full-text index over tabs, kept in sync by `write_tab`. The rowid is the tab id.
+/// `domain` is only used for filtering, so it is not tokenized.
+const SCHEMA_V3: &str = r#"
+CREATE VIRTUAL TABLE IF NOT EXISTS tab_search USING fts5(
+    title, url, description, digest, domain UNINDEXED,
+    tokenize = 'unicode61 remove_diacritics 2'
+);
+"#;
+
+/// Embedding vectors keyed by a hash of the model and embedded text,
+/// stored as little-endian f32 bytes
+const SCHEMA_V4: &str = r#"
+CREATE TABLE IF NOT EXISTS embeddings (
+    content_hash TEXT PRIMARY KEY,
+    model TEXT NOT NULL,
+    vector BLOB NOT NULL,
+    created_at INTEGER NOT NULL
+);
+"#;
+
+/// Ledger of AI provider calls. `cost_usd` is NULL for models without a known price;
+/// `retries` is NULL for failed calls.
+const SCHEMA_V5: &str = r#"
+CREATE TABLE IF NOT EXISTS ai_usage (
+    id INTEGER PRIMARY KEY,
+    created_at INTEGER NOT NULL,
+    provider TEXT NOT NULL,
+    model TEXT NOT NULL,
+    purpose TEXT NOT NULL,
+    prompt_tokens INTEGER NOT NULL,
+    completion_tokens INTEGER NOT NULL,
+    estimated INTEGER NOT NULL,
+    image_count INTEGER NOT NULL,
+    latency_ms INTEGER NOT NULL,
+    retries INTEGER,
+    cost_usd REAL,
+    error TEXT
+);
+CREATE INDEX IF NOT EXISTS ai_usage_created_at ON ai_usage (created_at);
+"#;
+
+/// Suggestions keyed by a hash of their prompt inputs, reused while the inputs are unchanged
+const SCHEMA_V6: &str = r#"
+CREATE TABLE IF NOT EXISTS suggestion_cache (
+    input_hash TEXT PRIMARY KEY,
+    decision TEXT NOT NULL,
+    reason TEXT NOT NULL,
+    category TEXT,
+    digest TEXT,
+    scored_at INTEGER NOT NULL
+);
+"#;
+
+/// A streamed report as far as it got, kept until the full report is saved
+const SCHEMA_V7: &str = r#"
+CREATE TABLE IF NOT EXISTS report_drafts (
+    date TEXT PRIMARY KEY,
+    content TEXT NOT NULL,
+    updated_at INTEGER NOT NULL,
+    error TEXT
+);
+"#;
+
+/// Weekly and monthly rollups, kept next to the daily `reports`. A rollup replaces an
+/// earlier one of the same granularity starting the same day, e.g. a week-to-date rollup.
+const SCHEMA_V8: &str = r#"
+CREATE TABLE IF NOT EXISTS period_reports (
+    granularity TEXT NOT NULL,
+    start_date TEXT NOT NULL,
+    end_date TEXT NOT NULL,
+    content TEXT NOT NULL,
+    generated_at INTEGER NOT NULL,
+    PRIMARY KEY (granularity, start_date)
+);
+"#;
+
+/// Extensions paired with the desktop server. Only a hash of each token is kept.
+const SCHEMA_V9: &str = r#"
+CREATE TABLE IF NOT EXISTS paired_clients (
+    token_hash TEXT PRIMARY KEY,
+    origin TEXT NOT NULL,
+    name TEXT,
+    paired_at INTEGER NOT NULL
+);
+"#;
+
+/// Commands for extensions that waited for their browser to connect, and what became of
+/// them. `command` is the JSON sent in the envelope; `tab_id` the tab it acts on.
+const SCHEMA_V10: &str = r#"
+CREATE TABLE IF NOT EXISTS command_queue (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    command TEXT NOT NULL,
+    tab_id INTEGER,
+    status TEXT NOT NULL DEFAULT 'pending',
+    error TEXT,
+    created_at INTEGER NOT NULL,
+    expires_at INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS idx_command_queue_status ON command_queue(status);
+"#;
+
+/// Active time per tab and local date, so rollups can count only the days in a period.
+/// Written with each flush from the growth of `tabs.total_active_ms`.
+const SCHEMA_V11: &str = r#"
+CREATE TABLE IF NOT EXISTS tab_activity (
+    tab_id INTEGER NOT NULL REFERENCES tabs(id) ON DELETE CASCADE,
+    date TEXT NOT NULL,
+    active_ms INTEGER NOT NULL,
+    PRIMARY KEY (tab_id, date)
+);
+CREATE INDEX IF NOT EXISTS idx_tab_activity_date ON tab_activity(date);
+"#;
+
+/// Schema migrations, applied in order inside a transaction.
+/// MIGRATIONS[n] upgrades a database at `user_version` n to n + 1.
+const MIGRATIONS: &[&str] = &[
+    SCHEMA_V1, SCHEMA_V2, SCHEMA_V3, SCHEMA_V4, SCHEMA_V5, SCHEMA_V6, SCHEMA_V7, SCHEMA_V8,
+    SCHEMA_V9, SCHEMA_V10, SCHEMA_V11,
+];
+
+/// Schema version that introduced `tab_search`; existing rows are indexed when reaching it
+const SEARCH_INDEX_VERSION: u32 = 3;
+
+/// Schema version that introduced `tab_activity`; existing tabs are seeded when reaching it
+const ACTIVITY_VERSION: u32 = 11;
+
+/// Credit the active time of tabs without activity rows to the local date they were last
+/// used, or opened. Records kept one running total before, so that day gets all of it.
+const SEED_ACTIVITY: &str = r#"
+INSERT INTO tab_activity (tab_id, date, active_ms)
+SELECT id, date(COALESCE(last_active_at, created_at) / 1000, 'unixepoch', 'localtime'),
+       total_active_ms
+FROM tabs
+WHERE total_active_ms > 0 AND id NOT IN (SELECT tab_id FROM tab_activity);
+"#;
+
+/// bm25 column weights for title, url, description, digest
+const SEARCH_WEIGHTS: &str = "10.0, 4.0, 1.0, 3.0";
+
+pub const SCHEMA_VERSION: u32 = MIGRATIONS.len() as u32;
+
+#[derive(Debug)]
+pub enum OpenError {
+    /// The file exists but is not a readable SQLite database
+    Corrupt(rusqlite::Error),
+    /// The database was written by a newer version of Tabula
+    NewerSchema(u32),
+    Sqlite(rusqlite::Error),
+}
+
+impl fmt::Display for OpenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OpenError::Corrupt(e) => write!(f, "database is corrupt: {}", e),
+            OpenError::NewerSchema(v) => write!(
+                f,
+                "database schema v{} is newer than supported v{}",
+                v, SCHEMA_VERSION
+            ),
+            OpenError::Sqlite(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<rusqlite::Error> for OpenError {
+    fn from(e: rusqlite::Error) -> Self {
+        match e.sqlite_error_code() {
+            Some(ErrorCode::NotADatabase) | Some(ErrorCode::DatabaseCorrupt) => {
+                OpenError::Corrupt(e)
+            }
+            _ => OpenError::Sqlite(e),
+        }
+    }
+}
+
+/// Embedded SQLite database backing `Storage`.
+/// The connection is guarded by a mutex so `Storage` stays `Sync`.
+pub struct Database {
+    conn: Mutex<Connection>,
+}
+
+impl Database {
+    pub fn open(path: &Path) -> Result<Self, OpenError> {
+        let conn = Connection::open(path)?;
+        Self::init(conn)
+    }
+
+    /// Fallback used when the database file cannot be opened
+    pub fn open_in_memory() -> Result<Self, OpenError> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    fn init(mut conn: Connection) -> Result<Self, OpenError> {
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        // Flushes are batched, so fsync on every commit is cheap
+        conn.pragma_update(None, "synchronous", "FULL")?;
+        conn.pragma_update(None, "foreign_keys", "ON")?;
+        migrate(&mut conn)?;
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    fn conn(&self) -> MutexGuard<'_, Connection> {
+        // A panic while holding the lock cannot leave SQLite in a bad state
+        self.conn.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    // Tabs
+
+    pub fn load_tabs(&self) -> rusqlite::Result<HashMap<i64, TabRecord>> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare(SELECT_TABS)?;
+        let rows = stmt.query_map([], tab_from_row)?;
+
+        let mut tabs = HashMap::new();
+        for tab in rows {
+            // Leave undecodable rows on disk untouched rather than failing the whole load
+            match tab {
+                Ok(tab) => {
+                    tabs.insert(tab.id, tab);
+                }
+                Err(e) => eprintln!("[DB] Skipping unreadable tab row: {}", e),
+            }
+        }
+        Ok(tabs)
+    }
+
+    /// Write several tab rows in a single transaction, e.g. ones imported from an older
+    /// version. Their active time is credited the way existing tabs were on upgrade.
+    pub fn upsert_tabs<'a>(
+        &self,
+        tabs: impl IntoIterator<Item = &'a TabRecord>,
+    ) -> rusqlite::Result<()> {
+        let mut conn = self.conn();
+        let tx = conn.transaction()?;
+        for tab in tabs {
+            write_tab(&tx, tab)?;
+        }
+        tx.execute_batch(SEED_ACTIVITY)?;
+        tx.commit()
+    }
+
+    /// Upsert and delete tab rows in a single transaction. Active time a tab gained since
+    /// its stored row is credited to `date`; a new tab's whole total counts towards it.
+    pub fn write_batch(
+        &self,
+        upserts: &[TabRecord],
+        deletes: &[i64],
+        date: &str,
+    ) -> rusqlite::Result<()> {
+        let mut conn = self.conn();
+        let tx = conn.transaction()?;
+        {
+            let mut stored = tx.prepare("SELECT total_active_ms FROM tabs WHERE id = ?1")?;
+            let mut credit = tx.prepare(
+                "INSERT INTO tab_activity (tab_id, date, active_ms) VALUES (?1, ?2, ?3)
+                 ON CONFLICT(tab_id, date) DO UPDATE SET
+                    active_ms = active_ms + excluded.active_ms",
+            )?;
+            for tab in upserts {
+                let before: Option<i64> =
+                    stored.query_row([tab.id], |row| row.get(0)).optional()?;
+                write_tab(&tx, tab)?;
+                // A total that went down (e.g. a browser restarting its timer) gains nothing
+                let gained = tab.total_active_ms - before.unwrap_or(0);
+                if gained > 0 {
+                    credit.execute(params![tab.id, date, gained])?;
+                }
+            }
+        }
+        {
+            let mut stmt = tx.prepare("DELETE FROM tabs WHERE id = ?1")?;
+            let mut unindex = tx.prepare("DELETE FROM tab_search WHERE rowid = ?1")?;
+            for tab_id in deletes {
+                stmt.execute([tab_id])?;
+                unindex.execute([tab_id])?;
+            }
+        }
+        tx.commit()
+    }
+
+    pub fn clear_suggestions(&self) -> rusqlite::Result<()> {
+        let mut conn = self.conn();
+        let tx = conn.transaction()?;
+        tx.execute("DELETE FROM suggestions", [])?;
+        tx.execute("UPDATE tab_search SET digest = NULL", [])?;
+        tx.commit()
+    }
+
+    pub fn clear_tabs(&self) -> rusqlite::Result<()> {
+        let mut conn = self.conn();
+        let tx = conn.transaction()?;
+        // Snapshots and suggestions cascade
+        tx.execute("DELETE FROM tabs", [])?;
+        tx.execute("DELETE FROM tab_search", [])?;
+        tx.execute("DELETE FROM embeddings", [])?;
+        tx.execute("DELETE FROM suggestion_cache", [])?;
+        tx.commit()
+    }
+
+    /// Active time per tab between `from` and `to` (inclusive local dates)
+    pub fn load_activity(&self, from: &str, to: &str) -> rusqlite::Result<HashMap<i64, i64>> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare(
+            "SELECT tab_id, SUM(active_ms) FROM tab_activity
+             WHERE date >= ?1 AND date <= ?2
+             GROUP BY tab_id",
+        )?;
+        let rows = stmt.query_map(params![from, to], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        rows.collect()
+    }
+
+    // Embeddings
+
+    /// Cached vectors for the given content hashes; missing hashes are left out
+    pub fn load_embeddings(
+        &self,
+        hashes: &[String],
+    ) -> rusqlite::Result<HashMap<String, Vec<f32>>> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare("SELECT vector FROM embeddings WHERE content_hash = ?1")?;
+        let mut vectors = HashMap::new();
+        for hash in hashes {
+            if vectors.contains_key(hash) {
+                continue;
+            }
+            let blob: Option<Vec<u8>> = stmt.query_row([hash], |row| row.get(0)).optional()?;
+            if let Some(blob) = blob {
+                vectors.insert(hash.clone(), vector_from_blob(&blob));
+            }
+        }
+        Ok(vectors)
+    }
+
+    pub fn save_embeddings(
+        &self,
+        model: &str,
+        vectors: &[(String, Vec<f32>)],
+    ) -> rusqlite::Result<()> {
+        let now = chrono::Utc::now().timestamp_millis();
+        let mut conn = self.conn();
+        let tx = conn.transaction()?;
+        {
+            let mut stmt = tx.prepare(
+                "INSERT OR REPLACE INTO embeddings (content_hash, model, vector, created_at)
+                 VALUES (?1, ?2, ?3, ?4)",
+            )?;
+            for (hash, vector) in vectors {
+                stmt.execute(params![hash, model, vector_to_blob(vector), now])?;
+            }
+        }
+        tx.commit()
+    }
+
+    /// Delete cached vectors whose hash is not in `keep`, returning how many were removed
+    pub fn prune_embeddings(&self, keep: &HashSet<String>) -> rusqlite::Result<usize> {
+        let mut conn = self.conn();
+        let tx = conn.transaction()?;
+        let stale: Vec<String> = {
+            let mut stmt = tx.prepare("SELECT content_hash FROM embeddings")?;
+            let hashes = stmt.query_map([], |row| row.get::<_, String>(0))?;
+            hashes
+                .filter_map(|h| h.ok())
+                .filter(|h| !keep.contains(h))
+                .collect()
+        };
+        {
+            let mut stmt = tx.prepare("DELETE FROM embeddings WHERE content_hash = ?1")?;
+            for hash in &stale {
+                stmt.execute([hash])?;
+            }
+        }
+        tx.commit()?;
+        Ok(stale.len())
+    }
+
+    // Suggestion cache
+
+    /// Cached suggestions for the given input hashes, scored at or after `since` (ms)
+    pub fn load_cached_suggestions(
+        &self,
+        hashes: &[String],
+        since: i64,
+    ) -> rusqlite::Result<HashMap<String, TabSuggestion>> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare(
+            "SELECT decision, reason, category, digest, scored_at FROM suggestion_cache
+             WHERE input_hash = ?1 AND scored_at >= ?2",
+        )?;
+        let mut cached = HashMap::new();
+        for hash in hashes {
+            let suggestion = stmt
+                .query_row(params![hash, since], |row| {
+                    Ok(TabSuggestion {
+                        decision: row.get(0)?,
+                        reason: row.get(1)?,
+                        category: row.get(2)?,
+                        digest: row.get(3)?,
+                        scored_at: row.get(4)?,
+                    })
+                })
+                .optional()?;
+            if let Some(suggestion) = suggestion {
+                cached.insert(hash.clone(), suggestion);
+            }
+        }
+        Ok(cached)
+    }
+
+    /// Cache suggestions by input hash and drop entries scored before `expired_before` (ms)
+    pub fn save_cached_suggestions(
+        &self,
+        entries: &[(String, TabSuggestion)],
+        expired_before: i64,
+    ) -> rusqlite::Result<()> {
+        let mut conn = self.conn();
+        let tx = conn.transaction()?;
+        {
+            let mut stmt = tx.prepare(
+                "INSERT OR REPLACE INTO suggestion_cache
+                    (input_hash, decision, reason, category, digest, scored_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            )?;
+            for (hash, s) in entries {
+                stmt.execute(params![
+                    hash,
+                    s.decision,
+                    s.reason,
+                    s.category,
+                    s.digest,
+                    s.scored_at
+                ])?;
+            }
+        }
+        tx.execute(
+            "DELETE FROM suggestion_cache WHERE scored_at < ?1",
+            [expired_before],
+        )?;
+        tx.commit()
+    }
+
+    // AI usage
+
+    pub fn record_ai_call(
+        &self,
+        call: &AiCall,
+        cost_usd: Option<f64>,
+        created_at: i64,
+    ) -> rusqlite::Result<()> {
+        self.conn().execute(
+            "INSERT INTO ai_usage (created_at, provider, model, purpose, prompt_tokens,
+                                   completion_tokens, estimated, image_count, latency_ms,
+                                   retries, cost_usd, error)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+            params![
+                created_at,
+                call.provider.name(),
+                call.model,
+                call.task.purpose(),
+                call.prompt_tokens as i64,
+                call.completion_tokens as i64,
+                call.estimated,
+                call.image_count as i64,
+                call.latency_ms as i64,
+                call.retries,
+                cost_usd,
+                call.error,
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Estimated spend on calls made at or after `since` (ms)
+    pub fn ai_cost_since(&self, since: i64) -> rusqlite::Result<f64> {
+        self.conn().query_row(
+            "SELECT COALESCE(SUM(cost_usd), 0.0) FROM ai_usage WHERE created_at >= ?1",
+            [since],
+            |row| row.get(0),
+        )
+    }
+
+    /// Usage since `since` (ms) grouped by local-time period, newest first.
+    /// `period_format` is a strftime pattern such as '%Y-%m-%d'.
+    pub fn ai_usage_by_period(
+        &self,
+        period_format: &str,
+        since: i64,
+    ) -> rusqlite::Result<Vec<UsagePeriod>> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare(
+            "SELECT strftime(?1, created_at / 1000, 'unixepoch', 'localtime') AS period,
+                    COUNT(*),
+                    COUNT(error),
+                    SUM(prompt_tokens),
+                    SUM(completion_tokens),
+                    SUM(image_count),
+                    COALESCE(SUM(cost_usd), 0.0),
+                    COUNT(*) - COUNT(cost_usd),
+                    CAST(AVG(latency_ms) AS INTEGER)
+             FROM ai_usage
+             WHERE created_at >= ?2
+             GROUP BY period
+             ORDER BY period DESC",
+        )?;
+        let periods = stmt
+            .query_map(params![period_format, since], |row| {
+                Ok(UsagePeriod {
+                    period: row.get(0)?,
+                    calls: row.get(1)?,
+                    failed: row.get(2)?,
+                    prompt_tokens: row.get(3)?,
+                    completion_tokens: row.get(4)?,
+                    image_count: row.get(5)?,
+                    cost_usd: row.get(6)?,
+                    unpriced_calls: row.get(7)?,
+                    avg_latency_ms: row.get(8)?,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(periods)
+    }
+
+    // Search
+
+    /// Ranked full-text search over open and closed tabs.
+    /// Without a match expression, filtered tabs are returned by most recent activity.
+    pub fn search(&self, filters: &SearchFilters) -> rusqlite::Result<Vec<SearchHit>> {
+        // FTS5 only accepts MATCH as a top-level AND term, so it is spliced in when present
+        let (select, matches, order) = if filters.match_expr.is_some() {
+            (
+                format!(
+                    "-bm25(tab_search, {weights}),
+                     highlight(tab_search, 0, '{start}', '{end}'),
+                     snippet(tab_search, 3, '{start}', '{end}', '…', 24),
+                     snippet(tab_search, 2, '{start}', '{end}', '…', 24)",
+                    weights = SEARCH_WEIGHTS,
+                    start = MATCH_START,
+                    end = MATCH_END
+                ),
+                "tab_search MATCH ?1",
+                format!("bm25(tab_search, {}),", SEARCH_WEIGHTS),
+            )
+        } else {
+            (
+                "0.0, tab_search.title, NULL, NULL".to_string(),
+                "?1 IS NULL",
+                String::new(),
+            )
+        };
+
+        let sql = format!(
+            "SELECT tab_search.rowid, {select}
+             FROM tab_search
+             JOIN tabs t ON t.id = tab_search.rowid
+             LEFT JOIN suggestions su ON su.tab_id = t.id
+             WHERE {matches}
+               AND (?2 IS NULL OR su.category = ?2 COLLATE NOCASE)
+               AND (?3 IS NULL OR su.decision = ?3 COLLATE NOCASE)
+               AND (?4 IS NULL OR tab_search.domain = ?4
+                    OR substr(tab_search.domain, -length(?4) - 1) = '.' || ?4)
+               AND (?5 IS NULL OR t.closed_at IS NULL OR t.closed_at >= ?5)
+               AND (?6 IS NULL OR t.created_at < ?6)
+             ORDER BY {order} COALESCE(t.last_active_at, t.created_at) DESC
+             LIMIT ?7"
+        );
+
+        let conn = self.conn();
+        let mut stmt = conn.prepare(&sql)?;
+        let hits = stmt
+            .query_map(
+                params![
+                    filters.match_expr,
+                    filters.category,
+                    filters.decision,
+                    filters.domain,
+                    filters.from_ms,
+                    filters.to_ms,
+                    filters.limit as i64,
+                ],
+                |row| {
+                    // Prefer the digest excerpt; fall back to the description
+                    let digest: Option<String> = row.get(3)?;
+                    let description: Option<String> = row.get(4)?;
+                    let has_match = |s: &String| s.contains(MATCH_START);
+                    let snippet = digest
+                        .filter(has_match)
+                        .or_else(|| description.filter(has_match));
+                    Ok(SearchHit {
+                        tab_id: row.get(0)?,
+                        score: row.get(1)?,
+                        title: row.get(2)?,
+                        snippet,
+                    })
+                },
+            )?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(hits)
+    }
+
+    // Settings
+
+    pub fn load_settings(&self) -> rusqlite::Result<Option<String>> {
+        self.conn()
+            .query_row(
+                "SELECT value FROM settings WHERE key = 'settings'",
+                [],
+                |row| row.get(0),
+            )
+            .optional()
+    }
+
+    pub fn save_settings(&self, json: &str) -> rusqlite::Result<()> {
+        self.conn().execute(
+            "INSERT INTO settings (key, value) VALUES ('settings', ?1)
+             ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+            [json],
+        )?;
+        Ok(())
+    }
+
+    // Pairing
+
+    pub fn load_pairing_secret(&self) -> rusqlite::Result<Option<String>> {
+        self.conn()
+            .query_row(
+                "SELECT value FROM settings WHERE key = 'pairing_secret'",
+                [],
+                |row| row.get(0),
+            )
+            .optional()
+    }
+
+    pub fn save_pairing_secret(&self, secret: &str) -> rusqlite::Result<()> {
+        self.conn().execute(
+            "INSERT INTO settings (key, value) VALUES ('pairing_secret', ?1)
+             ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+            [secret],
+        )?;
+        Ok(())
+    }
+
+    /// Paired clients keyed by token hash
+    pub fn load_paired_clients(&self) -> rusqlite::Result<HashMap<String, PairedClient>> {
+        let conn = self.conn();
+        let mut stmt =
+            conn.prepare("SELECT token_hash, origin, name, paired_at FROM paired_clients")?;
+        let rows = stmt.query_map([], |row| {
+            let token_hash: String = row.get(0)?;
+            Ok((
+                token_hash.clone(),
+                PairedClient::new(&token_hash, row.get(1)?, row.get(2)?, row.get(3)?),
+            ))
+        })?;
+        rows.collect()
+    }
+
+    pub fn save_paired_client(
+        &self,
+        token_hash: &str,
+        client: &PairedClient,
+    ) -> rusqlite::Result<()> {
+        self.conn().execute(
+            "INSERT INTO paired_clients (token_hash, origin, name, paired_at)
+             VALUES (?1, ?2, ?3, ?4)",
+            params![token_hash, client.origin, client.name, client.paired_at],
+        )?;
+        Ok(())
+    }
+
+    pub fn delete_paired_client(&self, token_hash: &str) -> rusqlite::Result<()> {
+        self.conn().execute(
+            "DELETE FROM paired_clients WHERE token_hash = ?1",
+            params![token_hash],
+        )?;
+        Ok(())
+    }
+
+    /// Sets a new secret and forgets every paired client but those from `keep_origin`,
+    /// in one transaction
+    pub fn reset_pairing(&self, secret: &str, keep_origin: &str) -> rusqlite::Result<()> {
+        let mut conn = self.conn();
+        let tx = conn.transaction()?;
+        tx.execute(
+            "DELETE FROM paired_clients WHERE origin != ?1",
+            [keep_origin],
+        )?;
+        tx.execute(
+            "INSERT INTO settings (key, value) VALUES ('pairing_secret', ?1)
+             ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+            [secret],
+        )?;
+        tx.commit()
+    }
+
+    // Command queue
+
+    pub fn insert_queued_command(
+        &self,
+        command: &str,
+        tab_id: Option<i64>,
+        created_at: i64,
+        expires_at: i64,
+    ) -> rusqlite::Result<i64> {
+        let conn = self.conn();
+        conn.execute(
+            "INSERT INTO command_queue (command, tab_id, created_at, expires_at, updated_at)
+             VALUES (?1, ?2, ?3, ?4, ?3)",
+            params![command, tab_id, created_at, expires_at],
+        )?;
+        Ok(conn.last_insert_rowid())
+    }
+
+    /// Queued commands with the given status, or all, oldest first
+    pub fn load_queued_commands(
+        &self,
+        status: Option<CommandStatus>,
+    ) -> rusqlite::Result<Vec<QueuedCommand>> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare(
+            "SELECT id, command, tab_id, status, error, created_at, expires_at, updated_at
+             FROM command_queue
+             WHERE ?1 IS NULL OR status = ?1
+             ORDER BY id",
+        )?;
+        let commands = stmt
+            .query_map(params![status.map(|s| s.as_str())], queued_command_from_row)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(commands)
+    }
+
+    /// Moves a pending command to `status`; false if it was not pending
+    pub fn finish_queued_command(
+        &self,
+        id: i64,
+        status: CommandStatus,
+        error: Option<&str>,
+        updated_at: i64,
+    ) -> rusqlite::Result<bool> {
+        let changed = self.conn().execute(
+            "UPDATE command_queue SET status = ?2, error = ?3, updated_at = ?4
+             WHERE id = ?1 AND status = 'pending'",
+            params![id, status.as_str(), error, updated_at],
+        )?;
+        Ok(changed > 0)
+    }
+
+    /// Fails pending commands that expired by `now`
+    pub fn expire_queued_commands(&self, now: i64, error: &str) -> rusqlite::Result<usize> {
+        self.conn().execute(
+            "UPDATE command_queue SET status = 'failed', error = ?2, updated_at = ?1
+             WHERE status = 'pending' AND expires_at <= ?1",
+            params![now, error],
+        )
+    }
+
+    /// Forgets delivered and failed commands last updated before `cutoff`
+    pub fn delete_finished_commands_before(&self, cutoff: i64) -> rusqlite::Result<usize> {
+        self.conn().execute(
+            "DELETE FROM command_queue WHERE status != 'pending' AND updated_at < ?1",
+            params![cutoff],
+        )
+    }
+
+    // Reports
+
+    pub fn load_latest_report(&self) -> rusqlite::Result<Option<DailyReport>> {
+        self.conn()
+            .query_row(
+                "SELECT date, content, generated_at FROM reports
+                 ORDER BY date DESC LIMIT 1",
+                [],
+                report_from_row,
+            )
+            .optional()
+    }
+
+    pub fn load_report(&self, date: &str) -> rusqlite::Result<Option<DailyReport>> {
+        self.conn()
+            .query_row(
+                "SELECT date, content, generated_at FROM reports WHERE date = ?1",
+                params![date],
+                report_from_row,
+            )
+            .optional()
+    }
+
+    /// Reports dated within `[from, to]` (inclusive, `YYYY-MM-DD`), newest first
+    pub fn list_reports(
+        &self,
+        from: Option<&str>,
+        to: Option<&str>,
+    ) -> rusqlite::Result<Vec<DailyReport>> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare(
+            "SELECT date, content, generated_at FROM reports
+             WHERE (?1 IS NULL OR date >= ?1) AND (?2 IS NULL OR date <= ?2)
+             ORDER BY date DESC",
+        )?;
+        let reports = stmt
+            .query_map(params![from, to], report_from_row)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(reports)
+    }
+
+    pub fn save_report(&self, report: &DailyReport) -> rusqlite::Result<()> {
+        self.conn().execute(
+            "INSERT INTO reports (date, content, generated_at) VALUES (?1, ?2, ?3)
+             ON CONFLICT(date) DO UPDATE SET
+                content = excluded.content,
+                generated_at = excluded.generated_at",
+            params![report.date, report.content, report.generated_at],
+        )?;
+        Ok(())
+    }
+
+    /// Returns whether a report existed for `date`
+    pub fn delete_report(&self, date: &str) -> rusqlite::Result<bool> {
+        let deleted = self
+            .conn()
+            .execute("DELETE FROM reports WHERE date = ?1", params![date])?;
+        Ok(deleted > 0)
+    }
+
+    /// Delete every report dated before `date`, and rollups that ended before it,
+    /// returning how many were removed
+    pub fn delete_reports_before(&self, date: &str) -> rusqlite::Result<usize> {
+        let conn = self.conn();
+        let daily = conn.execute("DELETE FROM reports WHERE date < ?1", params![date])?;
+        let rollups = conn.execute(
+            "DELETE FROM period_reports WHERE end_date < ?1",
+            params![date],
+        )?;
+        Ok(daily + rollups)
+    }
+
+    pub fn clear_reports(&self) -> rusqlite::Result<()> {
+        let conn = self.conn();
+        conn.execute("DELETE FROM reports", [])?;
+        conn.execute("DELETE FROM report_drafts", [])?;
+        conn.execute("DELETE FROM period_reports", [])?;
+        Ok(())
+    }
+
+    // Rollups
+
+    /// Rollups of one granularity, or all, newest period first
+    pub fn list_period_reports(
+        &self,
+        granularity: Option<&str>,
+    ) -> rusqlite::Result<Vec<PeriodReport>> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare(
+            "SELECT granularity, start_date, end_date, content, generated_at FROM period_reports
+             WHERE ?1 IS NULL OR granularity = ?1
+             ORDER BY start_date DESC",
+        )?;
+        let reports = stmt
+            .query_map(params![granularity], |row| {
+                Ok(PeriodReport {
+                    granularity: row.get(0)?,
+                    start: row.get(1)?,
+                    end: row.get(2)?,
+                    content: row.get(3)?,
+                    generated_at: row.get(4)?,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(reports)
+    }
+
+    pub fn save_period_report(&self, report: &PeriodReport) -> rusqlite::Result<()> {
+        self.conn().execute(
+            "INSERT INTO period_reports (granularity, start_date, end_date, content, generated_at)
+             VALUES (?1, ?2, ?3, ?4, ?5)
+             ON CONFLICT(granularity, start_date) DO UPDATE SET
+                end_date = excluded.end_date,
+                content = excluded.content,
+                generated_at = excluded.generated_at",
+            params![
+                report.granularity,
+                report.start,
+                report.end,
+                report.content,
+                report.generated_at
+            ],
+        )?;
+        Ok(())
+    }
+
+    // Report drafts
+
+    pub fn load_report_draft(&self, date: &str) -> rusqlite::Result<Option<ReportDraft>> {
+        self.conn()
+            .query_row(
+                "SELECT date, content, updated_at, error FROM report_drafts WHERE date = ?1",
+                params![date],
+                |row| {
+                    Ok(ReportDraft {
+                        date: row.get(0)?,
+                        content: row.get(1)?,
+                        updated_at: row.get(2)?,
+                        error: row.get(3)?,
+                    })
+                },
+            )
+            .optional()
+    }
+
+    pub fn save_report_draft(&self, draft: &ReportDraft) -> rusqlite::Result<()> {
+        self.conn().execute(
+            "INSERT INTO report_drafts (date, content, updated_at, error) VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT(date) DO UPDATE SET
+                content = excluded.content,
+                updated_at = excluded.updated_at,
+                error = excluded.error",
+            params![draft.date, draft.content, draft.updated_at, draft.error],
+        )?;
+        Ok(())
+    }
+
+    pub fn delete_report_draft(&self, date: &str) -> rusqlite::Result<()> {
+        self.conn()
+            .execute("DELETE FROM report_drafts WHERE date = ?1", params![date])?;
+        Ok(())
+    }
+}
+
+/// Bring the schema up to SCHEMA_VERSION, one transaction per step
+fn migrate(conn: &mut Connection) -> Result<(), OpenError> {
+    let version: u32 = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    if version > SCHEMA_VERSION {
+        return Err(OpenError::NewerSchema(version));
+    }
+
+    for (index, sql) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        let next = index as u32 + 1;
+        let tx = conn.transaction()?;
+        tx.execute_batch(sql)?;
+        if next == SEARCH_INDEX_VERSION {
+            rebuild_search_index(&tx)?;
+        }
+        if next == ACTIVITY_VERSION {
+            tx.execute_batch(SEED_ACTIVITY)?;
+        }
+        tx.pragma_update(None, "user_version", next)?;
+        tx.commit()?;
+        println!("[DB] Migrated schema to v{}", next);
+    }
+    Ok(())
+}
+
+fn write_tab(conn: &Connection, tab: &TabRecord) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT INTO tabs (id, window_id, url, title, fav_icon_url, created_at,
+                           last_active_at, total_active_ms, is_active, closed_at, description,
+                           chrome_id, session_id, tab_index, active_ms_base)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)
+         ON CONFLICT(id) DO UPDATE SET
+            window_id = excluded.window_id,
+            url = excluded.url,
+            title = excluded.title,
+            fav_icon_url = excluded.fav_icon_url,
+            created_at = excluded.created_at,
+            last_active_at = excluded.last_active_at,
+            total_active_ms = excluded.total_active_ms,
+            is_active = excluded.is_active,
+            closed_at = excluded.closed_at,
+            description = excluded.description,
+            chrome_id = excluded.chrome_id,
+            session_id = excluded.session_id,
+            tab_index = excluded.tab_index,
+            active_ms_base = excluded.active_ms_base",
+        params![
+            tab.id,
+            tab.window_id,
+            tab.url,
+            tab.title,
+            tab.fav_icon_url,
+            tab.created_at,
+            tab.last_active_at,
+            tab.total_active_ms,
+            tab.is_active,
+            tab.closed_at,
+            tab.description,
+            tab.chrome_id,
+            tab.session_id,
+            tab.tab_index,
+            tab.active_ms_base,
+        ],
+    )?;
+
+    match &tab.snapshot {
+        Some(snapshot) => {
+            conn.execute(
+                "INSERT INTO snapshots (tab_id, screenshot_path, captured_at) VALUES (?1, ?2, ?3)
+                 ON CONFLICT(tab_id) DO UPDATE SET
+                    screenshot_path = excluded.screenshot_path,
+                    captured_at = excluded.captured_at",
+                params![tab.id, snapshot.screenshot_path, snapshot.captured_at],
+            )?;
+        }
+        None => {
+            conn.execute("DELETE FROM snapshots WHERE tab_id = ?1", [tab.id])?;
+        }
+    }
+
+    match &tab.suggestion {
+        Some(suggestion) => {
+            conn.execute(
+                "INSERT INTO suggestions (tab_id, decision, reason, category, digest, scored_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+                 ON CONFLICT(tab_id) DO UPDATE SET
+                    decision = excluded.decision,
+                    reason = excluded.reason,
+                    category = excluded.category,
+                    digest = excluded.digest,
+                    scored_at = excluded.scored_at",
+                params![
+                    tab.id,
+                    suggestion.decision,
+                    suggestion.reason,
+                    suggestion.category,
+                    suggestion.digest,
+                    suggestion.scored_at,
+                ],
+            )?;
+        }
+        None => {
+            conn.execute("DELETE FROM suggestions WHERE tab_id = ?1", [tab.id])?;
+        }
+    }
+
+    index_tab(conn, tab)
+}
+
+/// Replace the tab's row in the full-text index
+fn index_tab(conn: &Connection, tab: &TabRecord) -> rusqlite::Result<()> {
+    conn.execute("DELETE FROM tab_search WHERE rowid = ?1", [tab.id])?;
+    conn.execute(
+        "INSERT INTO tab_search (rowid, title, url, description, digest, domain)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![
+            tab.id,
+            tab.title,
+            tab.url,
+            tab.description,
+            tab.suggestion.as_ref().and_then(|s| s.digest.as_ref()),
+            tab.url.as_deref().and_then(search::url_domain),
+        ],
+    )?;
+    Ok(())
+}
+
+/// Index every stored tab (used when the index is first created)
+fn rebuild_search_index(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute("DELETE FROM tab_search", [])?;
+    let mut stmt = conn.prepare(SELECT_TABS)?;
+    let tabs = stmt.query_map([], tab_from_row)?;
+    let mut count = 0;
+    for tab in tabs.flatten() {
+        index_tab(conn, &tab)?;
+        count += 1;
+    }
+    println!("[DB] Indexed {} tabs for search", count);
+    Ok(())
+}
+
+fn vector_to_blob(vector: &[f32]) -> Vec<u8> {
+    vector.iter().flat_map(|v| v.to_le_bytes()).collect()
+}
+
+fn vector_from_blob(blob: &[u8]) -> Vec<f32> {
+    blob.chunks_exact(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect()
+}
+
+fn report_from_row(row: &Row) -> rusqlite::Result<DailyReport> {
+    Ok(DailyReport {
+        date: row.get(0)?,
+        content: row.get(1)?,
+        generated_at: row.get(2)?,
+    })
+}
+
+fn queued_command_from_row(row: &Row) -> rusqlite::Result<QueuedCommand> {
+    let command: String = row.get(1)?;
+    let status: String = row.get(3)?;
+    Ok(QueuedCommand {
+        id: row.get(0)?,
+        command: serde_json::from_str(&command).map_err(|e| {
+            rusqlite::Error::FromSqlConversionFailure(1, rusqlite::types::Type::Text, e.into())
+        })?,
+        tab_id: row.get(2)?,
+        // Unknown statuses only come from newer versions; leave those commands alone
+        status: CommandStatus::parse(&status).unwrap_or(CommandStatus::Failed),
+        error: row.get(4)?,
+        created_at: row.get(5)?,
+        expires_at: row.get(6)?,
+        updated_at: row.get(7)?,
+    })
+}
+
+fn tab_from_row(row: &Row) -> rusqlite::Result<TabRecord> {
+    // LEFT JOIN columns are NULL when the tab has no snapshot/suggestion
+    let snapshot = match row.get::<_, Option<i64>>(12)? {
+        Some(captured_at) => Some(TabSnapshot {
+            screenshot_path: row.get(11)?,
+            captured_at,
+        }),
+        None => None,
+    };
+    let suggestion = match row.get::<_, Option<String>>(13)? {
+        Some(decision) => Some(TabSuggestion {
+            decision,
+            reason: row.get(14)?,
+            category: row.get(15)?,
+            digest: row.get(16)?,
+            scored_at: row.get(17)?,
+        }),
+        None => None,
+    };
+
+    Ok(TabRecord {
+        id: row.get(0)?,
+        chrome_id: row.get(18)?,
+        session_id: row.get(19)?,
+        tab_index: row.get(20)?,
+        window_id: row.get(1)?,
+        url: row.get(2)?,
+        title: row.get(3)?,
+        fav_icon_url: row.get(4)?,
+        created_at: row.get(5)?,
+        last_active_at: row.get(6)?,
+        total_active_ms: row.get(7)?,
+        active_ms_base: row.get(21)?,
+        is_active: row.get(8)?,
+        closed_at: row.get(9)?,
+        description: row.get(10)?,
+        snapshot,
+        suggestion,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{day_bounds, parse_date};
+    use crate::test_support;
+
+    #[test]
+    fn upgrading_credits_existing_active_time_to_the_day_last_used() {
+        let conn = Connection::open_in_memory().unwrap();
+        for sql in &MIGRATIONS[..ACTIVITY_VERSION as usize - 1] {
+            conn.execute_batch(sql).unwrap();
+        }
+        conn.pragma_update(None, "user_version", ACTIVITY_VERSION - 1)
+            .unwrap();
+        let (monday, _) = day_bounds(parse_date("2026-10-12").unwrap());
+        let (wednesday, _) = day_bounds(parse_date("2026-10-14").unwrap());
+        let tabs = [
+            test_support::tab(1, "https://docs.rs/")
+                .created_at(monday + 1_000)
+                .last_active_at(wednesday + 1_000)
+                .active_ms(90_000)
+                .build(),
+            test_support::tab(2, "https://example.com/")
+                .created_at(monday + 2_000)
+                .active_ms(30_000)
+                .build(),
+            test_support::tab(3, "https://news.example/")
+                .created_at(monday + 3_000)
+                .build(),
+        ];
+        for tab in &tabs {
+            write_tab(&conn, tab).unwrap();
+        }
+
+        let db = Database::init(conn).unwrap();
+
+        assert_eq!(
+            db.load_activity("2026-10-12", "2026-10-12").unwrap(),
+            HashMap::from([(2, 30_000)])
+        );
+        assert_eq!(
+            db.load_activity("2026-10-14", "2026-10-14").unwrap(),
+            HashMap::from([(1, 90_000)])
+        );
+        // Flushing the same totals again adds nothing
+        db.write_batch(&tabs, &[], "2026-10-15").unwrap();
+        assert!(db
+            .load_activity("2026-10-15", "2026-10-15")
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -0,0 +1,441 @@
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use url::Url;
+
+use crate::storage::TabRecord;
+
+/// Click-tracking parameters no site uses for content (plus every `utm_*`). Generic names
+/// like `ref`, `si` or `feature` stay: on many sites they pick the page shown.
+const TRACKING_PARAMS: &[&str] = &[
+    "fbclid", "gclid", "dclid", "gbraid", "wbraid", "msclkid", "yclid", "mc_cid", "mc_eid",
+    "igshid", "_ga", "_gl",
+];
+
+/// Host prefixes for mobile/AMP mirrors of the same site
+const MIRROR_HOST_PREFIXES: &[&str] = &["www.", "m.", "mobile.", "amp."];
+
+/// Word-set similarity above which two titles from the same host are near-duplicates
+const TITLE_SIMILARITY: f32 = 0.85;
+/// Shingle similarity needed for descriptions when titles match
+const DESCRIPTION_SIMILARITY: f32 = 0.6;
+/// Shingle similarity at which descriptions alone mark a near-duplicate
+const STRONG_DESCRIPTION_SIMILARITY: f32 = 0.9;
+/// Descriptions shorter than this (in words) are too generic to compare on their own
+const MIN_DESCRIPTION_WORDS: usize = 50;
+const SHINGLE_SIZE: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DuplicateReason {
+    /// URLs are identical after normalization
+    SameUrl,
+    /// Same site with near-identical title or content
+    SimilarContent,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DuplicateGroup {
+    pub reason: DuplicateReason,
+    /// Normalized URL shared by the group (for `SameUrl`)
+    pub url: Option<String>,
+    /// Recommended tab to keep
+    pub survivor: i64,
+    /// Tabs to close in favour of the survivor
+    pub duplicates: Vec<i64>,
+}
+
+/// Canonical form of a URL for duplicate detection: https, no mobile/AMP host prefix,
+/// no fragment, tracking parameters or trailing slash, remaining parameters sorted.
+/// Returns None for non-web URLs.
+pub fn normalize_url(raw: &str) -> Option<String> {
+    let mut url = Url::parse(raw).ok()?;
+    if url.scheme() != "http" && url.scheme() != "https" {
+        return None;
+    }
+
+    let mut host = url.host_str()?.to_lowercase();
+    // Only a prefix in front of a full domain: amp.dev and mobile.de are sites of their own
+    while let Some(stripped) = MIRROR_HOST_PREFIXES
+        .iter()
+        .find_map(|prefix| host.strip_prefix(prefix))
+        .filter(|rest| rest.contains('.'))
+    {
+        host = stripped.to_string();
+    }
+
+    let mut path = url.path().to_string();
+    // AMP articles live at <article>/amp or <article>.amp.html next to the canonical page.
+    // A shorter path ending in /amp, like github.com/ampproject/amp, is a page of its own.
+    if let Some(article) = path.trim_end_matches('/').strip_suffix("/amp") {
+        if article.split('/').filter(|s| !s.is_empty()).count() >= 2 {
+            path = article.to_string();
+        }
+    } else {
+        for suffix in [".amp.html", "/amp.html"] {
+            if let Some(stripped) = path.strip_suffix(suffix) {
+                path = stripped.to_string();
+                break;
+            }
+        }
+    }
+    if let Some(stripped) = path.strip_prefix("/amp/") {
+        path = format!("/{}", stripped);
+    }
+    let path = path.trim_end_matches('/');
+
+    let mut params: Vec<(String, String)> = url
+        .query_pairs()
+        .filter(|(key, _)| {
+            let key = key.to_lowercase();
+            !key.starts_with("utm_") && !TRACKING_PARAMS.contains(&key.as_str())
+        })
+        .map(|(k, v)| (k.into_owned(), v.into_owned()))
+        .collect();
+    params.sort();
+
+    url.set_fragment(None);
+    let query = if params.is_empty() {
+        String::new()
+    } else {
+        let encoded: String = url::form_urlencoded::Serializer::new(String::new())
+            .extend_pairs(&params)
+            .finish();
+        format!("?{}", encoded)
+    };
+
+    let port = url
+        .port()
+        .filter(|p| !matches!(p, 80 | 443))
+        .map(|p| format!(":{}", p))
+        .unwrap_or_default();
+
+    Some(format!("https://{}{}{}{}", host, port, path, query))
+}
+
+fn host_of(normalized: &str) -> &str {
+    normalized
+        .trim_start_matches("https://")
+        .split(['/', '?'])
+        .next()
+        .unwrap_or("")
+}
+
+fn words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(|w| w.to_lowercase())
+        .collect()
+}
+
+fn jaccard<T: Eq + std::hash::Hash>(a: &HashSet<T>, b: &HashSet<T>) -> f32 {
+    if a.is_empty() || b.is_empty() {
+        return 0.0;
+    }
+    let intersection = a.intersection(b).count();
+    let union = a.len() + b.len() - intersection;
+    intersection as f32 / union as f32
+}
+
+fn shingles(words: &[String]) -> HashSet<String> {
+    if words.len() < SHINGLE_SIZE {
+        return words.iter().cloned().collect();
+    }
+    words.windows(SHINGLE_SIZE).map(|w| w.join(" ")).collect()
+}
+
+/// Comparable features of one open tab
+struct Fingerprint<'a> {
+    tab: &'a TabRecord,
+    url: Option<String>,
+    title: HashSet<String>,
+    description_words: usize,
+    description: HashSet<String>,
+}
+
+impl<'a> Fingerprint<'a> {
+    fn new(tab: &'a TabRecord) -> Self {
+        let description = words(tab.description.as_deref().unwrap_or(""));
+        Self {
+            tab,
+            url: tab.url.as_deref().and_then(normalize_url),
+            title: words(tab.title.as_deref().unwrap_or(""))
+                .into_iter()
+                .collect(),
+            description_words: description.len(),
+            description: shingles(&description),
+        }
+    }
+
+    fn is_near_duplicate(&self, other: &Fingerprint) -> bool {
+        let (Some(a), Some(b)) = (&self.url, &other.url) else {
+            return false;
+        };
+        if host_of(a) != host_of(b) {
+            return false;
+        }
+
+        let description_similarity = jaccard(&self.description, &other.description);
+        let both_described = self.description_words >= MIN_DESCRIPTION_WORDS
+            && other.description_words >= MIN_DESCRIPTION_WORDS;
+        if both_described && description_similarity >= STRONG_DESCRIPTION_SIMILARITY {
+            return true;
+        }
+
+        // Short titles ("Home", "Inbox") say little about the page
+        if self.title.len() < 3 || other.title.len() < 3 {
+            return false;
+        }
+        jaccard(&self.title, &other.title) >= TITLE_SIMILARITY
+            && (self.description.is_empty()
+                || other.description.is_empty()
+                || description_similarity >= DESCRIPTION_SIMILARITY)
+    }
+}
+
+/// The tab most worth keeping: active, marked keep, most used, most recent
+fn pick_survivor(tabs: &[&TabRecord]) -> i64 {
+    tabs.iter()
+        .max_by_key(|t| {
+            (
+                t.is_active,
+                t.suggestion.as_ref().is_some_and(|s| s.decision == "keep"),
+                t.total_active_ms,
+                t.last_active_at.unwrap_or(t.created_at),
+                std::cmp::Reverse(t.id),
+            )
+        })
+        .map(|t| t.id)
+        .unwrap_or_default()
+}
+
+fn union(parent: &mut [usize], a: usize, b: usize) {
+    let (ra, rb) = (find(parent, a), find(parent, b));
+    if ra != rb {
+        parent[ra.max(rb)] = ra.min(rb);
+    }
+}
+
+fn find(parent: &mut [usize], i: usize) -> usize {
+    let mut root = i;
+    while parent[root] != root {
+        root = parent[root];
+    }
+    let mut node = i;
+    while parent[node] != root {
+        let next = parent[node];
+        parent[node] = root;
+        node = next;
+    }
+    root
+}
+
+/// Group open tabs that show the same page. Tabs with equal normalized URLs are joined,
+/// then tabs on the same host with near-identical titles or descriptions.
+pub fn find_duplicates<'a>(tabs: impl IntoIterator<Item = &'a TabRecord>) -> Vec<DuplicateGroup> {
+    let mut open: Vec<&TabRecord> = tabs.into_iter().filter(|t| t.closed_at.is_none()).collect();
+    open.sort_by_key(|t| t.id);
+    let prints: Vec<Fingerprint> = open.iter().map(|t| Fingerprint::new(t)).collect();
+
+    let mut parent: Vec<usize> = (0..prints.len()).collect();
+
+    let mut by_url: HashMap<&str, usize> = HashMap::new();
+    for (i, print) in prints.iter().enumerate() {
+        if let Some(url) = &print.url {
+            match by_url.get(url.as_str()) {
+                Some(&first) => union(&mut parent, first, i),
+                None => {
+                    by_url.insert(url, i);
+                }
+            }
+        }
+    }
+
+    for i in 0..prints.len() {
+        for j in (i + 1)..prints.len() {
+            if find(&mut parent, i) != find(&mut parent, j)
+                && prints[i].is_near_duplicate(&prints[j])
+            {
+                union(&mut parent, i, j);
+            }
+        }
+    }
+
+    let mut members: HashMap<usize, Vec<usize>> = HashMap::new();
+    for i in 0..prints.len() {
+        members.entry(find(&mut parent, i)).or_default().push(i);
+    }
+
+    let mut groups: Vec<DuplicateGroup> = members
+        .into_values()
+        .filter(|m| m.len() > 1)
+        .map(|m| {
+            let group_tabs: Vec<&TabRecord> = m.iter().map(|&i| prints[i].tab).collect();
+            let urls: HashSet<Option<&String>> =
+                m.iter().map(|&i| prints[i].url.as_ref()).collect();
+            let same_url = urls.len() == 1;
+            let survivor = pick_survivor(&group_tabs);
+            DuplicateGroup {
+                reason: if same_url {
+                    DuplicateReason::SameUrl
+                } else {
+                    DuplicateReason::SimilarContent
+                },
+                url: if same_url {
+                    prints[m[0]].url.clone()
+                } else {
+                    None
+                },
+                survivor,
+                duplicates: group_tabs
+                    .iter()
+                    .map(|t| t.id)
+                    .filter(|&id| id != survivor)
+                    .collect(),
+            }
+        })
+        .collect();
+
+    groups.sort_by(|a, b| {
+        b.duplicates
+            .len()
+            .cmp(&a.duplicates.len())
+            .then(a.survivor.cmp(&b.survivor))
+    });
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    fn normalized(url: &str) -> String {
+        normalize_url(url).unwrap()
+    }
+
+    #[test]
+    fn normalizes_urls_of_the_same_page() {
+        let canonical = "https://example.com/news/story";
+        for url in [
+            "http://example.com/news/story",
+            "https://www.example.com/news/story/",
+            "https://m.example.com/news/story#comments",
+            "https://amp.example.com/news/story/amp",
+            "https://example.com/amp/news/story",
+            "https://example.com/news/story?utm_source=feed&utm_medium=rss",
+            "https://example.com/news/story?fbclid=abc&gclid=def",
+        ] {
+            assert_eq!(normalized(url), canonical, "{}", url);
+        }
+        assert_eq!(
+            normalized("https://example.com/search?b=2&a=1&utm_campaign=x"),
+            "https://example.com/search?a=1&b=2"
+        );
+        assert_eq!(normalize_url("chrome://settings"), None);
+    }
+
+    #[test]
+    fn keeps_parameters_that_pick_the_page() {
+        for (a, b) in [
+            (
+                "https://github.com/tokio-rs/tokio/compare?ref=master",
+                "https://github.com/tokio-rs/tokio/compare?ref=v1.0",
+            ),
+            (
+                "https://www.youtube.com/watch?v=abc&feature=share",
+                "https://www.youtube.com/watch?v=abc",
+            ),
+            (
+                "https://example.com/page?si=1",
+                "https://example.com/page?si=2",
+            ),
+        ] {
+            assert_ne!(normalized(a), normalized(b), "{} vs {}", a, b);
+        }
+    }
+
+    #[test]
+    fn keeps_sites_and_paths_that_only_look_like_mirrors() {
+        assert_eq!(
+            normalized("https://amp.dev/documentation/"),
+            "https://amp.dev/documentation"
+        );
+        assert_eq!(normalized("https://www.mobile.de/"), "https://mobile.de");
+        assert_eq!(
+            normalized("https://m.example.co.uk/"),
+            "https://example.co.uk"
+        );
+        assert_eq!(
+            normalized("https://github.com/ampproject/amp"),
+            "https://github.com/ampproject/amp"
+        );
+        assert_eq!(
+            normalized("https://example.com/news/2024/story/amp/"),
+            "https://example.com/news/2024/story"
+        );
+
+        let tabs = [
+            test_support::tab(1, "https://github.com/ampproject")
+                .title("AMP Project")
+                .build(),
+            test_support::tab(2, "https://github.com/ampproject/amp")
+                .title("ampproject/amp: The AMP web component framework")
+                .build(),
+        ];
+        assert!(find_duplicates(tabs.iter()).is_empty());
+    }
+
+    #[test]
+    fn groups_near_duplicate_titles_on_the_same_site() {
+        let tabs = [
+            test_support::tab(1, "https://docs.rs/tokio/latest/tokio/runtime/index.html")
+                .title("tokio::runtime - Rust async runtime docs")
+                .build(),
+            test_support::tab(2, "https://docs.rs/tokio/1.40.0/tokio/runtime/index.html")
+                .title("tokio::runtime - Rust async runtime docs")
+                .active_ms(5_000)
+                .build(),
+            // Same title on another site
+            test_support::tab(3, "https://example.com/mirror")
+                .title("tokio::runtime - Rust async runtime docs")
+                .build(),
+            // Same site, different page
+            test_support::tab(4, "https://docs.rs/serde")
+                .title("serde - Rust serialization framework docs")
+                .build(),
+            // Titles too short to compare
+            test_support::tab(5, "https://mail.example.org/a")
+                .title("Inbox")
+                .build(),
+            test_support::tab(6, "https://mail.example.org/b")
+                .title("Inbox")
+                .build(),
+        ];
+
+        let groups = find_duplicates(&tabs);
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].reason, DuplicateReason::SimilarContent);
+        assert_eq!(groups[0].survivor, 2);
+        assert_eq!(groups[0].duplicates, vec![1]);
+    }
+
+    #[test]
+    fn same_url_groups_ignore_closed_tabs() {
+        let tabs = [
+            test_support::tab(1, "https://example.com/a?utm_source=x").build(),
+            test_support::tab(2, "https://www.example.com/a#top").build(),
+            test_support::tab(3, "https://example.com/a")
+                .closed_at(1)
+                .build(),
+        ];
+
+        let groups = find_duplicates(&tabs);
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].reason, DuplicateReason::SameUrl);
+        assert_eq!(groups[0].url.as_deref(), Some("https://example.com/a"));
+        assert_eq!(
+            (groups[0].survivor, groups[0].duplicates.clone()),
+            (1, vec![2])
+        );
+    }
+}
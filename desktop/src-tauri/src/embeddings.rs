@@ -0,0 +1,688 @@
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+
+use crate::ai;
+use crate::privacy::PrivacyFilter;
+use crate::storage::TabRecord;
+use crate::AppState;
+
+/// Average cosine similarity at which two clusters are merged
+const DEFAULT_CLUSTER_THRESHOLD: f32 = 0.5;
+const DEFAULT_SIMILAR_LIMIT: usize = 10;
+const MAX_LABEL_TERMS: usize = 3;
+
+/// Words too common in page titles to name a topic
+const STOPWORDS: &[&str] = &[
+    "the", "and", "for", "with", "from", "that", "this", "your", "you", "are", "how", "what",
+    "why", "when", "into", "about", "new", "all", "not", "but", "can", "has", "have", "was", "its",
+    "our", "use", "using", "com", "www", "http", "https", "html", "page", "home",
+];
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SimilarTab {
+    pub tab: TabRecord,
+    /// Cosine similarity in [-1, 1]
+    pub similarity: f32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TabCluster {
+    /// Topic label built from the members' titles
+    pub label: String,
+    pub tab_ids: Vec<i64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Clustering {
+    /// Largest first
+    pub clusters: Vec<TabCluster>,
+    /// Tabs not similar enough to any other tab, or without a cached vector
+    pub unclustered: Vec<i64>,
+}
+
+/// Cache key for an embedding: the same text embedded by another model is a different vector
+pub fn content_hash(model: &str, text: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(model.as_bytes());
+    hasher.update([0]);
+    hasher.update(text.as_bytes());
+    hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Unit-length vectors for `tabs`, keyed by tab id. With `embed_missing`, content without a
+/// cached vector is sent to the API; otherwise those tabs get no vector.
+/// Returns the vectors, the hashes in use and how many were embedded.
+async fn vectors_for(
+    state: &AppState,
+    tabs: &[TabRecord],
+    embed_missing: bool,
+) -> Result<(HashMap<i64, Vec<f32>>, HashSet<String>, usize), String> {
+    let (settings, db) = {
+        let storage = state.read().await;
+        (storage.settings.clone(), storage.db())
+    };
+    let model = ai::get_embedding_model(&settings)?;
+
+    // Tabs on the privacy blocklist get no vector
+    let entries: Vec<(i64, String, String)> = PrivacyFilter::new(&settings)
+        .shareable(tabs)
+        .iter()
+        .map(|tab| (tab.id, ai::embedding_text(tab)))
+        .filter(|(_, text)| !text.trim().is_empty())
+        .map(|(id, text)| (id, content_hash(&model, &text), text))
+        .collect();
+    let hashes: Vec<String> = entries.iter().map(|(_, hash, _)| hash.clone()).collect();
+
+    let cached_db = db.clone();
+    let lookup = hashes.clone();
+    let mut cached = tokio::task::spawn_blocking(move || cached_db.load_embeddings(&lookup))
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| e.to_string())?;
+
+    // Unchanged pages are never re-embedded; identical content is embedded once
+    let mut missing: Vec<(String, String)> = Vec::new();
+    if embed_missing {
+        let mut seen = HashSet::new();
+        for (_, hash, text) in &entries {
+            if !cached.contains_key(hash) && seen.insert(hash.clone()) {
+                missing.push((hash.clone(), text.clone()));
+            }
+        }
+    }
+
+    let embedded = missing.len();
+    if embedded > 0 {
+        println!(
+            "[Embeddings] {} cached, {} to embed",
+            cached.len(),
+            embedded
+        );
+    }
+
+    for batch in missing.chunks(ai::EMBEDDING_BATCH_SIZE) {
+        let texts: Vec<String> = batch.iter().map(|(_, text)| text.clone()).collect();
+        let vectors = ai::embed_texts(&settings, &texts).await?;
+        let rows: Vec<(String, Vec<f32>)> = batch
+            .iter()
+            .map(|(hash, _)| hash.clone())
+            .zip(vectors)
+            .collect();
+
+        // Save each batch as it arrives so a later failure keeps the earlier work
+        let save_db = db.clone();
+        let save_model = model.clone();
+        let save_rows = rows.clone();
+        tokio::task::spawn_blocking(move || save_db.save_embeddings(&save_model, &save_rows))
+            .await
+            .map_err(|e| e.to_string())?
+            .map_err(|e| e.to_string())?;
+        cached.extend(rows);
+    }
+
+    let vectors = entries
+        .iter()
+        .filter_map(|(id, hash, _)| cached.get(hash).map(|v| (*id, normalized(v))))
+        .collect();
+    Ok((vectors, hashes.into_iter().collect(), embedded))
+}
+
+/// Embed every tab in storage that is missing a vector and drop vectors no tab uses anymore.
+/// Returns how many texts were sent to the API.
+pub async fn refresh_embeddings(state: &AppState) -> Result<usize, String> {
+    let tabs: Vec<TabRecord> = state.read().await.tabs.values().cloned().collect();
+    let (_, hashes, embedded) = vectors_for(state, &tabs, true).await?;
+
+    let db = state.read().await.db();
+    let pruned = tokio::task::spawn_blocking(move || db.prune_embeddings(&hashes))
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| e.to_string())?;
+    if pruned > 0 {
+        println!("[Embeddings] Pruned {} unused vectors", pruned);
+    }
+    Ok(embedded)
+}
+
+/// Tabs (open or closed) whose content is closest to the given tab
+pub async fn find_similar(
+    state: &AppState,
+    tab_id: i64,
+    limit: Option<usize>,
+) -> Result<Vec<SimilarTab>, String> {
+    let tabs: Vec<TabRecord> = state.read().await.tabs.values().cloned().collect();
+    if !tabs.iter().any(|t| t.id == tab_id) {
+        return Err(format!("Tab {} not found", tab_id));
+    }
+
+    let (vectors, _, _) = vectors_for(state, &tabs, true).await?;
+    let target = vectors
+        .get(&tab_id)
+        .ok_or_else(|| format!("Tab {} has no content to compare", tab_id))?;
+
+    Ok(rank_by_similarity(
+        target,
+        tabs.into_iter().filter(|t| t.id != tab_id),
+        &vectors,
+        limit.unwrap_or(DEFAULT_SIMILAR_LIMIT),
+    ))
+}
+
+/// Tabs (open or closed) whose content is closest in meaning to a free-text query
+pub async fn semantic_search(
+    state: &AppState,
+    query: &str,
+    limit: Option<usize>,
+) -> Result<Vec<SimilarTab>, String> {
+    let query = query.trim();
+    if query.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let tabs: Vec<TabRecord> = state.read().await.tabs.values().cloned().collect();
+    let (vectors, _, _) = vectors_for(state, &tabs, true).await?;
+
+    let settings = state.read().await.settings.clone();
+    let query_vector = ai::embed_texts(&settings, &[query.to_string()])
+        .await?
+        .into_iter()
+        .next()
+        .map(|v| normalized(&v))
+        .ok_or_else(|| "No embedding returned for query".to_string())?;
+
+    Ok(rank_by_similarity(
+        &query_vector,
+        tabs.into_iter(),
+        &vectors,
+        limit.unwrap_or(DEFAULT_SIMILAR_LIMIT),
+    ))
+}
+
+/// Group open tabs into topics. Runs locally over cached vectors (only tabs without one
+/// are embedded first); `threshold` is the average cosine similarity needed to merge.
+pub async fn cluster_open_tabs(
+    state: &AppState,
+    threshold: Option<f32>,
+) -> Result<Clustering, String> {
+    let tabs: Vec<TabRecord> = state.read().await.get_open_tabs();
+    // Runs locally: tabs not embedded yet (see `refresh_embeddings`) are left unclustered
+    let (vectors, _, _) = vectors_for(state, &tabs, false).await?;
+    let threshold = threshold.unwrap_or(DEFAULT_CLUSTER_THRESHOLD);
+
+    let mut members: Vec<&TabRecord> = tabs
+        .iter()
+        .filter(|t| vectors.contains_key(&t.id))
+        .collect();
+    members.sort_by_key(|t| t.id);
+    let points: Vec<Vec<f32>> = members.iter().map(|t| vectors[&t.id].clone()).collect();
+
+    let groups = tokio::task::spawn_blocking(move || average_linkage(&points, threshold))
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let mut clusters = Vec::new();
+    let mut unclustered: Vec<i64> = tabs
+        .iter()
+        .filter(|t| !vectors.contains_key(&t.id))
+        .map(|t| t.id)
+        .collect();
+
+    for group in groups {
+        let group_tabs: Vec<&TabRecord> = group.iter().map(|&i| members[i]).collect();
+        if group_tabs.len() < 2 {
+            unclustered.extend(group_tabs.iter().map(|t| t.id));
+            continue;
+        }
+        clusters.push(TabCluster {
+            label: cluster_label(&group_tabs, &members),
+            tab_ids: group_tabs.iter().map(|t| t.id).collect(),
+        });
+    }
+
+    clusters.sort_by(|a, b| {
+        b.tab_ids
+            .len()
+            .cmp(&a.tab_ids.len())
+            .then(a.tab_ids.cmp(&b.tab_ids))
+    });
+    unclustered.sort_unstable();
+
+    Ok(Clustering {
+        clusters,
+        unclustered,
+    })
+}
+
+fn rank_by_similarity(
+    target: &[f32],
+    tabs: impl Iterator<Item = TabRecord>,
+    vectors: &HashMap<i64, Vec<f32>>,
+    limit: usize,
+) -> Vec<SimilarTab> {
+    let mut ranked: Vec<SimilarTab> = tabs
+        .filter_map(|tab| {
+            let similarity = dot(target, vectors.get(&tab.id)?);
+            Some(SimilarTab { tab, similarity })
+        })
+        .collect();
+    ranked.sort_by(|a, b| b.similarity.total_cmp(&a.similarity));
+    ranked.truncate(limit);
+    ranked
+}
+
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+/// Scale to unit length so the dot product is the cosine similarity
+fn normalized(v: &[f32]) -> Vec<f32> {
+    let norm = dot(v, v).sqrt();
+    if norm == 0.0 {
+        v.to_vec()
+    } else {
+        v.iter().map(|x| x / norm).collect()
+    }
+}
+
+/// Agglomerative clustering with average linkage over unit vectors.
+/// Repeatedly merges the two most similar clusters until none are at least `threshold` alike.
+fn average_linkage(points: &[Vec<f32>], threshold: f32) -> Vec<Vec<usize>> {
+    let n = points.len();
+    let mut sim = vec![vec![0.0f32; n]; n];
+    for i in 0..n {
+        for j in (i + 1)..n {
+            let s = dot(&points[i], &points[j]);
+            sim[i][j] = s;
+            sim[j][i] = s;
+        }
+    }
+
+    let mut clusters: Vec<Option<Vec<usize>>> = (0..n).map(|i| Some(vec![i])).collect();
+    // Each cluster's most similar other cluster, so finding the next merge scans n rows
+    // rather than every pair. Ties go to the lower index, as a scan over all pairs would.
+    let mut nearest: Vec<Option<(usize, f32)>> = (0..n)
+        .map(|i| nearest_cluster(&sim, &clusters, i))
+        .collect();
+    loop {
+        let mut best: Option<(usize, usize, f32)> = None;
+        for (i, candidate) in nearest.iter().enumerate() {
+            if let Some((j, s)) = *candidate {
+                if clusters[i].is_some() && best.is_none_or(|(_, _, b)| s > b) {
+                    best = Some((i, j, s));
+                }
+            }
+        }
+
+        let Some((i, j, s)) = best else { break };
+        if s < threshold {
+            break;
+        }
+        let (i, j) = (i.min(j), i.max(j));
+
+        // Lance-Williams update: similarity to the merged cluster is the size-weighted average
+        let merged = clusters[j].take().unwrap_or_default();
+        let size_i = clusters[i].as_ref().map_or(0, |c| c.len()) as f32;
+        let size_j = merged.len() as f32;
+        for k in 0..n {
+            if k != i && clusters[k].is_some() {
+                let s = (size_i * sim[i][k] + size_j * sim[j][k]) / (size_i + size_j);
+                sim[i][k] = s;
+                sim[k][i] = s;
+            }
+        }
+        if let Some(cluster) = clusters[i].as_mut() {
+            cluster.extend(merged);
+        }
+
+        nearest[j] = None;
+        nearest[i] = nearest_cluster(&sim, &clusters, i);
+        for k in 0..n {
+            if k == i || clusters[k].is_none() {
+                continue;
+            }
+            match nearest[k] {
+                Some((m, _)) if m == i || m == j => {
+                    nearest[k] = nearest_cluster(&sim, &clusters, k);
+                }
+                Some((m, s)) if sim[k][i] > s || (sim[k][i] == s && i < m) => {
+                    nearest[k] = Some((i, sim[k][i]));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    clusters.into_iter().flatten().collect()
+}
+
+/// The live cluster most similar to cluster `i`, lowest index first on ties
+fn nearest_cluster(
+    sim: &[Vec<f32>],
+    clusters: &[Option<Vec<usize>>],
+    i: usize,
+) -> Option<(usize, f32)> {
+    let mut nearest: Option<(usize, f32)> = None;
+    for (k, cluster) in clusters.iter().enumerate() {
+        if k != i && cluster.is_some() && nearest.is_none_or(|(_, s)| sim[i][k] > s) {
+            nearest = Some((k, sim[i][k]));
+        }
+    }
+    nearest
+}
+
+fn title_terms(tab: &TabRecord) -> HashSet<String> {
+    tab.title
+        .as_deref()
+        .unwrap_or("")
+        .split(|c: char| !c.is_alphanumeric())
+        .map(|w| w.to_lowercase())
+        .filter(|w| w.chars().count() >= 3 && !w.chars().all(|c| c.is_numeric()))
+        .filter(|w| !STOPWORDS.contains(&w.as_str()))
+        .collect()
+}
+
+/// Name a cluster by the title words most specific to it (frequent in the cluster,
+/// rare across all tabs), falling back to its most common category or domain
+fn cluster_label(cluster: &[&TabRecord], all: &[&TabRecord]) -> String {
+    let mut global_df: HashMap<String, usize> = HashMap::new();
+    for tab in all {
+        for term in title_terms(tab) {
+            *global_df.entry(term).or_default() += 1;
+        }
+    }
+    let mut cluster_df: HashMap<String, usize> = HashMap::new();
+    for tab in cluster {
+        for term in title_terms(tab) {
+            *cluster_df.entry(term).or_default() += 1;
+        }
+    }
+
+    let total = all.len().max(1) as f32;
+    let mut scored: Vec<(String, f32)> = cluster_df
+        .into_iter()
+        .filter(|(_, count)| *count >= 2)
+        .map(|(term, count)| {
+            let df = global_df.get(&term).copied().unwrap_or(1) as f32;
+            let score = count as f32 * (1.0 + total / df).ln();
+            (term, score)
+        })
+        .collect();
+    scored.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+    if !scored.is_empty() {
+        return scored
+            .into_iter()
+            .take(MAX_LABEL_TERMS)
+            .map(|(term, _)| term)
+            .collect::<Vec<_>>()
+            .join(", ");
+    }
+
+    let most_common = |values: Vec<String>| {
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        for v in values {
+            *counts.entry(v).or_default() += 1;
+        }
+        counts
+            .into_iter()
+            .max_by(|a, b| a.1.cmp(&b.1).then_with(|| b.0.cmp(&a.0)))
+            .map(|(v, _)| v)
+    };
+
+    let categories = cluster
+        .iter()
+        .filter_map(|t| t.suggestion.as_ref().and_then(|s| s.category.clone()))
+        .collect();
+    let domains = cluster
+        .iter()
+        .filter_map(|t| t.url.as_deref().and_then(crate::search::url_domain))
+        .collect();
+    most_common(categories)
+        .or_else(|| most_common(domains))
+        .unwrap_or_else(|| "Related tabs".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{Settings, Storage};
+    use crate::test_support;
+    use std::sync::Arc;
+    use tokio::sync::RwLock;
+
+    fn state(tabs: Vec<TabRecord>) -> AppState {
+        let mut storage = Storage::in_memory();
+        storage.settings = Settings {
+            embedding_provider: Some("mock".to_string()),
+            ..Settings::default()
+        };
+        storage.tabs = tabs.into_iter().map(|t| (t.id, t)).collect();
+        Arc::new(RwLock::new(storage))
+    }
+
+    fn topics() -> Vec<TabRecord> {
+        vec![
+            test_support::tab(1, "https://example.com/1")
+                .title("Rust async runtime tokio")
+                .build(),
+            test_support::tab(2, "https://example.com/2")
+                .title("Tokio async tutorial rust")
+                .build(),
+            test_support::tab(3, "https://example.com/3")
+                .title("Banana bread recipe")
+                .build(),
+            test_support::tab(4, "https://example.com/4")
+                .title("Easy banana bread recipe")
+                .build(),
+            test_support::tab(5, "https://example.com/5")
+                .title("Banana bread recipe")
+                .build(),
+        ]
+    }
+
+    fn ids(tabs: &[SimilarTab]) -> Vec<i64> {
+        tabs.iter().map(|t| t.tab.id).collect()
+    }
+
+    /// Unit vector at `degrees` in the plane
+    fn angle(degrees: f32) -> Vec<f32> {
+        let radians = degrees.to_radians();
+        vec![radians.cos(), radians.sin()]
+    }
+
+    /// Merge the most similar pair over all pairs each time, recomputing the averages
+    fn brute_force_linkage(points: &[Vec<f32>], threshold: f32) -> Vec<Vec<usize>> {
+        let mut clusters: Vec<Vec<usize>> = (0..points.len()).map(|i| vec![i]).collect();
+        let linkage = |a: &[usize], b: &[usize]| {
+            let total: f32 = a
+                .iter()
+                .flat_map(|&i| b.iter().map(move |&j| dot(&points[i], &points[j])))
+                .sum();
+            total / (a.len() * b.len()) as f32
+        };
+        loop {
+            let mut best: Option<(usize, usize, f32)> = None;
+            for i in 0..clusters.len() {
+                for j in (i + 1)..clusters.len() {
+                    let s = linkage(&clusters[i], &clusters[j]);
+                    if best.is_none_or(|(_, _, b)| s > b) {
+                        best = Some((i, j, s));
+                    }
+                }
+            }
+            match best {
+                Some((i, j, s)) if s >= threshold => {
+                    let merged = clusters.remove(j);
+                    clusters[i].extend(merged);
+                }
+                _ => break,
+            }
+        }
+        clusters
+    }
+
+    fn sorted(mut clusters: Vec<Vec<usize>>) -> Vec<Vec<usize>> {
+        for cluster in &mut clusters {
+            cluster.sort_unstable();
+        }
+        clusters.sort();
+        clusters
+    }
+
+    #[test]
+    fn content_hash_depends_on_model_and_text() {
+        let hash = content_hash("openai/text-embedding-3-small", "Rust book");
+        assert_eq!(hash.len(), 64);
+        assert_eq!(
+            hash,
+            content_hash("openai/text-embedding-3-small", "Rust book")
+        );
+        assert_ne!(hash, content_hash("ollama/nomic-embed-text", "Rust book"));
+        assert_ne!(
+            hash,
+            content_hash("openai/text-embedding-3-small", "Rust books")
+        );
+    }
+
+    #[tokio::test]
+    async fn embeds_only_content_without_a_cached_vector() {
+        let state = state(topics());
+
+        // Tabs 3 and 5 share their content, so it is embedded once
+        assert_eq!(refresh_embeddings(&state).await.unwrap(), 4);
+        assert_eq!(refresh_embeddings(&state).await.unwrap(), 0);
+
+        if let Some(tab) = state.write().await.tabs.get_mut(&1) {
+            tab.title = Some("Rust async book".to_string());
+        }
+        assert_eq!(refresh_embeddings(&state).await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn finds_tabs_about_the_same_topic() {
+        let state = state(topics());
+
+        let similar = find_similar(&state, 1, Some(2)).await.unwrap();
+        assert_eq!(similar[0].tab.id, 2);
+        assert_eq!(similar.len(), 2);
+        assert!(!ids(&similar).contains(&1));
+        assert!(similar[0].similarity > similar[1].similarity);
+
+        assert!(find_similar(&state, 99, None).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn searches_by_meaning() {
+        let state = state(topics());
+
+        let results = semantic_search(&state, "banana bread", Some(3))
+            .await
+            .unwrap();
+        let mut top = ids(&results);
+        top.sort_unstable();
+        assert_eq!(top, vec![3, 4, 5]);
+
+        assert!(semantic_search(&state, "   ", None)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn clusters_embedded_open_tabs_by_topic() {
+        let mut tabs = topics();
+        tabs.push(
+            test_support::tab(6, "https://example.com/6")
+                .title("Quarterly tax filing")
+                .build(),
+        );
+        let state = state(tabs);
+        refresh_embeddings(&state).await.unwrap();
+        state.write().await.tabs.insert(
+            7,
+            test_support::tab(7, "https://example.com/7")
+                .title("Not embedded yet")
+                .build(),
+        );
+
+        let clustering = cluster_open_tabs(&state, None).await.unwrap();
+
+        let clusters: Vec<(&str, &[i64])> = clustering
+            .clusters
+            .iter()
+            .map(|c| (c.label.as_str(), c.tab_ids.as_slice()))
+            .collect();
+        assert_eq!(
+            clusters,
+            vec![
+                // In merge order: the identical pages first
+                ("banana, bread, recipe", &[3, 5, 4][..]),
+                ("async, rust, tokio", &[1, 2][..]),
+            ]
+        );
+        assert_eq!(clustering.unclustered, vec![6, 7]);
+    }
+
+    #[test]
+    fn average_linkage_does_not_chain_through_neighbours() {
+        // 0-40 and 40-80 degrees are alike, but 0 and 80 are not; single linkage would
+        // join all three
+        let points = vec![angle(0.0), angle(40.0), angle(80.0)];
+        assert_eq!(
+            sorted(average_linkage(&points, 0.5)),
+            vec![vec![0, 1], vec![2]]
+        );
+        assert_eq!(average_linkage(&[], 0.5), Vec::<Vec<usize>>::new());
+    }
+
+    #[test]
+    fn average_linkage_matches_merging_over_all_pairs() {
+        // Spread over a half circle with repeats, so merges tie and clusters grow unevenly
+        let points: Vec<Vec<f32>> = (0..40)
+            .map(|i| angle(((i * 37) % 180) as f32 / 2.0 + (i % 3 * 20) as f32))
+            .collect();
+
+        for threshold in [0.3, 0.6, 0.9, 0.99] {
+            assert_eq!(
+                sorted(average_linkage(&points, threshold)),
+                sorted(brute_force_linkage(&points, threshold)),
+                "threshold {}",
+                threshold
+            );
+        }
+    }
+
+    #[test]
+    fn labels_fall_back_to_category_then_domain() {
+        let a = test_support::tab(1, "https://docs.rs/")
+            .title("Tokio")
+            .category("Research")
+            .build();
+        let b = test_support::tab(2, "https://docs.rs/")
+            .title("Serde")
+            .category("Research")
+            .build();
+        let c = test_support::tab(3, "https://example.com/3")
+            .title("Something else")
+            .build();
+        let all = [&a, &b, &c];
+
+        assert_eq!(cluster_label(&[&a, &b], &all), "Research");
+
+        let d = test_support::tab(4, "https://docs.rs/a")
+            .title("Tokio")
+            .build();
+        let e = test_support::tab(5, "https://docs.rs/b")
+            .title("Serde")
+            .build();
+        assert_eq!(cluster_label(&[&d, &e], &[&d, &e]), "docs.rs");
+
+        let untitled = test_support::tab(6, "").title("").build();
+        assert_eq!(cluster_label(&[&untitled], &[&untitled]), "Related tabs");
+    }
+}
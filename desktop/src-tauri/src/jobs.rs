@@ -0,0 +1,219 @@
+//! Registry of running AI jobs, so the UI can follow their progress and cancel them.
+
+use serde::Serialize;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+use tauri::{AppHandle, Emitter};
+use tokio_util::sync::CancellationToken;
+
+/// Error returned by a job that was cancelled
+pub const CANCELLED: &str = "Cancelled";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobKind {
+    Analyze,
+    Report,
+}
+
+/// A running job as shown to the UI; `done` of `total` batches have finished
+#[derive(Debug, Clone, Serialize)]
+pub struct JobInfo {
+    pub id: u64,
+    pub kind: JobKind,
+    pub done: usize,
+    pub total: usize,
+    pub started_at: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct JobFinished {
+    id: u64,
+    kind: JobKind,
+    cancelled: bool,
+}
+
+struct Entry {
+    info: JobInfo,
+    token: CancellationToken,
+}
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+static JOBS: OnceLock<Mutex<HashMap<u64, Entry>>> = OnceLock::new();
+static EVENTS: OnceLock<AppHandle> = OnceLock::new();
+
+fn jobs() -> std::sync::MutexGuard<'static, HashMap<u64, Entry>> {
+    JOBS.get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+}
+
+/// Send `job-started`, `job-progress` and `job-finished` events to the app from now on
+pub fn install(app_handle: AppHandle) {
+    let _ = EVENTS.set(app_handle);
+}
+
+fn emit<S: Serialize + Clone>(event: &str, payload: S) {
+    if let Some(app_handle) = EVENTS.get() {
+        let _ = app_handle.emit(event, payload);
+    }
+}
+
+/// Register a new job. It stays listed until the returned handle is dropped.
+pub fn start(kind: JobKind) -> Job {
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    let token = CancellationToken::new();
+    let info = JobInfo {
+        id,
+        kind,
+        done: 0,
+        total: 0,
+        started_at: chrono::Utc::now().timestamp_millis(),
+    };
+    println!("[Jobs] Started {:?} job {}", kind, id);
+    jobs().insert(
+        id,
+        Entry {
+            info: info.clone(),
+            token: token.clone(),
+        },
+    );
+    emit("job-started", info);
+    Job { id, kind, token }
+}
+
+/// Cancel a running job; returns false if no job has this id
+pub fn cancel(id: u64) -> bool {
+    match jobs().get(&id) {
+        Some(entry) => {
+            println!("[Jobs] Cancelling job {}", id);
+            entry.token.cancel();
+            true
+        }
+        None => false,
+    }
+}
+
+/// Running jobs, oldest first
+pub fn list() -> Vec<JobInfo> {
+    let mut running: Vec<JobInfo> = jobs().values().map(|e| e.info.clone()).collect();
+    running.sort_by_key(|info| info.id);
+    running
+}
+
+/// Handle held by the code doing the work
+pub struct Job {
+    id: u64,
+    kind: JobKind,
+    token: CancellationToken,
+}
+
+impl Job {
+    pub fn is_cancelled(&self) -> bool {
+        self.token.is_cancelled()
+    }
+
+    /// `Err(CANCELLED)` once the job was cancelled; call right before writing results
+    pub fn check(&self) -> Result<(), String> {
+        if self.is_cancelled() {
+            Err(CANCELLED.to_string())
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Drive `work` until it finishes or the job is cancelled. Cancelling drops `work`,
+    /// which aborts any HTTP request it has in flight.
+    pub async fn run<F: Future>(&self, work: F) -> Result<F::Output, String> {
+        tokio::select! {
+            _ = self.token.cancelled() => Err(CANCELLED.to_string()),
+            output = work => Ok(output),
+        }
+    }
+
+    pub fn progress(&self, done: usize, total: usize) {
+        let info = {
+            let mut jobs = jobs();
+            let Some(entry) = jobs.get_mut(&self.id) else {
+                return;
+            };
+            entry.info.done = done;
+            entry.info.total = total;
+            entry.info.clone()
+        };
+        emit("job-progress", info);
+    }
+}
+
+impl Drop for Job {
+    fn drop(&mut self) {
+        jobs().remove(&self.id);
+        let cancelled = self.is_cancelled();
+        println!(
+            "[Jobs] {:?} job {} {}",
+            self.kind,
+            self.id,
+            if cancelled { "cancelled" } else { "finished" }
+        );
+        emit(
+            "job-finished",
+            JobFinished {
+                id: self.id,
+                kind: self.kind,
+                cancelled,
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn listed(id: u64) -> Option<JobInfo> {
+        list().into_iter().find(|info| info.id == id)
+    }
+
+    #[tokio::test]
+    async fn cancel_stops_running_work() {
+        let job = start(JobKind::Analyze);
+        let id = job.id;
+
+        let work = job.run(async {
+            tokio::time::sleep(Duration::from_secs(30)).await;
+            "finished"
+        });
+        let canceller = async {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            assert!(cancel(id));
+        };
+        let (result, ()) = tokio::join!(work, canceller);
+
+        assert_eq!(result.unwrap_err(), CANCELLED);
+        assert!(job.check().is_err());
+    }
+
+    #[tokio::test]
+    async fn run_returns_output_of_uncancelled_work() {
+        let job = start(JobKind::Report);
+        assert_eq!(job.run(async { 7 }).await.unwrap(), 7);
+        assert!(job.check().is_ok());
+    }
+
+    #[test]
+    fn jobs_are_listed_with_progress_until_dropped() {
+        let job = start(JobKind::Analyze);
+        let id = job.id;
+
+        job.progress(2, 5);
+        let info = listed(id).unwrap();
+        assert_eq!((info.done, info.total, info.kind), (2, 5, JobKind::Analyze));
+
+        drop(job);
+        assert!(listed(id).is_none());
+        assert!(!cancel(id));
+    }
+}
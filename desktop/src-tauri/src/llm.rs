@@ -0,0 +1,1597 @@
+use async_trait::async_trait;
+use reqwest::{Client, StatusCode};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::time::Duration;
+use tokio::time::sleep;
+
+use crate::storage::Settings;
+
+pub const DEFAULT_OPENAI_BASE_URL: &str = "https://api.openai.com/v1";
+pub const DEFAULT_ANTHROPIC_BASE_URL: &str = "https://api.anthropic.com/v1";
+pub const DEFAULT_OLLAMA_BASE_URL: &str = "http://localhost:11434";
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+/// Anthropic requires `max_tokens`; used when the caller does not set one
+const DEFAULT_MAX_TOKENS: u32 = 4096;
+/// Dimensions of the mock provider's embeddings
+const MOCK_EMBEDDING_DIMS: usize = 64;
+
+/// The AI tasks that can each use their own provider and model
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Task {
+    Suggestions,
+    Report,
+    Embeddings,
+}
+
+impl Task {
+    /// Name recorded in the usage ledger
+    pub fn purpose(self) -> &'static str {
+        match self {
+            Task::Suggestions => "suggest",
+            Task::Report => "report",
+            Task::Embeddings => "embed",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProviderKind {
+    OpenAi,
+    Anthropic,
+    Ollama,
+    Mock,
+}
+
+impl ProviderKind {
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name.trim().to_lowercase().as_str() {
+            "" | "openai" => Ok(ProviderKind::OpenAi),
+            "anthropic" => Ok(ProviderKind::Anthropic),
+            "ollama" => Ok(ProviderKind::Ollama),
+            "mock" => Ok(ProviderKind::Mock),
+            other => Err(format!(
+                "Unknown provider \"{}\" (expected openai, anthropic, ollama or mock)",
+                other
+            )),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ProviderKind::OpenAi => "openai",
+            ProviderKind::Anthropic => "anthropic",
+            ProviderKind::Ollama => "ollama",
+            ProviderKind::Mock => "mock",
+        }
+    }
+
+    fn default_model(self, task: Task) -> Option<&'static str> {
+        match (self, task) {
+            (ProviderKind::OpenAi, Task::Embeddings) => Some("text-embedding-3-small"),
+            (ProviderKind::OpenAi, _) => Some("gpt-4o-mini"),
+            (ProviderKind::Anthropic, Task::Embeddings) => None,
+            (ProviderKind::Anthropic, _) => Some("claude-3-5-haiku-latest"),
+            (ProviderKind::Ollama, Task::Embeddings) => Some("nomic-embed-text"),
+            (ProviderKind::Ollama, _) => Some("llama3.2"),
+            (ProviderKind::Mock, _) => Some("mock"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    System,
+    User,
+    Assistant,
+}
+
+impl Role {
+    fn as_str(self) -> &'static str {
+        match self {
+            Role::System => "system",
+            Role::User => "user",
+            Role::Assistant => "assistant",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum ContentPart {
+    Text(String),
+    /// Base64-encoded image
+    Image {
+        media_type: String,
+        data: String,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct Message {
+    pub role: Role,
+    pub content: Vec<ContentPart>,
+}
+
+impl Message {
+    pub fn system(text: impl Into<String>) -> Self {
+        Self {
+            role: Role::System,
+            content: vec![ContentPart::Text(text.into())],
+        }
+    }
+
+    pub fn user(text: impl Into<String>) -> Self {
+        Self {
+            role: Role::User,
+            content: vec![ContentPart::Text(text.into())],
+        }
+    }
+
+    pub fn assistant(text: impl Into<String>) -> Self {
+        Self {
+            role: Role::Assistant,
+            content: vec![ContentPart::Text(text.into())],
+        }
+    }
+
+    pub fn user_parts(content: Vec<ContentPart>) -> Self {
+        Self {
+            role: Role::User,
+            content,
+        }
+    }
+
+    /// All text parts joined, ignoring images
+    pub fn text(&self) -> String {
+        self.content
+            .iter()
+            .filter_map(|part| match part {
+                ContentPart::Text(text) => Some(text.as_str()),
+                ContentPart::Image { .. } => None,
+            })
+            .collect::<Vec<_>>()
+            .join("")
+    }
+
+    pub fn image_count(&self) -> usize {
+        self.content
+            .iter()
+            .filter(|part| matches!(part, ContentPart::Image { .. }))
+            .count()
+    }
+
+    /// The message as a dry run shows it: images are described by type and size
+    pub fn preview(&self) -> serde_json::Value {
+        let content: Vec<serde_json::Value> = self
+            .content
+            .iter()
+            .map(|part| match part {
+                ContentPart::Text(text) => serde_json::json!({ "type": "text", "text": text }),
+                ContentPart::Image { media_type, data } => serde_json::json!({
+                    "type": "image",
+                    "media_type": media_type,
+                    "bytes": data.len() / 4 * 3,
+                }),
+            })
+            .collect();
+        serde_json::json!({ "role": self.role.as_str(), "content": content })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CompletionOptions {
+    pub model: String,
+    pub temperature: f32,
+    pub max_tokens: Option<u32>,
+    /// Constrain the reply to this JSON schema where the provider supports it
+    /// (OpenAI `response_format`, Ollama `format`). Others rely on the prompt.
+    pub response_schema: Option<ResponseSchema>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ResponseSchema {
+    pub name: &'static str,
+    /// JSON schema; OpenAI strict mode needs an object root with every property required
+    pub schema: serde_json::Value,
+}
+
+/// Token counts a provider reported for one call, and how many times it was retried
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Usage {
+    /// `None` when the response did not include it
+    pub prompt_tokens: Option<u64>,
+    pub completion_tokens: Option<u64>,
+    pub retries: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct Completion {
+    pub text: String,
+    pub usage: Usage,
+}
+
+#[derive(Debug, Clone)]
+pub struct EmbeddingBatch {
+    /// One vector per input, in input order
+    pub vectors: Vec<Vec<f32>>,
+    pub usage: Usage,
+}
+
+/// Receives each piece of a streamed reply as it arrives
+pub type ChunkSink<'a> = &'a mut (dyn FnMut(&str) + Send);
+
+/// A chat-completion backend. Implementations translate `Message`s to their wire format.
+#[async_trait]
+pub trait LlmProvider: Send + Sync {
+    fn kind(&self) -> ProviderKind;
+
+    async fn complete(
+        &self,
+        messages: &[Message],
+        options: &CompletionOptions,
+    ) -> Result<Completion, String>;
+
+    /// Like `complete`, passing text to `on_chunk` as it is generated. If the stream breaks
+    /// off, the chunks already delivered are all there is. Backends without streaming
+    /// deliver the whole reply as one chunk.
+    async fn complete_streaming(
+        &self,
+        messages: &[Message],
+        options: &CompletionOptions,
+        on_chunk: ChunkSink<'_>,
+    ) -> Result<Completion, String> {
+        let completion = self.complete(messages, options).await?;
+        on_chunk(&completion.text);
+        Ok(completion)
+    }
+
+    async fn embed(&self, texts: &[String], model: &str) -> Result<EmbeddingBatch, String> {
+        let _ = (texts, model);
+        Err(format!(
+            "The {} provider does not support embeddings",
+            self.kind().name()
+        ))
+    }
+}
+
+/// Provider and model configured for a task
+pub struct TaskConfig {
+    pub provider: Box<dyn LlmProvider>,
+    pub model: String,
+}
+
+impl TaskConfig {
+    /// Identifies the provider and model, e.g. "openai/gpt-4o-mini"
+    pub fn label(&self) -> String {
+        format!("{}/{}", self.provider.kind().name(), self.model)
+    }
+}
+
+fn non_empty(value: &Option<String>) -> Option<String> {
+    value.clone().filter(|v| !v.trim().is_empty())
+}
+
+/// Resolve the provider and model for a task from settings. A task without its own model
+/// uses the global `model` when it runs on OpenAI, otherwise the provider's default.
+pub fn task_config(settings: &Settings, task: Task) -> Result<TaskConfig, String> {
+    let (provider, model) = match task {
+        Task::Suggestions => (&settings.suggestion_provider, &settings.suggestion_model),
+        Task::Report => (&settings.report_provider, &settings.report_model),
+        Task::Embeddings => (&settings.embedding_provider, &settings.embedding_model),
+    };
+    let kind = ProviderKind::parse(provider.as_deref().unwrap_or(""))?;
+
+    let model = non_empty(model)
+        .or_else(|| match (kind, task) {
+            (ProviderKind::OpenAi, Task::Suggestions | Task::Report) => non_empty(&settings.model),
+            _ => None,
+        })
+        .or_else(|| kind.default_model(task).map(str::to_string))
+        .ok_or_else(|| {
+            format!(
+                "The {} provider does not support embeddings. Choose another embedding provider.",
+                kind.name()
+            )
+        })?;
+
+    Ok(TaskConfig {
+        provider: build_provider(settings, kind)?,
+        model,
+    })
+}
+
+pub fn build_provider(
+    settings: &Settings,
+    kind: ProviderKind,
+) -> Result<Box<dyn LlmProvider>, String> {
+    Ok(match kind {
+        ProviderKind::OpenAi => Box::new(OpenAiProvider {
+            http: HttpClient::new(RetryPolicy::default())?,
+            base_url: non_empty(&settings.base_url)
+                .unwrap_or_else(|| DEFAULT_OPENAI_BASE_URL.to_string()),
+            api_key: non_empty(&settings.openai_api_key).ok_or_else(|| {
+                "Missing OpenAI API key. Please configure it in settings.".to_string()
+            })?,
+        }),
+        ProviderKind::Anthropic => Box::new(AnthropicProvider {
+            http: HttpClient::new(RetryPolicy::default())?,
+            base_url: non_empty(&settings.anthropic_base_url)
+                .unwrap_or_else(|| DEFAULT_ANTHROPIC_BASE_URL.to_string()),
+            api_key: non_empty(&settings.anthropic_api_key).ok_or_else(|| {
+                "Missing Anthropic API key. Please configure it in settings.".to_string()
+            })?,
+        }),
+        ProviderKind::Ollama => Box::new(OllamaProvider {
+            http: HttpClient::new(RetryPolicy::default())?,
+            base_url: non_empty(&settings.ollama_base_url)
+                .unwrap_or_else(|| DEFAULT_OLLAMA_BASE_URL.to_string()),
+        }),
+        ProviderKind::Mock => Box::new(MockProvider),
+    })
+}
+
+/// Timeout and retry behaviour for provider HTTP calls
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Per-attempt request timeout
+    pub timeout: Duration,
+    pub max_attempts: u32,
+    /// Delay before the first retry; doubles on each further retry
+    pub initial_delay: Duration,
+    /// Total time a streamed response may take; `timeout` then bounds each wait for data
+    pub stream_timeout: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(120),
+            max_attempts: 3,
+            initial_delay: Duration::from_millis(1000),
+            stream_timeout: Duration::from_secs(600),
+        }
+    }
+}
+
+fn is_retryable_error(status: StatusCode) -> bool {
+    matches!(status.as_u16(), 429 | 500 | 502 | 503 | 504)
+}
+
+/// HTTP client shared by the network providers
+struct HttpClient {
+    client: Client,
+    policy: RetryPolicy,
+}
+
+impl HttpClient {
+    fn new(policy: RetryPolicy) -> Result<Self, String> {
+        let client = Client::builder()
+            .timeout(policy.timeout)
+            .build()
+            .map_err(|e| format!("Failed to create HTTP client: {}", e))?;
+        Ok(Self { client, policy })
+    }
+
+    /// POST a JSON body, retrying network errors and retryable statuses with exponential
+    /// backoff. Returns the first successful response and how many retries it took.
+    async fn post_with_retry<T: Serialize + ?Sized>(
+        &self,
+        url: &str,
+        headers: &[(&str, &str)],
+        body: &T,
+    ) -> Result<(reqwest::Response, u32), String> {
+        self.send_with_retry(url, headers, body, self.policy.timeout)
+            .await
+    }
+
+    /// Like `post_with_retry`, for a response read incrementally. Retries only happen
+    /// before the body starts, so no chunk is delivered twice.
+    async fn post_stream_with_retry<T: Serialize + ?Sized>(
+        &self,
+        url: &str,
+        headers: &[(&str, &str)],
+        body: &T,
+    ) -> Result<(BodyLines, u32), String> {
+        let (response, retries) = self
+            .send_with_retry(url, headers, body, self.policy.stream_timeout)
+            .await?;
+        Ok((
+            BodyLines {
+                response,
+                buffer: Vec::new(),
+                idle_timeout: self.policy.timeout,
+                finished: false,
+            },
+            retries,
+        ))
+    }
+
+    async fn send_with_retry<T: Serialize + ?Sized>(
+        &self,
+        url: &str,
+        headers: &[(&str, &str)],
+        body: &T,
+        timeout: Duration,
+    ) -> Result<(reqwest::Response, u32), String> {
+        let max_attempts = self.policy.max_attempts.max(1);
+        let start_time = std::time::Instant::now();
+        let mut delay = self.policy.initial_delay;
+
+        for attempt in 1..=max_attempts {
+            println!(
+                "[AI] Sending request (attempt {}/{})...",
+                attempt, max_attempts
+            );
+
+            let mut request = self.client.post(url).timeout(timeout).json(body);
+            for (name, value) in headers {
+                request = request.header(*name, *value);
+            }
+            let response = request.send().await;
+
+            match response {
+                Ok(resp) => {
+                    let elapsed = start_time.elapsed();
+                    let status = resp.status();
+                    println!(
+                        "[AI] Response status: {} (took {:.2}s)",
+                        status,
+                        elapsed.as_secs_f64()
+                    );
+
+                    if status.is_success() {
+                        return Ok((resp, attempt - 1));
+                    } else if is_retryable_error(status) && attempt < max_attempts {
+                        let error_text = resp.text().await.unwrap_or_default();
+                        println!(
+                            "[AI] Retryable error ({}): {}. Retry {}/{} after {:?}",
+                            status, error_text, attempt, max_attempts, delay
+                        );
+                        sleep(delay).await;
+                        delay *= 2;
+                    } else {
+                        let error_text = resp.text().await.unwrap_or_default();
+                        println!("[AI] API error: {}", error_text);
+                        return Err(format!("API error ({}): {}", status, error_text));
+                    }
+                }
+                Err(e) => {
+                    if attempt < max_attempts {
+                        println!(
+                            "[AI] Network error: {}. Retry {}/{} after {:?}",
+                            e, attempt, max_attempts, delay
+                        );
+                        sleep(delay).await;
+                        delay *= 2;
+                    } else if e.is_timeout() {
+                        println!("[AI] Request timed out after {} attempts", max_attempts);
+                        return Err(format!(
+                            "Request timed out after {}s",
+                            timeout.as_secs_f64()
+                        ));
+                    } else {
+                        println!("[AI] Request failed after {} attempts: {}", max_attempts, e);
+                        return Err(format!("Request failed: {}", e));
+                    }
+                }
+            }
+        }
+
+        unreachable!("Retry loop should have returned")
+    }
+}
+
+/// Reads a streamed response body one line at a time
+struct BodyLines {
+    response: reqwest::Response,
+    buffer: Vec<u8>,
+    /// Longest wait for the next piece of the body
+    idle_timeout: Duration,
+    finished: bool,
+}
+
+impl BodyLines {
+    /// The next line without its line ending, or `None` at the end of the body
+    async fn next_line(&mut self) -> Result<Option<String>, String> {
+        loop {
+            if let Some(end) = self.buffer.iter().position(|&b| b == b'\n') {
+                let line: Vec<u8> = self.buffer.drain(..=end).collect();
+                let line = String::from_utf8_lossy(&line);
+                return Ok(Some(line.trim_end_matches(['\r', '\n']).to_string()));
+            }
+            if self.finished {
+                if self.buffer.is_empty() {
+                    return Ok(None);
+                }
+                let line = String::from_utf8_lossy(&self.buffer).into_owned();
+                self.buffer.clear();
+                return Ok(Some(line.trim_end_matches('\r').to_string()));
+            }
+
+            match tokio::time::timeout(self.idle_timeout, self.response.chunk()).await {
+                Ok(Ok(Some(bytes))) => self.buffer.extend_from_slice(&bytes),
+                Ok(Ok(None)) => self.finished = true,
+                Ok(Err(e)) => return Err(format!("Stream interrupted: {}", e)),
+                Err(_) => {
+                    return Err(format!(
+                        "Stream stalled: no data for {}s",
+                        self.idle_timeout.as_secs_f64()
+                    ))
+                }
+            }
+        }
+    }
+}
+
+/// Payload of a server-sent event `data:` line
+fn sse_data(line: &str) -> Option<&str> {
+    line.strip_prefix("data:").map(str::trim_start)
+}
+
+fn parse_stream_chunk(data: &str) -> Result<serde_json::Value, String> {
+    serde_json::from_str(data).map_err(|e| format!("Failed to parse stream chunk: {}", e))
+}
+
+async fn parse_json<T: for<'de> Deserialize<'de>>(resp: reqwest::Response) -> Result<T, String> {
+    resp.json().await.map_err(|e| {
+        println!("[AI] Failed to parse response: {}", e);
+        format!("Failed to parse response: {}", e)
+    })
+}
+
+// OpenAI (and compatible) chat completions
+
+pub struct OpenAiProvider {
+    http: HttpClient,
+    base_url: String,
+    api_key: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAiChoice {
+    message: OpenAiMessage,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAiMessage {
+    content: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAiUsage {
+    prompt_tokens: u64,
+    /// Absent for embeddings
+    #[serde(default)]
+    completion_tokens: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAiResponse {
+    choices: Vec<OpenAiChoice>,
+    usage: Option<OpenAiUsage>,
+}
+
+fn openai_usage(usage: Option<OpenAiUsage>, retries: u32) -> Usage {
+    Usage {
+        prompt_tokens: usage.as_ref().map(|u| u.prompt_tokens),
+        completion_tokens: usage.as_ref().map(|u| u.completion_tokens),
+        retries,
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAiEmbedding {
+    index: usize,
+    embedding: Vec<f32>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAiEmbeddingResponse {
+    data: Vec<OpenAiEmbedding>,
+    usage: Option<OpenAiUsage>,
+}
+
+fn openai_message(message: &Message) -> serde_json::Value {
+    // Plain strings for text-only messages keep requests readable for compatible servers
+    if message.image_count() == 0 {
+        return json!({ "role": message.role.as_str(), "content": message.text() });
+    }
+    let parts: Vec<serde_json::Value> = message
+        .content
+        .iter()
+        .map(|part| match part {
+            ContentPart::Text(text) => json!({ "type": "text", "text": text }),
+            ContentPart::Image { media_type, data } => json!({
+                "type": "image_url",
+                "image_url": {
+                    "url": format!("data:{};base64,{}", media_type, data),
+                    "detail": "low"
+                }
+            }),
+        })
+        .collect();
+    json!({ "role": message.role.as_str(), "content": parts })
+}
+
+fn openai_chat_body(messages: &[Message], options: &CompletionOptions) -> serde_json::Value {
+    let mut body = json!({
+        "model": options.model,
+        "messages": messages.iter().map(openai_message).collect::<Vec<_>>(),
+        "temperature": options.temperature,
+    });
+    if let Some(max_tokens) = options.max_tokens {
+        body["max_tokens"] = json!(max_tokens);
+    }
+    if let Some(schema) = &options.response_schema {
+        body["response_format"] = json!({
+            "type": "json_schema",
+            "json_schema": { "name": schema.name, "strict": true, "schema": schema.schema }
+        });
+    }
+    body
+}
+
+#[async_trait]
+impl LlmProvider for OpenAiProvider {
+    fn kind(&self) -> ProviderKind {
+        ProviderKind::OpenAi
+    }
+
+    async fn complete(
+        &self,
+        messages: &[Message],
+        options: &CompletionOptions,
+    ) -> Result<Completion, String> {
+        let body = openai_chat_body(messages, options);
+        let auth = format!("Bearer {}", self.api_key);
+        let (resp, retries) = self
+            .http
+            .post_with_retry(
+                &format!("{}/chat/completions", self.base_url),
+                &[("Authorization", &auth)],
+                &body,
+            )
+            .await?;
+
+        let response: OpenAiResponse = parse_json(resp).await?;
+        let usage = openai_usage(response.usage, retries);
+        response
+            .choices
+            .into_iter()
+            .next()
+            .map(|c| Completion {
+                text: c.message.content,
+                usage,
+            })
+            .ok_or_else(|| "No response from API".to_string())
+    }
+
+    async fn complete_streaming(
+        &self,
+        messages: &[Message],
+        options: &CompletionOptions,
+        on_chunk: ChunkSink<'_>,
+    ) -> Result<Completion, String> {
+        let mut body = openai_chat_body(messages, options);
+        body["stream"] = json!(true);
+        // The last event then carries token counts for the usage ledger
+        body["stream_options"] = json!({ "include_usage": true });
+
+        let auth = format!("Bearer {}", self.api_key);
+        let (mut lines, retries) = self
+            .http
+            .post_stream_with_retry(
+                &format!("{}/chat/completions", self.base_url),
+                &[("Authorization", &auth)],
+                &body,
+            )
+            .await?;
+
+        let mut text = String::new();
+        let mut usage = None;
+        while let Some(line) = lines.next_line().await? {
+            let Some(data) = sse_data(&line) else {
+                continue;
+            };
+            if data == "[DONE]" {
+                break;
+            }
+            let event = parse_stream_chunk(data)?;
+            if let Some(error) = event.get("error") {
+                return Err(format!("API error: {}", error));
+            }
+            if let Some(delta) = event["choices"][0]["delta"]["content"].as_str() {
+                text.push_str(delta);
+                on_chunk(delta);
+            }
+            if let Some(tokens) = event.get("usage").filter(|u| u.is_object()) {
+                usage = Some(tokens.clone());
+            }
+        }
+
+        if text.is_empty() {
+            return Err("No response from API".to_string());
+        }
+        Ok(Completion {
+            text,
+            usage: Usage {
+                prompt_tokens: usage.as_ref().and_then(|u| u["prompt_tokens"].as_u64()),
+                completion_tokens: usage.as_ref().and_then(|u| u["completion_tokens"].as_u64()),
+                retries,
+            },
+        })
+    }
+
+    async fn embed(&self, texts: &[String], model: &str) -> Result<EmbeddingBatch, String> {
+        let auth = format!("Bearer {}", self.api_key);
+        let (resp, retries) = self
+            .http
+            .post_with_retry(
+                &format!("{}/embeddings", self.base_url),
+                &[("Authorization", &auth)],
+                &json!({ "model": model, "input": texts }),
+            )
+            .await?;
+
+        let mut response: OpenAiEmbeddingResponse = parse_json(resp).await?;
+        response.data.sort_by_key(|d| d.index);
+        Ok(EmbeddingBatch {
+            vectors: response.data.into_iter().map(|d| d.embedding).collect(),
+            usage: openai_usage(response.usage, retries),
+        })
+    }
+}
+
+// Anthropic Messages API
+
+pub struct AnthropicProvider {
+    http: HttpClient,
+    base_url: String,
+    api_key: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct AnthropicBlock {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    text: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct AnthropicUsage {
+    input_tokens: u64,
+    output_tokens: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct AnthropicResponse {
+    content: Vec<AnthropicBlock>,
+    usage: Option<AnthropicUsage>,
+}
+
+fn anthropic_content(message: &Message) -> Vec<serde_json::Value> {
+    message
+        .content
+        .iter()
+        .map(|part| match part {
+            ContentPart::Text(text) => json!({ "type": "text", "text": text }),
+            ContentPart::Image { media_type, data } => json!({
+                "type": "image",
+                "source": { "type": "base64", "media_type": media_type, "data": data }
+            }),
+        })
+        .collect()
+}
+
+#[async_trait]
+impl LlmProvider for AnthropicProvider {
+    fn kind(&self) -> ProviderKind {
+        ProviderKind::Anthropic
+    }
+
+    async fn complete(
+        &self,
+        messages: &[Message],
+        options: &CompletionOptions,
+    ) -> Result<Completion, String> {
+        let body = anthropic_body(messages, options);
+        let (resp, retries) = self
+            .http
+            .post_with_retry(
+                &format!("{}/messages", self.base_url),
+                &[
+                    ("x-api-key", &self.api_key),
+                    ("anthropic-version", ANTHROPIC_VERSION),
+                ],
+                &body,
+            )
+            .await?;
+
+        let response: AnthropicResponse = parse_json(resp).await?;
+        let usage = Usage {
+            prompt_tokens: response.usage.as_ref().map(|u| u.input_tokens),
+            completion_tokens: response.usage.as_ref().map(|u| u.output_tokens),
+            retries,
+        };
+        let text: String = response
+            .content
+            .into_iter()
+            .filter(|block| block.kind == "text")
+            .map(|block| block.text)
+            .collect();
+        if text.is_empty() {
+            Err("No response from API".to_string())
+        } else {
+            Ok(Completion { text, usage })
+        }
+    }
+
+    async fn complete_streaming(
+        &self,
+        messages: &[Message],
+        options: &CompletionOptions,
+        on_chunk: ChunkSink<'_>,
+    ) -> Result<Completion, String> {
+        let mut body = anthropic_body(messages, options);
+        body["stream"] = json!(true);
+        let (mut lines, retries) = self
+            .http
+            .post_stream_with_retry(
+                &format!("{}/messages", self.base_url),
+                &[
+                    ("x-api-key", &self.api_key),
+                    ("anthropic-version", ANTHROPIC_VERSION),
+                ],
+                &body,
+            )
+            .await?;
+
+        let mut text = String::new();
+        let mut usage = Usage {
+            retries,
+            ..Usage::default()
+        };
+        while let Some(line) = lines.next_line().await? {
+            let Some(data) = sse_data(&line) else {
+                continue;
+            };
+            let event = parse_stream_chunk(data)?;
+            match event["type"].as_str().unwrap_or("") {
+                "message_start" => {
+                    usage.prompt_tokens = event["message"]["usage"]["input_tokens"].as_u64();
+                }
+                "content_block_delta" => {
+                    if let Some(delta) = event["delta"]["text"].as_str() {
+                        text.push_str(delta);
+                        on_chunk(delta);
+                    }
+                }
+                "message_delta" => {
+                    if let Some(tokens) = event["usage"]["output_tokens"].as_u64() {
+                        usage.completion_tokens = Some(tokens);
+                    }
+                }
+                "error" => return Err(format!("API error: {}", event["error"])),
+                "message_stop" => break,
+                _ => {}
+            }
+        }
+
+        if text.is_empty() {
+            Err("No response from API".to_string())
+        } else {
+            Ok(Completion { text, usage })
+        }
+    }
+}
+
+fn anthropic_body(messages: &[Message], options: &CompletionOptions) -> serde_json::Value {
+    // System prompts are a top-level field rather than a message role
+    let system: Vec<String> = messages
+        .iter()
+        .filter(|m| m.role == Role::System)
+        .map(Message::text)
+        .collect();
+    let turns: Vec<serde_json::Value> = messages
+        .iter()
+        .filter(|m| m.role != Role::System)
+        .map(|m| json!({ "role": m.role.as_str(), "content": anthropic_content(m) }))
+        .collect();
+
+    let mut body = json!({
+        "model": options.model,
+        "messages": turns,
+        "temperature": options.temperature,
+        "max_tokens": options.max_tokens.unwrap_or(DEFAULT_MAX_TOKENS),
+    });
+    if !system.is_empty() {
+        body["system"] = json!(system.join("\n\n"));
+    }
+    body
+}
+
+// Ollama native API
+
+pub struct OllamaProvider {
+    http: HttpClient,
+    base_url: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct OllamaChatResponse {
+    message: OpenAiMessage,
+    prompt_eval_count: Option<u64>,
+    eval_count: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OllamaEmbedResponse {
+    embeddings: Vec<Vec<f32>>,
+    prompt_eval_count: Option<u64>,
+}
+
+#[async_trait]
+impl LlmProvider for OllamaProvider {
+    fn kind(&self) -> ProviderKind {
+        ProviderKind::Ollama
+    }
+
+    async fn complete(
+        &self,
+        messages: &[Message],
+        options: &CompletionOptions,
+    ) -> Result<Completion, String> {
+        let body = ollama_chat_body(messages, options, false);
+        let (resp, retries) = self
+            .http
+            .post_with_retry(&format!("{}/api/chat", self.base_url), &[], &body)
+            .await?;
+
+        let response: OllamaChatResponse = parse_json(resp).await?;
+        Ok(Completion {
+            text: response.message.content,
+            usage: Usage {
+                prompt_tokens: response.prompt_eval_count,
+                completion_tokens: response.eval_count,
+                retries,
+            },
+        })
+    }
+
+    async fn complete_streaming(
+        &self,
+        messages: &[Message],
+        options: &CompletionOptions,
+        on_chunk: ChunkSink<'_>,
+    ) -> Result<Completion, String> {
+        let body = ollama_chat_body(messages, options, true);
+        let (mut lines, retries) = self
+            .http
+            .post_stream_with_retry(&format!("{}/api/chat", self.base_url), &[], &body)
+            .await?;
+
+        // Newline-delimited JSON rather than SSE
+        let mut text = String::new();
+        let mut usage = Usage {
+            retries,
+            ..Usage::default()
+        };
+        while let Some(line) = lines.next_line().await? {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let event = parse_stream_chunk(&line)?;
+            if let Some(error) = event["error"].as_str() {
+                return Err(format!("API error: {}", error));
+            }
+            if let Some(delta) = event["message"]["content"].as_str() {
+                if !delta.is_empty() {
+                    text.push_str(delta);
+                    on_chunk(delta);
+                }
+            }
+            if event["done"].as_bool() == Some(true) {
+                usage.prompt_tokens = event["prompt_eval_count"].as_u64();
+                usage.completion_tokens = event["eval_count"].as_u64();
+                break;
+            }
+        }
+        Ok(Completion { text, usage })
+    }
+
+    async fn embed(&self, texts: &[String], model: &str) -> Result<EmbeddingBatch, String> {
+        let (resp, retries) = self
+            .http
+            .post_with_retry(
+                &format!("{}/api/embed", self.base_url),
+                &[],
+                &json!({ "model": model, "input": texts }),
+            )
+            .await?;
+
+        let response: OllamaEmbedResponse = parse_json(resp).await?;
+        Ok(EmbeddingBatch {
+            vectors: response.embeddings,
+            usage: Usage {
+                prompt_tokens: response.prompt_eval_count,
+                completion_tokens: Some(0),
+                retries,
+            },
+        })
+    }
+}
+
+fn ollama_chat_body(
+    messages: &[Message],
+    options: &CompletionOptions,
+    stream: bool,
+) -> serde_json::Value {
+    let turns: Vec<serde_json::Value> = messages
+        .iter()
+        .map(|m| {
+            let images: Vec<&str> = m
+                .content
+                .iter()
+                .filter_map(|part| match part {
+                    ContentPart::Image { data, .. } => Some(data.as_str()),
+                    ContentPart::Text(_) => None,
+                })
+                .collect();
+            let mut turn = json!({ "role": m.role.as_str(), "content": m.text() });
+            if !images.is_empty() {
+                turn["images"] = json!(images);
+            }
+            turn
+        })
+        .collect();
+
+    let mut model_options = json!({ "temperature": options.temperature });
+    if let Some(max_tokens) = options.max_tokens {
+        model_options["num_predict"] = json!(max_tokens);
+    }
+
+    let mut body = json!({
+        "model": options.model,
+        "messages": turns,
+        "stream": stream,
+        "options": model_options,
+    });
+    if let Some(schema) = &options.response_schema {
+        body["format"] = schema.schema.clone();
+    }
+    body
+}
+
+// Deterministic mock, for development without an API key
+
+pub struct MockProvider;
+
+impl MockProvider {
+    /// Every `tabId: N` line in the prompt gets a "keep" suggestion; anything else gets a
+    /// fixed markdown summary. The output depends only on the input.
+    fn respond(messages: &[Message]) -> String {
+        let prompt: String = messages
+            .iter()
+            .filter(|m| m.role == Role::User)
+            .map(Message::text)
+            .collect();
+
+        let mut items = Vec::new();
+        let mut lines = prompt.lines().peekable();
+        while let Some(line) = lines.next() {
+            let Some(id) = line.trim().strip_prefix("tabId:") else {
+                continue;
+            };
+            let Ok(id) = id.trim().parse::<i64>() else {
+                continue;
+            };
+            let title = lines
+                .peek()
+                .and_then(|l| l.trim().strip_prefix("title:"))
+                .map(|t| t.trim().to_string())
+                .unwrap_or_default();
+            items.push(json!({
+                "tabId": id,
+                "category": "reference",
+                "decision": "keep",
+                "reason": "Mock provider",
+                "digest": title,
+            }));
+        }
+
+        if items.is_empty() {
+            format!(
+                "## Mock report\n\n- {} messages, {} words of input",
+                messages.len(),
+                prompt.split_whitespace().count()
+            )
+        } else {
+            serde_json::Value::Array(items).to_string()
+        }
+    }
+
+    /// Hashed bag of words, so texts sharing words get similar vectors
+    fn embed_text(text: &str) -> Vec<f32> {
+        let mut vector = vec![0.0; MOCK_EMBEDDING_DIMS];
+        for word in text
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|w| !w.is_empty())
+        {
+            // FNV-1a; stable across runs and platforms
+            let hash = word
+                .to_lowercase()
+                .bytes()
+                .fold(0xcbf29ce484222325u64, |h, b| {
+                    (h ^ b as u64).wrapping_mul(0x100000001b3)
+                });
+            vector[(hash % MOCK_EMBEDDING_DIMS as u64) as usize] += 1.0;
+        }
+        vector
+    }
+}
+
+#[async_trait]
+impl LlmProvider for MockProvider {
+    fn kind(&self) -> ProviderKind {
+        ProviderKind::Mock
+    }
+
+    async fn complete(
+        &self,
+        messages: &[Message],
+        _options: &CompletionOptions,
+    ) -> Result<Completion, String> {
+        // No usage reported; callers estimate it
+        Ok(Completion {
+            text: Self::respond(messages),
+            usage: Usage::default(),
+        })
+    }
+
+    async fn embed(&self, texts: &[String], _model: &str) -> Result<EmbeddingBatch, String> {
+        Ok(EmbeddingBatch {
+            vectors: texts.iter().map(|t| Self::embed_text(t)).collect(),
+            usage: Usage::default(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{StubResponse, StubServer};
+
+    fn fast_policy(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy {
+            timeout: Duration::from_secs(5),
+            max_attempts,
+            initial_delay: Duration::from_millis(10),
+            stream_timeout: Duration::from_secs(5),
+        }
+    }
+
+    async fn stream(
+        provider: &dyn LlmProvider,
+        messages: &[Message],
+    ) -> (Result<Completion, String>, Vec<String>) {
+        let mut chunks = Vec::new();
+        let result = provider
+            .complete_streaming(messages, &options(), &mut |chunk: &str| {
+                chunks.push(chunk.to_string())
+            })
+            .await;
+        (result, chunks)
+    }
+
+    fn openai(server: &StubServer, policy: RetryPolicy) -> OpenAiProvider {
+        OpenAiProvider {
+            http: HttpClient::new(policy).unwrap(),
+            base_url: server.url.clone(),
+            api_key: "test-key".to_string(),
+        }
+    }
+
+    fn options() -> CompletionOptions {
+        CompletionOptions {
+            model: "test-model".to_string(),
+            temperature: 0.2,
+            max_tokens: None,
+            response_schema: None,
+        }
+    }
+
+    fn conversation() -> Vec<Message> {
+        vec![
+            Message::system("be brief"),
+            Message::user_parts(vec![
+                ContentPart::Text("describe".to_string()),
+                ContentPart::Image {
+                    media_type: "image/jpeg".to_string(),
+                    data: "aGVsbG8=".to_string(),
+                },
+            ]),
+        ]
+    }
+
+    #[tokio::test]
+    async fn retries_rate_limits_and_server_errors() {
+        let server = StubServer::start(vec![
+            StubResponse::error(429, "slow down"),
+            StubResponse::error(503, "unavailable"),
+            StubResponse::chat("done"),
+        ])
+        .await;
+
+        let result = openai(&server, fast_policy(3))
+            .complete(&[Message::user("hi")], &options())
+            .await;
+
+        let completion = result.unwrap();
+        assert_eq!(completion.text, "done");
+        assert_eq!(completion.usage.retries, 2);
+        assert_eq!(server.requests().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_max_attempts() {
+        let server = StubServer::start(vec![
+            StubResponse::error(500, "boom"),
+            StubResponse::error(502, "boom"),
+            StubResponse::error(504, "still down"),
+        ])
+        .await;
+
+        let err = openai(&server, fast_policy(3))
+            .complete(&[Message::user("hi")], &options())
+            .await
+            .unwrap_err();
+
+        assert!(err.contains("504") && err.contains("still down"), "{}", err);
+        assert_eq!(server.requests().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn does_not_retry_client_errors() {
+        let server = StubServer::start(vec![
+            StubResponse::error(401, "bad key"),
+            StubResponse::chat("unreachable"),
+        ])
+        .await;
+
+        let err = openai(&server, fast_policy(3))
+            .complete(&[Message::user("hi")], &options())
+            .await
+            .unwrap_err();
+
+        assert!(err.contains("401"), "{}", err);
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn times_out_slow_responses() {
+        let server = StubServer::start(vec![
+            StubResponse::chat("late").delayed(Duration::from_millis(500))
+        ])
+        .await;
+        let policy = RetryPolicy {
+            timeout: Duration::from_millis(100),
+            ..fast_policy(1)
+        };
+
+        let err = openai(&server, policy)
+            .complete(&[Message::user("hi")], &options())
+            .await
+            .unwrap_err();
+
+        assert!(err.contains("timed out"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn retries_after_a_timeout() {
+        let server = StubServer::start(vec![
+            StubResponse::chat("late").delayed(Duration::from_millis(500)),
+            StubResponse::chat("on time"),
+        ])
+        .await;
+        let policy = RetryPolicy {
+            timeout: Duration::from_millis(100),
+            ..fast_policy(2)
+        };
+
+        let result = openai(&server, policy)
+            .complete(&[Message::user("hi")], &options())
+            .await;
+
+        assert_eq!(result.unwrap().text, "on time");
+    }
+
+    #[tokio::test]
+    async fn openai_sends_bearer_auth_and_image_parts() {
+        let server = StubServer::start(vec![StubResponse::chat("ok")]).await;
+
+        openai(&server, fast_policy(1))
+            .complete(&conversation(), &options())
+            .await
+            .unwrap();
+
+        let request = &server.requests()[0];
+        assert_eq!(request.path, "/chat/completions");
+        assert_eq!(request.headers["authorization"], "Bearer test-key");
+        assert_eq!(request.body["model"], "test-model");
+        assert_eq!(request.body["messages"][0]["content"], "be brief");
+        let parts = &request.body["messages"][1]["content"];
+        assert_eq!(parts[0]["text"], "describe");
+        assert_eq!(
+            parts[1]["image_url"]["url"],
+            "data:image/jpeg;base64,aGVsbG8="
+        );
+    }
+
+    #[tokio::test]
+    async fn openai_reports_token_usage() {
+        let server = StubServer::start(vec![
+            StubResponse::ok(json!({
+                "choices": [{ "message": { "role": "assistant", "content": "ok" } }],
+                "usage": { "prompt_tokens": 120, "completion_tokens": 8, "total_tokens": 128 }
+            })),
+            StubResponse::ok(json!({
+                "data": [{ "index": 0, "embedding": [1.0] }],
+                "usage": { "prompt_tokens": 5, "total_tokens": 5 }
+            })),
+            StubResponse::chat("no usage block"),
+        ])
+        .await;
+        let provider = openai(&server, fast_policy(1));
+
+        let chat = provider.complete(&[Message::user("hi")], &options()).await;
+        let embedded = provider.embed(&["hi".to_string()], "embedder").await;
+        let bare = provider.complete(&[Message::user("hi")], &options()).await;
+
+        assert_eq!(
+            chat.unwrap().usage,
+            Usage {
+                prompt_tokens: Some(120),
+                completion_tokens: Some(8),
+                retries: 0
+            }
+        );
+        let usage = embedded.unwrap().usage;
+        assert_eq!(
+            (usage.prompt_tokens, usage.completion_tokens),
+            (Some(5), Some(0))
+        );
+        assert_eq!(bare.unwrap().usage, Usage::default());
+    }
+
+    #[tokio::test]
+    async fn anthropic_moves_system_prompt_and_uses_api_key_header() {
+        let server = StubServer::start(vec![StubResponse::ok(json!({
+            "content": [
+                { "type": "text", "text": "hello " },
+                { "type": "text", "text": "there" }
+            ],
+            "usage": { "input_tokens": 40, "output_tokens": 2 }
+        }))])
+        .await;
+        let provider = AnthropicProvider {
+            http: HttpClient::new(fast_policy(1)).unwrap(),
+            base_url: server.url.clone(),
+            api_key: "ant-key".to_string(),
+        };
+
+        let result = provider.complete(&conversation(), &options()).await;
+
+        let completion = result.unwrap();
+        assert_eq!(completion.text, "hello there");
+        assert_eq!(completion.usage.prompt_tokens, Some(40));
+        assert_eq!(completion.usage.completion_tokens, Some(2));
+        let request = &server.requests()[0];
+        assert_eq!(request.path, "/messages");
+        assert_eq!(request.headers["x-api-key"], "ant-key");
+        assert_eq!(request.headers["anthropic-version"], ANTHROPIC_VERSION);
+        assert_eq!(request.body["system"], "be brief");
+        assert_eq!(request.body["max_tokens"], DEFAULT_MAX_TOKENS);
+        let turns = request.body["messages"].as_array().unwrap();
+        assert_eq!(turns.len(), 1);
+        assert_eq!(turns[0]["content"][1]["source"]["data"], "aGVsbG8=");
+    }
+
+    #[tokio::test]
+    async fn ollama_sends_images_alongside_text() {
+        let server = StubServer::start(vec![
+            StubResponse::ok(json!({
+                "message": { "role": "assistant", "content": "local" },
+                "prompt_eval_count": 30,
+                "eval_count": 4
+            })),
+            StubResponse::ok(json!({ "embeddings": [[1.0, 0.0], [0.0, 1.0]] })),
+        ])
+        .await;
+        let provider = OllamaProvider {
+            http: HttpClient::new(fast_policy(1)).unwrap(),
+            base_url: server.url.clone(),
+        };
+
+        let result = provider.complete(&conversation(), &options()).await;
+        let vectors = provider
+            .embed(&["a".to_string(), "b".to_string()], "embedder")
+            .await;
+
+        let completion = result.unwrap();
+        assert_eq!(completion.text, "local");
+        assert_eq!(completion.usage.prompt_tokens, Some(30));
+        assert_eq!(completion.usage.completion_tokens, Some(4));
+        assert_eq!(
+            vectors.unwrap().vectors,
+            vec![vec![1.0, 0.0], vec![0.0, 1.0]]
+        );
+        let requests = server.requests();
+        assert_eq!(requests[0].path, "/api/chat");
+        assert_eq!(requests[0].body["stream"], false);
+        assert_eq!(requests[0].body["messages"][1]["content"], "describe");
+        assert_eq!(requests[0].body["messages"][1]["images"][0], "aGVsbG8=");
+        assert_eq!(requests[1].path, "/api/embed");
+        assert_eq!(requests[1].body["model"], "embedder");
+    }
+
+    #[tokio::test]
+    async fn openai_streams_deltas_and_final_usage() {
+        let delta =
+            |text: &str| json!({ "choices": [{ "delta": { "content": text } }] }).to_string();
+        let server = StubServer::start(vec![StubResponse::sse(&[
+            json!({ "choices": [{ "delta": { "role": "assistant" } }] }).to_string(),
+            delta("## Day"),
+            delta(" report"),
+            json!({ "choices": [], "usage": { "prompt_tokens": 90, "completion_tokens": 3 } })
+                .to_string(),
+            "[DONE]".to_string(),
+        ])])
+        .await;
+
+        let (result, chunks) = stream(&openai(&server, fast_policy(1)), &conversation()).await;
+
+        let completion = result.unwrap();
+        assert_eq!(chunks, vec!["## Day", " report"]);
+        assert_eq!(completion.text, "## Day report");
+        assert_eq!(completion.usage.prompt_tokens, Some(90));
+        assert_eq!(completion.usage.completion_tokens, Some(3));
+        let request = &server.requests()[0];
+        assert_eq!(request.body["stream"], true);
+        assert_eq!(request.body["stream_options"]["include_usage"], true);
+    }
+
+    #[tokio::test]
+    async fn openai_stream_error_keeps_delivered_chunks() {
+        let server = StubServer::start(vec![StubResponse::sse(&[
+            json!({ "choices": [{ "delta": { "content": "partial" } }] }).to_string(),
+            json!({ "error": { "message": "overloaded" } }).to_string(),
+        ])])
+        .await;
+
+        let (result, chunks) = stream(&openai(&server, fast_policy(1)), &conversation()).await;
+
+        assert!(result.unwrap_err().contains("overloaded"));
+        assert_eq!(chunks, vec!["partial"]);
+    }
+
+    #[tokio::test]
+    async fn anthropic_streams_text_deltas() {
+        let event = |value: serde_json::Value| value.to_string();
+        let server = StubServer::start(vec![StubResponse::lines(&[
+            "event: message_start".to_string(),
+            format!(
+                "data: {}",
+                event(json!({ "type": "message_start", "message": { "usage": { "input_tokens": 12 } } }))
+            ),
+            String::new(),
+            format!(
+                "data: {}",
+                event(json!({ "type": "content_block_delta", "delta": { "type": "text_delta", "text": "hi" } }))
+            ),
+            format!("data: {}", event(json!({ "type": "ping" }))),
+            format!(
+                "data: {}",
+                event(json!({ "type": "message_delta", "usage": { "output_tokens": 7 } }))
+            ),
+            format!("data: {}", event(json!({ "type": "message_stop" }))),
+        ])])
+        .await;
+        let provider = AnthropicProvider {
+            http: HttpClient::new(fast_policy(1)).unwrap(),
+            base_url: server.url.clone(),
+            api_key: "ant-key".to_string(),
+        };
+
+        let (result, chunks) = stream(&provider, &conversation()).await;
+
+        let completion = result.unwrap();
+        assert_eq!(chunks, vec!["hi"]);
+        assert_eq!(completion.usage.prompt_tokens, Some(12));
+        assert_eq!(completion.usage.completion_tokens, Some(7));
+        assert_eq!(server.requests()[0].body["stream"], true);
+    }
+
+    #[tokio::test]
+    async fn ollama_streams_json_lines() {
+        let server = StubServer::start(vec![StubResponse::lines(&[
+            json!({ "message": { "content": "lo" }, "done": false }).to_string(),
+            json!({ "message": { "content": "cal" }, "done": false }).to_string(),
+            json!({ "message": { "content": "" }, "done": true, "prompt_eval_count": 5, "eval_count": 2 })
+                .to_string(),
+        ])])
+        .await;
+        let provider = OllamaProvider {
+            http: HttpClient::new(fast_policy(1)).unwrap(),
+            base_url: server.url.clone(),
+        };
+
+        let (result, chunks) = stream(&provider, &conversation()).await;
+
+        let completion = result.unwrap();
+        assert_eq!(chunks, vec!["lo", "cal"]);
+        assert_eq!(completion.text, "local");
+        assert_eq!(completion.usage.completion_tokens, Some(2));
+        assert_eq!(server.requests()[0].body["stream"], true);
+    }
+
+    #[test]
+    fn task_config_falls_back_to_global_and_provider_defaults() {
+        let settings = Settings {
+            openai_api_key: Some("key".to_string()),
+            model: Some("gpt-legacy".to_string()),
+            report_provider: Some("ollama".to_string()),
+            embedding_provider: Some("Mock".to_string()),
+            ..Settings::default()
+        };
+
+        let label = |task| task_config(&settings, task).map(|c| c.label());
+        assert_eq!(label(Task::Suggestions).unwrap(), "openai/gpt-legacy");
+        assert_eq!(label(Task::Report).unwrap(), "ollama/llama3.2");
+        assert_eq!(label(Task::Embeddings).unwrap(), "mock/mock");
+    }
+
+    #[test]
+    fn task_config_rejects_unusable_providers() {
+        let settings = Settings {
+            suggestion_provider: Some("gemini".to_string()),
+            report_provider: Some("anthropic".to_string()),
+            embedding_provider: Some("anthropic".to_string()),
+            ..Settings::default()
+        };
+
+        let err = |task| task_config(&settings, task).err().unwrap();
+        assert!(err(Task::Suggestions).contains("Unknown provider"));
+        assert!(err(Task::Report).contains("Anthropic API key"));
+        assert!(err(Task::Embeddings).contains("does not support embeddings"));
+    }
+
+    #[tokio::test]
+    async fn mock_is_deterministic() {
+        let messages = [Message::user(
+            "tabs:\n\ntabId: 7\ntitle: Rust book\nurl: https://doc.rust-lang.org",
+        )];
+
+        let first = MockProvider
+            .complete(&messages, &options())
+            .await
+            .unwrap()
+            .text;
+        let second = MockProvider
+            .complete(&messages, &options())
+            .await
+            .unwrap()
+            .text;
+        let vectors = MockProvider
+            .embed(&["Rust book".to_string(), "rust BOOK".to_string()], "mock")
+            .await
+            .unwrap()
+            .vectors;
+
+        assert_eq!(first, second);
+        let items: serde_json::Value = serde_json::from_str(&first).unwrap();
+        assert_eq!(items[0]["tabId"], 7);
+        assert_eq!(items[0]["digest"], "Rust book");
+        assert_eq!(vectors[0], vectors[1]);
+    }
+}
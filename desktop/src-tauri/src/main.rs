@@ -2,11 +2,32 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 mod ai;
+mod capture;
+mod db;
+mod duplicates;
+mod embeddings;
+mod jobs;
+mod llm;
+mod migrate;
+mod pairing;
+mod persist;
+mod privacy;
+mod protocol;
+mod queue;
+mod rest;
+mod rollup;
+mod search;
 mod server;
 mod storage;
+#[cfg(test)]
+mod test_support;
+mod usage;
 
+use serde_json::json;
+use std::collections::HashMap;
 use std::sync::Arc;
-use tauri::Manager;
+use std::time::{Duration, Instant};
+use tauri::{Emitter, Manager};
 use tokio::sync::RwLock;
 
 pub type AppState = Arc<RwLock<storage::Storage>>;
@@ -19,19 +40,30 @@ fn main() {
 
             // Initialize storage
             let storage = storage::Storage::new(&app_handle);
+            usage::install(storage.db());
+            if let Err(e) = pairing::install(storage.db()) {
+                eprintln!(
+                    "[Pairing] Failed to load pairing, extensions cannot connect: {}",
+                    e
+                );
+            }
+            queue::install(storage.db());
+            jobs::install(app_handle.clone());
             let state: AppState = Arc::new(RwLock::new(storage));
 
             // Store state in app
             app.manage(state.clone());
 
-            // Cleanup old tabs on startup (older than 7 days)
+            // Closed tabs are what past reports and rollups are rebuilt from, so they are
+            // only pruned on startup when the user has set a retention
             let cleanup_state = state.clone();
             tauri::async_runtime::spawn(async move {
                 let mut storage = cleanup_state.write().await;
-                let count = storage.cleanup_old_tabs(7);
-                if count > 0 {
-                    let _ = storage.save_tabs();
-                    println!("[Startup] Cleaned up {} old tabs", count);
+                if let Some(days) = storage.settings.closed_tab_retention_days {
+                    let count = storage.cleanup_old_tabs(days.into());
+                    if count > 0 {
+                        println!("[Startup] Cleaned up {} old tabs", count);
+                    }
                 }
                 let (total, open, closed) = storage.get_stats();
                 println!(
@@ -40,6 +72,9 @@ fn main() {
                 );
             });
 
+            // Write dirty tab rows in the background
+            persist::spawn_flusher(state.clone());
+
             // Start HTTP server for extension communication
             let server_state = state.clone();
             let server_app_handle = app_handle.clone();
@@ -56,10 +91,30 @@ fn main() {
             get_closed_tabs,
             get_settings,
             get_report,
+            get_report_for_date,
+            list_reports,
+            delete_report,
+            delete_reports_before,
+            get_tabs_for_date,
+            get_closed_tabs_for_date,
+            search_tabs,
+            refresh_embeddings,
+            find_similar_tabs,
+            semantic_search,
+            cluster_tabs,
+            find_duplicate_tabs,
+            merge_duplicate_tabs,
             save_settings,
             analyze_tabs,
             analyze_batch,
             generate_report,
+            generate_report_stream,
+            get_report_draft,
+            preview_ai_request,
+            generate_period_report,
+            list_period_reports,
+            list_jobs,
+            cancel_job,
             close_tab,
             mark_keep,
             clear_suggestions,
@@ -67,10 +122,26 @@ fn main() {
             trigger_refresh,
             cleanup_old_tabs,
             get_storage_stats,
+            get_ai_usage,
             sync_tabs,
+            get_pairing,
+            rotate_pairing_secret,
+            revoke_paired_client,
+            get_server_token,
+            list_clients,
+            create_api_token,
+            list_queued_commands,
+            cancel_queued_command,
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|app_handle, event| {
+            if let tauri::RunEvent::Exit = event {
+                // Final flush so nothing queued since the last tick is lost
+                let state = app_handle.state::<AppState>().inner().clone();
+                tauri::async_runtime::block_on(persist::flush(&state));
+            }
+        });
 }
 
 #[tauri::command]
@@ -87,6 +158,49 @@ async fn get_closed_tabs(
     Ok(storage.get_today_closed_tabs())
 }
 
+/// Full-text search over open and closed tabs
+#[tauri::command]
+async fn search_tabs(
+    state: tauri::State<'_, AppState>,
+    query: search::SearchQuery,
+) -> Result<Vec<search::SearchResult>, String> {
+    search::search(&state, query).await
+}
+
+/// Embed tabs missing a cached vector; returns how many were sent to the API
+#[tauri::command]
+async fn refresh_embeddings(state: tauri::State<'_, AppState>) -> Result<usize, String> {
+    embeddings::refresh_embeddings(&state).await
+}
+
+/// "Tabs like this one"
+#[tauri::command]
+async fn find_similar_tabs(
+    state: tauri::State<'_, AppState>,
+    tab_id: i64,
+    limit: Option<usize>,
+) -> Result<Vec<embeddings::SimilarTab>, String> {
+    embeddings::find_similar(&state, tab_id, limit).await
+}
+
+#[tauri::command]
+async fn semantic_search(
+    state: tauri::State<'_, AppState>,
+    query: String,
+    limit: Option<usize>,
+) -> Result<Vec<embeddings::SimilarTab>, String> {
+    embeddings::semantic_search(&state, &query, limit).await
+}
+
+/// Group open tabs into topics using cached embeddings
+#[tauri::command]
+async fn cluster_tabs(
+    state: tauri::State<'_, AppState>,
+    threshold: Option<f32>,
+) -> Result<embeddings::Clustering, String> {
+    embeddings::cluster_open_tabs(&state, threshold).await
+}
+
 #[tauri::command]
 async fn get_settings(state: tauri::State<'_, AppState>) -> Result<storage::Settings, String> {
     let storage = state.read().await;
@@ -98,7 +212,70 @@ async fn get_report(
     state: tauri::State<'_, AppState>,
 ) -> Result<Option<storage::DailyReport>, String> {
     let storage = state.read().await;
-    Ok(storage.report.clone())
+    storage.latest_report().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn get_report_for_date(
+    state: tauri::State<'_, AppState>,
+    date: String,
+) -> Result<Option<storage::DailyReport>, String> {
+    let date = storage::parse_date(&date)?;
+    let storage = state.read().await;
+    storage.get_report(date).map_err(|e| e.to_string())
+}
+
+/// List archived reports between two dates (inclusive), newest first
+#[tauri::command]
+async fn list_reports(
+    state: tauri::State<'_, AppState>,
+    from: Option<String>,
+    to: Option<String>,
+) -> Result<Vec<storage::DailyReport>, String> {
+    let from = from.as_deref().map(storage::parse_date).transpose()?;
+    let to = to.as_deref().map(storage::parse_date).transpose()?;
+    let storage = state.read().await;
+    storage.list_reports(from, to).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn delete_report(state: tauri::State<'_, AppState>, date: String) -> Result<bool, String> {
+    let date = storage::parse_date(&date)?;
+    let storage = state.read().await;
+    storage.delete_report(date).map_err(|e| e.to_string())
+}
+
+/// Delete archived reports dated before `before`
+#[tauri::command]
+async fn delete_reports_before(
+    state: tauri::State<'_, AppState>,
+    before: String,
+) -> Result<usize, String> {
+    let before = storage::parse_date(&before)?;
+    let storage = state.read().await;
+    storage
+        .delete_reports_before(before)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn get_tabs_for_date(
+    state: tauri::State<'_, AppState>,
+    date: String,
+) -> Result<Vec<storage::TabRecord>, String> {
+    let date = storage::parse_date(&date)?;
+    let storage = state.read().await;
+    Ok(storage.get_tabs_for_date(date))
+}
+
+#[tauri::command]
+async fn get_closed_tabs_for_date(
+    state: tauri::State<'_, AppState>,
+    date: String,
+) -> Result<Vec<storage::TabRecord>, String> {
+    let date = storage::parse_date(&date)?;
+    let storage = state.read().await;
+    Ok(storage.get_closed_tabs_for_date(date))
 }
 
 #[tauri::command]
@@ -107,108 +284,580 @@ async fn save_settings(
     settings: storage::Settings,
 ) -> Result<(), String> {
     let mut storage = state.write().await;
+    let policies_changed = storage.settings.capture_policies != settings.capture_policies;
     storage.settings = settings;
-    storage.save_settings().map_err(|e| e.to_string())
+    storage.save_settings().map_err(|e| e.to_string())?;
+
+    // Extensions keep a copy of the capture policies; have them fetch the new ones
+    if policies_changed {
+        let _ = server::notify(protocol::Command::CapturePoliciesChanged);
+    }
+    Ok(())
+}
+
+/// Suggestions for `tabs`. Tabs whose prompt inputs match a cached suggestion within the TTL
+/// reuse it unless `force` is set; everything else is sent to the model and cached.
+async fn suggest_with_cache(
+    state: &AppState,
+    tabs: &[storage::TabRecord],
+    settings: &storage::Settings,
+    force: bool,
+    job: &jobs::Job,
+) -> Result<ai::SuggestionOutcome, String> {
+    let on_progress = |done, total| job.progress(done, total);
+    let ttl_hours = settings
+        .suggestion_cache_ttl_hours
+        .unwrap_or(ai::DEFAULT_SUGGESTION_CACHE_TTL_HOURS);
+    if ttl_hours == 0 {
+        return ai::suggest_tabs(tabs, settings, &on_progress).await;
+    }
+
+    let model = ai::get_suggestion_model(settings)?;
+    let hashes: Vec<(i64, String)> = tabs
+        .iter()
+        .map(|tab| (tab.id, ai::suggestion_input_hash(tab, settings, &model)))
+        .collect();
+    let expired_before = chrono::Utc::now().timestamp_millis() - ttl_hours as i64 * 3_600_000;
+
+    let cached = if force {
+        HashMap::new()
+    } else {
+        let keys: Vec<String> = hashes.iter().map(|(_, hash)| hash.clone()).collect();
+        state
+            .read()
+            .await
+            .cached_suggestions(&keys, expired_before)
+            .map_err(|e| e.to_string())?
+    };
+    let misses: Vec<storage::TabRecord> = tabs
+        .iter()
+        .zip(&hashes)
+        .filter(|(_, (_, hash))| !cached.contains_key(hash))
+        .map(|(tab, _)| tab.clone())
+        .collect();
+    println!(
+        "[AI Cache] {} of {} tabs unchanged{}",
+        tabs.len() - misses.len(),
+        tabs.len(),
+        if force { " (cache bypassed)" } else { "" }
+    );
+
+    let mut outcome = match ai::suggest_tabs(&misses, settings, &on_progress).await {
+        Ok(outcome) => outcome,
+        // Cached tabs still get their suggestions when the model is unreachable
+        Err(e) if !cached.is_empty() => ai::SuggestionOutcome {
+            suggestions: HashMap::new(),
+            issues: ai::SuggestionIssues {
+                skipped: misses.iter().map(|tab| tab.id).collect(),
+                errors: vec![e],
+                ..Default::default()
+            },
+        },
+        Err(e) => return Err(e),
+    };
+
+    let fresh: Vec<(String, storage::TabSuggestion)> = hashes
+        .iter()
+        .filter_map(|(id, hash)| {
+            let suggestion = outcome.suggestions.get(id)?;
+            Some((hash.clone(), suggestion.clone()))
+        })
+        .collect();
+    let storage = state.read().await;
+    job.check()?;
+    if let Err(e) = storage.cache_suggestions(&fresh, expired_before) {
+        eprintln!("[AI Cache] Failed to cache suggestions: {}", e);
+    }
+    drop(storage);
+
+    for (id, hash) in hashes {
+        if let Some(suggestion) = cached.get(&hash) {
+            outcome.suggestions.insert(id, suggestion.clone());
+        }
+    }
+    Ok(outcome)
 }
 
+/// Analyze every open tab; `force` ignores cached suggestions
 #[tauri::command]
 async fn analyze_tabs(
     state: tauri::State<'_, AppState>,
+    force: Option<bool>,
 ) -> Result<Vec<storage::TabRecord>, String> {
     let storage = state.read().await;
     let tabs = storage.get_open_tabs();
     let settings = storage.settings.clone();
     drop(storage);
 
-    let suggestions = ai::suggest_tabs(&tabs, &settings)
-        .await
-        .map_err(|e| e.to_string())?;
+    let job = jobs::start(jobs::JobKind::Analyze);
+    let force = force.unwrap_or(false);
+    let outcome = job
+        .run(suggest_with_cache(&state, &tabs, &settings, force, &job))
+        .await??;
 
     let mut storage = state.write().await;
-    for (tab_id, suggestion) in suggestions {
+    job.check()?;
+    for (tab_id, suggestion) in outcome.suggestions {
         storage.update_suggestion(tab_id, suggestion);
     }
-    storage.save_tabs().map_err(|e| e.to_string())?;
 
     Ok(storage.get_open_tabs())
 }
 
+/// Open tabs `analyze_batch` sends next: the first `limit` without a suggestion, skipping
+/// blocklisted tabs, which would never get one
+fn next_analyze_batch(storage: &storage::Storage, limit: usize) -> Vec<storage::TabRecord> {
+    let privacy = privacy::PrivacyFilter::new(&storage.settings);
+    storage
+        .get_open_tabs()
+        .into_iter()
+        .filter(|t| t.suggestion.is_none() && !privacy.is_withheld(t))
+        .take(limit)
+        .collect()
+}
+
+/// Returns the open tabs, how many were sent for analysis, and problems with the response.
+/// `force` ignores cached suggestions.
 #[tauri::command]
 async fn analyze_batch(
     state: tauri::State<'_, AppState>,
     limit: usize,
-) -> Result<(Vec<storage::TabRecord>, usize), String> {
+    force: Option<bool>,
+) -> Result<(Vec<storage::TabRecord>, usize, ai::SuggestionIssues), String> {
     let storage = state.read().await;
-    let all_tabs = storage.get_open_tabs();
     let settings = storage.settings.clone();
-
-    // Filter to only tabs without suggestions
-    let unanalyzed: Vec<_> = all_tabs
-        .iter()
-        .filter(|t| t.suggestion.is_none())
-        .cloned()
-        .collect();
-
-    let to_analyze: Vec<_> = unanalyzed.into_iter().take(limit).collect();
+    let to_analyze = next_analyze_batch(&storage, limit);
     let analyze_count = to_analyze.len();
 
     drop(storage);
 
     if to_analyze.is_empty() {
         let storage = state.read().await;
-        return Ok((storage.get_open_tabs(), 0));
+        return Ok((storage.get_open_tabs(), 0, ai::SuggestionIssues::default()));
     }
 
-    let suggestions = ai::suggest_tabs(&to_analyze, &settings)
-        .await
-        .map_err(|e| e.to_string())?;
+    let job = jobs::start(jobs::JobKind::Analyze);
+    let force = force.unwrap_or(false);
+    let outcome = job
+        .run(suggest_with_cache(
+            &state,
+            &to_analyze,
+            &settings,
+            force,
+            &job,
+        ))
+        .await??;
 
     let mut storage = state.write().await;
-    for (tab_id, suggestion) in suggestions {
+    job.check()?;
+    for (tab_id, suggestion) in outcome.suggestions {
         storage.update_suggestion(tab_id, suggestion);
     }
-    storage.save_tabs().map_err(|e| e.to_string())?;
 
-    Ok((storage.get_open_tabs(), analyze_count))
+    Ok((storage.get_open_tabs(), analyze_count, outcome.issues))
+}
+
+/// Dry run of an AI task with the current privacy settings: what would be sent, without
+/// sending it. `task` is "suggestions" (the next `analyze_batch`, cache not consulted),
+/// "report" (the report for `date`, default today) or "embeddings" (open tabs).
+#[tauri::command]
+async fn preview_ai_request(
+    state: tauri::State<'_, AppState>,
+    task: String,
+    date: Option<String>,
+) -> Result<ai::PrivacyPreview, String> {
+    let storage = state.read().await;
+    let settings = storage.settings.clone();
+    match task.as_str() {
+        "suggestions" => {
+            let limit = settings.analyze_batch_size.unwrap_or(30) as usize;
+            let tabs = next_analyze_batch(&storage, limit);
+            drop(storage);
+            ai::preview_suggestions(&tabs, &settings)
+        }
+        "report" => {
+            let date = report_date(date)?;
+            let tabs = storage.get_tabs_for_date(date);
+            drop(storage);
+            ai::preview_daily_report(&tabs, &settings, &storage::format_date(date))
+        }
+        "embeddings" => {
+            let tabs = storage.get_open_tabs();
+            drop(storage);
+            ai::preview_embeddings(&tabs, &settings)
+        }
+        _ => Err(format!(
+            "Unknown task \"{}\", expected suggestions, report or embeddings",
+            task
+        )),
+    }
 }
 
+/// Generate (or regenerate) the report for `date`, defaulting to today.
+/// Past days are rebuilt from the archived tab records for that day.
 #[tauri::command]
 async fn generate_report(
     state: tauri::State<'_, AppState>,
+    date: Option<String>,
 ) -> Result<storage::DailyReport, String> {
+    let date = report_date(date)?;
+    let date_str = storage::format_date(date);
+
     let storage = state.read().await;
-    let tabs = storage.get_today_tabs();
+    let tabs = storage.get_tabs_for_date(date);
     let settings = storage.settings.clone();
     drop(storage);
 
-    let content = ai::generate_daily_report(&tabs, &settings)
-        .await
-        .map_err(|e| e.to_string())?;
+    let job = jobs::start(jobs::JobKind::Report);
+    let on_progress = |done, total| job.progress(done, total);
+    let content = job
+        .run(ai::generate_daily_report(
+            &tabs,
+            &settings,
+            &date_str,
+            &on_progress,
+        ))
+        .await??;
 
     let report = storage::DailyReport {
-        date: chrono::Local::now().format("%Y-%m-%d").to_string(),
+        date: date_str,
         content,
         generated_at: chrono::Utc::now().timestamp_millis(),
     };
 
-    let mut storage = state.write().await;
-    storage.report = Some(report.clone());
-    storage.save_report().map_err(|e| e.to_string())?;
+    let storage = state.read().await;
+    job.check()?;
+    storage.save_report(&report).map_err(|e| e.to_string())?;
 
     Ok(report)
 }
 
+/// How often a streaming report's draft is written to the database
+const DRAFT_SAVE_INTERVAL: Duration = Duration::from_millis(500);
+
+fn report_date(date: Option<String>) -> Result<chrono::NaiveDate, String> {
+    match date {
+        Some(date) => storage::parse_date(&date),
+        None => Ok(chrono::Local::now().date_naive()),
+    }
+}
+
+/// `generate_report`, streamed: emits `report-chunk` events as text arrives, then
+/// `report-done` with the saved report or `report-error` with whatever was written.
+/// The text so far is kept as a draft, so with `resume` an interrupted report is
+/// continued instead of regenerated.
 #[tauri::command]
-async fn close_tab(state: tauri::State<'_, AppState>, tab_id: i64) -> Result<(), String> {
-    // First, send command to extension to close the actual Chrome tab
-    if let Some(sender) = server::get_command_sender() {
-        let _ = sender.send(format!("close_tab:{}", tab_id));
+async fn generate_report_stream(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+    date: Option<String>,
+    resume: Option<bool>,
+) -> Result<storage::DailyReport, String> {
+    let date = report_date(date)?;
+    let date_str = storage::format_date(date);
+
+    let storage = state.read().await;
+    let tabs = storage.get_tabs_for_date(date);
+    let settings = storage.settings.clone();
+    let previous_draft = storage.report_draft(date).map_err(|e| e.to_string())?;
+    let db = storage.db();
+    drop(storage);
+
+    let partial = match &previous_draft {
+        Some(draft) if resume.unwrap_or(false) => draft.content.clone(),
+        _ => String::new(),
+    };
+    if !partial.is_empty() {
+        // Listeners rebuild the report from chunks, so start them off with the draft
+        let _ = app.emit("report-chunk", json!({ "date": date_str, "text": partial }));
     }
 
-    // Then mark as closed in storage
-    let mut storage = state.write().await;
-    storage.close_tab(tab_id);
-    storage.save_tabs().map_err(|e| e.to_string())
+    let job = jobs::start(jobs::JobKind::Report);
+    let on_progress = |done, total| job.progress(done, total);
+    let mut written = partial.clone();
+    let mut last_save = Instant::now();
+    let mut on_chunk = |text: &str| {
+        written.push_str(text);
+        let _ = app.emit("report-chunk", json!({ "date": date_str, "text": text }));
+        if last_save.elapsed() >= DRAFT_SAVE_INTERVAL && !job.is_cancelled() {
+            last_save = Instant::now();
+            let draft = storage::ReportDraft {
+                date: date_str.clone(),
+                content: written.clone(),
+                updated_at: chrono::Utc::now().timestamp_millis(),
+                error: None,
+            };
+            if let Err(e) = db.save_report_draft(&draft) {
+                eprintln!("[Report] Failed to save draft: {}", e);
+            }
+        }
+    };
+    let result = job
+        .run(ai::stream_daily_report(
+            &tabs,
+            &settings,
+            &date_str,
+            Some(partial.as_str()),
+            &mut on_chunk,
+            &on_progress,
+        ))
+        .await
+        .and_then(|result| result);
+
+    let storage = state.read().await;
+    if job.is_cancelled() {
+        // Leave no trace of the cancelled run: put back the draft it started from
+        let restored = match &previous_draft {
+            Some(draft) => storage.save_report_draft(draft),
+            None => storage.delete_report_draft(date),
+        };
+        if let Err(e) = restored {
+            eprintln!("[Report] Failed to restore draft: {}", e);
+        }
+        let partial = previous_draft.map(|d| d.content).unwrap_or_default();
+        let _ = app.emit(
+            "report-error",
+            json!({ "date": date_str, "error": jobs::CANCELLED, "partial": partial }),
+        );
+        return Err(jobs::CANCELLED.to_string());
+    }
+    match result {
+        Ok(content) => {
+            let report = storage::DailyReport {
+                date: date_str,
+                content,
+                generated_at: chrono::Utc::now().timestamp_millis(),
+            };
+            storage.save_report(&report).map_err(|e| e.to_string())?;
+            if let Err(e) = storage.delete_report_draft(date) {
+                eprintln!("[Report] Failed to delete draft: {}", e);
+            }
+            let _ = app.emit("report-done", &report);
+            Ok(report)
+        }
+        Err(error) => {
+            let draft = storage::ReportDraft {
+                date: date_str.clone(),
+                content: written,
+                updated_at: chrono::Utc::now().timestamp_millis(),
+                error: Some(error.clone()),
+            };
+            if !draft.content.is_empty() {
+                if let Err(e) = storage.save_report_draft(&draft) {
+                    eprintln!("[Report] Failed to save draft: {}", e);
+                }
+            }
+            let _ = app.emit(
+                "report-error",
+                json!({ "date": date_str, "error": error, "partial": draft.content }),
+            );
+            Err(error)
+        }
+    }
+}
+
+/// Weekly or monthly rollup of `start` to `end` (inclusive), from the archived tabs and
+/// daily reports, compared with the period of the same length before it. Replaces an
+/// earlier rollup of the same granularity starting the same day.
+#[tauri::command]
+async fn generate_period_report(
+    state: tauri::State<'_, AppState>,
+    start: String,
+    end: String,
+    granularity: String,
+) -> Result<storage::PeriodReport, String> {
+    let granularity = rollup::Granularity::parse(&granularity)?;
+    let period = rollup::Period::new(storage::parse_date(&start)?, storage::parse_date(&end)?)?;
+    let previous = period.previous();
+
+    let storage = state.read().await;
+    // Statistics are sent too, so they leave out what the privacy settings withhold
+    let privacy = privacy::PrivacyFilter::new(&storage.settings);
+    let tabs = privacy.shareable(&storage.get_tabs_between(period.start, period.end));
+    let previous_tabs = privacy.shareable(&storage.get_tabs_between(previous.start, previous.end));
+    let active_ms = storage
+        .get_activity_between(period.start, period.end)
+        .map_err(|e| e.to_string())?;
+    let previous_active_ms = storage
+        .get_activity_between(previous.start, previous.end)
+        .map_err(|e| e.to_string())?;
+    let mut daily_reports = storage
+        .list_reports(Some(period.start), Some(period.end))
+        .map_err(|e| e.to_string())?;
+    daily_reports.reverse();
+    let settings = storage.settings.clone();
+    drop(storage);
+
+    let stats = rollup::period_stats(&tabs, &active_ms, storage::day_bounds(period.end).1);
+    let previous_stats = rollup::period_stats(
+        &previous_tabs,
+        &previous_active_ms,
+        storage::day_bounds(previous.end).1,
+    );
+    let stats_markdown = rollup::stats_markdown(&stats, &previous_stats);
+    let activity = ai::PeriodActivity {
+        period,
+        granularity,
+        tabs: &tabs,
+        daily_reports: &daily_reports,
+        stats: &stats,
+        stats_markdown: &stats_markdown,
+    };
+
+    let job = jobs::start(jobs::JobKind::Report);
+    job.progress(0, 1);
+    let content = job
+        .run(ai::generate_period_report(&activity, &settings))
+        .await??;
+    job.progress(1, 1);
+
+    let report = storage::PeriodReport {
+        granularity: granularity.as_str().to_string(),
+        start: storage::format_date(period.start),
+        end: storage::format_date(period.end),
+        content,
+        generated_at: chrono::Utc::now().timestamp_millis(),
+    };
+
+    let storage = state.read().await;
+    job.check()?;
+    storage
+        .save_period_report(&report)
+        .map_err(|e| e.to_string())?;
+    Ok(report)
+}
+
+/// Archived rollups, newest period first; `granularity` is "week" or "month"
+#[tauri::command]
+async fn list_period_reports(
+    state: tauri::State<'_, AppState>,
+    granularity: Option<String>,
+) -> Result<Vec<storage::PeriodReport>, String> {
+    let granularity = granularity
+        .as_deref()
+        .map(rollup::Granularity::parse)
+        .transpose()?;
+    let storage = state.read().await;
+    storage
+        .list_period_reports(granularity.map(|g| g.as_str()))
+        .map_err(|e| e.to_string())
+}
+
+/// AI jobs currently running, oldest first
+#[tauri::command]
+async fn list_jobs() -> Result<Vec<jobs::JobInfo>, String> {
+    Ok(jobs::list())
+}
+
+/// Cancel a running AI job; its results are discarded. Returns false if it already finished.
+#[tauri::command]
+async fn cancel_job(job_id: u64) -> Result<bool, String> {
+    Ok(jobs::cancel(job_id))
+}
+
+/// The unfinished report for `date`, if its last streamed generation was interrupted
+#[tauri::command]
+async fn get_report_draft(
+    state: tauri::State<'_, AppState>,
+    date: String,
+) -> Result<Option<storage::ReportDraft>, String> {
+    let date = storage::parse_date(&date)?;
+    let storage = state.read().await;
+    storage.report_draft(date).map_err(|e| e.to_string())
+}
+
+/// Close the tab in the browser it belongs to, waiting for its extension to confirm, then
+/// mark it closed. If that browser is not connected, the close is queued and the tab stays
+/// open until the browser connects and confirms.
+#[tauri::command]
+async fn close_tab(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+    tab_id: i64,
+) -> Result<server::CloseOutcome, String> {
+    // Not holding the lock while the extension answers
+    let browser_tab = state
+        .read()
+        .await
+        .tabs
+        .get(&tab_id)
+        .filter(|t| t.closed_at.is_none())
+        .and_then(|t| t.browser_tab());
+    let outcome = match browser_tab {
+        Some(tab) => server::close_browser_tab(&tab)
+            .await
+            .map_err(|e| e.to_string())?,
+        // Not open in the current browser session
+        None => server::CloseOutcome::AlreadyGone,
+    };
+
+    if outcome == server::CloseOutcome::Queued {
+        let _ = app.emit("command-queue-changed", ());
+    } else {
+        state.write().await.close_tab(tab_id);
+    }
+    Ok(outcome)
+}
+
+/// Groups of open tabs showing the same page, each with a recommended survivor
+#[tauri::command]
+async fn find_duplicate_tabs(
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<duplicates::DuplicateGroup>, String> {
+    let storage = state.read().await;
+    Ok(duplicates::find_duplicates(storage.tabs.values()))
+}
+
+/// Keep `survivor` and close `duplicates` in their browsers; returns how many were confirmed
+/// closed (or found already gone), or queued until their browser connects
+#[tauri::command]
+async fn merge_duplicate_tabs(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+    survivor: i64,
+    duplicates: Vec<i64>,
+) -> Result<usize, String> {
+    let merged = state
+        .write()
+        .await
+        .merge_duplicates(survivor, &duplicates)?;
+
+    // As in close_tab: not holding the lock while the extensions answer
+    let results =
+        futures_util::future::join_all(merged.iter().map(|(_, browser_tab)| async move {
+            match browser_tab {
+                Some(tab) => server::close_browser_tab(tab).await,
+                // Not open in the current browser session
+                None => Ok(server::CloseOutcome::AlreadyGone),
+            }
+        }))
+        .await;
+    let mut closed = 0;
+    let mut queued = false;
+    let mut confirmed = Vec::new();
+    for ((tab_id, _), result) in merged.iter().zip(results) {
+        match result {
+            Ok(server::CloseOutcome::Queued) => {
+                closed += 1;
+                queued = true;
+            }
+            Ok(_) => {
+                closed += 1;
+                confirmed.push(*tab_id);
+            }
+            Err(e) => println!("[Duplicates] Tab {} was not closed: {}", tab_id, e),
+        }
+    }
+    if !confirmed.is_empty() {
+        let mut storage = state.write().await;
+        for tab_id in confirmed {
+            storage.close_tab(tab_id);
+        }
+    }
+    if queued {
+        let _ = app.emit("command-queue-changed", ());
+    }
+    Ok(closed)
 }
 
 #[tauri::command]
@@ -232,37 +881,110 @@ async fn mark_keep(state: tauri::State<'_, AppState>, tab_id: i64) -> Result<(),
             scored_at: chrono::Utc::now().timestamp_millis(),
         },
     );
-    storage.save_tabs().map_err(|e| e.to_string())
+    Ok(())
 }
 
 #[tauri::command]
 async fn clear_suggestions(state: tauri::State<'_, AppState>) -> Result<(), String> {
+    let _paused = persist::pause_flushes(&state).await;
     let mut storage = state.write().await;
-    for tab in storage.tabs.values_mut() {
-        tab.suggestion = None;
-    }
-    storage.save_tabs().map_err(|e| e.to_string())
+    storage.clear_suggestions().map_err(|e| e.to_string())
 }
 
 #[tauri::command]
 async fn clear_data(state: tauri::State<'_, AppState>) -> Result<(), String> {
+    let _paused = persist::pause_flushes(&state).await;
     let mut storage = state.write().await;
-    storage.clear();
-    storage.save_tabs().map_err(|e| e.to_string())?;
-    storage.save_report().map_err(|e| e.to_string())
+    storage.clear().map_err(|e| e.to_string())
 }
 
+/// Have every extension recapture and resync its tabs; resolves once one of them is done
 #[tauri::command]
 async fn trigger_refresh() -> Result<(), String> {
-    // Send refresh command to connected extensions via WebSocket
-    if let Some(sender) = server::get_command_sender() {
-        sender
-            .send("refresh_all".to_string())
-            .map_err(|e| format!("Failed to send refresh command: {}", e))?;
-        Ok(())
-    } else {
-        Err("Command sender not initialized".to_string())
+    server::send_command(protocol::Command::RefreshAll, server::Target::All)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Commands waiting for their browser to connect, and those delivered or failed in the past
+/// week, newest first
+#[tauri::command]
+fn list_queued_commands() -> Result<Vec<queue::QueuedCommand>, String> {
+    let queue = queue::get().ok_or_else(|| "The command queue is unavailable".to_string())?;
+    queue
+        .list(chrono::Utc::now().timestamp_millis())
+        .map_err(|e| e.to_string())
+}
+
+/// Drop a pending command; false if it was already delivered or failed
+#[tauri::command]
+fn cancel_queued_command(app: tauri::AppHandle, command_id: i64) -> Result<bool, String> {
+    let queue = queue::get().ok_or_else(|| "The command queue is unavailable".to_string())?;
+    let cancelled = queue
+        .failed(
+            command_id,
+            "Cancelled",
+            chrono::Utc::now().timestamp_millis(),
+        )
+        .map_err(|e| e.to_string())?;
+    if cancelled {
+        let _ = app.emit("command-queue-changed", ());
+    }
+    Ok(cancelled)
+}
+
+/// A read-only token for scripts calling the REST API; shown once, listed with the paired
+/// extensions for revoking
+#[tauri::command]
+fn create_api_token(app: tauri::AppHandle, name: Option<String>) -> Result<String, String> {
+    let token = installed_pairing()?
+        .create_api_token(name)
+        .map_err(|e| e.to_string())?;
+    let _ = app.emit("pairing-changed", ());
+    Ok(token)
+}
+
+/// Extensions connected right now, with the browser and profile each runs in
+#[tauri::command]
+fn list_clients() -> Vec<server::ConnectedClient> {
+    server::connected_clients()
+}
+
+fn installed_pairing() -> Result<&'static pairing::Pairing, String> {
+    pairing::get().ok_or_else(|| "Pairing is unavailable".to_string())
+}
+
+/// The pairing secret and the extensions paired with it
+#[tauri::command]
+async fn get_pairing() -> Result<pairing::PairingInfo, String> {
+    Ok(installed_pairing()?.info())
+}
+
+/// Replaces the secret and unpairs every extension
+#[tauri::command]
+async fn rotate_pairing_secret() -> Result<pairing::PairingInfo, String> {
+    let info = installed_pairing()?
+        .rotate_secret()
+        .map_err(|e| e.to_string())?;
+    println!("[Pairing] Rotated secret, all extensions unpaired");
+    Ok(info)
+}
+
+#[tauri::command]
+async fn revoke_paired_client(client_id: String) -> Result<bool, String> {
+    let revoked = installed_pairing()?
+        .revoke(&client_id)
+        .map_err(|e| e.to_string())?;
+    if revoked {
+        println!("[Pairing] Revoked extension {}", client_id);
     }
+    Ok(revoked)
+}
+
+/// Token the desktop window adds to screenshot URLs
+#[tauri::command]
+async fn get_server_token() -> Result<String, String> {
+    Ok(installed_pairing()?.app_token().to_string())
 }
 
 #[tauri::command]
@@ -272,11 +994,7 @@ async fn cleanup_old_tabs(
 ) -> Result<usize, String> {
     let days_old = days.unwrap_or(7); // Default to 7 days
     let mut storage = state.write().await;
-    let count = storage.cleanup_old_tabs(days_old);
-    if count > 0 {
-        storage.save_tabs().map_err(|e| e.to_string())?;
-    }
-    Ok(count)
+    Ok(storage.cleanup_old_tabs(days_old))
 }
 
 #[tauri::command]
@@ -287,6 +1005,16 @@ async fn get_storage_stats(
     Ok(storage.get_stats())
 }
 
+/// AI calls and estimated cost per day and per month, newest first
+#[tauri::command]
+async fn get_ai_usage(
+    state: tauri::State<'_, AppState>,
+    days: Option<u32>,
+    months: Option<u32>,
+) -> Result<usage::UsageSummary, String> {
+    usage::summary(&state, days, months).await
+}
+
 /// Sync storage with actual Chrome tabs - removes tabs that no longer exist
 #[tauri::command]
 async fn sync_tabs(
@@ -294,9 +1022,5 @@ async fn sync_tabs(
     chrome_tab_ids: Vec<i64>,
 ) -> Result<usize, String> {
     let mut storage = state.write().await;
-    let count = storage.sync_with_chrome_tabs(&chrome_tab_ids);
-    if count > 0 {
-        storage.save_tabs().map_err(|e| e.to_string())?;
-    }
-    Ok(count)
+    Ok(storage.sync_with_chrome_tabs(&chrome_tab_ids, None))
 }
@@ -0,0 +1,324 @@
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::persist;
+
+/// Field stamped into every persisted JSON document
+const VERSION_FIELD: &str = "schema_version";
+
+/// A forward migration that upgrades a document by exactly one version
+type Migration = fn(Value) -> Result<Value, String>;
+
+/// SETTINGS_MIGRATIONS[n] upgrades a version-n settings document to version n + 1
+const SETTINGS_MIGRATIONS: &[Migration] = &[settings_v0_to_v1];
+
+/// TABS_MIGRATIONS[n] upgrades a version-n tabs document to version n + 1
+const TABS_MIGRATIONS: &[Migration] = &[tabs_v0_to_v1, tabs_v1_to_v2];
+
+pub const SETTINGS_VERSION: u32 = SETTINGS_MIGRATIONS.len() as u32;
+
+fn document_version(value: &Value) -> u32 {
+    value
+        .get(VERSION_FIELD)
+        .and_then(|v| v.as_u64())
+        .map(|v| v as u32)
+        .unwrap_or(0)
+}
+
+/// Run every migration between the document's stamped version and the latest one
+fn upgrade(mut value: Value, migrations: &[Migration]) -> Result<Value, String> {
+    let latest = migrations.len() as u32;
+    let mut version = document_version(&value);
+    if version > latest {
+        return Err(format!(
+            "written by a newer version of Tabula (schema {} > {})",
+            version, latest
+        ));
+    }
+
+    while version < latest {
+        value = migrations[version as usize](value)
+            .map_err(|e| format!("migration v{} -> v{} failed: {}", version, version + 1, e))?;
+        version += 1;
+        stamp(&mut value, version);
+    }
+    Ok(value)
+}
+
+fn stamp(value: &mut Value, version: u32) {
+    if let Value::Object(map) = value {
+        map.insert(VERSION_FIELD.to_string(), Value::from(version));
+    }
+}
+
+fn parse_versioned<T: DeserializeOwned>(data: &str, migrations: &[Migration]) -> Result<T, String> {
+    let value: Value = serde_json::from_str(data).map_err(|e| e.to_string())?;
+    let value = upgrade(value, migrations)?;
+    serde_json::from_value(value).map_err(|e| e.to_string())
+}
+
+/// Parse a stored settings document, upgrading older layouts
+pub fn parse_settings<T: DeserializeOwned>(data: &str) -> Result<T, String> {
+    parse_versioned(data, SETTINGS_MIGRATIONS)
+}
+
+/// Serialize settings with the current schema version stamped in
+pub fn settings_to_string<T: serde::Serialize>(settings: &T) -> Result<String, String> {
+    let mut value = serde_json::to_value(settings).map_err(|e| e.to_string())?;
+    stamp(&mut value, SETTINGS_VERSION);
+    serde_json::to_string(&value).map_err(|e| e.to_string())
+}
+
+/// Parse a tabs document (the legacy `tabs.json` layout is version 0)
+pub fn parse_tabs<T: DeserializeOwned>(data: &str) -> Result<T, String> {
+    let value = parse_versioned::<Value>(data, TABS_MIGRATIONS)?;
+    let tabs = value
+        .get("tabs")
+        .cloned()
+        .ok_or_else(|| "missing \"tabs\"".to_string())?;
+    serde_json::from_value(tabs).map_err(|e| e.to_string())
+}
+
+/// Settings v0 (unversioned settings.json) already matches the v1 layout.
+/// Drop values of the wrong type so one bad field doesn't discard the rest.
+fn settings_v0_to_v1(mut value: Value) -> Result<Value, String> {
+    let map = value.as_object_mut().ok_or("settings is not an object")?;
+    for key in ["openai_api_key", "base_url", "model", "user_context"] {
+        if map.get(key).is_some_and(|v| !v.is_string() && !v.is_null()) {
+            map.remove(key);
+        }
+    }
+    if map
+        .get("analyze_batch_size")
+        .is_some_and(|v| !v.is_u64() && !v.is_null())
+    {
+        map.remove("analyze_batch_size");
+    }
+    Ok(value)
+}
+
+/// Tabs v0 is a bare `{ "<id>": TabRecord }` map. v1 wraps it as `{ "tabs": { ... } }`
+/// and fills in fields that early builds did not write.
+fn tabs_v0_to_v1(value: Value) -> Result<Value, String> {
+    let Value::Object(mut tabs) = value else {
+        return Err("tabs is not an object".to_string());
+    };
+    tabs.remove(VERSION_FIELD);
+
+    for (key, tab) in tabs.iter_mut() {
+        let Some(tab) = tab.as_object_mut() else {
+            continue;
+        };
+        if !tab.contains_key("id") {
+            if let Ok(id) = key.parse::<i64>() {
+                tab.insert("id".to_string(), Value::from(id));
+            }
+        }
+        if !tab.contains_key("created_at") {
+            let created_at = tab
+                .get("last_active_at")
+                .filter(|v| !v.is_null())
+                .cloned()
+                .unwrap_or(Value::from(0));
+            tab.insert("created_at".to_string(), created_at);
+        }
+        tab.entry("total_active_ms").or_insert(Value::from(0));
+        tab.entry("is_active").or_insert(Value::from(false));
+    }
+
+    Ok(serde_json::json!({ "tabs": tabs }))
+}
+
+/// Tabs v2 separates the logical id from Chrome's tab id. Older records were keyed
+/// by the Chrome id, so it becomes both.
+fn tabs_v1_to_v2(mut value: Value) -> Result<Value, String> {
+    let tabs = value
+        .get_mut("tabs")
+        .and_then(|t| t.as_object_mut())
+        .ok_or("missing \"tabs\"")?;
+
+    for tab in tabs.values_mut().filter_map(|t| t.as_object_mut()) {
+        if let Some(id) = tab.get("id").cloned() {
+            tab.entry("chrome_id").or_insert(id);
+        }
+    }
+    Ok(value)
+}
+
+fn corrupt_suffix() -> String {
+    format!("corrupt-{}", chrono::Local::now().format("%Y%m%d%H%M%S"))
+}
+
+/// Move an unreadable file aside as `<name>.corrupt-<timestamp>` so it is never overwritten
+pub fn quarantine(path: &Path) -> Option<PathBuf> {
+    let mut target = path.as_os_str().to_owned();
+    target.push(format!(".{}", corrupt_suffix()));
+    let target = PathBuf::from(target);
+
+    match fs::rename(path, &target) {
+        Ok(()) => {
+            eprintln!(
+                "[Migrate] Quarantined {} as {}",
+                path.display(),
+                target.display()
+            );
+            Some(target)
+        }
+        Err(e) => {
+            eprintln!("[Migrate] Failed to quarantine {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+/// Write an unreadable stored document to `<dir>/<name>.corrupt-<timestamp>`
+pub fn quarantine_contents(dir: &Path, name: &str, contents: &str) -> Option<PathBuf> {
+    let target = dir.join(format!("{}.{}", name, corrupt_suffix()));
+    match persist::write_atomic(&target, contents.as_bytes()) {
+        Ok(()) => {
+            eprintln!("[Migrate] Quarantined {} as {}", name, target.display());
+            Some(target)
+        }
+        Err(e) => {
+            eprintln!("[Migrate] Failed to quarantine {}: {}", name, e);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{Settings, TabRecord};
+    use std::collections::HashMap;
+
+    fn temp_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("tabula-migrate-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Files in `dir` whose name starts with `prefix`
+    fn files_starting_with(dir: &Path, prefix: &str) -> Vec<PathBuf> {
+        fs::read_dir(dir)
+            .unwrap()
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| {
+                path.file_name()
+                    .is_some_and(|name| name.to_string_lossy().starts_with(prefix))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn upgrades_a_legacy_tabs_file() {
+        // As the first builds wrote it: keyed by Chrome id, no version, missing fields
+        let data = r#"{
+            "412": {
+                "id": 412,
+                "window_id": 3,
+                "url": "https://docs.rs/tokio",
+                "title": "tokio - Rust",
+                "fav_icon_url": null,
+                "last_active_at": 1700000000000
+            },
+            "415": {
+                "url": "https://example.com/",
+                "title": "Example",
+                "window_id": 3,
+                "fav_icon_url": null,
+                "last_active_at": null,
+                "total_active_ms": 5000,
+                "is_active": true
+            }
+        }"#;
+
+        let tabs: HashMap<i64, TabRecord> = parse_tabs(data).unwrap();
+
+        let docs = &tabs[&412];
+        assert_eq!(docs.chrome_id, Some(412));
+        assert_eq!(docs.created_at, 1_700_000_000_000);
+        assert_eq!(docs.total_active_ms, 0);
+        assert!(!docs.is_active);
+        // The id comes from the key when the record lacks one
+        let example = &tabs[&415];
+        assert_eq!(example.id, 415);
+        assert_eq!(example.chrome_id, Some(415));
+        assert_eq!(example.created_at, 0);
+        assert_eq!(example.total_active_ms, 5_000);
+        assert!(example.is_active);
+    }
+
+    #[test]
+    fn a_legacy_settings_file_keeps_the_fields_it_can() {
+        let data = r#"{
+            "openai_api_key": 12345,
+            "model": "gpt-4o-mini",
+            "user_context": "Rust developer",
+            "analyze_batch_size": "thirty"
+        }"#;
+
+        let settings: Settings = parse_settings(data).unwrap();
+
+        assert_eq!(settings.openai_api_key, None);
+        assert_eq!(settings.model.as_deref(), Some("gpt-4o-mini"));
+        assert_eq!(settings.user_context.as_deref(), Some("Rust developer"));
+        assert_eq!(
+            settings.analyze_batch_size,
+            Settings::default().analyze_batch_size
+        );
+    }
+
+    #[test]
+    fn saved_settings_are_stamped_and_read_back() {
+        let settings = Settings {
+            model: Some("gpt-4o".to_string()),
+            ..Settings::default()
+        };
+
+        let data = settings_to_string(&settings).unwrap();
+        let value: Value = serde_json::from_str(&data).unwrap();
+        assert_eq!(document_version(&value), SETTINGS_VERSION);
+
+        let read: Settings = parse_settings(&data).unwrap();
+        assert_eq!(read.model.as_deref(), Some("gpt-4o"));
+    }
+
+    #[test]
+    fn rejects_documents_from_a_newer_version() {
+        let settings = format!(r#"{{"schema_version": {}}}"#, SETTINGS_VERSION + 1);
+        let error = parse_settings::<Settings>(&settings).unwrap_err();
+        assert!(error.contains("newer version"), "{}", error);
+
+        let tabs = r#"{"schema_version": 99, "tabs": {}}"#;
+        assert!(parse_tabs::<HashMap<i64, TabRecord>>(tabs).is_err());
+    }
+
+    #[test]
+    fn quarantines_corrupt_files_instead_of_overwriting_them() {
+        let dir = temp_dir();
+        let path = dir.join("tabs.json");
+        fs::write(&path, "{ not json").unwrap();
+        assert!(parse_tabs::<HashMap<i64, TabRecord>>("{ not json").is_err());
+
+        let moved = quarantine(&path).unwrap();
+
+        assert!(!path.exists());
+        assert_eq!(moved.parent(), Some(dir.as_path()));
+        let name = moved.file_name().unwrap().to_string_lossy().into_owned();
+        assert!(name.starts_with("tabs.json.corrupt-"), "{}", name);
+        assert_eq!(fs::read_to_string(&moved).unwrap(), "{ not json");
+
+        let copied = quarantine_contents(&dir, "settings.json", "{\"model\": ").unwrap();
+        assert_eq!(
+            files_starting_with(&dir, "settings.json.corrupt-"),
+            vec![copied.clone()]
+        );
+        assert_eq!(fs::read_to_string(&copied).unwrap(), "{\"model\": ");
+
+        fs::remove_dir_all(&dir).ok();
+    }
+}
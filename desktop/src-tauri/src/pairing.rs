@@ -0,0 +1,418 @@
+//! Pairing between browser extensions and the desktop server. The desktop shows a secret;
+//! an extension sends it once to `/pair` and gets a token that every later request
+//! carries. Scripts use read-only API tokens created in the desktop app instead. Only hashes
+//! of the tokens are stored.
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, OnceLock, RwLock};
+use tokio::sync::watch;
+
+use crate::db::Database;
+
+/// Origins extension pages and service workers are served from
+const EXTENSION_SCHEMES: &[&str] = &[
+    "chrome-extension://",
+    "moz-extension://",
+    "safari-web-extension://",
+];
+
+/// Stored as the origin of API tokens; never sent by browsers, so it gets no CORS access
+pub const API_TOKEN_ORIGIN: &str = "tabula-api";
+
+/// Hex characters of the token hash used as a client's id
+const CLIENT_ID_LEN: usize = 12;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PairedClient {
+    /// Short id for revoking, derived from the token hash
+    pub id: String,
+    /// Extension origin, e.g. `chrome-extension://<extension id>`
+    pub origin: String,
+    /// What the extension called itself when pairing, e.g. "Chrome on macOS"
+    pub name: Option<String>,
+    pub paired_at: i64,
+}
+
+impl PairedClient {
+    pub fn new(token_hash: &str, origin: String, name: Option<String>, paired_at: i64) -> Self {
+        Self {
+            id: token_hash.chars().take(CLIENT_ID_LEN).collect(),
+            origin,
+            name,
+            paired_at,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PairingInfo {
+    /// Entered in the extension to pair it
+    pub secret: String,
+    /// Oldest first
+    pub clients: Vec<PairedClient>,
+}
+
+#[derive(Debug)]
+pub enum PairError {
+    /// The request did not come from a browser extension
+    NotAnExtension,
+    WrongSecret,
+    Storage(rusqlite::Error),
+}
+
+impl fmt::Display for PairError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PairError::NotAnExtension => write!(f, "pairing is only open to browser extensions"),
+            PairError::WrongSecret => write!(f, "wrong pairing secret"),
+            PairError::Storage(e) => write!(f, "failed to save pairing: {}", e),
+        }
+    }
+}
+
+fn hash(value: &str) -> String {
+    Sha256::digest(value.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+fn new_secret() -> String {
+    uuid::Uuid::new_v4().simple().to_string()
+}
+
+fn new_token() -> String {
+    format!(
+        "{}{}",
+        uuid::Uuid::new_v4().simple(),
+        uuid::Uuid::new_v4().simple()
+    )
+}
+
+struct State {
+    secret: String,
+    /// Keyed by token hash
+    clients: HashMap<String, PairedClient>,
+}
+
+pub struct Pairing {
+    db: Arc<Database>,
+    state: RwLock<State>,
+    /// Lets the desktop window load screenshots; new on every launch and never stored
+    app_token: String,
+    /// Bumped whenever tokens are revoked, so open WebSockets can check theirs
+    revocations: watch::Sender<u64>,
+}
+
+impl Pairing {
+    /// Loads the secret and paired clients, creating a secret on first run
+    pub fn load(db: Arc<Database>) -> rusqlite::Result<Self> {
+        let secret = match db.load_pairing_secret()? {
+            Some(secret) => secret,
+            None => {
+                let secret = new_secret();
+                db.save_pairing_secret(&secret)?;
+                secret
+            }
+        };
+        let clients = db.load_paired_clients()?;
+        Ok(Self {
+            db,
+            state: RwLock::new(State { secret, clients }),
+            app_token: new_token(),
+            revocations: watch::channel(0).0,
+        })
+    }
+
+    fn state(&self) -> std::sync::RwLockReadGuard<'_, State> {
+        self.state.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn state_mut(&self) -> std::sync::RwLockWriteGuard<'_, State> {
+        self.state.write().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn info(&self) -> PairingInfo {
+        let state = self.state();
+        let mut clients: Vec<PairedClient> = state.clients.values().cloned().collect();
+        clients.sort_by(|a, b| a.paired_at.cmp(&b.paired_at).then(a.id.cmp(&b.id)));
+        PairingInfo {
+            secret: state.secret.clone(),
+            clients,
+        }
+    }
+
+    /// Pairs the extension at `origin` if `secret` is right, returning its new token
+    pub fn pair(
+        &self,
+        secret: &str,
+        origin: Option<&str>,
+        name: Option<String>,
+    ) -> Result<String, PairError> {
+        let origin = origin
+            .filter(|o| EXTENSION_SCHEMES.iter().any(|scheme| o.starts_with(scheme)))
+            .ok_or(PairError::NotAnExtension)?;
+
+        let mut state = self.state_mut();
+        // Compare hashes so the comparison takes no longer for a closer guess
+        if hash(secret.trim()) != hash(&state.secret) {
+            return Err(PairError::WrongSecret);
+        }
+
+        Self::add_client(&self.db, &mut state, origin, name).map_err(PairError::Storage)
+    }
+
+    /// A token for scripts that may only read, e.g. `GET /tabs`
+    pub fn create_api_token(&self, name: Option<String>) -> rusqlite::Result<String> {
+        Self::add_client(&self.db, &mut self.state_mut(), API_TOKEN_ORIGIN, name)
+    }
+
+    fn add_client(
+        db: &Database,
+        state: &mut State,
+        origin: &str,
+        name: Option<String>,
+    ) -> rusqlite::Result<String> {
+        let token = new_token();
+        let token_hash = hash(&token);
+        let client = PairedClient::new(
+            &token_hash,
+            origin.to_string(),
+            name.filter(|n| !n.trim().is_empty()),
+            chrono::Utc::now().timestamp_millis(),
+        );
+        db.save_paired_client(&token_hash, &client)?;
+        state.clients.insert(token_hash, client);
+        Ok(token)
+    }
+
+    pub fn authorize(&self, token: &str) -> bool {
+        token == self.app_token || self.state().clients.contains_key(&hash(token))
+    }
+
+    /// Id of the paired client `token` belongs to; `None` for the desktop window's token
+    pub fn client_id(&self, token: &str) -> Option<String> {
+        self.state().clients.get(&hash(token)).map(|c| c.id.clone())
+    }
+
+    /// Whether `token` is an API token, which may not change anything
+    pub fn read_only(&self, token: &str) -> bool {
+        self.state()
+            .clients
+            .get(&hash(token))
+            .is_some_and(|c| c.origin == API_TOKEN_ORIGIN)
+    }
+
+    /// Whether browsers may read responses for `origin`: only paired extensions
+    pub fn allows_origin(&self, origin: &str) -> bool {
+        origin != API_TOKEN_ORIGIN && self.state().clients.values().any(|c| c.origin == origin)
+    }
+
+    pub fn app_token(&self) -> &str {
+        &self.app_token
+    }
+
+    /// A new secret; every paired extension has to pair again. API tokens were never
+    /// paired with the secret and stay valid until revoked.
+    pub fn rotate_secret(&self) -> rusqlite::Result<PairingInfo> {
+        let secret = new_secret();
+        self.db.reset_pairing(&secret, API_TOKEN_ORIGIN)?;
+        {
+            let mut state = self.state_mut();
+            state.secret = secret;
+            state.clients.retain(|_, c| c.origin == API_TOKEN_ORIGIN);
+        }
+        self.revocations.send_modify(|n| *n += 1);
+        Ok(self.info())
+    }
+
+    /// Revokes the token of client `id`; false if there is no such client
+    pub fn revoke(&self, id: &str) -> rusqlite::Result<bool> {
+        let removed = {
+            let mut state = self.state_mut();
+            let Some(token_hash) = state
+                .clients
+                .iter()
+                .find(|(_, c)| c.id == id)
+                .map(|(hash, _)| hash.clone())
+            else {
+                return Ok(false);
+            };
+            self.db.delete_paired_client(&token_hash)?;
+            state.clients.remove(&token_hash).is_some()
+        };
+        self.revocations.send_modify(|n| *n += 1);
+        Ok(removed)
+    }
+
+    /// Resolves once `token` is no longer valid
+    pub async fn revoked(&self, token: &str) {
+        let mut revocations = self.revocations.subscribe();
+        while self.authorize(token) {
+            if revocations.changed().await.is_err() {
+                // Never happens while `self` is alive; wait forever rather than spin
+                std::future::pending::<()>().await;
+            }
+        }
+    }
+}
+
+// Installed at startup; without it the server rejects every request
+static PAIRING: OnceLock<Pairing> = OnceLock::new();
+
+pub fn install(db: Arc<Database>) -> rusqlite::Result<()> {
+    let _ = PAIRING.set(Pairing::load(db)?);
+    Ok(())
+}
+
+pub fn get() -> Option<&'static Pairing> {
+    PAIRING.get()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ORIGIN: &str = "chrome-extension://abcdefghijklmnop";
+
+    fn pairing() -> Pairing {
+        Pairing::load(Arc::new(Database::open_in_memory().unwrap())).unwrap()
+    }
+
+    #[test]
+    fn pairs_extensions_that_know_the_secret() {
+        let pairing = pairing();
+        let secret = pairing.info().secret;
+
+        assert!(matches!(
+            pairing.pair("wrong", Some(ORIGIN), None),
+            Err(PairError::WrongSecret)
+        ));
+        assert!(matches!(
+            pairing.pair(&secret, Some("https://evil.example"), None),
+            Err(PairError::NotAnExtension)
+        ));
+        assert!(matches!(
+            pairing.pair(&secret, None, None),
+            Err(PairError::NotAnExtension)
+        ));
+        assert!(!pairing.allows_origin(ORIGIN));
+
+        let token = pairing
+            .pair(&secret, Some(ORIGIN), Some("Chrome".to_string()))
+            .unwrap();
+
+        assert!(pairing.authorize(&token));
+        assert!(!pairing.authorize("not-a-token"));
+        assert!(pairing.authorize(pairing.app_token()));
+        assert!(pairing.allows_origin(ORIGIN));
+        assert!(!pairing.allows_origin("https://evil.example"));
+        let clients = pairing.info().clients;
+        assert_eq!(clients.len(), 1);
+        assert_eq!(clients[0].name.as_deref(), Some("Chrome"));
+        assert_eq!(pairing.client_id(&token), Some(clients[0].id.clone()));
+        assert_eq!(pairing.client_id(pairing.app_token()), None);
+    }
+
+    #[test]
+    fn pairings_survive_a_restart() {
+        let db = Arc::new(Database::open_in_memory().unwrap());
+        let first = Pairing::load(db.clone()).unwrap();
+        let token = first
+            .pair(&first.info().secret, Some(ORIGIN), None)
+            .unwrap();
+
+        let second = Pairing::load(db).unwrap();
+
+        assert_eq!(second.info().secret, first.info().secret);
+        assert!(second.authorize(&token));
+        // The desktop window's token is per launch
+        assert!(!second.authorize(first.app_token()));
+    }
+
+    #[test]
+    fn revoking_and_rotating_invalidate_tokens() {
+        let pairing = pairing();
+        let secret = pairing.info().secret;
+        let first = pairing.pair(&secret, Some(ORIGIN), None).unwrap();
+        let second = pairing
+            .pair(&secret, Some("moz-extension://1234"), None)
+            .unwrap();
+
+        let id = pairing
+            .info()
+            .clients
+            .into_iter()
+            .find(|c| c.origin == ORIGIN)
+            .unwrap()
+            .id;
+        assert!(pairing.revoke(&id).unwrap());
+        assert!(!pairing.revoke(&id).unwrap());
+        assert!(!pairing.authorize(&first));
+        assert!(pairing.authorize(&second));
+
+        let rotated = pairing.rotate_secret().unwrap();
+        assert_ne!(rotated.secret, secret);
+        assert!(rotated.clients.is_empty());
+        assert!(!pairing.authorize(&second));
+        assert!(matches!(
+            pairing.pair(&secret, Some(ORIGIN), None),
+            Err(PairError::WrongSecret)
+        ));
+    }
+
+    #[test]
+    fn api_tokens_are_read_only_and_get_no_cors_access() {
+        let pairing = pairing();
+        let extension = pairing
+            .pair(&pairing.info().secret, Some(ORIGIN), None)
+            .unwrap();
+        let api = pairing
+            .create_api_token(Some("shell scripts".to_string()))
+            .unwrap();
+
+        assert!(pairing.authorize(&api));
+        assert!(pairing.read_only(&api));
+        assert!(!pairing.read_only(&extension));
+        assert!(!pairing.read_only(pairing.app_token()));
+        assert!(!pairing.allows_origin(API_TOKEN_ORIGIN));
+        let listed = pairing.info().clients;
+        assert!(listed
+            .iter()
+            .any(|c| c.origin == API_TOKEN_ORIGIN && c.name.as_deref() == Some("shell scripts")));
+
+        // Rotating unpairs extensions only, also after a restart
+        let rotated = pairing.rotate_secret().unwrap();
+        assert_eq!(rotated.clients.len(), 1);
+        assert!(!pairing.authorize(&extension));
+        assert!(pairing.authorize(&api));
+        let reloaded = Pairing::load(pairing.db.clone()).unwrap();
+        assert!(reloaded.authorize(&api));
+        assert!(!reloaded.authorize(&extension));
+    }
+
+    #[tokio::test]
+    async fn revoked_resolves_when_the_token_is_revoked() {
+        let pairing = Arc::new(pairing());
+        let token = pairing
+            .pair(&pairing.info().secret, Some(ORIGIN), None)
+            .unwrap();
+
+        let waiting = {
+            let pairing = pairing.clone();
+            let token = token.clone();
+            tokio::spawn(async move { pairing.revoked(&token).await })
+        };
+        tokio::task::yield_now().await;
+        assert!(!waiting.is_finished());
+
+        pairing.rotate_secret().unwrap();
+        tokio::time::timeout(std::time::Duration::from_secs(1), waiting)
+            .await
+            .unwrap()
+            .unwrap();
+    }
+}
@@ -0,0 +1,97 @@
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::Duration;
+use tokio::sync::OwnedMutexGuard;
+
+use crate::AppState;
+
+/// How often dirty tab rows are flushed to the database
+pub const FLUSH_INTERVAL_SECS: u64 = 5;
+
+/// Write a file so readers only ever see the old or the new contents:
+/// write to a temp file in the same directory, fsync it, then rename it into place.
+pub fn write_atomic(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let dir = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let file_name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let tmp_path = dir.join(format!(".{}.{}.tmp", file_name, uuid::Uuid::new_v4()));
+
+    let result = (|| {
+        let mut file = File::create(&tmp_path)?;
+        file.write_all(contents)?;
+        file.sync_all()?;
+        fs::rename(&tmp_path, path)?;
+        sync_dir(dir)
+    })();
+
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+    result
+}
+
+/// Persist the rename itself (directory entries are not covered by fsync on the file)
+#[cfg(unix)]
+fn sync_dir(dir: &Path) -> io::Result<()> {
+    File::open(dir)?.sync_all()
+}
+
+#[cfg(not(unix))]
+fn sync_dir(_dir: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+/// Write all dirty tab rows to the database.
+/// The storage lock is only held while collecting the rows, never during disk I/O.
+pub async fn flush(state: &AppState) {
+    let (db, flush_lock) = {
+        let storage = state.read().await;
+        (storage.db(), storage.flush_lock())
+    };
+
+    // Serialize flushes so an older batch never lands after a newer one
+    let _guard = flush_lock.lock().await;
+
+    let pending = state.write().await.take_pending_writes();
+    if pending.is_empty() {
+        return;
+    }
+
+    let tab_ids = pending.tab_ids();
+    let count = tab_ids.len();
+    let result = tokio::task::spawn_blocking(move || pending.apply(&db))
+        .await
+        .map_err(|e| e.to_string())
+        .and_then(|r| r.map_err(|e| e.to_string()));
+
+    if let Err(e) = result {
+        eprintln!("[Persist] Failed to flush {} tabs: {}", count, e);
+        // Retry on the next tick
+        state.write().await.mark_tabs_dirty(&tab_ids);
+    }
+}
+
+/// Wait for a running flush and hold off new ones until the guard is dropped.
+/// Anything writing to the database directly (clearing data, say) takes this first, so a
+/// batch taken before it cannot be written after it.
+pub async fn pause_flushes(state: &AppState) -> OwnedMutexGuard<()> {
+    let flush_lock = state.read().await.flush_lock();
+    flush_lock.lock_owned().await
+}
+
+/// Flush dirty state every FLUSH_INTERVAL_SECS seconds
+pub fn spawn_flusher(state: AppState) {
+    tauri::async_runtime::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(FLUSH_INTERVAL_SECS));
+        loop {
+            interval.tick().await;
+            flush(&state).await;
+        }
+    });
+}
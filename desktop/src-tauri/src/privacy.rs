@@ -0,0 +1,413 @@
+//! What may leave the machine: tabs withheld or reduced to their title by domain/URL
+//! globs, screenshots dropped for some sites, and personal data masked in any text sent to
+//! an AI provider.
+
+use regex::{Captures, Regex};
+use serde::Serialize;
+use std::sync::OnceLock;
+use url::Url;
+
+use crate::storage::{Settings, TabRecord};
+
+/// How much of a tab is sent to AI providers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Exposure {
+    /// Title, URL, description and screenshot
+    Full,
+    /// Everything but the screenshot
+    NoScreenshot,
+    /// Title and site only
+    TitleOnly,
+    /// Nothing; the tab is left out
+    Withheld,
+}
+
+/// A domain or URL glob. Patterns without a `/` match the host, and `*.example.com` also
+/// matches `example.com`; patterns with a `/` match host, path and query, e.g.
+/// `docs.google.com/document/*`. The scheme is ignored, and matching is case-insensitive.
+pub struct Glob {
+    regex: Regex,
+    matches_path: bool,
+}
+
+impl Glob {
+    /// `None` for an empty pattern
+    pub fn new(pattern: &str) -> Option<Self> {
+        let pattern = pattern.trim().to_lowercase();
+        let pattern = pattern
+            .strip_prefix("https://")
+            .or_else(|| pattern.strip_prefix("http://"))
+            .unwrap_or(&pattern);
+        if pattern.is_empty() {
+            return None;
+        }
+
+        let matches_path = pattern.contains('/');
+        let (prefix, rest) = match pattern.strip_prefix("*.") {
+            Some(rest) if !matches_path => (r"(?:.*\.)?", rest),
+            _ => ("", pattern),
+        };
+        let mut source = format!("^{}", prefix);
+        for c in rest.chars() {
+            match c {
+                '*' => source.push_str(".*"),
+                '?' => source.push('.'),
+                c => source.push_str(&regex::escape(&c.to_string())),
+            }
+        }
+        source.push('$');
+
+        // Every character is escaped, so the pattern always compiles
+        Regex::new(&source).ok().map(|regex| Glob {
+            regex,
+            matches_path,
+        })
+    }
+
+    fn matches(&self, target: &Target) -> bool {
+        if self.matches_path {
+            self.regex.is_match(&target.location)
+        } else {
+            self.regex.is_match(&target.host)
+        }
+    }
+
+    pub fn matches_url(&self, url: &str) -> bool {
+        self.matches(&Target::new(url))
+    }
+}
+
+/// The parts of a tab's URL that globs are matched against, lowercased like the globs
+struct Target {
+    host: String,
+    /// Host, path and query
+    location: String,
+}
+
+impl Target {
+    fn new(url: &str) -> Self {
+        match Url::parse(url) {
+            Ok(parsed) => {
+                let host = parsed.host_str().unwrap_or("").to_string();
+                let mut location = format!("{}{}", host, parsed.path());
+                if let Some(query) = parsed.query() {
+                    location.push('?');
+                    location.push_str(query);
+                }
+                Self {
+                    host,
+                    location: location.to_lowercase(),
+                }
+            }
+            Err(_) => {
+                let location = url.split("://").last().unwrap_or(url).to_lowercase();
+                let host = location.split('/').next().unwrap_or("").to_string();
+                Self { host, location }
+            }
+        }
+    }
+}
+
+fn globs(patterns: &Option<Vec<String>>) -> Vec<Glob> {
+    patterns
+        .iter()
+        .flatten()
+        .filter_map(|p| Glob::new(p))
+        .collect()
+}
+
+/// Masks for personal data and secrets, most specific first
+fn redactions() -> &'static [(Regex, &'static str)] {
+    static RULES: OnceLock<Vec<(Regex, &'static str)>> = OnceLock::new();
+    RULES.get_or_init(|| {
+        [
+            // Well-known API key formats and JWTs
+            (
+                r"\b(?:sk-[A-Za-z0-9_-]{16,}|gh[pousr]_[A-Za-z0-9]{20,}|github_pat_[A-Za-z0-9_]{20,}|xox[abprs]-[A-Za-z0-9-]{10,}|AKIA[0-9A-Z]{16}|AIza[0-9A-Za-z_-]{35}|eyJ[A-Za-z0-9_-]{8,}\.[A-Za-z0-9_-]{8,}\.[A-Za-z0-9_-]{8,})",
+                "[token]",
+            ),
+            (
+                r"(?i)\b(bearer)\s+[A-Za-z0-9._~+/-]{11,}[A-Za-z0-9_~+/-]=*",
+                "$1 [token]",
+            ),
+            // `token=...`, `api_key: ...` and the like, in URLs and text
+            (
+                r#"(?i)\b((?:access_|refresh_|api_?|auth_|client_|secret_|session_)?(?:token|key|secret|password|passwd|pwd|sig|signature))(["']?\s*[:=]\s*["']?)[^\s&#"',;]{8,}"#,
+                "${1}${2}[token]",
+            ),
+            (
+                r"[A-Za-z0-9._%+-]+@[A-Za-z0-9-]+(?:\.[A-Za-z0-9-]+)*\.[A-Za-z]{2,}",
+                "[email]",
+            ),
+            // Card numbers are masked only if their checksum is valid; see `redact`
+            (r"\b[2-6](?:[ -]?\d){12,18}\b", "[card]"),
+            (
+                r"(?:\+\d{1,3}[\s.-]?)?(?:\(\d{1,4}\)[\s.-]?|\b\d{2,4}[\s.-])\d{3,4}[\s.-]\d{3,4}\b|\+\d{10,14}\b",
+                "[phone]",
+            ),
+        ]
+        .into_iter()
+        .map(|(pattern, mask)| (Regex::new(pattern).expect("valid redaction pattern"), mask))
+        .collect()
+    })
+}
+
+/// Luhn checksum, to tell card numbers from other long digit runs
+fn luhn_valid(number: &str) -> bool {
+    let digits: Vec<u32> = number.chars().filter_map(|c| c.to_digit(10)).collect();
+    let sum: u32 = digits
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, &d)| {
+            if i % 2 == 1 {
+                let doubled = d * 2;
+                if doubled > 9 {
+                    doubled - 9
+                } else {
+                    doubled
+                }
+            } else {
+                d
+            }
+        })
+        .sum();
+    sum.is_multiple_of(10)
+}
+
+/// Privacy settings, compiled once per AI task
+pub struct PrivacyFilter {
+    blocked: Vec<Glob>,
+    title_only: Vec<Glob>,
+    no_screenshots: Vec<Glob>,
+    redact: bool,
+}
+
+impl PrivacyFilter {
+    pub fn new(settings: &Settings) -> Self {
+        Self {
+            blocked: globs(&settings.privacy_blocklist),
+            title_only: globs(&settings.privacy_title_only),
+            no_screenshots: globs(&settings.privacy_no_screenshots),
+            redact: settings.redact_personal_data.unwrap_or(true),
+        }
+    }
+
+    pub fn exposure(&self, tab: &TabRecord) -> Exposure {
+        let target = Target::new(tab.url.as_deref().unwrap_or(""));
+        let matches = |globs: &[Glob]| globs.iter().any(|g| g.matches(&target));
+        if matches(&self.blocked) {
+            Exposure::Withheld
+        } else if matches(&self.title_only) {
+            Exposure::TitleOnly
+        } else if matches(&self.no_screenshots) {
+            Exposure::NoScreenshot
+        } else {
+            Exposure::Full
+        }
+    }
+
+    pub fn is_withheld(&self, tab: &TabRecord) -> bool {
+        self.exposure(tab) == Exposure::Withheld
+    }
+
+    /// `text` with emails, tokens, API keys, phone and card numbers masked
+    pub fn redact(&self, text: &str) -> String {
+        if !self.redact {
+            return text.to_string();
+        }
+        let mut text = text.to_string();
+        for (regex, mask) in redactions() {
+            let replaced = if *mask == "[card]" {
+                regex.replace_all(&text, |caps: &Captures| {
+                    if luhn_valid(&caps[0]) {
+                        mask.to_string()
+                    } else {
+                        caps[0].to_string()
+                    }
+                })
+            } else {
+                regex.replace_all(&text, *mask)
+            };
+            text = replaced.into_owned();
+        }
+        text
+    }
+
+    /// The copy of `tab` that may be sent, or `None` if it is withheld
+    pub fn prepare(&self, tab: &TabRecord) -> Option<TabRecord> {
+        let exposure = self.exposure(tab);
+        if exposure == Exposure::Withheld {
+            return None;
+        }
+
+        let mut shared = tab.clone();
+        let redact = |text: &mut Option<String>| {
+            if let Some(value) = text.as_mut() {
+                *value = self.redact(value);
+            }
+        };
+        if exposure == Exposure::TitleOnly {
+            shared.url = tab.url.as_deref().map(site_of);
+            shared.description = None;
+            if let Some(suggestion) = shared.suggestion.as_mut() {
+                suggestion.digest = None;
+            }
+        }
+        if exposure != Exposure::Full {
+            shared.snapshot = None;
+        }
+        redact(&mut shared.url);
+        redact(&mut shared.title);
+        redact(&mut shared.description);
+        if let Some(suggestion) = shared.suggestion.as_mut() {
+            redact(&mut suggestion.digest);
+        }
+        Some(shared)
+    }
+
+    /// The tabs that may be sent, prepared, in order
+    pub fn shareable(&self, tabs: &[TabRecord]) -> Vec<TabRecord> {
+        let shared: Vec<TabRecord> = tabs.iter().filter_map(|tab| self.prepare(tab)).collect();
+        if shared.len() < tabs.len() {
+            println!(
+                "[Privacy] Withheld {} of {} tabs",
+                tabs.len() - shared.len(),
+                tabs.len()
+            );
+        }
+        shared
+    }
+}
+
+/// Scheme and host of a URL, e.g. "https://example.com/"
+fn site_of(url: &str) -> String {
+    match Url::parse(url) {
+        Ok(parsed) => format!("{}://{}/", parsed.scheme(), parsed.host_str().unwrap_or("")),
+        Err(_) => String::new(),
+    }
+}
+
+/// The privacy settings as one string, for cache keys of what was sent
+pub fn settings_key(settings: &Settings) -> String {
+    serde_json::json!([
+        settings.privacy_blocklist,
+        settings.privacy_title_only,
+        settings.privacy_no_screenshots,
+        settings.redact_personal_data.unwrap_or(true),
+    ])
+    .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    fn filter(blocked: &[&str], title_only: &[&str], no_screenshots: &[&str]) -> PrivacyFilter {
+        let list = |patterns: &[&str]| Some(patterns.iter().map(|p| p.to_string()).collect());
+        PrivacyFilter::new(&Settings {
+            privacy_blocklist: list(blocked),
+            privacy_title_only: list(title_only),
+            privacy_no_screenshots: list(no_screenshots),
+            ..Settings::default()
+        })
+    }
+
+    #[test]
+    fn globs_match_hosts_and_urls() {
+        let privacy = filter(
+            &["*.bank.example"],
+            &[
+                "wiki.corp.example/private/*",
+                "wiki.corp.example/display/HR/*",
+            ],
+            &["mail.*"],
+        );
+        let exposure = |url: &str| privacy.exposure(&test_support::tab(1, url).build());
+
+        assert_eq!(exposure("https://bank.example/login"), Exposure::Withheld);
+        assert_eq!(exposure("https://www.BANK.example/"), Exposure::Withheld);
+        assert_eq!(exposure("https://notbank.example/"), Exposure::Full);
+        assert_eq!(
+            exposure("https://wiki.corp.example/private/hr?page=2"),
+            Exposure::TitleOnly
+        );
+        assert_eq!(exposure("https://wiki.corp.example/public"), Exposure::Full);
+        // Paths match whatever their case in the pattern and the URL
+        assert_eq!(
+            exposure("https://wiki.corp.example/display/HR/Salaries?Tab=2"),
+            Exposure::TitleOnly
+        );
+        assert_eq!(
+            exposure("https://wiki.corp.example/Private/Notes"),
+            Exposure::TitleOnly
+        );
+        assert_eq!(
+            exposure("https://mail.example.com/"),
+            Exposure::NoScreenshot
+        );
+    }
+
+    #[test]
+    fn title_only_tabs_keep_title_and_site() {
+        let privacy = filter(&[], &["health.example"], &[]);
+        let shared = privacy
+            .prepare(
+                &test_support::tab(1, "https://health.example/results/42")
+                    .title("Account overview")
+                    .description("Blood test results")
+                    .screenshot("/tmp/1.jpg")
+                    .build(),
+            )
+            .unwrap();
+
+        assert_eq!(shared.url.as_deref(), Some("https://health.example/"));
+        assert_eq!(shared.title.as_deref(), Some("Account overview"));
+        assert!(shared.description.is_none());
+        assert!(shared.snapshot.is_none());
+        assert!(privacy
+            .prepare(
+                &test_support::tab(1, "https://other.example/")
+                    .screenshot("/tmp/1.jpg")
+                    .build(),
+            )
+            .unwrap()
+            .snapshot
+            .is_some());
+    }
+
+    #[test]
+    fn redacts_personal_data_and_secrets() {
+        let privacy = filter(&[], &[], &[]);
+        let text = "Mail jane.doe@example.com or call +1 (555) 123-4567. \
+                    Card 4111 1111 1111 1111, order #4111111111111112. \
+                    key sk-abcdefghijklmnopqrstu, Authorization: Bearer abcdef123456789. \
+                    Released 2026-10-17, version 1.2.3.";
+
+        let redacted = privacy.redact(text);
+
+        assert_eq!(
+            redacted,
+            "Mail [email] or call [phone]. \
+             Card [card], order #4111111111111112. \
+             key [token], Authorization: Bearer [token]. \
+             Released 2026-10-17, version 1.2.3."
+        );
+        let url = privacy.redact("https://app.example/cb?code=1&access_token=abc123def456&x=1");
+        assert_eq!(
+            url,
+            "https://app.example/cb?code=1&access_token=[token]&x=1"
+        );
+    }
+
+    #[test]
+    fn redaction_can_be_turned_off() {
+        let privacy = PrivacyFilter::new(&Settings {
+            redact_personal_data: Some(false),
+            ..Settings::default()
+        });
+        assert_eq!(privacy.redact("jane@example.com"), "jane@example.com");
+    }
+}
@@ -0,0 +1,187 @@
+//! WebSocket protocol between the desktop server and extensions. Every message is a JSON
+//! envelope `{"version", "id", "type", "payload"}`. An extension introduces itself with a
+//! `hello` once connected and answers each command with an `ack` or `error` carrying the
+//! command's id.
+
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Bumped on incompatible changes; extensions reject commands from a newer version
+pub const PROTOCOL_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Envelope<T> {
+    pub version: u32,
+    /// Chosen by the sender of a command and echoed in its reply
+    pub id: u64,
+    #[serde(flatten)]
+    pub body: T,
+}
+
+impl<T> Envelope<T> {
+    pub fn new(id: u64, body: T) -> Self {
+        Self {
+            version: PROTOCOL_VERSION,
+            id,
+            body,
+        }
+    }
+}
+
+/// Desktop → extension
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "payload", rename_all = "snake_case")]
+pub enum Command {
+    /// Capture each window's active tab and resync every tab
+    RefreshAll,
+    /// Close a Chrome tab
+    CloseTab { tab_id: i64 },
+    /// Fetch the capture policies again from `/health`
+    CapturePoliciesChanged,
+}
+
+impl Command {
+    /// How long to wait for the extension's reply
+    pub fn timeout(&self) -> Duration {
+        match self {
+            // Captures every window in turn
+            Command::RefreshAll => Duration::from_secs(120),
+            _ => Duration::from_secs(10),
+        }
+    }
+
+    /// How long the command may wait for its browser to connect. `None` for commands that
+    /// are moot by then, since extensions resync and refetch policies when they connect.
+    pub fn queue_ttl(&self) -> Option<Duration> {
+        match self {
+            Command::CloseTab { .. } => Some(Duration::from_secs(24 * 60 * 60)),
+            Command::RefreshAll | Command::CapturePoliciesChanged => None,
+        }
+    }
+}
+
+/// Extension → desktop. Acks and errors answer the command with the same id.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "payload", rename_all = "snake_case")]
+pub enum ClientMessage {
+    /// Sent right after connecting, before anything else
+    Hello(Hello),
+    Ack,
+    Error(ReplyError),
+}
+
+/// Which browser an extension runs in
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Hello {
+    /// e.g. "Chrome", "Edge"
+    pub browser: String,
+    #[serde(default)]
+    pub browser_version: Option<String>,
+    /// Label the user gave this browser profile in the extension
+    #[serde(default)]
+    pub profile: Option<String>,
+    #[serde(default)]
+    pub extension_version: Option<String>,
+    /// The `X-Tabula-Session` the extension sends with its tabs
+    #[serde(default)]
+    pub session_id: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReplyError {
+    pub code: ErrorCode,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// The tab to act on no longer exists
+    TabNotFound,
+    /// The extension does not know the command type
+    UnknownCommand,
+    /// The command came with a newer protocol version than the extension speaks
+    UnsupportedVersion,
+    /// Anything else; see the message
+    Failed,
+    /// Sent by extensions newer than this desktop app
+    #[serde(other)]
+    Unknown,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn commands_serialize_as_typed_envelopes() {
+        let close = Envelope::new(7, Command::CloseTab { tab_id: 123 });
+        assert_eq!(
+            serde_json::to_value(&close).unwrap(),
+            json!({"version": 1, "id": 7, "type": "close_tab", "payload": {"tab_id": 123}})
+        );
+        assert_eq!(
+            serde_json::to_value(Envelope::new(8, Command::RefreshAll)).unwrap(),
+            json!({"version": 1, "id": 8, "type": "refresh_all"})
+        );
+    }
+
+    #[test]
+    fn parses_hellos() {
+        let hello: Envelope<ClientMessage> = serde_json::from_value(json!({
+            "version": 1,
+            "id": 0,
+            "type": "hello",
+            "payload": {"browser": "Edge", "browser_version": "126", "session_id": "s1"}
+        }))
+        .unwrap();
+        assert_eq!(
+            hello.body,
+            ClientMessage::Hello(Hello {
+                browser: "Edge".to_string(),
+                browser_version: Some("126".to_string()),
+                session_id: Some("s1".to_string()),
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn parses_acks_and_errors() {
+        let ack: Envelope<ClientMessage> =
+            serde_json::from_value(json!({"version": 1, "id": 7, "type": "ack"})).unwrap();
+        assert_eq!(ack.body, ClientMessage::Ack);
+
+        let error: Envelope<ClientMessage> = serde_json::from_value(json!({
+            "version": 1,
+            "id": 8,
+            "type": "error",
+            "payload": {"code": "tab_not_found", "message": "No tab with id: 123"}
+        }))
+        .unwrap();
+        assert_eq!(error.id, 8);
+        assert_eq!(
+            error.body,
+            ClientMessage::Error(ReplyError {
+                code: ErrorCode::TabNotFound,
+                message: "No tab with id: 123".to_string(),
+            })
+        );
+
+        let newer: Envelope<ClientMessage> = serde_json::from_value(json!({
+            "version": 2,
+            "id": 9,
+            "type": "error",
+            "payload": {"code": "quota_exceeded", "message": "…"}
+        }))
+        .unwrap();
+        assert!(matches!(
+            newer.body,
+            ClientMessage::Error(ReplyError {
+                code: ErrorCode::Unknown,
+                ..
+            })
+        ));
+    }
+}
@@ -0,0 +1,231 @@
+//! Commands for browser extensions that could not be delivered right away, e.g. closing a
+//! tab while its browser is not running. They are kept in the database and replayed when the
+//! browser connects again, until they expire.
+
+use serde::Serialize;
+use std::sync::{Arc, OnceLock};
+
+use crate::db::Database;
+use crate::protocol::Command;
+
+/// How long delivered and failed commands stay listed
+const KEEP_FINISHED_MS: i64 = 7 * 24 * 60 * 60 * 1000;
+
+const EXPIRED: &str = "Expired before the browser connected";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CommandStatus {
+    /// Waiting for its browser to connect
+    Pending,
+    Delivered,
+    /// Refused by the extension, expired or cancelled; see `error`
+    Failed,
+}
+
+impl CommandStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CommandStatus::Pending => "pending",
+            CommandStatus::Delivered => "delivered",
+            CommandStatus::Failed => "failed",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "pending" => Some(CommandStatus::Pending),
+            "delivered" => Some(CommandStatus::Delivered),
+            "failed" => Some(CommandStatus::Failed),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct QueuedCommand {
+    pub id: i64,
+    pub command: Command,
+    /// Tab the command acts on. Its browser and Chrome id are looked up when the command is
+    /// sent, since both change when the browser restarts.
+    pub tab_id: Option<i64>,
+    pub status: CommandStatus,
+    pub error: Option<String>,
+    pub created_at: i64,
+    pub expires_at: i64,
+    pub updated_at: i64,
+}
+
+pub struct CommandQueue {
+    db: Arc<Database>,
+}
+
+impl CommandQueue {
+    pub fn new(db: Arc<Database>) -> Self {
+        Self { db }
+    }
+
+    /// Queues `command` for `tab_id`, or returns the same command already pending. `None` if
+    /// the command is not worth queueing.
+    pub fn enqueue(
+        &self,
+        command: Command,
+        tab_id: Option<i64>,
+        now: i64,
+    ) -> rusqlite::Result<Option<QueuedCommand>> {
+        let Some(ttl) = command.queue_ttl() else {
+            return Ok(None);
+        };
+        if let Some(existing) = self
+            .pending(now)?
+            .into_iter()
+            .find(|q| q.command == command && q.tab_id == tab_id)
+        {
+            return Ok(Some(existing));
+        }
+
+        let json = serde_json::to_string(&command)
+            .map_err(|e| rusqlite::Error::ToSqlConversionFailure(e.into()))?;
+        let expires_at = now + ttl.as_millis() as i64;
+        let id = self
+            .db
+            .insert_queued_command(&json, tab_id, now, expires_at)?;
+        Ok(Some(QueuedCommand {
+            id,
+            command,
+            tab_id,
+            status: CommandStatus::Pending,
+            error: None,
+            created_at: now,
+            expires_at,
+            updated_at: now,
+        }))
+    }
+
+    /// Commands still waiting to be sent, oldest first; overdue ones are failed first
+    pub fn pending(&self, now: i64) -> rusqlite::Result<Vec<QueuedCommand>> {
+        self.db.expire_queued_commands(now, EXPIRED)?;
+        self.db.load_queued_commands(Some(CommandStatus::Pending))
+    }
+
+    /// Every command from the past week, newest first
+    pub fn list(&self, now: i64) -> rusqlite::Result<Vec<QueuedCommand>> {
+        self.db.expire_queued_commands(now, EXPIRED)?;
+        self.db
+            .delete_finished_commands_before(now - KEEP_FINISHED_MS)?;
+        let mut commands = self.db.load_queued_commands(None)?;
+        commands.reverse();
+        Ok(commands)
+    }
+
+    /// False if the command was no longer pending
+    pub fn delivered(&self, id: i64, now: i64) -> rusqlite::Result<bool> {
+        self.db
+            .finish_queued_command(id, CommandStatus::Delivered, None, now)
+    }
+
+    pub fn failed(&self, id: i64, error: &str, now: i64) -> rusqlite::Result<bool> {
+        self.db
+            .finish_queued_command(id, CommandStatus::Failed, Some(error), now)
+    }
+}
+
+// Installed at startup; without it undeliverable commands fail instead of waiting
+static QUEUE: OnceLock<CommandQueue> = OnceLock::new();
+
+pub fn install(db: Arc<Database>) {
+    let _ = QUEUE.set(CommandQueue::new(db));
+}
+
+pub fn get() -> Option<&'static CommandQueue> {
+    QUEUE.get()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOUR_MS: i64 = 60 * 60 * 1000;
+
+    fn queue() -> CommandQueue {
+        CommandQueue::new(Arc::new(Database::open_in_memory().unwrap()))
+    }
+
+    fn close(tab_id: i64) -> Command {
+        Command::CloseTab { tab_id }
+    }
+
+    #[test]
+    fn queues_commands_once_and_only_when_worth_it() {
+        let queue = queue();
+
+        let first = queue.enqueue(close(11), Some(1), 1_000).unwrap().unwrap();
+        let again = queue.enqueue(close(11), Some(1), 2_000).unwrap().unwrap();
+        queue.enqueue(close(12), Some(2), 3_000).unwrap().unwrap();
+
+        assert_eq!(again.id, first.id);
+        assert_eq!(first.expires_at, 1_000 + 24 * HOUR_MS);
+        assert!(queue
+            .enqueue(Command::RefreshAll, None, 1_000)
+            .unwrap()
+            .is_none());
+        let pending = queue.pending(4_000).unwrap();
+        assert_eq!(
+            pending.iter().map(|q| q.tab_id).collect::<Vec<_>>(),
+            vec![Some(1), Some(2)]
+        );
+        assert_eq!(pending[0], first);
+    }
+
+    #[test]
+    fn commands_finish_once() {
+        let queue = queue();
+        let delivered = queue.enqueue(close(11), Some(1), 1_000).unwrap().unwrap();
+        let refused = queue.enqueue(close(12), Some(2), 1_000).unwrap().unwrap();
+
+        assert!(queue.delivered(delivered.id, 2_000).unwrap());
+        assert!(!queue.failed(delivered.id, "too late", 3_000).unwrap());
+        assert!(queue
+            .failed(refused.id, "Tabs cannot be edited", 2_000)
+            .unwrap());
+
+        assert!(queue.pending(4_000).unwrap().is_empty());
+        let listed = queue.list(4_000).unwrap();
+        assert_eq!(listed[0].id, refused.id);
+        assert_eq!(listed[0].status, CommandStatus::Failed);
+        assert_eq!(listed[0].error.as_deref(), Some("Tabs cannot be edited"));
+        assert_eq!(listed[1].status, CommandStatus::Delivered);
+        assert_eq!(listed[1].error, None);
+        // A finished close does not block queueing the tab again
+        assert_ne!(
+            queue
+                .enqueue(close(11), Some(1), 5_000)
+                .unwrap()
+                .unwrap()
+                .id,
+            delivered.id
+        );
+    }
+
+    #[test]
+    fn expired_commands_fail_and_old_ones_are_forgotten() {
+        let db = Arc::new(Database::open_in_memory().unwrap());
+        let queue = CommandQueue::new(db.clone());
+        let queued = queue.enqueue(close(11), Some(1), 0).unwrap().unwrap();
+
+        assert_eq!(queue.pending(queued.expires_at - 1).unwrap().len(), 1);
+        assert!(queue.pending(queued.expires_at).unwrap().is_empty());
+
+        // Survives a restart
+        let listed = CommandQueue::new(db).list(queued.expires_at).unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].status, CommandStatus::Failed);
+        assert_eq!(listed[0].error.as_deref(), Some(EXPIRED));
+        assert_eq!(listed[0].command, close(11));
+
+        assert!(queue
+            .list(queued.expires_at + KEEP_FINISHED_MS + 1)
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -0,0 +1,285 @@
+//! Read-only REST API for scripts and editor plugins: tab listing with filters and
+//! pagination, and tab statistics. Handlers live in `server.rs`.
+
+use chrono::{Local, NaiveDate};
+use serde::{Deserialize, Serialize};
+
+use crate::rollup::{self, PeriodStats};
+use crate::storage::{self, Storage, TabRecord};
+
+const DEFAULT_LIMIT: usize = 50;
+const MAX_LIMIT: usize = 500;
+
+/// Matches tabs the AI has not categorized, as in the statistics
+const UNCATEGORIZED: &str = "uncategorized";
+
+/// `GET /tabs` query
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct TabListQuery {
+    /// "open" (default), "closed" or "all"
+    pub status: Option<String>,
+    /// Tabs created, last active or closed on this day (YYYY-MM-DD, local time)
+    pub date: Option<String>,
+    /// Category of the AI suggestion; "uncategorized" for tabs without one
+    pub category: Option<String>,
+    pub limit: Option<usize>,
+    pub offset: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Status {
+    Open,
+    Closed,
+    All,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TabPage {
+    /// Matching tabs across all pages
+    pub total: usize,
+    pub offset: usize,
+    pub limit: usize,
+    /// Most recently active first
+    pub tabs: Vec<TabRecord>,
+}
+
+/// One page of the tabs matching `query`
+pub fn list_tabs<'a>(
+    tabs: impl Iterator<Item = &'a TabRecord>,
+    query: &TabListQuery,
+) -> Result<TabPage, String> {
+    let status = match query.status.as_deref().map(str::trim) {
+        None | Some("") | Some("open") => Status::Open,
+        Some("closed") => Status::Closed,
+        Some("all") => Status::All,
+        Some(other) => {
+            return Err(format!(
+                "Invalid status \"{}\", expected open, closed or all",
+                other
+            ))
+        }
+    };
+    let day = query
+        .date
+        .as_deref()
+        .map(storage::parse_date)
+        .transpose()?
+        .map(storage::day_bounds);
+    let category = query
+        .category
+        .as_deref()
+        .map(|c| c.trim().to_lowercase())
+        .filter(|c| !c.is_empty());
+
+    let mut matching: Vec<&TabRecord> = tabs
+        .filter(|t| match status {
+            Status::Open => t.closed_at.is_none(),
+            Status::Closed => t.closed_at.is_some(),
+            Status::All => true,
+        })
+        .filter(|t| {
+            day.is_none_or(|(start, end)| {
+                let on_day = |ts: i64| ts >= start && ts < end;
+                on_day(t.created_at)
+                    || t.last_active_at.is_some_and(on_day)
+                    || t.closed_at.is_some_and(on_day)
+            })
+        })
+        .filter(|t| {
+            category.as_deref().is_none_or(|wanted| {
+                match t.suggestion.as_ref().and_then(|s| s.category.as_deref()) {
+                    Some(c) => c.to_lowercase() == wanted,
+                    None => wanted == UNCATEGORIZED,
+                }
+            })
+        })
+        .collect();
+    matching.sort_by_key(|t| std::cmp::Reverse((t.last_active_at.unwrap_or(t.created_at), t.id)));
+
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let offset = query.offset.unwrap_or(0);
+    Ok(TabPage {
+        total: matching.len(),
+        offset,
+        limit,
+        tabs: matching
+            .into_iter()
+            .skip(offset)
+            .take(limit)
+            .cloned()
+            .collect(),
+    })
+}
+
+/// `GET /stats` query; both days default to today
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct StatsQuery {
+    pub from: Option<String>,
+    pub to: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StatsResponse {
+    /// Every tab record kept, open or closed
+    pub total_tabs: usize,
+    pub open_tabs: usize,
+    pub closed_tabs: usize,
+    pub from: String,
+    pub to: String,
+    /// Tabs created or active between `from` and `to`, as in weekly and monthly reports
+    pub period: PeriodStats,
+}
+
+pub fn stats(storage: &Storage, query: &StatsQuery) -> Result<StatsResponse, String> {
+    let today = Local::now().date_naive();
+    let parse = |date: &Option<String>| -> Result<NaiveDate, String> {
+        date.as_deref()
+            .map(storage::parse_date)
+            .transpose()
+            .map(|d| d.unwrap_or(today))
+    };
+    let from = parse(&query.from)?;
+    let to = parse(&query.to)?;
+    if from > to {
+        return Err("\"from\" is after \"to\"".to_string());
+    }
+
+    let (total_tabs, open_tabs, closed_tabs) = storage.get_stats();
+    let tabs = storage.get_tabs_between(from, to);
+    let active_ms = storage
+        .get_activity_between(from, to)
+        .map_err(|e| e.to_string())?;
+    Ok(StatsResponse {
+        total_tabs,
+        open_tabs,
+        closed_tabs,
+        from: storage::format_date(from),
+        to: storage::format_date(to),
+        period: rollup::period_stats(&tabs, &active_ms, storage::day_bounds(to).1),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    fn ids(page: &TabPage) -> Vec<i64> {
+        page.tabs.iter().map(|t| t.id).collect()
+    }
+
+    #[test]
+    fn lists_open_tabs_most_recent_first_by_default() {
+        let tabs = [
+            test_support::tab(1, "https://example.com/1")
+                .last_active_at(100)
+                .build(),
+            test_support::tab(2, "https://example.com/2")
+                .last_active_at(300)
+                .category("Research")
+                .build(),
+            test_support::tab(3, "https://example.com/3")
+                .last_active_at(200)
+                .closed_at(400)
+                .category("research")
+                .build(),
+        ];
+
+        let page = list_tabs(tabs.iter(), &TabListQuery::default()).unwrap();
+        assert_eq!(ids(&page), vec![2, 1]);
+        assert_eq!(page.total, 2);
+
+        let all = TabListQuery {
+            status: Some("all".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(ids(&list_tabs(tabs.iter(), &all).unwrap()), vec![2, 3, 1]);
+
+        let closed_research = TabListQuery {
+            status: Some("closed".to_string()),
+            category: Some("RESEARCH".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            ids(&list_tabs(tabs.iter(), &closed_research).unwrap()),
+            vec![3]
+        );
+
+        let uncategorized = TabListQuery {
+            category: Some("uncategorized".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            ids(&list_tabs(tabs.iter(), &uncategorized).unwrap()),
+            vec![1]
+        );
+    }
+
+    #[test]
+    fn filters_by_day_and_pages() {
+        let day = storage::parse_date("2024-03-10").unwrap();
+        let (start, end) = storage::day_bounds(day);
+        let tabs = [
+            test_support::tab(1, "https://example.com/1")
+                .last_active_at(start + 1_000)
+                .build(),
+            test_support::tab(2, "https://example.com/2")
+                .last_active_at(start - 1_000)
+                .build(),
+            // Closed that day after last being used the day before
+            test_support::tab(3, "https://example.com/3")
+                .last_active_at(start - 1_000)
+                .closed_at(start + 5_000)
+                .build(),
+            test_support::tab(4, "https://example.com/4")
+                .last_active_at(end)
+                .build(),
+        ];
+
+        let on_day = TabListQuery {
+            status: Some("all".to_string()),
+            date: Some("2024-03-10".to_string()),
+            limit: Some(1),
+            ..Default::default()
+        };
+        let first = list_tabs(tabs.iter(), &on_day).unwrap();
+        assert_eq!((first.total, first.limit), (2, 1));
+        assert_eq!(ids(&first), vec![1]);
+
+        let second = list_tabs(
+            tabs.iter(),
+            &TabListQuery {
+                offset: Some(1),
+                ..on_day.clone()
+            },
+        )
+        .unwrap();
+        assert_eq!(ids(&second), vec![3]);
+
+        let past_the_end = TabListQuery {
+            offset: Some(5),
+            ..on_day
+        };
+        assert!(list_tabs(tabs.iter(), &past_the_end)
+            .unwrap()
+            .tabs
+            .is_empty());
+    }
+
+    #[test]
+    fn rejects_bad_filters() {
+        let bad_status = TabListQuery {
+            status: Some("gone".to_string()),
+            ..Default::default()
+        };
+        assert!(list_tabs(std::iter::empty(), &bad_status).is_err());
+
+        let bad_date = TabListQuery {
+            date: Some("10/03/2024".to_string()),
+            ..Default::default()
+        };
+        assert!(list_tabs(std::iter::empty(), &bad_date).is_err());
+    }
+}
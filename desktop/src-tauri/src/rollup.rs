@@ -0,0 +1,350 @@
+//! Weekly and monthly rollups: the period covered, and time statistics computed from the
+//! archived tab records so the report's numbers do not depend on the model.
+
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::ai::extract_domain;
+use crate::storage::TabRecord;
+
+/// Longest period a rollup may cover
+const MAX_PERIOD_DAYS: i64 = 366;
+/// Domains listed in the time table; the rest are summed as "other"
+const TOP_DOMAINS: usize = 15;
+/// Tabs listed as still open at the end of the period
+const OPEN_TABS_LISTED: usize = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Granularity {
+    Week,
+    Month,
+}
+
+impl Granularity {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.to_ascii_lowercase().as_str() {
+            "week" | "weekly" => Ok(Granularity::Week),
+            "month" | "monthly" => Ok(Granularity::Month),
+            _ => Err(format!(
+                "Unknown granularity \"{}\", expected week or month",
+                value
+            )),
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Granularity::Week => "week",
+            Granularity::Month => "month",
+        }
+    }
+
+    pub fn adjective(self) -> &'static str {
+        match self {
+            Granularity::Week => "weekly",
+            Granularity::Month => "monthly",
+        }
+    }
+}
+
+/// Inclusive range of local dates
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Period {
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+}
+
+impl Period {
+    pub fn new(start: NaiveDate, end: NaiveDate) -> Result<Self, String> {
+        if end < start {
+            return Err(format!(
+                "Period ends ({}) before it starts ({})",
+                end, start
+            ));
+        }
+        let period = Self { start, end };
+        if period.days() > MAX_PERIOD_DAYS {
+            return Err(format!(
+                "Period of {} days is longer than {} days",
+                period.days(),
+                MAX_PERIOD_DAYS
+            ));
+        }
+        Ok(period)
+    }
+
+    pub fn days(&self) -> i64 {
+        (self.end - self.start).num_days() + 1
+    }
+
+    /// The period of the same length that ends the day before this one starts
+    pub fn previous(&self) -> Self {
+        let end = self.start.pred_opt().unwrap_or(self.start);
+        let start = end - chrono::Duration::days(self.days() - 1);
+        Self { start, end }
+    }
+
+    pub fn dates(&self) -> impl Iterator<Item = NaiveDate> {
+        self.start.iter_days().take(self.days() as usize)
+    }
+}
+
+/// Active time and tab count for one category or domain
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TimeShare {
+    pub name: String,
+    pub active_ms: i64,
+    pub tabs: usize,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PeriodStats {
+    pub tab_count: usize,
+    pub active_ms: i64,
+    /// Most time first
+    pub categories: Vec<TimeShare>,
+    /// Most time first, at most `TOP_DOMAINS` plus "other"
+    pub domains: Vec<TimeShare>,
+    /// Titles of tabs still open when the period ended, most used first
+    pub open_at_end: Vec<String>,
+}
+
+fn shares(totals: HashMap<String, (i64, usize)>) -> Vec<TimeShare> {
+    let mut shares: Vec<TimeShare> = totals
+        .into_iter()
+        .map(|(name, (active_ms, tabs))| TimeShare {
+            name,
+            active_ms,
+            tabs,
+        })
+        .collect();
+    shares.sort_by(|a, b| b.active_ms.cmp(&a.active_ms).then(a.name.cmp(&b.name)));
+    shares
+}
+
+/// Statistics over the tabs touched in a period. `active_ms` is the active time per tab id
+/// recorded on the days of the period; tabs missing from it count as unused.
+/// `end_ms` is the end of the period, for telling which tabs were still open.
+pub fn period_stats(tabs: &[TabRecord], active_ms: &HashMap<i64, i64>, end_ms: i64) -> PeriodStats {
+    let active = |tab: &TabRecord| active_ms.get(&tab.id).copied().unwrap_or(0);
+    let mut categories: HashMap<String, (i64, usize)> = HashMap::new();
+    let mut domains: HashMap<String, (i64, usize)> = HashMap::new();
+    for tab in tabs {
+        let category = tab
+            .suggestion
+            .as_ref()
+            .and_then(|s| s.category.clone())
+            .unwrap_or_else(|| "uncategorized".to_string());
+        let domain = tab
+            .url
+            .as_deref()
+            .map(extract_domain)
+            .unwrap_or_else(|| "unknown".to_string());
+        for (totals, key) in [(&mut categories, category), (&mut domains, domain)] {
+            let entry = totals.entry(key).or_default();
+            entry.0 += active(tab);
+            entry.1 += 1;
+        }
+    }
+
+    let mut domains = shares(domains);
+    if domains.len() > TOP_DOMAINS {
+        let rest = domains.split_off(TOP_DOMAINS);
+        domains.push(TimeShare {
+            name: "other".to_string(),
+            active_ms: rest.iter().map(|s| s.active_ms).sum(),
+            tabs: rest.iter().map(|s| s.tabs).sum(),
+        });
+    }
+
+    let mut open: Vec<&TabRecord> = tabs
+        .iter()
+        .filter(|t| t.closed_at.is_none_or(|closed| closed >= end_ms))
+        .collect();
+    open.sort_by_key(|t| std::cmp::Reverse(active(t)));
+
+    PeriodStats {
+        tab_count: tabs.len(),
+        active_ms: tabs.iter().map(active).sum(),
+        categories: shares(categories),
+        domains,
+        open_at_end: open
+            .into_iter()
+            .take(OPEN_TABS_LISTED)
+            .map(|t| t.title.clone().unwrap_or_else(|| "Untitled".to_string()))
+            .collect(),
+    }
+}
+
+fn format_hours(ms: i64) -> String {
+    format!("{:.1}h", ms as f64 / 3_600_000.0)
+}
+
+/// Change against the previous period, e.g. "+25%", or "new" if there was none
+fn format_change(current: i64, previous: Option<i64>) -> String {
+    match previous {
+        Some(previous) if previous > 0 => {
+            let change = (current - previous) as f64 / previous as f64 * 100.0;
+            format!("{:+.0}%", change)
+        }
+        _ if current > 0 => "new".to_string(),
+        _ => "-".to_string(),
+    }
+}
+
+fn time_table(title: &str, current: &[TimeShare], previous: &[TimeShare]) -> String {
+    let mut table = format!(
+        "### {}\n\n| {} | Time | Tabs | vs previous |\n|---|---|---|---|\n",
+        title, title
+    );
+    for share in current {
+        let before = previous
+            .iter()
+            .find(|p| p.name == share.name)
+            .map(|p| p.active_ms);
+        table.push_str(&format!(
+            "| {} | {} | {} | {} |\n",
+            share.name,
+            format_hours(share.active_ms),
+            share.tabs,
+            format_change(share.active_ms, before)
+        ));
+    }
+    table
+}
+
+/// Markdown tables of time per category and domain, compared with the previous period
+pub fn stats_markdown(current: &PeriodStats, previous: &PeriodStats) -> String {
+    format!(
+        "## Time spent\n\n{} tabs, {} active ({} vs previous period)\n\n{}\n{}",
+        current.tab_count,
+        format_hours(current.active_ms),
+        format_change(current.active_ms, Some(previous.active_ms)),
+        time_table("Category", &current.categories, &previous.categories),
+        time_table("Domain", &current.domains, &previous.domains)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    fn date(s: &str) -> NaiveDate {
+        crate::storage::parse_date(s).unwrap()
+    }
+
+    fn activity(entries: &[(i64, i64)]) -> HashMap<i64, i64> {
+        entries.iter().copied().collect()
+    }
+
+    #[test]
+    fn previous_period_has_the_same_length() {
+        let week = Period::new(date("2026-10-12"), date("2026-10-18")).unwrap();
+        assert_eq!(week.days(), 7);
+        let before = week.previous();
+        assert_eq!(
+            (before.start, before.end),
+            (date("2026-10-05"), date("2026-10-11"))
+        );
+        assert_eq!(week.dates().count(), 7);
+
+        assert!(Period::new(date("2026-10-18"), date("2026-10-12")).is_err());
+        assert!(Period::new(date("2025-01-01"), date("2026-10-12")).is_err());
+    }
+
+    #[test]
+    fn sums_time_by_category_and_domain() {
+        let tabs = vec![
+            // Also used before the period
+            test_support::tab(1, "https://docs.rs/tokio")
+                .category("research")
+                .active_ms(9_000_000)
+                .build(),
+            test_support::tab(2, "https://docs.rs/serde")
+                .category("research")
+                .active_ms(1_800_000)
+                .closed_at(10)
+                .build(),
+            test_support::tab(3, "https://mail.example.com/")
+                .active_ms(600_000)
+                .closed_at(500)
+                .build(),
+        ];
+        let in_period = activity(&[(1, 3_600_000), (2, 1_800_000), (3, 600_000)]);
+
+        let stats = period_stats(&tabs, &in_period, 100);
+
+        assert_eq!(stats.tab_count, 3);
+        assert_eq!(stats.active_ms, 6_000_000);
+        assert_eq!(stats.categories[0].name, "research");
+        // Only the time recorded in the period, not the lifetime totals
+        assert_eq!(stats.categories[0].active_ms, 5_400_000);
+        assert_eq!(stats.categories[1].name, "uncategorized");
+        assert_eq!(stats.domains[0].name, "docs.rs");
+        assert_eq!(stats.domains[0].tabs, 2);
+        // Closed after the period ended, so still open at its end
+        assert_eq!(
+            stats.open_at_end,
+            vec!["https://docs.rs/tokio", "https://mail.example.com/"]
+        );
+    }
+
+    #[test]
+    fn tabs_without_activity_in_the_period_count_no_time() {
+        let tabs = vec![
+            test_support::tab(1, "https://docs.rs/")
+                .category("research")
+                .active_ms(7_200_000)
+                .build(),
+            test_support::tab(2, "https://news.example/")
+                .active_ms(360_000)
+                .build(),
+        ];
+
+        let stats = period_stats(&tabs, &activity(&[(2, 60_000)]), 0);
+
+        assert_eq!(stats.tab_count, 2);
+        assert_eq!(stats.active_ms, 60_000);
+        assert_eq!(stats.categories[0].name, "uncategorized");
+        assert_eq!(stats.categories[1].active_ms, 0);
+        assert_eq!(
+            stats.open_at_end,
+            vec!["https://news.example/", "https://docs.rs/"]
+        );
+    }
+
+    #[test]
+    fn compares_with_previous_period() {
+        let docs = test_support::tab(1, "https://docs.rs/")
+            .category("research")
+            .active_ms(10_800_000)
+            .build();
+        let news = test_support::tab(2, "https://news.example/")
+            .category("entertainment")
+            .active_ms(360_000)
+            .build();
+        let current = period_stats(
+            &[docs.clone(), news],
+            &activity(&[(1, 7_200_000), (2, 360_000)]),
+            0,
+        );
+        let previous = period_stats(&[docs], &activity(&[(1, 3_600_000)]), 0);
+
+        let markdown = stats_markdown(&current, &previous);
+
+        assert!(
+            markdown.contains("| research | 2.0h | 1 | +100% |"),
+            "{}",
+            markdown
+        );
+        assert!(
+            markdown.contains("| entertainment | 0.1h | 1 | new |"),
+            "{}",
+            markdown
+        );
+        assert!(markdown.contains("2 tabs, 2.1h active (+110% vs previous period)"));
+    }
+}
@@ -0,0 +1,345 @@
+use serde::{Deserialize, Serialize};
+
+use crate::persist;
+use crate::storage::{self, TabRecord};
+use crate::AppState;
+
+const DEFAULT_LIMIT: usize = 50;
+const MAX_LIMIT: usize = 500;
+
+/// Markers FTS5 wraps around matched terms; swapped for `<mark>` after HTML-escaping
+pub const MATCH_START: char = '\u{1}';
+pub const MATCH_END: char = '\u{2}';
+
+/// Search request shared by the `search_tabs` command and `GET /search`
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct SearchQuery {
+    /// Free text; every word must match (prefix match). Empty means "filters only".
+    #[serde(alias = "q")]
+    pub query: String,
+    pub category: Option<String>,
+    /// "close" or "keep"
+    pub decision: Option<String>,
+    /// Host name; subdomains match too ("github.com" matches "gist.github.com")
+    pub domain: Option<String>,
+    /// First day (YYYY-MM-DD, inclusive) the tab must have been open on
+    pub from: Option<String>,
+    /// Last day (YYYY-MM-DD, inclusive) the tab must have been open on
+    pub to: Option<String>,
+    pub limit: Option<usize>,
+}
+
+/// Query after validation, in the form `Database::search` runs it
+pub struct SearchFilters {
+    pub match_expr: Option<String>,
+    pub category: Option<String>,
+    pub decision: Option<String>,
+    pub domain: Option<String>,
+    /// Tabs closed before this timestamp (ms) are excluded
+    pub from_ms: Option<i64>,
+    /// Tabs created at or after this timestamp (ms) are excluded
+    pub to_ms: Option<i64>,
+    pub limit: usize,
+}
+
+/// Raw row from the index; snippets still contain MATCH_START/MATCH_END markers
+pub struct SearchHit {
+    pub tab_id: i64,
+    pub score: f64,
+    pub title: Option<String>,
+    pub snippet: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchResult {
+    pub tab: TabRecord,
+    /// Higher is more relevant (0 for filter-only searches)
+    pub score: f64,
+    /// HTML-escaped title with matches wrapped in `<mark>`
+    pub title_highlight: Option<String>,
+    /// HTML-escaped excerpt of the description or digest with matches wrapped in `<mark>`
+    pub snippet: Option<String>,
+}
+
+impl SearchQuery {
+    pub fn into_filters(self) -> Result<SearchFilters, String> {
+        let from_ms = self
+            .from
+            .as_deref()
+            .map(storage::parse_date)
+            .transpose()?
+            .map(|d| storage::day_bounds(d).0);
+        let to_ms = self
+            .to
+            .as_deref()
+            .map(storage::parse_date)
+            .transpose()?
+            .map(|d| storage::day_bounds(d).1);
+
+        Ok(SearchFilters {
+            match_expr: match_expression(&self.query),
+            category: non_empty(self.category),
+            decision: non_empty(self.decision),
+            domain: non_empty(self.domain).map(|d| d.to_lowercase()),
+            from_ms,
+            to_ms,
+            limit: self.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT),
+        })
+    }
+}
+
+fn non_empty(value: Option<String>) -> Option<String> {
+    value
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+}
+
+/// Turn user input into an FTS5 expression: each word becomes a quoted prefix term,
+/// so punctuation in the input can never produce a syntax error.
+fn match_expression(query: &str) -> Option<String> {
+    let terms: Vec<String> = query
+        .split_whitespace()
+        .map(|word| word.replace('"', ""))
+        .filter(|word| word.chars().any(|c| c.is_alphanumeric()))
+        .map(|word| format!("\"{}\"*", word))
+        .collect();
+
+    if terms.is_empty() {
+        None
+    } else {
+        Some(terms.join(" "))
+    }
+}
+
+/// Lower-cased host of a URL without port, e.g. "docs.rs" for "https://docs.rs:443/tokio"
+pub fn url_domain(url: &str) -> Option<String> {
+    let rest = url.split("://").nth(1)?;
+    let host = rest.split(['/', '?', '#']).next()?;
+    let host = host.rsplit('@').next()?;
+    let host = host.split(':').next()?;
+    if host.is_empty() {
+        None
+    } else {
+        Some(host.to_lowercase())
+    }
+}
+
+/// HTML-escape a snippet, then turn the FTS5 match markers into `<mark>` tags
+fn render_highlight(raw: &str) -> String {
+    let mut out = String::with_capacity(raw.len() + 16);
+    for c in raw.chars() {
+        match c {
+            MATCH_START => out.push_str("<mark>"),
+            MATCH_END => out.push_str("</mark>"),
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Search all tabs in storage, open and closed.
+/// Pending writes are flushed first so the index reflects the latest captures.
+pub async fn search(state: &AppState, query: SearchQuery) -> Result<Vec<SearchResult>, String> {
+    let filters = query.into_filters()?;
+
+    persist::flush(state).await;
+
+    let db = state.read().await.db();
+    let hits = tokio::task::spawn_blocking(move || db.search(&filters))
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| e.to_string())?;
+
+    let storage = state.read().await;
+    Ok(hits
+        .into_iter()
+        .filter_map(|hit| {
+            let tab = storage.tabs.get(&hit.tab_id)?.clone();
+            Some(SearchResult {
+                tab,
+                score: hit.score,
+                title_highlight: hit.title.as_deref().map(render_highlight),
+                snippet: hit.snippet.as_deref().map(render_highlight),
+            })
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::Database;
+    use crate::test_support;
+
+    fn database() -> Database {
+        let db = Database::open_in_memory().unwrap();
+        let close = storage::TabSuggestion {
+            decision: "close".to_string(),
+            reason: String::new(),
+            category: Some("Research".to_string()),
+            digest: Some("Gists about async closures".to_string()),
+            scored_at: 0,
+        };
+        db.upsert_tabs(&[
+            test_support::tab(1, "https://docs.rs/tokio")
+                .title("Tokio async runtime")
+                .category("research")
+                .created_at(1_000)
+                .build(),
+            test_support::tab(2, "https://gist.github.com/abc")
+                .title("Snippets")
+                .description("An async example <script> & more")
+                .suggestion(close)
+                .created_at(2_000)
+                .build(),
+            test_support::tab(3, "https://github.com/tokio-rs/tokio")
+                .title("tokio-rs/tokio")
+                .created_at(3_000)
+                .closed_at(5_000)
+                .build(),
+            test_support::tab(4, "https://notgithub.com/x")
+                .title("Not GitHub")
+                .created_at(4_000)
+                .build(),
+        ])
+        .unwrap();
+        db
+    }
+
+    fn search(db: &Database, query: SearchQuery) -> Vec<i64> {
+        let filters = query.into_filters().unwrap();
+        db.search(&filters)
+            .unwrap()
+            .iter()
+            .map(|hit| hit.tab_id)
+            .collect()
+    }
+
+    fn text(query: &str) -> SearchQuery {
+        SearchQuery {
+            query: query.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn quotes_every_word_as_a_prefix_term() {
+        assert_eq!(
+            match_expression("rust async").as_deref(),
+            Some("\"rust\"* \"async\"*")
+        );
+        // Operators and stray quotes are searched for as plain words
+        assert_eq!(
+            match_expression("tok\"io OR NEAR( - *").as_deref(),
+            Some("\"tokio\"* \"OR\"* \"NEAR(\"*")
+        );
+        assert_eq!(match_expression("  - * \" "), None);
+    }
+
+    #[test]
+    fn escapes_html_around_match_markers() {
+        let raw = format!("<b>{}Rust{}</b> & 'co' \"x\"", MATCH_START, MATCH_END);
+        assert_eq!(
+            render_highlight(&raw),
+            "&lt;b&gt;<mark>Rust</mark>&lt;/b&gt; &amp; &#39;co&#39; &quot;x&quot;"
+        );
+    }
+
+    #[test]
+    fn extracts_the_host_of_a_url() {
+        assert_eq!(
+            url_domain("https://user@Docs.RS:443/tokio?q=1").as_deref(),
+            Some("docs.rs")
+        );
+        assert_eq!(
+            url_domain("http://localhost#top").as_deref(),
+            Some("localhost")
+        );
+        assert_eq!(url_domain("file:///tmp/notes.txt"), None);
+        assert_eq!(url_domain("about:blank"), None);
+    }
+
+    #[test]
+    fn ranks_title_matches_first_and_highlights_them() {
+        let db = database();
+
+        assert_eq!(search(&db, text("async")), vec![1, 2]);
+        assert_eq!(search(&db, text("asy tok")), vec![1]);
+        assert!(search(&db, text("\"unbalanced OR (")).is_empty());
+
+        let filters = text("async").into_filters().unwrap();
+        let hits = db.search(&filters).unwrap();
+        assert_eq!(
+            hits[0].title.as_deref().map(render_highlight).as_deref(),
+            Some("Tokio <mark>async</mark> runtime")
+        );
+        // The digest excerpt is preferred over the description
+        let snippet = hits[1].snippet.as_deref().map(render_highlight).unwrap();
+        assert!(
+            snippet.contains("<mark>async</mark> closures"),
+            "{}",
+            snippet
+        );
+    }
+
+    #[test]
+    fn filters_by_category_decision_domain_and_dates() {
+        let db = database();
+
+        let category = SearchQuery {
+            category: Some("RESEARCH".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(search(&db, category), vec![2, 1]);
+
+        let decision = SearchQuery {
+            decision: Some("close".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(search(&db, decision), vec![2]);
+
+        let domain = |domain: &str| SearchQuery {
+            domain: Some(domain.to_string()),
+            ..Default::default()
+        };
+        assert_eq!(search(&db, domain("GitHub.com")), vec![3, 2]);
+        // LIKE wildcards in the input match nothing
+        assert!(search(&db, domain("%")).is_empty());
+        assert!(search(&db, domain("_ithub.com")).is_empty());
+
+        let after_closing = SearchFilters {
+            from_ms: Some(6_000),
+            ..SearchQuery::default().into_filters().unwrap()
+        };
+        let ids: Vec<i64> = db
+            .search(&after_closing)
+            .unwrap()
+            .iter()
+            .map(|h| h.tab_id)
+            .collect();
+        assert_eq!(ids, vec![4, 2, 1]);
+        let before_the_fourth = SearchFilters {
+            to_ms: Some(4_000),
+            ..SearchQuery::default().into_filters().unwrap()
+        };
+        let ids: Vec<i64> = db
+            .search(&before_the_fourth)
+            .unwrap()
+            .iter()
+            .map(|h| h.tab_id)
+            .collect();
+        assert_eq!(ids, vec![3, 2, 1]);
+
+        let bad_date = SearchQuery {
+            from: Some("last week".to_string()),
+            ..Default::default()
+        };
+        assert!(bad_date.into_filters().is_err());
+    }
+}
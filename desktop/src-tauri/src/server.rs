@@ -2,38 +2,48 @@ use axum::{
     body::Body,
     extract::{
         ws::{Message, WebSocket, WebSocketUpgrade},
-        Path, State,
+        Extension, Path, Query, Request, State,
     },
-    http::{header, StatusCode},
+    http::{header, HeaderMap, HeaderName, Method, StatusCode},
+    middleware::{self, Next},
     response::{IntoResponse, Response},
     routing::{get, post},
     Json, Router,
 };
 use futures_util::{SinkExt, StreamExt};
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 use std::net::SocketAddr;
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard, OnceLock};
 use tauri::{AppHandle, Emitter, Manager};
 use tokio::fs::File;
-use tokio::sync::broadcast;
+use tokio::sync::{mpsc, oneshot};
 use tokio_util::io::ReaderStream;
-use tower_http::cors::{Any, CorsLayer};
+use tower_http::cors::{AllowOrigin, CorsLayer};
 
 use crate::{
-    storage::{TabRecord, TabSnapshot},
+    capture::{self, CaptureLevel, CapturePolicy, CaptureTarget},
+    pairing::{self, PairError},
+    protocol::{ClientMessage, Command, Envelope, ErrorCode, Hello, ReplyError, PROTOCOL_VERSION},
+    queue::{self, QueuedCommand},
+    rest::{self, StatsQuery, StatsResponse, TabListQuery, TabPage},
+    search::{self, SearchQuery, SearchResult},
+    storage::{self, BrowserTab, ChromeTab, DailyReport, TabRecord, TabSnapshot},
     AppState,
 };
 
 const SERVER_PORT: u16 = 21890;
 
-// Channel for sending commands to connected extensions
-pub type CommandSender = broadcast::Sender<String>;
+/// Header carrying the extension's browser session id (reset on every browser restart)
+const SESSION_HEADER: &str = "x-tabula-session";
 
 #[derive(Clone)]
 struct ServerState {
     storage: AppState,
     app_handle: AppHandle,
-    command_tx: CommandSender,
 }
 
 #[derive(Debug, Deserialize)]
@@ -42,6 +52,9 @@ pub struct TabData {
     pub id: i64,
     #[serde(rename = "windowId")]
     pub window_id: Option<i64>,
+    // Position of the tab within its window
+    #[serde(default)]
+    pub index: Option<i64>,
     pub url: Option<String>,
     pub title: Option<String>,
     #[serde(rename = "favIconUrl")]
@@ -64,6 +77,66 @@ pub struct TabData {
     pub last_screenshot_at: Option<i64>,
     // Rich description extracted from page meta/content
     pub description: Option<String>,
+    #[serde(default)]
+    pub incognito: bool,
+}
+
+impl TabData {
+    fn chrome_tab<'a>(&'a self, session_id: Option<&'a str>) -> ChromeTab<'a> {
+        ChromeTab {
+            chrome_id: self.id,
+            session_id,
+            window_id: self.window_id,
+            index: self.index,
+            url: self.url.as_deref(),
+        }
+    }
+
+    /// The record for a tab seen for the first time
+    fn new_record(&self, id: i64, session_id: Option<&str>) -> TabRecord {
+        TabRecord {
+            id,
+            chrome_id: Some(self.id),
+            session_id: session_id.map(str::to_string),
+            tab_index: self.index,
+            window_id: self.window_id,
+            url: self.url.clone(),
+            title: self.title.clone(),
+            fav_icon_url: self.fav_icon_url.clone(),
+            created_at: self.created_at,
+            last_active_at: self.last_active_at,
+            total_active_ms: 0,
+            active_ms_base: 0,
+            is_active: self.is_active,
+            closed_at: None,
+            description: self.description.clone(),
+            snapshot: None,
+            suggestion: None,
+        }
+    }
+
+    fn capture_target(&self) -> CaptureTarget<'_> {
+        CaptureTarget {
+            url: self.url.as_deref(),
+            incognito: self.incognito,
+            window_id: self.window_id,
+        }
+    }
+}
+
+/// How much of `tab` the capture policies in settings let us store
+async fn capture_level(state: &ServerState, tab: &TabData) -> CaptureLevel {
+    let storage = state.storage.read().await;
+    let policies = storage.settings.capture_policies.as_deref().unwrap_or(&[]);
+    capture::capture_level(policies, &tab.capture_target())
+}
+
+fn session_id(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get(SESSION_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .filter(|v| !v.is_empty())
+        .map(|v| v.to_string())
 }
 
 #[derive(Debug, Deserialize)]
@@ -87,43 +160,420 @@ pub struct TabEvent {
 struct HealthResponse {
     status: String,
     version: String,
+    // Sent so the extension can skip capturing what would be dropped
+    capture_policies: Vec<CapturePolicy>,
+}
+
+static NEXT_CLIENT_ID: AtomicU64 = AtomicU64::new(1);
+static CLIENTS: OnceLock<Mutex<HashMap<u64, Connection>>> = OnceLock::new();
+
+static NEXT_COMMAND_ID: AtomicU64 = AtomicU64::new(1);
+static PENDING: OnceLock<Mutex<HashMap<u64, PendingReply>>> = OnceLock::new();
+
+/// An extension connected over the WebSocket
+#[derive(Debug, Clone, Serialize)]
+pub struct ConnectedClient {
+    /// Per connection; a reconnecting extension gets a new one
+    pub id: u64,
+    /// The paired client its token belongs to
+    pub pairing_id: Option<String>,
+    /// The rest is empty until the extension's hello arrives
+    pub browser: Option<String>,
+    pub browser_version: Option<String>,
+    pub profile: Option<String>,
+    pub extension_version: Option<String>,
+    /// Matches the `session_id` of the tabs this browser sent
+    pub session_id: Option<String>,
+    pub connected_at: i64,
+}
+
+struct Connection {
+    info: ConnectedClient,
+    /// Serialized envelopes for this socket
+    tx: mpsc::UnboundedSender<String>,
+}
+
+fn clients() -> MutexGuard<'static, HashMap<u64, Connection>> {
+    CLIENTS
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+}
+
+/// Connected extensions, oldest connection first
+pub fn connected_clients() -> Vec<ConnectedClient> {
+    let mut list: Vec<ConnectedClient> = clients().values().map(|c| c.info.clone()).collect();
+    list.sort_by_key(|c| (c.connected_at, c.id));
+    list
+}
+
+fn register_client(pairing_id: Option<String>, tx: mpsc::UnboundedSender<String>) -> u64 {
+    let id = NEXT_CLIENT_ID.fetch_add(1, Ordering::Relaxed);
+    let info = ConnectedClient {
+        id,
+        pairing_id,
+        browser: None,
+        browser_version: None,
+        profile: None,
+        extension_version: None,
+        session_id: None,
+        connected_at: chrono::Utc::now().timestamp_millis(),
+    };
+    clients().insert(id, Connection { info, tx });
+    id
+}
+
+fn introduce_client(id: u64, hello: Hello) {
+    if let Some(connection) = clients().get_mut(&id) {
+        let info = &mut connection.info;
+        info.browser = Some(hello.browser);
+        info.browser_version = hello.browser_version;
+        info.profile = hello.profile.filter(|p| !p.trim().is_empty());
+        info.extension_version = hello.extension_version;
+        info.session_id = hello.session_id;
+    }
+}
+
+/// Drop a closed connection and stop waiting for its replies
+fn unregister_client(id: u64) {
+    clients().remove(&id);
+
+    let mut pending = pending();
+    let finished: Vec<u64> = pending
+        .iter_mut()
+        .filter_map(|(command_id, entry)| {
+            (entry.waiting_on.remove(&id) && entry.waiting_on.is_empty()).then_some(*command_id)
+        })
+        .collect();
+    for command_id in finished {
+        if let Some(entry) = pending.remove(&command_id) {
+            let error = entry
+                .last_error
+                .map_or(CommandError::Disconnected, CommandError::Rejected);
+            let _ = entry.reply_tx.send(Err(error));
+        }
+    }
+}
+
+/// A command waiting for its reply
+struct PendingReply {
+    reply_tx: oneshot::Sender<Result<(), CommandError>>,
+    /// Connections the command went to that have not answered yet
+    waiting_on: HashSet<u64>,
+    last_error: Option<ReplyError>,
+}
+
+fn pending() -> MutexGuard<'static, HashMap<u64, PendingReply>> {
+    PENDING
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+}
+
+/// Which extensions a command goes to
+#[derive(Debug, Clone, Copy)]
+pub enum Target<'a> {
+    All,
+    /// The browser whose tabs carry this session id
+    Session(&'a str),
+}
+
+#[derive(Debug)]
+pub enum CommandError {
+    NoExtension,
+    /// Extensions are connected, but not the one the command is for
+    BrowserNotConnected,
+    Timeout,
+    /// The extension disconnected before answering
+    Disconnected,
+    /// Every extension that answered refused or failed the command
+    Rejected(ReplyError),
 }
 
-// Store the command sender globally so it can be accessed from Tauri commands
-static COMMAND_SENDER: std::sync::OnceLock<CommandSender> = std::sync::OnceLock::new();
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CommandError::NoExtension => write!(f, "No browser extension is connected"),
+            CommandError::BrowserNotConnected => {
+                write!(f, "The browser this tab belongs to is not connected")
+            }
+            CommandError::Timeout => write!(f, "The browser extension did not answer in time"),
+            CommandError::Disconnected => write!(f, "The browser disconnected before answering"),
+            CommandError::Rejected(e) => write!(f, "{}", e.message),
+        }
+    }
+}
+
+/// Send `command` to the extensions `target` picks, returning its id and the connections
+/// it went to
+fn dispatch(command: Command, target: Target) -> Result<(u64, HashSet<u64>), CommandError> {
+    let id = NEXT_COMMAND_ID.fetch_add(1, Ordering::Relaxed);
+    let text = serde_json::to_string(&Envelope::new(id, command))
+        .map_err(|e| CommandError::Rejected(failure(e.to_string())))?;
+
+    let clients = clients();
+    let sent: HashSet<u64> = clients
+        .values()
+        .filter(|c| match target {
+            Target::All => true,
+            Target::Session(session) => c.info.session_id.as_deref() == Some(session),
+        })
+        .filter(|c| c.tx.send(text.clone()).is_ok())
+        .map(|c| c.info.id)
+        .collect();
+
+    if sent.is_empty() {
+        return Err(match target {
+            Target::Session(_) if !clients.is_empty() => CommandError::BrowserNotConnected,
+            _ => CommandError::NoExtension,
+        });
+    }
+    Ok((id, sent))
+}
+
+fn failure(message: String) -> ReplyError {
+    ReplyError {
+        code: ErrorCode::Failed,
+        message,
+    }
+}
+
+/// Send `command` to every extension without waiting for replies, e.g. for notifications
+pub fn notify(command: Command) -> Result<(), CommandError> {
+    dispatch(command, Target::All).map(|_| ())
+}
+
+/// Send `command` and wait until an extension acks it. With several extensions addressed,
+/// the first ack wins; the command fails only if every one of them answers with an error
+/// or disconnects.
+pub async fn send_command(command: Command, target: Target<'_>) -> Result<(), CommandError> {
+    let timeout = command.timeout();
+    let (reply_tx, reply_rx) = oneshot::channel();
+    let id = {
+        // Held while sending, so a fast reply waits until the entry is registered
+        let mut pending = pending();
+        let (id, waiting_on) = dispatch(command, target)?;
+        pending.insert(
+            id,
+            PendingReply {
+                reply_tx,
+                waiting_on,
+                last_error: None,
+            },
+        );
+        id
+    };
+
+    match tokio::time::timeout(timeout, reply_rx).await {
+        Ok(Ok(result)) => result,
+        // The sender is only dropped when the entry is removed, which we do ourselves
+        Ok(Err(_)) => Err(CommandError::Timeout),
+        Err(_) => {
+            let entry = pending().remove(&id);
+            match entry.and_then(|e| e.last_error) {
+                Some(error) => Err(CommandError::Rejected(error)),
+                None => Err(CommandError::Timeout),
+            }
+        }
+    }
+}
+
+/// Match a reply from connection `client_id` to the command waiting for it
+fn resolve_reply(client_id: u64, command_id: u64, result: Result<(), ReplyError>) {
+    let mut pending = pending();
+    let Some(entry) = pending.get_mut(&command_id) else {
+        // Timed out already, or a notification nobody waits for
+        return;
+    };
+    // Only the connections the command went to can answer it
+    if !entry.waiting_on.remove(&client_id) {
+        return;
+    }
+    match result {
+        Ok(()) => {
+            if let Some(entry) = pending.remove(&command_id) {
+                let _ = entry.reply_tx.send(Ok(()));
+            }
+        }
+        Err(error) => {
+            if !entry.waiting_on.is_empty() {
+                entry.last_error = Some(error);
+            } else if let Some(entry) = pending.remove(&command_id) {
+                let _ = entry.reply_tx.send(Err(CommandError::Rejected(error)));
+            }
+        }
+    }
+}
+
+/// What became of a `close_browser_tab`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CloseOutcome {
+    Closed,
+    AlreadyGone,
+    /// Its browser is not connected; the close waits in the command queue
+    Queued,
+}
+
+/// Ask the browser a tab is open in to close it and wait for it to confirm. Tabs from
+/// before sessions were recorded go to every extension.
+async fn request_close(tab: &BrowserTab) -> Result<CloseOutcome, CommandError> {
+    let target = tab
+        .session_id
+        .as_deref()
+        .map_or(Target::All, Target::Session);
+    let command = Command::CloseTab {
+        tab_id: tab.chrome_id,
+    };
+    match send_command(command, target).await {
+        Ok(()) => Ok(CloseOutcome::Closed),
+        Err(CommandError::Rejected(e)) if e.code == ErrorCode::TabNotFound => {
+            Ok(CloseOutcome::AlreadyGone)
+        }
+        Err(e) => Err(e),
+    }
+}
 
-pub fn get_command_sender() -> Option<&'static CommandSender> {
-    COMMAND_SENDER.get()
+/// Close a tab in its browser. If that browser is not connected, the close is queued and
+/// sent once it connects.
+pub async fn close_browser_tab(tab: &BrowserTab) -> Result<CloseOutcome, CommandError> {
+    match request_close(tab).await {
+        Err(e @ (CommandError::NoExtension | CommandError::BrowserNotConnected)) => {
+            let Some(queue) = queue::get() else {
+                return Err(e);
+            };
+            let command = Command::CloseTab {
+                tab_id: tab.chrome_id,
+            };
+            let now = chrono::Utc::now().timestamp_millis();
+            match queue.enqueue(command, Some(tab.tab_id), now) {
+                Ok(_) => Ok(CloseOutcome::Queued),
+                Err(err) => {
+                    println!(
+                        "[Queue] Failed to queue closing tab {}: {}",
+                        tab.tab_id, err
+                    );
+                    Err(e)
+                }
+            }
+        }
+        result => result,
+    }
+}
+
+// Replays run one at a time, so a queued command is not sent twice
+static REPLAY: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+/// Send a queued command to wherever its tab is now; it may have moved to a new Chrome id
+/// and session after a browser restart
+async fn deliver(state: &ServerState, queued: &QueuedCommand) -> Result<(), CommandError> {
+    let Command::CloseTab { .. } = queued.command else {
+        return send_command(queued.command.clone(), Target::All).await;
+    };
+    let tab = match queued.tab_id {
+        Some(tab_id) => state
+            .storage
+            .read()
+            .await
+            .tabs
+            .get(&tab_id)
+            .and_then(|t| t.browser_tab()),
+        None => None,
+    };
+    // The tab was deleted since, so there is nothing left to close
+    let Some(tab) = tab else {
+        return Ok(());
+    };
+
+    // Closed now, or closed in the browser while we waited
+    request_close(&tab).await?;
+    let mut storage = state.storage.write().await;
+    if storage
+        .tabs
+        .get(&tab.tab_id)
+        .is_some_and(|t| t.closed_at.is_none())
+    {
+        storage.close_tab(tab.tab_id);
+    }
+    Ok(())
+}
+
+/// Send the queued commands whose browser is connected now. Runs when an extension says
+/// hello and after it syncs its tabs, which is when restarted browsers get re-linked.
+async fn replay_queue(state: &ServerState) {
+    let Some(queue) = queue::get() else {
+        return;
+    };
+    let _replaying = REPLAY.lock().await;
+    let pending = match queue.pending(chrono::Utc::now().timestamp_millis()) {
+        Ok(pending) => pending,
+        Err(e) => {
+            println!("[Queue] Failed to load queued commands: {}", e);
+            return;
+        }
+    };
+
+    let mut finished = 0;
+    for queued in pending {
+        let result = deliver(state, &queued).await;
+        let now = chrono::Utc::now().timestamp_millis();
+        let saved = match result {
+            Ok(()) => queue.delivered(queued.id, now),
+            Err(CommandError::Rejected(e)) => queue.failed(queued.id, &e.message, now),
+            // Not reachable yet; try again when it next connects
+            Err(_) => continue,
+        };
+        match saved {
+            Ok(_) => finished += 1,
+            Err(e) => println!("[Queue] Failed to update command {}: {}", queued.id, e),
+        }
+    }
+
+    if finished > 0 {
+        println!("[Queue] Delivered or failed {} queued commands", finished);
+        let _ = state.app_handle.emit("command-queue-changed", ());
+        let _ = state.app_handle.emit("tab-event", "queue");
+    }
 }
 
 pub async fn start_server(
     storage: AppState,
     app_handle: AppHandle,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    // Create broadcast channel for commands (capacity 16)
-    let (command_tx, _) = broadcast::channel::<String>(16);
-
-    // Store the sender globally
-    let _ = COMMAND_SENDER.set(command_tx.clone());
-
     let state = ServerState {
         storage,
         app_handle,
-        command_tx,
     };
 
+    // Only paired extensions may read responses; web pages get no CORS headers
     let cors = CorsLayer::new()
-        .allow_origin(Any)
-        .allow_methods(Any)
-        .allow_headers(Any);
+        .allow_origin(AllowOrigin::predicate(|origin, _| {
+            let origin = origin.to_str().unwrap_or_default();
+            pairing::get().is_some_and(|p| p.allows_origin(origin))
+        }))
+        .allow_methods([Method::GET, Method::POST])
+        .allow_headers([
+            header::AUTHORIZATION,
+            header::CONTENT_TYPE,
+            HeaderName::from_static(SESSION_HEADER),
+        ]);
 
     let app = Router::new()
         .route("/health", get(health_check))
         .route("/capture", post(handle_capture))
         .route("/event", post(handle_event))
         .route("/sync", post(handle_sync))
+        .route("/search", get(handle_search))
+        .route("/tabs", get(list_tabs))
+        .route("/tabs/:id", get(get_tab))
+        .route("/reports", get(list_reports))
+        .route("/reports/:date", get(get_report))
+        .route("/stats", get(get_stats))
         .route("/screenshot/:filename", get(serve_screenshot))
         .route("/ws", get(websocket_handler))
+        // Everything above needs a token; pairing is how an extension gets one
+        .route_layer(middleware::from_fn(require_token))
+        .route("/pair", post(handle_pair))
         .layer(cors)
         .with_state(state);
 
@@ -136,33 +586,145 @@ pub async fn start_server(
     Ok(())
 }
 
+/// Token a request was authorized with
+#[derive(Clone)]
+struct ClientToken(String);
+
+/// The token from `Authorization: Bearer`, or from the `token` query parameter for
+/// WebSocket upgrades and images, which cannot set headers
+fn request_token(request: &Request) -> Option<String> {
+    let bearer = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(|v| v.trim().to_string());
+    bearer.or_else(|| {
+        url::form_urlencoded::parse(request.uri().query().unwrap_or_default().as_bytes())
+            .find(|(key, _)| key == "token")
+            .map(|(_, value)| value.into_owned())
+    })
+}
+
+async fn require_token(mut request: Request, next: Next) -> Response {
+    let Some(token) =
+        request_token(&request).filter(|token| pairing::get().is_some_and(|p| p.authorize(token)))
+    else {
+        return (
+            StatusCode::UNAUTHORIZED,
+            "Pair this extension with Tabula first",
+        )
+            .into_response();
+    };
+    // API tokens may read, but not send tabs or take commands
+    let writes = request.method() != Method::GET || request.uri().path() == "/ws";
+    if writes && pairing::get().is_some_and(|p| p.read_only(&token)) {
+        return (StatusCode::FORBIDDEN, "API tokens are read-only").into_response();
+    }
+    request.extensions_mut().insert(ClientToken(token));
+    next.run(request).await
+}
+
+#[derive(Debug, Deserialize)]
+struct PairRequest {
+    secret: String,
+    /// Shown in the desktop's list of paired extensions
+    #[serde(default)]
+    name: Option<String>,
+}
+
+#[derive(Serialize)]
+struct PairResponse {
+    token: String,
+}
+
+async fn handle_pair(
+    State(state): State<ServerState>,
+    headers: HeaderMap,
+    Json(request): Json<PairRequest>,
+) -> Result<Json<PairResponse>, (StatusCode, String)> {
+    let pairing = pairing::get().ok_or((
+        StatusCode::SERVICE_UNAVAILABLE,
+        "Pairing is unavailable".to_string(),
+    ))?;
+    let origin = headers.get(header::ORIGIN).and_then(|v| v.to_str().ok());
+
+    match pairing.pair(&request.secret, origin, request.name) {
+        Ok(token) => {
+            println!("[Pairing] Paired {}", origin.unwrap_or_default());
+            let _ = state.app_handle.emit("pairing-changed", ());
+            Ok(Json(PairResponse { token }))
+        }
+        Err(e) => {
+            println!("[Pairing] Rejected pairing from {:?}: {}", origin, e);
+            let status = match e {
+                PairError::NotAnExtension => StatusCode::FORBIDDEN,
+                PairError::WrongSecret => StatusCode::UNAUTHORIZED,
+                PairError::Storage(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            };
+            Err((status, e.to_string()))
+        }
+    }
+}
+
 // WebSocket handler for bidirectional communication with extension
 async fn websocket_handler(
     ws: WebSocketUpgrade,
     State(state): State<ServerState>,
+    Extension(ClientToken(token)): Extension<ClientToken>,
 ) -> impl IntoResponse {
-    ws.on_upgrade(move |socket| handle_websocket(socket, state))
+    ws.on_upgrade(move |socket| handle_websocket(socket, state, token))
 }
 
-async fn handle_websocket(socket: WebSocket, state: ServerState) {
+async fn handle_websocket(socket: WebSocket, state: ServerState, token: String) {
     let (mut sender, mut receiver) = socket.split();
-    let mut command_rx = state.command_tx.subscribe();
+    let (command_tx, mut command_rx) = mpsc::unbounded_channel::<String>();
+    let pairing_id = pairing::get().and_then(|p| p.client_id(&token));
+    let client_id = register_client(pairing_id, command_tx);
+    let _ = state.app_handle.emit("clients-changed", ());
 
-    // Task to forward commands from broadcast channel to WebSocket
-    let send_task = tokio::spawn(async move {
-        while let Ok(cmd) = command_rx.recv().await {
-            if sender.send(Message::Text(cmd.into())).await.is_err() {
+    // Task to forward this client's commands to the WebSocket
+    let mut send_task = tokio::spawn(async move {
+        while let Some(cmd) = command_rx.recv().await {
+            if sender.send(Message::Text(cmd)).await.is_err() {
                 break;
             }
         }
     });
 
-    // Task to receive messages from extension (for acknowledgments, etc.)
-    let recv_task = tokio::spawn(async move {
+    // Task to receive the hello and replies from the extension
+    let recv_state = state.clone();
+    let mut recv_task = tokio::spawn(async move {
         while let Some(msg) = receiver.next().await {
             match msg {
-                Ok(Message::Text(_)) => {
-                    // Acknowledgment received
+                Ok(Message::Text(text)) => {
+                    match serde_json::from_str::<Envelope<ClientMessage>>(&text) {
+                        Ok(message) => {
+                            if message.version != PROTOCOL_VERSION {
+                                println!(
+                                    "[Server] Extension speaks protocol v{}, expected v{}",
+                                    message.version, PROTOCOL_VERSION
+                                );
+                            }
+                            match message.body {
+                                ClientMessage::Hello(hello) => {
+                                    println!(
+                                        "[Server] {} connected (profile: {:?})",
+                                        hello.browser, hello.profile
+                                    );
+                                    introduce_client(client_id, hello);
+                                    let _ = recv_state.app_handle.emit("clients-changed", ());
+                                    let state = recv_state.clone();
+                                    tokio::spawn(async move { replay_queue(&state).await });
+                                }
+                                ClientMessage::Ack => resolve_reply(client_id, message.id, Ok(())),
+                                ClientMessage::Error(error) => {
+                                    resolve_reply(client_id, message.id, Err(error))
+                                }
+                            }
+                        }
+                        Err(e) => println!("[Server] Ignoring malformed extension message: {}", e),
+                    }
                 }
                 Ok(Message::Close(_)) => {
                     break;
@@ -175,55 +737,96 @@ async fn handle_websocket(socket: WebSocket, state: ServerState) {
         }
     });
 
-    // Wait for either task to complete
+    let revoked = async {
+        if let Some(pairing) = pairing::get() {
+            pairing.revoked(&token).await;
+        }
+    };
+
+    // Wait for either task to complete, or for the client's token to be revoked
     tokio::select! {
-        _ = send_task => {},
-        _ = recv_task => {},
+        _ = &mut send_task => {},
+        _ = &mut recv_task => {},
+        _ = revoked => println!("[Pairing] Closing WebSocket of a revoked extension"),
     }
+    // Dropping both halves closes the socket
+    send_task.abort();
+    recv_task.abort();
+    unregister_client(client_id);
+    let _ = state.app_handle.emit("clients-changed", ());
 }
 
-async fn health_check() -> Json<HealthResponse> {
+async fn health_check(State(state): State<ServerState>) -> Json<HealthResponse> {
+    let capture_policies = state
+        .storage
+        .read()
+        .await
+        .settings
+        .capture_policies
+        .clone()
+        .unwrap_or_default();
     Json(HealthResponse {
         status: "ok".to_string(),
         version: "0.1.0".to_string(),
+        capture_policies,
     })
 }
 
 async fn handle_capture(
     State(state): State<ServerState>,
-    Json(payload): Json<CapturePayload>,
+    headers: HeaderMap,
+    Json(mut payload): Json<CapturePayload>,
 ) -> StatusCode {
-    let mut storage = state.storage.write().await;
+    let level = capture_level(&state, &payload.tab).await;
+    if level == CaptureLevel::Nothing {
+        println!(
+            "[Capture] Dropped capture of tab {} by policy",
+            payload.tab.id
+        );
+        return StatusCode::NO_CONTENT;
+    }
+    if !level.keeps_screenshot() {
+        payload.screenshot_base64 = None;
+    }
+    if !level.keeps_description() {
+        payload.tab.description = None;
+    }
 
-    // Save screenshot to disk if present
-    let screenshot_path = if let Some(base64_data) = &payload.screenshot_base64 {
-        match storage.save_screenshot(payload.tab.id, base64_data) {
-            Ok(path) => Some(path),
-            Err(_) => None,
-        }
+    let session_id = session_id(&headers);
+    let tab_id = {
+        let chrome_tab = payload.tab.chrome_tab(session_id.as_deref());
+        let mut storage = state.storage.write().await;
+        storage
+            .resolve_tab(&chrome_tab, |id| {
+                payload.tab.new_record(id, session_id.as_deref())
+            })
+            .id
+    };
+
+    // Save screenshot to disk if present (without holding the write lock)
+    let screenshot_path = if let Some(base64_data) = payload.screenshot_base64.clone() {
+        let screenshots_dir = state.storage.read().await.screenshots_dir().to_path_buf();
+        tokio::task::spawn_blocking(move || {
+            storage::save_screenshot(&screenshots_dir, tab_id, &base64_data)
+        })
+        .await
+        .ok()
+        .and_then(|r| r.ok())
     } else {
         None
     };
 
-    // Update or create tab record
-    let tab = storage
-        .tabs
-        .entry(payload.tab.id)
-        .or_insert_with(|| TabRecord {
-            id: payload.tab.id,
-            window_id: payload.tab.window_id,
-            url: payload.tab.url.clone(),
-            title: payload.tab.title.clone(),
-            fav_icon_url: payload.tab.fav_icon_url.clone(),
-            created_at: payload.tab.created_at,
-            last_active_at: payload.tab.last_active_at,
-            total_active_ms: payload.tab.total_active_ms,
-            is_active: payload.tab.is_active,
-            closed_at: None,
-            description: payload.tab.description.clone(),
-            snapshot: None,
-            suggestion: None,
-        });
+    let mut storage = state.storage.write().await;
+    let Some(tab) = storage.tabs.get_mut(&tab_id) else {
+        // Deleted while the screenshot was saved, e.g. by clearing all data
+        storage.delete_screenshot(tab_id);
+        return StatusCode::NO_CONTENT;
+    };
+
+    // Drop what an earlier capture stored before a policy applied
+    if !level.keeps_description() {
+        tab.description = None;
+    }
 
     // Update snapshot
     tab.snapshot = Some(TabSnapshot {
@@ -235,53 +838,46 @@ async fn handle_capture(
     tab.url = payload.tab.url;
     tab.title = payload.tab.title;
     tab.last_active_at = payload.tab.last_active_at;
-    tab.total_active_ms = payload.tab.total_active_ms;
+    tab.total_active_ms = tab.active_ms_base + payload.tab.total_active_ms;
     tab.is_active = payload.tab.is_active;
     // Update description if provided
     if payload.tab.description.is_some() {
         tab.description = payload.tab.description;
     }
 
-    // Save to disk
-    if let Err(e) = storage.save_tabs() {
-        eprintln!("Failed to save tabs: {}", e);
+    if !level.keeps_screenshot() {
+        storage.delete_screenshot(tab_id);
     }
 
+    // Persisted by the background flusher
+    storage.mark_tab_dirty(tab_id);
+
     // Emit event to frontend
-    let _ = state.app_handle.emit("tab-captured", payload.tab.id);
+    let _ = state.app_handle.emit("tab-captured", tab_id);
 
     StatusCode::OK
 }
 
-async fn handle_event(State(state): State<ServerState>, Json(event): Json<TabEvent>) -> StatusCode {
+async fn handle_event(
+    State(state): State<ServerState>,
+    headers: HeaderMap,
+    Json(mut event): Json<TabEvent>,
+) -> StatusCode {
+    let session_id = session_id(&headers);
+    let level = capture_level(&state, &event.tab).await;
+    if !level.keeps_description() {
+        event.tab.description = None;
+    }
     let mut storage = state.storage.write().await;
 
-    match event.event_type.as_str() {
+    let tab_id = match event.event_type.as_str() {
+        "created" | "updated" | "activated" if level == CaptureLevel::Nothing => None,
         "created" | "updated" | "activated" => {
-            let existing = storage.tabs.get(&event.tab.id);
-            let prev_active_ms = existing.map(|t| t.total_active_ms).unwrap_or(0);
-            let prev_snapshot = existing.and_then(|t| t.snapshot.clone());
-            let prev_suggestion = existing.and_then(|t| t.suggestion.clone());
-            let prev_description = existing.and_then(|t| t.description.clone());
-
-            let tab = storage
-                .tabs
-                .entry(event.tab.id)
-                .or_insert_with(|| TabRecord {
-                    id: event.tab.id,
-                    window_id: event.tab.window_id,
-                    url: event.tab.url.clone(),
-                    title: event.tab.title.clone(),
-                    fav_icon_url: event.tab.fav_icon_url.clone(),
-                    created_at: event.tab.created_at,
-                    last_active_at: event.tab.last_active_at,
-                    total_active_ms: 0,
-                    is_active: event.tab.is_active,
-                    closed_at: None,
-                    description: event.tab.description.clone(),
-                    snapshot: None,
-                    suggestion: None,
-                });
+            let chrome_tab = event.tab.chrome_tab(session_id.as_deref());
+            let tab = storage.resolve_tab(&chrome_tab, |id| {
+                event.tab.new_record(id, session_id.as_deref())
+            });
+            let tab_id = tab.id;
 
             // Update fields
             tab.url = event.tab.url;
@@ -293,24 +889,23 @@ async fn handle_event(State(state): State<ServerState>, Json(event): Json<TabEve
             // Update description if provided, otherwise preserve existing
             if event.tab.description.is_some() {
                 tab.description = event.tab.description;
-            } else if tab.description.is_none() {
-                tab.description = prev_description;
+            } else if !level.keeps_description() {
+                tab.description = None;
             }
 
-            // Accumulate active time - take max of existing and incoming
-            // Extension sends accumulated time, so take the larger value
-            tab.total_active_ms = prev_active_ms.max(event.tab.total_active_ms);
+            // Extension sends time accumulated in this browser session only,
+            // so add it to what earlier sessions carried over
+            tab.total_active_ms = tab
+                .total_active_ms
+                .max(tab.active_ms_base + event.tab.total_active_ms);
 
-            // Preserve existing snapshot and suggestion
-            if tab.snapshot.is_none() {
-                tab.snapshot = prev_snapshot;
-            }
-            if tab.suggestion.is_none() {
-                tab.suggestion = prev_suggestion;
-            }
+            Some(tab_id)
         }
         "removed" => {
-            if let Some(tab) = storage.tabs.get_mut(&event.tab.id) {
+            let tab_id = storage.find_live_tab(event.tab.id, session_id.as_deref());
+            if let Some((id, tab)) =
+                tab_id.and_then(|id| storage.tabs.get_mut(&id).map(|tab| (id, tab)))
+            {
                 tab.closed_at = Some(event.timestamp);
                 tab.is_active = false;
 
@@ -335,15 +930,16 @@ async fn handle_event(State(state): State<ServerState>, Json(event): Json<TabEve
 
                 if !is_today {
                     // Delete screenshot for old tabs to save disk space
-                    storage.delete_screenshot(event.tab.id);
+                    storage.delete_screenshot(id);
                 }
             }
+            tab_id
         }
-        _ => {}
-    }
+        _ => None,
+    };
 
-    if let Err(e) = storage.save_tabs() {
-        eprintln!("Failed to save tabs: {}", e);
+    if let Some(tab_id) = tab_id {
+        storage.mark_tab_dirty(tab_id);
     }
 
     // Emit event to frontend
@@ -352,6 +948,85 @@ async fn handle_event(State(state): State<ServerState>, Json(event): Json<TabEve
     StatusCode::OK
 }
 
+// Full-text search, e.g. GET /search?q=rust+async&domain=docs.rs&from=2024-01-01
+async fn handle_search(
+    State(state): State<ServerState>,
+    Query(query): Query<SearchQuery>,
+) -> Result<Json<Vec<SearchResult>>, (StatusCode, String)> {
+    search::search(&state.storage, query)
+        .await
+        .map(Json)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))
+}
+
+async fn list_tabs(
+    State(state): State<ServerState>,
+    Query(query): Query<TabListQuery>,
+) -> Result<Json<TabPage>, (StatusCode, String)> {
+    let storage = state.storage.read().await;
+    rest::list_tabs(storage.tabs.values(), &query)
+        .map(Json)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))
+}
+
+async fn get_tab(
+    State(state): State<ServerState>,
+    Path(id): Path<i64>,
+) -> Result<Json<TabRecord>, (StatusCode, String)> {
+    let storage = state.storage.read().await;
+    storage
+        .tabs
+        .get(&id)
+        .cloned()
+        .map(Json)
+        .ok_or((StatusCode::NOT_FOUND, format!("No tab with id {}", id)))
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct ReportListQuery {
+    /// First and last day (YYYY-MM-DD, inclusive); either end may be open
+    from: Option<String>,
+    to: Option<String>,
+}
+
+async fn list_reports(
+    State(state): State<ServerState>,
+    Query(query): Query<ReportListQuery>,
+) -> Result<Json<Vec<DailyReport>>, (StatusCode, String)> {
+    let parse = |date: Option<String>| date.as_deref().map(storage::parse_date).transpose();
+    let from = parse(query.from).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let to = parse(query.to).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let storage = state.storage.read().await;
+    storage
+        .list_reports(from, to)
+        .map(Json)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
+
+async fn get_report(
+    State(state): State<ServerState>,
+    Path(date): Path<String>,
+) -> Result<Json<DailyReport>, (StatusCode, String)> {
+    let day = storage::parse_date(&date).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let storage = state.storage.read().await;
+    match storage.get_report(day) {
+        Ok(Some(report)) => Ok(Json(report)),
+        Ok(None) => Err((StatusCode::NOT_FOUND, format!("No report for {}", date))),
+        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
+    }
+}
+
+async fn get_stats(
+    State(state): State<ServerState>,
+    Query(query): Query<StatsQuery>,
+) -> Result<Json<StatsResponse>, (StatusCode, String)> {
+    let storage = state.storage.read().await;
+    rest::stats(&storage, &query)
+        .map(Json)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))
+}
+
 #[derive(Debug, Deserialize)]
 pub struct SyncPayload {
     pub tab_ids: Vec<i64>,
@@ -359,20 +1034,22 @@ pub struct SyncPayload {
 
 async fn handle_sync(
     State(state): State<ServerState>,
+    headers: HeaderMap,
     Json(payload): Json<SyncPayload>,
 ) -> StatusCode {
-    let mut storage = state.storage.write().await;
-    let count = storage.sync_with_chrome_tabs(&payload.tab_ids);
-
-    if count > 0 {
-        if let Err(e) = storage.save_tabs() {
-            eprintln!("Failed to save tabs after sync: {}", e);
-        }
-    }
+    let session_id = session_id(&headers);
+    state
+        .storage
+        .write()
+        .await
+        .sync_with_chrome_tabs(&payload.tab_ids, session_id.as_deref());
 
     // Emit event to frontend to refresh
     let _ = state.app_handle.emit("tab-event", "sync");
 
+    // Tabs of a restarted browser are re-linked by now, so their queued closes can go out
+    tokio::spawn(async move { replay_queue(&state).await });
+
     StatusCode::OK
 }
 
@@ -1,12 +1,18 @@
 use base64::Engine;
-use chrono::{Local, TimeZone};
+use chrono::{Local, NaiveDate, TimeZone};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fs;
-use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use tauri::{AppHandle, Manager};
 
+use crate::capture::CapturePolicy;
+use crate::db::{Database, OpenError};
+use crate::migrate;
+use crate::persist;
+use crate::usage::ModelPrice;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TabSnapshot {
     pub screenshot_path: Option<String>,
@@ -24,7 +30,17 @@ pub struct TabSuggestion {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TabRecord {
+    /// Stable logical id assigned by Tabula; survives browser restarts
     pub id: i64,
+    /// Chrome's tab id in the current browser session (Chrome reuses ids after a restart)
+    #[serde(default)]
+    pub chrome_id: Option<i64>,
+    /// Browser session that `chrome_id` belongs to
+    #[serde(default)]
+    pub session_id: Option<String>,
+    /// Position of the tab within its window
+    #[serde(default)]
+    pub tab_index: Option<i64>,
     pub window_id: Option<i64>,
     pub url: Option<String>,
     pub title: Option<String>,
@@ -32,6 +48,10 @@ pub struct TabRecord {
     pub created_at: i64,
     pub last_active_at: Option<i64>,
     pub total_active_ms: i64,
+    /// Active time carried over from earlier browser sessions; the extension's
+    /// counter restarts at zero when a tab is re-linked
+    #[serde(default)]
+    pub active_ms_base: i64,
     pub is_active: bool,
     pub closed_at: Option<i64>,
     /// Rich description extracted from page meta/content (max 8000 words)
@@ -40,6 +60,52 @@ pub struct TabRecord {
     pub suggestion: Option<TabSuggestion>,
 }
 
+/// A tab as reported by the extension, used to find its logical record
+pub struct ChromeTab<'a> {
+    pub chrome_id: i64,
+    pub session_id: Option<&'a str>,
+    pub window_id: Option<i64>,
+    pub index: Option<i64>,
+    pub url: Option<&'a str>,
+}
+
+/// How long a tab from a previous browser session stays eligible for re-linking
+const RELINK_WINDOW_MS: i64 = 24 * 60 * 60 * 1000;
+
+/// Where an open tab lives in the browser, for sending it commands
+#[derive(Debug, Clone, PartialEq)]
+pub struct BrowserTab {
+    pub tab_id: i64,
+    pub chrome_id: i64,
+    pub session_id: Option<String>,
+}
+
+impl TabRecord {
+    /// Where the tab was last seen in the browser, even if it has been closed since; `None`
+    /// if the extension never reported its Chrome id
+    pub fn browser_tab(&self) -> Option<BrowserTab> {
+        Some(BrowserTab {
+            tab_id: self.id,
+            chrome_id: self.chrome_id?,
+            session_id: self.session_id.clone(),
+        })
+    }
+
+    /// Point this record at a (possibly new) Chrome tab
+    pub fn link_chrome_tab(&mut self, tab: &ChromeTab) {
+        self.chrome_id = Some(tab.chrome_id);
+        if let Some(session_id) = tab.session_id {
+            self.session_id = Some(session_id.to_string());
+        }
+        if tab.window_id.is_some() {
+            self.window_id = tab.window_id;
+        }
+        if tab.index.is_some() {
+            self.tab_index = tab.index;
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DailyReport {
     pub date: String,
@@ -47,13 +113,55 @@ pub struct DailyReport {
     pub generated_at: i64,
 }
 
+/// A weekly or monthly rollup covering `start` to `end` (inclusive, `YYYY-MM-DD`)
 #[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PeriodReport {
+    pub granularity: String,
+    pub start: String,
+    pub end: String,
+    pub content: String,
+    pub generated_at: i64,
+}
+
+/// A report whose generation stopped part way; `error` says why, if it failed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReportDraft {
+    pub date: String,
+    pub content: String,
+    pub updated_at: i64,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Settings {
     pub openai_api_key: Option<String>,
     pub base_url: Option<String>,
     pub model: Option<String>,
     pub user_context: Option<String>, // User's work habits, goals, preferences
     pub analyze_batch_size: Option<u32>, // Number of tabs to analyze at once (default: 30)
+    pub analyze_concurrency: Option<u32>, // Token-budgeted sub-batches analyzed in parallel (default: 2)
+    pub suggestion_context_window: Option<u32>, // Overrides the built-in context size of the suggestion model
+    pub report_context_window: Option<u32>, // Overrides the built-in context size of the report model
+    pub embedding_model: Option<String>,    // Model for embeddings (default depends on provider)
+    pub anthropic_api_key: Option<String>,
+    pub anthropic_base_url: Option<String>,
+    pub ollama_base_url: Option<String>, // Default: http://localhost:11434
+    // Per-task provider: "openai" (default), "anthropic", "ollama" or "mock"
+    pub suggestion_provider: Option<String>,
+    pub suggestion_model: Option<String>, // Falls back to `model` on OpenAI
+    pub report_provider: Option<String>,
+    pub report_model: Option<String>, // Falls back to `model` on OpenAI
+    pub embedding_provider: Option<String>,
+    pub suggestion_cache_ttl_hours: Option<u32>, // Hours a cached suggestion stays valid; 0 disables the cache (default: 168)
+    pub monthly_budget_usd: Option<f64>, // AI calls are refused once this month's estimated cost reaches it
+    pub model_prices: Option<HashMap<String, ModelPrice>>, // Keyed by model name prefix; overrides built-in prices
+    pub privacy_blocklist: Option<Vec<String>>, // Domain/URL globs whose tabs are never sent to AI providers
+    pub privacy_title_only: Option<Vec<String>>, // Globs whose tabs are sent as title and site only
+    pub privacy_no_screenshots: Option<Vec<String>>, // Globs whose screenshots are never sent
+    pub redact_personal_data: Option<bool>, // Mask emails, tokens, API keys, phone and card numbers sent to AI (default: true)
+    pub capture_policies: Option<Vec<CapturePolicy>>, // First match decides what the extension server stores for a tab
+    pub closed_tab_retention_days: Option<u32>, // Closed tabs older than this are removed at startup; unset keeps them
 }
 
 impl Default for Settings {
@@ -64,6 +172,27 @@ impl Default for Settings {
             model: Some("gpt-4o-mini".to_string()),
             user_context: None,
             analyze_batch_size: Some(30),
+            analyze_concurrency: Some(2),
+            suggestion_context_window: None,
+            report_context_window: None,
+            embedding_model: None,
+            anthropic_api_key: None,
+            anthropic_base_url: None,
+            ollama_base_url: None,
+            suggestion_provider: None,
+            suggestion_model: None,
+            report_provider: None,
+            report_model: None,
+            embedding_provider: None,
+            suggestion_cache_ttl_hours: Some(168),
+            monthly_budget_usd: None,
+            model_prices: None,
+            privacy_blocklist: None,
+            privacy_title_only: None,
+            privacy_no_screenshots: None,
+            redact_personal_data: Some(true),
+            capture_policies: None,
+            closed_tab_retention_days: None,
         }
     }
 }
@@ -71,11 +200,41 @@ impl Default for Settings {
 pub struct Storage {
     pub tabs: HashMap<i64, TabRecord>,
     pub settings: Settings,
-    pub report: Option<DailyReport>,
+    db: Arc<Database>,
+    /// Tab rows changed in memory but not yet written; drained by `persist::flush`
+    dirty_tabs: HashSet<i64>,
+    flush_lock: Arc<tokio::sync::Mutex<()>>,
+    next_tab_id: i64,
     data_dir: PathBuf,
     screenshots_dir: PathBuf,
 }
 
+/// Tab rows collected from memory for one flush
+pub struct PendingWrites {
+    upserts: Vec<TabRecord>,
+    deletes: Vec<i64>,
+}
+
+impl PendingWrites {
+    pub fn is_empty(&self) -> bool {
+        self.upserts.is_empty() && self.deletes.is_empty()
+    }
+
+    pub fn tab_ids(&self) -> Vec<i64> {
+        self.upserts
+            .iter()
+            .map(|t| t.id)
+            .chain(self.deletes.iter().copied())
+            .collect()
+    }
+
+    /// Write the batch in a single transaction, crediting new active time to today
+    pub fn apply(&self, db: &Database) -> rusqlite::Result<()> {
+        let today = format_date(Local::now().date_naive());
+        db.write_batch(&self.upserts, &self.deletes, &today)
+    }
+}
+
 impl Storage {
     pub fn new(app_handle: &AppHandle) -> Self {
         let data_dir = app_handle
@@ -89,18 +248,25 @@ impl Storage {
         fs::create_dir_all(&data_dir).ok();
         fs::create_dir_all(&screenshots_dir).ok();
 
+        let db = open_database(&data_dir.join("tabula.db"));
+
         let mut storage = Self {
             tabs: HashMap::new(),
             settings: Settings::default(),
-            report: None,
+            db: Arc::new(db),
+            dirty_tabs: HashSet::new(),
+            flush_lock: Arc::new(tokio::sync::Mutex::new(())),
+            next_tab_id: 1,
             data_dir,
             screenshots_dir,
         };
 
+        // Import tabs.json / settings.json / report.json from older versions
+        storage.migrate_legacy_files();
+
         // Load existing data
         storage.load_tabs();
         storage.load_settings();
-        storage.load_report();
 
         // Clean up old screenshots (migrate from timestamp-based to simple naming)
         storage.cleanup_old_screenshots();
@@ -108,6 +274,22 @@ impl Storage {
         storage
     }
 
+    /// Storage over an in-memory database with nothing in it
+    #[cfg(test)]
+    pub fn in_memory() -> Self {
+        let data_dir = std::env::temp_dir().join(format!("tabula-test-{}", uuid::Uuid::new_v4()));
+        Self {
+            tabs: HashMap::new(),
+            settings: Settings::default(),
+            db: Arc::new(Database::open_in_memory().expect("in-memory database")),
+            dirty_tabs: HashSet::new(),
+            flush_lock: Arc::new(tokio::sync::Mutex::new(())),
+            next_tab_id: 1,
+            screenshots_dir: data_dir.join("screenshots"),
+            data_dir,
+        }
+    }
+
     /// Clean up old screenshots with timestamp in filename (migrate to new naming scheme)
     fn cleanup_old_screenshots(&self) {
         if let Ok(entries) = fs::read_dir(&self.screenshots_dir) {
@@ -132,19 +314,37 @@ impl Storage {
     }
 
     pub fn get_today_tabs(&self) -> Vec<TabRecord> {
-        let today = Local::now().date_naive();
-        let start_of_day = Local
-            .from_local_datetime(&today.and_hms_opt(0, 0, 0).unwrap())
-            .unwrap()
-            .timestamp_millis();
+        self.get_tabs_for_date(Local::now().date_naive())
+    }
+
+    /// Tabs created or used on `date` (local time) that were still open at its start
+    pub fn get_tabs_for_date(&self, date: NaiveDate) -> Vec<TabRecord> {
+        self.get_tabs_between(date, date)
+    }
+
+    /// Tabs created, last active or with active time recorded between `from` and `to`
+    /// (inclusive, local time) that were still open when the range started
+    pub fn get_tabs_between(&self, from: NaiveDate, to: NaiveDate) -> Vec<TabRecord> {
+        let (start_of_day, _) = day_bounds(from);
+        let (_, end_of_day) = day_bounds(to);
+        let in_range = |ts: i64| ts >= start_of_day && ts < end_of_day;
+        // A tab used in the range and again later only shows it in the activity rows
+        let used = match self.get_activity_between(from, to) {
+            Ok(activity) => activity.into_keys().collect(),
+            Err(e) => {
+                eprintln!("[Storage] Failed to load tab activity: {}", e);
+                HashSet::new()
+            }
+        };
 
         self.tabs
             .values()
             .filter(|t| {
-                t.created_at >= start_of_day
-                    || t.last_active_at
-                        .map(|la| la >= start_of_day)
-                        .unwrap_or(false)
+                t.created_at < end_of_day
+                    && t.closed_at.map(|c| c >= start_of_day).unwrap_or(true)
+                    && (in_range(t.created_at)
+                        || t.last_active_at.map(in_range).unwrap_or(false)
+                        || used.contains(&t.id))
             })
             .cloned()
             .collect()
@@ -152,48 +352,198 @@ impl Storage {
 
     /// Get today's closed tabs (for history view)
     pub fn get_today_closed_tabs(&self) -> Vec<TabRecord> {
-        let today = Local::now().date_naive();
-        let start_of_day = Local
-            .from_local_datetime(&today.and_hms_opt(0, 0, 0).unwrap())
-            .unwrap()
-            .timestamp_millis();
+        self.get_closed_tabs_for_date(Local::now().date_naive())
+    }
+
+    /// Closed tabs that were created, active or closed on `date` (local time)
+    pub fn get_closed_tabs_for_date(&self, date: NaiveDate) -> Vec<TabRecord> {
+        let (start_of_day, end_of_day) = day_bounds(date);
+        let on_day = |ts: i64| ts >= start_of_day && ts < end_of_day;
 
         self.tabs
             .values()
             .filter(|t| {
                 // Must be closed
                 t.closed_at.is_some() &&
-                // And was active today (closed today or was active today)
-                (t.closed_at.map(|c| c >= start_of_day).unwrap_or(false)
-                    || t.last_active_at.map(|la| la >= start_of_day).unwrap_or(false)
-                    || t.created_at >= start_of_day)
+                // And touched that day (closed, active or created)
+                (t.closed_at.map(on_day).unwrap_or(false)
+                    || t.last_active_at.map(on_day).unwrap_or(false)
+                    || on_day(t.created_at))
             })
             .cloned()
             .collect()
     }
 
+    /// Find the open record for a Chrome tab in the given browser session
+    pub fn find_live_tab(&self, chrome_id: i64, session_id: Option<&str>) -> Option<i64> {
+        self.tabs
+            .values()
+            .find(|t| {
+                t.closed_at.is_none()
+                    && t.chrome_id == Some(chrome_id)
+                    && match (session_id, t.session_id.as_deref()) {
+                        (Some(incoming), Some(stored)) => incoming == stored,
+                        _ => true,
+                    }
+            })
+            .map(|t| t.id)
+    }
+
+    /// Resolve the logical record for a tab reported by the extension.
+    ///
+    /// Chrome hands out new tab ids after a browser restart, so a tab we have not seen in
+    /// this session is matched against records from earlier sessions by URL, preferring the
+    /// same position in its window, then open records, then the most recent activity and
+    /// earliest creation time. A match is re-linked to the new Chrome id and keeps its
+    /// suggestion, active time and screenshot. Otherwise `new_record` is inserted under a
+    /// fresh id right away, so a concurrent report of the same tab finds it instead of
+    /// allocating another.
+    pub fn resolve_tab(
+        &mut self,
+        tab: &ChromeTab,
+        new_record: impl FnOnce(i64) -> TabRecord,
+    ) -> &mut TabRecord {
+        let tab_id = match self.find_live_tab(tab.chrome_id, tab.session_id) {
+            Some(tab_id) => tab_id,
+            None => self.relink(tab).unwrap_or_else(|| {
+                let tab_id = self.next_tab_id;
+                self.next_tab_id += 1;
+                tab_id
+            }),
+        };
+        self.dirty_tabs.insert(tab_id);
+        let record = self
+            .tabs
+            .entry(tab_id)
+            .or_insert_with(|| new_record(tab_id));
+        record.link_chrome_tab(tab);
+        record
+    }
+
+    /// Re-link the record a restarted browser's tab continues, if there is one
+    fn relink(&mut self, tab: &ChromeTab) -> Option<i64> {
+        let tab_id = self.find_relink_candidate(tab)?;
+        let record = self.tabs.get_mut(&tab_id)?;
+        println!(
+            "[Storage] Re-linked tab {} to Chrome tab {} ({})",
+            tab_id,
+            tab.chrome_id,
+            tab.url.unwrap_or("")
+        );
+        record.link_chrome_tab(tab);
+        record.active_ms_base = record.total_active_ms;
+        record.closed_at = None;
+        Some(tab_id)
+    }
+
+    fn find_relink_candidate(&self, tab: &ChromeTab) -> Option<i64> {
+        // Without a session id there is no way to tell a restart from a reused id
+        let session_id = tab.session_id?;
+        let url = tab.url.filter(|u| !u.is_empty())?;
+        let cutoff = chrono::Utc::now().timestamp_millis() - RELINK_WINDOW_MS;
+
+        self.tabs
+            .values()
+            .filter(|t| {
+                t.session_id.as_deref() != Some(session_id)
+                    && t.url.as_deref() == Some(url)
+                    && t.closed_at.map(|c| c >= cutoff).unwrap_or(true)
+            })
+            .max_by_key(|t| {
+                (
+                    tab.index.is_some() && t.tab_index == tab.index,
+                    t.closed_at.is_none(),
+                    t.last_active_at.unwrap_or(t.created_at),
+                    std::cmp::Reverse(t.created_at),
+                )
+            })
+            .map(|t| t.id)
+    }
+
     pub fn close_tab(&mut self, tab_id: i64) {
         if let Some(tab) = self.tabs.get_mut(&tab_id) {
             tab.closed_at = Some(chrono::Utc::now().timestamp_millis());
             tab.is_active = false;
+            self.dirty_tabs.insert(tab_id);
+        }
+    }
+
+    /// Fold duplicate tabs into `survivor`: it inherits a description or suggestion it lacks.
+    /// Active time stays with the tab it was spent in, so it is counted once. Returns the
+    /// open duplicates with their browser tab, if any; callers close them once the browser
+    /// confirms.
+    pub fn merge_duplicates(
+        &mut self,
+        survivor: i64,
+        duplicates: &[i64],
+    ) -> Result<Vec<(i64, Option<BrowserTab>)>, String> {
+        if self
+            .tabs
+            .get(&survivor)
+            .is_none_or(|t| t.closed_at.is_some())
+        {
+            return Err(format!("Tab {} is not open", survivor));
+        }
+        if duplicates.contains(&survivor) {
+            return Err("The surviving tab cannot also be a duplicate".to_string());
+        }
+
+        let merged: Vec<TabRecord> = duplicates
+            .iter()
+            .filter_map(|id| self.tabs.get(id))
+            .filter(|t| t.closed_at.is_none())
+            .cloned()
+            .collect();
+
+        if let Some(tab) = self.tabs.get_mut(&survivor) {
+            for duplicate in &merged {
+                if tab.description.is_none() {
+                    tab.description = duplicate.description.clone();
+                }
+                if tab.suggestion.is_none() {
+                    tab.suggestion = duplicate.suggestion.clone();
+                }
+            }
         }
+        self.dirty_tabs.insert(survivor);
+
+        println!(
+            "[Storage] Merged {} duplicate tabs into {}",
+            merged.len(),
+            survivor
+        );
+        Ok(merged.iter().map(|t| (t.id, t.browser_tab())).collect())
     }
 
     pub fn update_suggestion(&mut self, tab_id: i64, suggestion: TabSuggestion) {
         if let Some(tab) = self.tabs.get_mut(&tab_id) {
             tab.suggestion = Some(suggestion);
+            self.dirty_tabs.insert(tab_id);
         }
     }
 
-    pub fn clear(&mut self) {
+    /// Writes to the database directly; callers hold `persist::pause_flushes`
+    pub fn clear(&mut self) -> Result<(), Box<dyn std::error::Error>> {
         self.tabs.clear();
-        self.report = None;
+        self.dirty_tabs.clear();
+        self.db.clear_tabs()?;
+        self.db.clear_reports()?;
         // Clean up screenshots
         if let Ok(entries) = fs::read_dir(&self.screenshots_dir) {
             for entry in entries.flatten() {
                 fs::remove_file(entry.path()).ok();
             }
         }
+        Ok(())
+    }
+
+    /// Writes to the database directly; callers hold `persist::pause_flushes`
+    pub fn clear_suggestions(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        for tab in self.tabs.values_mut() {
+            tab.suggestion = None;
+        }
+        self.db.clear_suggestions()?;
+        Ok(())
     }
 
     /// Clean up old closed tabs (older than specified days)
@@ -225,6 +575,7 @@ impl Storage {
         }
 
         if count > 0 {
+            self.mark_tabs_dirty(&tabs_to_remove);
             println!("[Storage] Cleaned up {} old closed tabs", count);
         }
 
@@ -240,9 +591,14 @@ impl Storage {
     }
 
     /// Sync with actual Chrome tabs - remove tabs that no longer exist in Chrome
-    /// Takes a list of currently open tab IDs from Chrome
-    pub fn sync_with_chrome_tabs(&mut self, chrome_tab_ids: &[i64]) -> usize {
-        let chrome_set: std::collections::HashSet<i64> = chrome_tab_ids.iter().cloned().collect();
+    /// Takes a list of currently open tab IDs from Chrome and, when known, the browser
+    /// session they belong to (records from other sessions are stale even if ids collide)
+    pub fn sync_with_chrome_tabs(
+        &mut self,
+        chrome_tab_ids: &[i64],
+        session_id: Option<&str>,
+    ) -> usize {
+        let chrome_set: HashSet<i64> = chrome_tab_ids.iter().cloned().collect();
 
         // Find open tabs in storage that are NOT in Chrome anymore
         let stale_tabs: Vec<i64> = self
@@ -250,7 +606,9 @@ impl Storage {
             .iter()
             .filter(|(_, tab)| {
                 // Only check tabs that are still "open" in storage
-                tab.closed_at.is_none() && !chrome_set.contains(&tab.id)
+                tab.closed_at.is_none()
+                    && (!tab.chrome_id.is_some_and(|id| chrome_set.contains(&id))
+                        || session_id.is_some_and(|s| tab.session_id.as_deref() != Some(s)))
             })
             .map(|(id, _)| *id)
             .collect();
@@ -274,6 +632,7 @@ impl Storage {
         }
 
         if count > 0 {
+            self.mark_tabs_dirty(&stale_tabs);
             println!(
                 "[Storage] Synced {} stale tabs (no longer in Chrome)",
                 count
@@ -283,26 +642,8 @@ impl Storage {
         count
     }
 
-    /// Save screenshot for a tab. Only ONE screenshot per tab ID is kept (overwrites old one).
-    pub fn save_screenshot(
-        &self,
-        tab_id: i64,
-        base64_data: &str,
-    ) -> Result<String, Box<dyn std::error::Error>> {
-        let bytes = base64::engine::general_purpose::STANDARD.decode(base64_data)?;
-
-        // Use fixed filename per tab ID (will overwrite old screenshot)
-        let filename = format!("{}.jpg", tab_id);
-        let path = self.screenshots_dir.join(&filename);
-
-        // Delete old screenshot if exists (no-op if not exists)
-        let _ = fs::remove_file(&path);
-
-        // Write new screenshot
-        let mut file = fs::File::create(&path)?;
-        file.write_all(&bytes)?;
-
-        Ok(path.to_string_lossy().to_string())
+    pub fn screenshots_dir(&self) -> &Path {
+        &self.screenshots_dir
     }
 
     /// Delete screenshot for a tab (called when tab is closed)
@@ -313,57 +654,521 @@ impl Storage {
     }
 
     // Persistence methods
-    fn tabs_path(&self) -> PathBuf {
+    fn legacy_tabs_path(&self) -> PathBuf {
         self.data_dir.join("tabs.json")
     }
 
-    fn settings_path(&self) -> PathBuf {
+    fn legacy_settings_path(&self) -> PathBuf {
         self.data_dir.join("settings.json")
     }
 
-    fn report_path(&self) -> PathBuf {
+    fn legacy_report_path(&self) -> PathBuf {
         self.data_dir.join("report.json")
     }
 
-    pub fn save_tabs(&self) -> Result<(), Box<dyn std::error::Error>> {
-        let json = serde_json::to_string_pretty(&self.tabs)?;
-        fs::write(self.tabs_path(), json)?;
-        Ok(())
+    /// One-time import of the JSON files written by versions before the SQLite store.
+    /// Imported files are renamed to `*.migrated` so the import never runs twice;
+    /// files that cannot be parsed even after migration are quarantined.
+    fn migrate_legacy_files(&self) {
+        let tabs_path = self.legacy_tabs_path();
+        if let Ok(data) = fs::read_to_string(&tabs_path) {
+            match migrate::parse_tabs::<HashMap<i64, TabRecord>>(&data) {
+                Ok(tabs) => match self.db.upsert_tabs(tabs.values()) {
+                    Ok(()) => {
+                        println!("[Storage] Migrated {} tabs from tabs.json", tabs.len());
+                        mark_migrated(&tabs_path);
+                    }
+                    Err(e) => eprintln!("[Storage] Failed to migrate tabs.json: {}", e),
+                },
+                Err(e) => {
+                    eprintln!("[Storage] Failed to parse tabs.json: {}", e);
+                    migrate::quarantine(&tabs_path);
+                }
+            }
+        }
+
+        let settings_path = self.legacy_settings_path();
+        if let Ok(data) = fs::read_to_string(&settings_path) {
+            match migrate::parse_settings::<Settings>(&data) {
+                Ok(settings) => {
+                    let result = migrate::settings_to_string(&settings)
+                        .and_then(|json| self.db.save_settings(&json).map_err(|e| e.to_string()));
+                    match result {
+                        Ok(()) => {
+                            println!("[Storage] Migrated settings.json");
+                            mark_migrated(&settings_path);
+                        }
+                        Err(e) => eprintln!("[Storage] Failed to migrate settings.json: {}", e),
+                    }
+                }
+                Err(e) => {
+                    eprintln!("[Storage] Failed to parse settings.json: {}", e);
+                    migrate::quarantine(&settings_path);
+                }
+            }
+        }
+
+        let report_path = self.legacy_report_path();
+        if let Ok(data) = fs::read_to_string(&report_path) {
+            match serde_json::from_str::<Option<DailyReport>>(&data) {
+                Ok(report) => {
+                    let result = report.map_or(Ok(()), |r| self.db.save_report(&r));
+                    match result {
+                        Ok(()) => {
+                            println!("[Storage] Migrated report.json");
+                            mark_migrated(&report_path);
+                        }
+                        Err(e) => eprintln!("[Storage] Failed to migrate report.json: {}", e),
+                    }
+                }
+                Err(e) => {
+                    eprintln!("[Storage] Failed to parse report.json: {}", e);
+                    migrate::quarantine(&report_path);
+                }
+            }
+        }
     }
 
-    fn load_tabs(&mut self) {
-        if let Ok(data) = fs::read_to_string(self.tabs_path()) {
-            if let Ok(tabs) = serde_json::from_str(&data) {
-                self.tabs = tabs;
+    /// Queue a tab row for the next background flush.
+    /// Tabs no longer in memory are deleted from the database.
+    pub fn mark_tab_dirty(&mut self, tab_id: i64) {
+        self.dirty_tabs.insert(tab_id);
+    }
+
+    pub fn mark_tabs_dirty(&mut self, tab_ids: &[i64]) {
+        self.dirty_tabs.extend(tab_ids.iter().copied());
+    }
+
+    /// Drain the dirty set into a batch that can be written without holding the lock
+    pub fn take_pending_writes(&mut self) -> PendingWrites {
+        let mut pending = PendingWrites {
+            upserts: Vec::new(),
+            deletes: Vec::new(),
+        };
+        for tab_id in self.dirty_tabs.drain() {
+            match self.tabs.get(&tab_id) {
+                Some(tab) => pending.upserts.push(tab.clone()),
+                None => pending.deletes.push(tab_id),
             }
         }
+        pending
+    }
+
+    pub fn db(&self) -> Arc<Database> {
+        self.db.clone()
+    }
+
+    pub fn flush_lock(&self) -> Arc<tokio::sync::Mutex<()>> {
+        self.flush_lock.clone()
+    }
+
+    fn load_tabs(&mut self) {
+        match self.db.load_tabs() {
+            Ok(tabs) => self.tabs = tabs,
+            Err(e) => eprintln!("[Storage] Failed to load tabs: {}", e),
+        }
+        self.next_tab_id = self.tabs.keys().max().map_or(1, |max| max + 1);
     }
 
     pub fn save_settings(&self) -> Result<(), Box<dyn std::error::Error>> {
-        let json = serde_json::to_string_pretty(&self.settings)?;
-        fs::write(self.settings_path(), json)?;
+        let json = migrate::settings_to_string(&self.settings)?;
+        self.db.save_settings(&json)?;
         Ok(())
     }
 
     fn load_settings(&mut self) {
-        if let Ok(data) = fs::read_to_string(self.settings_path()) {
-            if let Ok(settings) = serde_json::from_str(&data) {
-                self.settings = settings;
+        let data = match self.db.load_settings() {
+            Ok(Some(data)) => data,
+            Ok(None) => return,
+            Err(e) => {
+                eprintln!("[Storage] Failed to load settings: {}", e);
+                return;
+            }
+        };
+
+        match migrate::parse_settings(&data) {
+            Ok(settings) => self.settings = settings,
+            Err(e) => {
+                // Keep a copy before defaults get written over the stored document
+                eprintln!("[Storage] Failed to parse settings: {}", e);
+                migrate::quarantine_contents(&self.data_dir, "settings.json", &data);
             }
         }
     }
 
-    pub fn save_report(&self) -> Result<(), Box<dyn std::error::Error>> {
-        let json = serde_json::to_string_pretty(&self.report)?;
-        fs::write(self.report_path(), json)?;
+    /// Active time per tab recorded between `from` and `to` (inclusive, local time).
+    /// Time not yet flushed is left out.
+    pub fn get_activity_between(
+        &self,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> Result<HashMap<i64, i64>, Box<dyn std::error::Error>> {
+        Ok(self
+            .db
+            .load_activity(&format_date(from), &format_date(to))?)
+    }
+
+    // Report archive (one report per local date, read straight from the database)
+
+    pub fn latest_report(&self) -> Result<Option<DailyReport>, Box<dyn std::error::Error>> {
+        Ok(self.db.load_latest_report()?)
+    }
+
+    pub fn get_report(
+        &self,
+        date: NaiveDate,
+    ) -> Result<Option<DailyReport>, Box<dyn std::error::Error>> {
+        Ok(self.db.load_report(&format_date(date))?)
+    }
+
+    /// Reports between `from` and `to` (inclusive, either end open), newest first
+    pub fn list_reports(
+        &self,
+        from: Option<NaiveDate>,
+        to: Option<NaiveDate>,
+    ) -> Result<Vec<DailyReport>, Box<dyn std::error::Error>> {
+        let from = from.map(format_date);
+        let to = to.map(format_date);
+        Ok(self.db.list_reports(from.as_deref(), to.as_deref())?)
+    }
+
+    /// Suggestions cached under these input hashes that were scored at or after `since` (ms)
+    pub fn cached_suggestions(
+        &self,
+        hashes: &[String],
+        since: i64,
+    ) -> Result<HashMap<String, TabSuggestion>, Box<dyn std::error::Error>> {
+        Ok(self.db.load_cached_suggestions(hashes, since)?)
+    }
+
+    /// Add suggestions to the cache, dropping entries scored before `expired_before` (ms)
+    pub fn cache_suggestions(
+        &self,
+        entries: &[(String, TabSuggestion)],
+        expired_before: i64,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.db.save_cached_suggestions(entries, expired_before)?;
         Ok(())
     }
 
-    fn load_report(&mut self) {
-        if let Ok(data) = fs::read_to_string(self.report_path()) {
-            if let Ok(report) = serde_json::from_str(&data) {
-                self.report = report;
-            }
+    /// Store a report, replacing any earlier one for the same date
+    pub fn save_report(&self, report: &DailyReport) -> Result<(), Box<dyn std::error::Error>> {
+        self.db.save_report(report)?;
+        Ok(())
+    }
+
+    pub fn report_draft(
+        &self,
+        date: NaiveDate,
+    ) -> Result<Option<ReportDraft>, Box<dyn std::error::Error>> {
+        Ok(self.db.load_report_draft(&format_date(date))?)
+    }
+
+    pub fn save_report_draft(&self, draft: &ReportDraft) -> Result<(), Box<dyn std::error::Error>> {
+        self.db.save_report_draft(draft)?;
+        Ok(())
+    }
+
+    pub fn delete_report_draft(&self, date: NaiveDate) -> Result<(), Box<dyn std::error::Error>> {
+        self.db.delete_report_draft(&format_date(date))?;
+        Ok(())
+    }
+
+    pub fn delete_report(&self, date: NaiveDate) -> Result<bool, Box<dyn std::error::Error>> {
+        Ok(self.db.delete_report(&format_date(date))?)
+    }
+
+    pub fn list_period_reports(
+        &self,
+        granularity: Option<&str>,
+    ) -> Result<Vec<PeriodReport>, Box<dyn std::error::Error>> {
+        Ok(self.db.list_period_reports(granularity)?)
+    }
+
+    /// Store a rollup, replacing an earlier one for the same period
+    pub fn save_period_report(
+        &self,
+        report: &PeriodReport,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.db.save_period_report(report)?;
+        Ok(())
+    }
+
+    /// Delete reports dated before `date` and rollups that ended before it,
+    /// returning how many were removed
+    pub fn delete_reports_before(
+        &self,
+        date: NaiveDate,
+    ) -> Result<usize, Box<dyn std::error::Error>> {
+        let count = self.db.delete_reports_before(&format_date(date))?;
+        if count > 0 {
+            println!("[Storage] Deleted {} reports before {}", count, date);
+        }
+        Ok(count)
+    }
+}
+
+/// Report dates are local calendar days formatted as `YYYY-MM-DD`
+pub const DATE_FORMAT: &str = "%Y-%m-%d";
+
+pub fn format_date(date: NaiveDate) -> String {
+    date.format(DATE_FORMAT).to_string()
+}
+
+pub fn parse_date(date: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(date, DATE_FORMAT)
+        .map_err(|_| format!("Invalid date \"{}\", expected YYYY-MM-DD", date))
+}
+
+/// Local-time `[start, end)` of a day in milliseconds
+pub fn day_bounds(date: NaiveDate) -> (i64, i64) {
+    let start_of = |day: NaiveDate| {
+        let midnight = day.and_hms_opt(0, 0, 0).unwrap();
+        // A DST jump can skip local midnight; fall back to treating it as UTC
+        Local
+            .from_local_datetime(&midnight)
+            .earliest()
+            .map(|dt| dt.timestamp_millis())
+            .unwrap_or_else(|| midnight.and_utc().timestamp_millis())
+    };
+    let next_day = date.succ_opt().unwrap_or(date);
+    (start_of(date), start_of(next_day))
+}
+
+/// Save screenshot for a tab. Only ONE screenshot per tab ID is kept (overwrites old one).
+/// Written atomically so a crash never leaves a truncated image behind.
+pub fn save_screenshot(
+    screenshots_dir: &Path,
+    tab_id: i64,
+    base64_data: &str,
+) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    let bytes = base64::engine::general_purpose::STANDARD.decode(base64_data)?;
+
+    // Use fixed filename per tab ID (will overwrite old screenshot)
+    let filename = format!("{}.jpg", tab_id);
+    let path = screenshots_dir.join(&filename);
+    persist::write_atomic(&path, &bytes)?;
+
+    Ok(path.to_string_lossy().to_string())
+}
+
+/// Open the database, quarantining it if SQLite reports it as corrupt.
+/// Falls back to an in-memory store so the app keeps running without touching the file.
+fn open_database(path: &Path) -> Database {
+    let result = match Database::open(path) {
+        Err(OpenError::Corrupt(e)) => {
+            eprintln!("[Storage] Database is corrupt: {}", e);
+            migrate::quarantine(path);
+            Database::open(path)
+        }
+        result => result,
+    };
+
+    result.unwrap_or_else(|e| {
+        eprintln!(
+            "[Storage] Failed to open database, using in-memory store: {}",
+            e
+        );
+        Database::open_in_memory().expect("in-memory database")
+    })
+}
+
+fn mark_migrated(path: &Path) {
+    let mut target = path.as_os_str().to_owned();
+    target.push(".migrated");
+    if let Err(e) = fs::rename(path, &target) {
+        eprintln!("[Storage] Failed to rename {}: {}", path.display(), e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    const OLD_SESSION: &str = "session-before-restart";
+    const SESSION: &str = "session-after-restart";
+
+    fn storage(records: Vec<TabRecord>) -> Storage {
+        let mut storage = Storage::in_memory();
+        storage.next_tab_id = records.iter().map(|t| t.id).max().unwrap_or(0) + 1;
+        storage.tabs = records.into_iter().map(|t| (t.id, t)).collect();
+        storage
+    }
+
+    fn chrome_tab<'a>(
+        chrome_id: i64,
+        session_id: &'a str,
+        index: i64,
+        url: &'a str,
+    ) -> ChromeTab<'a> {
+        ChromeTab {
+            chrome_id,
+            session_id: Some(session_id),
+            window_id: Some(1),
+            index: Some(index),
+            url: Some(url),
         }
     }
+
+    fn resolve(storage: &mut Storage, tab: &ChromeTab) -> i64 {
+        storage
+            .resolve_tab(tab, |id| {
+                test_support::tab(id, tab.url.unwrap_or("")).build()
+            })
+            .id
+    }
+
+    #[test]
+    fn relinks_tabs_after_a_browser_restart() {
+        let url = "https://docs.rs/tokio";
+        let mut storage = storage(vec![test_support::tab(1, url)
+            .in_browser(40, OLD_SESSION, 2)
+            .active_ms(90_000)
+            .build()]);
+
+        assert_eq!(resolve(&mut storage, &chrome_tab(7, SESSION, 2, url)), 1);
+        let record = &storage.tabs[&1];
+        assert_eq!(record.chrome_id, Some(7));
+        assert_eq!(record.session_id.as_deref(), Some(SESSION));
+        assert_eq!(record.active_ms_base, 90_000);
+        // Later reports in the new session find the live record
+        assert_eq!(resolve(&mut storage, &chrome_tab(7, SESSION, 2, url)), 1);
+        assert_eq!(storage.tabs.len(), 1);
+    }
+
+    #[test]
+    fn a_reused_chrome_id_in_the_same_session_is_a_new_tab() {
+        let url = "https://example.com/";
+        let mut storage = storage(vec![test_support::tab(1, url)
+            .in_browser(7, SESSION, 0)
+            .closed_at(chrono::Utc::now().timestamp_millis())
+            .build()]);
+
+        let tab_id = resolve(&mut storage, &chrome_tab(7, SESSION, 0, url));
+        assert_eq!(tab_id, 2);
+        assert!(storage.tabs[&1].closed_at.is_some());
+        // Inserted right away, so a second report of the tab does not allocate another id
+        assert_eq!(resolve(&mut storage, &chrome_tab(7, SESSION, 0, url)), 2);
+        assert_eq!(storage.tabs.len(), 2);
+    }
+
+    #[test]
+    fn relink_prefers_position_then_open_then_recent_then_oldest() {
+        let url = "https://news.ycombinator.com/";
+        let now = chrono::Utc::now().timestamp_millis();
+        let candidates = || {
+            vec![
+                // Same position, but closed and idle the longest
+                test_support::tab(1, url)
+                    .in_browser(11, OLD_SESSION, 3)
+                    .closed_at(now)
+                    .last_active_at(100)
+                    .build(),
+                test_support::tab(2, url)
+                    .in_browser(12, OLD_SESSION, 0)
+                    .last_active_at(200)
+                    .build(),
+                // Open, as recent as 2 and created later
+                test_support::tab(3, url)
+                    .in_browser(13, OLD_SESSION, 1)
+                    .created_at(50)
+                    .last_active_at(200)
+                    .build(),
+                test_support::tab(4, url)
+                    .in_browser(14, OLD_SESSION, 4)
+                    .closed_at(now)
+                    .last_active_at(900)
+                    .build(),
+            ]
+        };
+        let relinked = |index: i64, skip: &[i64]| {
+            let mut storage = storage(candidates());
+            storage.tabs.retain(|id, _| !skip.contains(id));
+            let tab = chrome_tab(99, SESSION, index, url);
+            storage.find_relink_candidate(&tab)
+        };
+
+        assert_eq!(relinked(3, &[]), Some(1));
+        assert_eq!(relinked(8, &[]), Some(2));
+        assert_eq!(relinked(8, &[2]), Some(3));
+        assert_eq!(relinked(8, &[2, 3]), Some(4));
+    }
+
+    #[test]
+    fn tabs_between_counts_only_tabs_used_in_the_range() {
+        let day = parse_date("2026-10-13").unwrap();
+        let (start, end) = day_bounds(day);
+        let later = end + 86_400_000;
+        let storage = storage(vec![
+            test_support::tab(1, "https://docs.rs/")
+                .created_at(start - 1_000)
+                .last_active_at(start + 1_000)
+                .build(),
+            // Opened before the day and only used after it
+            test_support::tab(2, "https://example.com/")
+                .created_at(start - 1_000)
+                .last_active_at(later)
+                .build(),
+            // Last used after the day, but also on it
+            test_support::tab(3, "https://news.example/")
+                .created_at(start - 1_000)
+                .last_active_at(later)
+                .active_ms(30_000)
+                .build(),
+            test_support::tab(4, "https://mail.example.com/")
+                .created_at(end)
+                .build(),
+        ]);
+        storage
+            .db()
+            .write_batch(&[storage.tabs[&3].clone()], &[], "2026-10-13")
+            .unwrap();
+
+        let mut ids: Vec<i64> = storage
+            .get_tabs_for_date(day)
+            .iter()
+            .map(|t| t.id)
+            .collect();
+        ids.sort();
+        assert_eq!(ids, vec![1, 3]);
+    }
+
+    #[test]
+    fn flushes_credit_active_time_to_the_day_it_was_gained() {
+        let storage = Storage::in_memory();
+        let db = storage.db();
+        let mut tab = test_support::tab(1, "https://docs.rs/")
+            .active_ms(60_000)
+            .build();
+        db.write_batch(&[tab.clone()], &[], "2026-10-12").unwrap();
+        tab.total_active_ms = 100_000;
+        db.write_batch(&[tab.clone()], &[], "2026-10-13").unwrap();
+        // Rewriting an unchanged row adds nothing
+        db.write_batch(&[tab.clone()], &[], "2026-10-13").unwrap();
+        tab.total_active_ms = 130_000;
+        db.write_batch(&[tab.clone()], &[], "2026-10-13").unwrap();
+
+        let between = |from: &str, to: &str| {
+            storage
+                .get_activity_between(parse_date(from).unwrap(), parse_date(to).unwrap())
+                .unwrap()
+        };
+        assert_eq!(
+            between("2026-10-12", "2026-10-12"),
+            HashMap::from([(1, 60_000)])
+        );
+        assert_eq!(
+            between("2026-10-13", "2026-10-13"),
+            HashMap::from([(1, 70_000)])
+        );
+        assert_eq!(
+            between("2026-10-12", "2026-10-13"),
+            HashMap::from([(1, 130_000)])
+        );
+        assert!(between("2026-10-14", "2026-10-20").is_empty());
+
+        db.write_batch(&[], &[1], "2026-10-13").unwrap();
+        assert!(between("2026-10-12", "2026-10-13").is_empty());
+    }
 }
@@ -0,0 +1,252 @@
+//! Local HTTP stub that plays back scripted responses, so AI code runs without an API key,
+//! and a builder for the tab records tests feed it.
+
+use axum::body::Bytes;
+use axum::extract::{OriginalUri, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::Router;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::storage::{Settings, TabRecord, TabSnapshot, TabSuggestion};
+
+#[derive(Clone)]
+pub struct StubResponse {
+    pub status: u16,
+    pub body: String,
+    /// Wait before answering, to exercise client timeouts
+    pub delay: Duration,
+}
+
+impl StubResponse {
+    pub fn ok(body: serde_json::Value) -> Self {
+        Self {
+            status: 200,
+            body: body.to_string(),
+            delay: Duration::ZERO,
+        }
+    }
+
+    pub fn error(status: u16, body: &str) -> Self {
+        Self {
+            status,
+            body: body.to_string(),
+            delay: Duration::ZERO,
+        }
+    }
+
+    /// An OpenAI `/chat/completions` reply with `content` as the assistant message
+    pub fn chat(content: &str) -> Self {
+        Self::ok(serde_json::json!({
+            "choices": [{ "message": { "role": "assistant", "content": content } }]
+        }))
+    }
+
+    /// A streamed reply: each item becomes one line of the body
+    pub fn lines(lines: &[String]) -> Self {
+        Self {
+            status: 200,
+            body: lines.iter().map(|line| format!("{}\n", line)).collect(),
+            delay: Duration::ZERO,
+        }
+    }
+
+    /// A server-sent event stream with one `data:` event per item
+    pub fn sse(events: &[String]) -> Self {
+        let lines: Vec<String> = events.iter().map(|e| format!("data: {}\n", e)).collect();
+        Self::lines(&lines)
+    }
+
+    pub fn delayed(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RecordedRequest {
+    pub path: String,
+    pub headers: HeaderMap,
+    pub body: serde_json::Value,
+}
+
+#[derive(Default)]
+struct Script {
+    responses: VecDeque<StubResponse>,
+    requests: Vec<RecordedRequest>,
+    in_flight: usize,
+    max_in_flight: usize,
+}
+
+pub struct StubServer {
+    pub url: String,
+    script: Arc<Mutex<Script>>,
+}
+
+impl StubServer {
+    /// Serve `responses` in order, one per request; later requests get a 500
+    pub async fn start(responses: Vec<StubResponse>) -> Self {
+        let script = Arc::new(Mutex::new(Script {
+            responses: responses.into(),
+            ..Script::default()
+        }));
+        let app = Router::new().fallback(respond).with_state(script.clone());
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+
+        Self { url, script }
+    }
+
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.script.lock().unwrap().requests.clone()
+    }
+
+    /// Most requests being answered at the same time so far
+    pub fn max_in_flight(&self) -> usize {
+        self.script.lock().unwrap().max_in_flight
+    }
+
+    /// Settings that send every task to this server as an OpenAI-compatible endpoint
+    pub fn settings(&self) -> Settings {
+        Settings {
+            openai_api_key: Some("test-key".to_string()),
+            base_url: Some(self.url.clone()),
+            model: Some("test-model".to_string()),
+            ..Settings::default()
+        }
+    }
+}
+
+async fn respond(
+    State(script): State<Arc<Mutex<Script>>>,
+    OriginalUri(uri): OriginalUri,
+    headers: HeaderMap,
+    body: Bytes,
+) -> (StatusCode, String) {
+    let next = {
+        let mut script = script.lock().unwrap();
+        script.requests.push(RecordedRequest {
+            path: uri.path().to_string(),
+            headers,
+            body: serde_json::from_slice(&body).unwrap_or(serde_json::Value::Null),
+        });
+        script.in_flight += 1;
+        script.max_in_flight = script.max_in_flight.max(script.in_flight);
+        script.responses.pop_front()
+    };
+
+    let reply = match next {
+        Some(response) => {
+            tokio::time::sleep(response.delay).await;
+            (
+                StatusCode::from_u16(response.status).unwrap(),
+                response.body,
+            )
+        }
+        None => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "stub script exhausted".to_string(),
+        ),
+    };
+    script.lock().unwrap().in_flight -= 1;
+    reply
+}
+
+/// An open, unscored tab at `url` in window 1, with `id` as its Chrome id and title
+pub fn tab(id: i64, url: &str) -> TabBuilder {
+    TabBuilder(TabRecord {
+        id,
+        chrome_id: Some(id),
+        session_id: None,
+        tab_index: None,
+        window_id: Some(1),
+        url: Some(url.to_string()),
+        title: Some(url.to_string()),
+        fav_icon_url: None,
+        created_at: 0,
+        last_active_at: None,
+        total_active_ms: 0,
+        active_ms_base: 0,
+        is_active: false,
+        closed_at: None,
+        description: None,
+        snapshot: None,
+        suggestion: None,
+    })
+}
+
+pub struct TabBuilder(TabRecord);
+
+impl TabBuilder {
+    pub fn title(mut self, title: &str) -> Self {
+        self.0.title = Some(title.to_string());
+        self
+    }
+
+    pub fn description(mut self, description: &str) -> Self {
+        self.0.description = Some(description.to_string());
+        self
+    }
+
+    /// Where the browser has the tab: Chrome id, session and position in its window
+    pub fn in_browser(mut self, chrome_id: i64, session_id: &str, index: i64) -> Self {
+        self.0.chrome_id = Some(chrome_id);
+        self.0.session_id = Some(session_id.to_string());
+        self.0.tab_index = Some(index);
+        self
+    }
+
+    pub fn created_at(mut self, created_at: i64) -> Self {
+        self.0.created_at = created_at;
+        self
+    }
+
+    pub fn last_active_at(mut self, last_active_at: impl Into<Option<i64>>) -> Self {
+        self.0.last_active_at = last_active_at.into();
+        self
+    }
+
+    pub fn active_ms(mut self, total_active_ms: i64) -> Self {
+        self.0.total_active_ms = total_active_ms;
+        self
+    }
+
+    pub fn closed_at(mut self, closed_at: impl Into<Option<i64>>) -> Self {
+        self.0.closed_at = closed_at.into();
+        self
+    }
+
+    pub fn screenshot(mut self, path: &str) -> Self {
+        self.0.snapshot = Some(TabSnapshot {
+            screenshot_path: Some(path.to_string()),
+            captured_at: 0,
+        });
+        self
+    }
+
+    /// A "keep" suggestion in `category`; `None` leaves the tab unscored
+    pub fn category<'a>(mut self, category: impl Into<Option<&'a str>>) -> Self {
+        self.0.suggestion = category.into().map(|c| TabSuggestion {
+            decision: "keep".to_string(),
+            reason: String::new(),
+            category: Some(c.to_string()),
+            digest: None,
+            scored_at: 0,
+        });
+        self
+    }
+
+    pub fn suggestion(mut self, suggestion: TabSuggestion) -> Self {
+        self.0.suggestion = Some(suggestion);
+        self
+    }
+
+    pub fn build(self) -> TabRecord {
+        self.0
+    }
+}
@@ -0,0 +1,421 @@
+//! Ledger of AI provider calls with estimated cost, and the optional monthly budget.
+
+use chrono::{DateTime, Datelike, Duration, Local, TimeZone};
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, OnceLock};
+
+use crate::db::Database;
+use crate::llm::{ProviderKind, Task};
+use crate::storage::Settings;
+use crate::AppState;
+
+/// Days and months covered by `summary` when the caller does not say
+const DEFAULT_DAYS: u32 = 30;
+const DEFAULT_MONTHS: u32 = 12;
+
+/// Built-in list prices in USD per million input and output tokens, matched by model name
+/// prefix. More specific prefixes come first.
+const PRICES: &[(&str, f64, f64)] = &[
+    ("gpt-4o-mini", 0.15, 0.60),
+    ("gpt-4o", 2.50, 10.00),
+    ("gpt-4.1-nano", 0.10, 0.40),
+    ("gpt-4.1-mini", 0.40, 1.60),
+    ("gpt-4.1", 2.00, 8.00),
+    ("gpt-5-nano", 0.05, 0.40),
+    ("gpt-5-mini", 0.25, 2.00),
+    ("gpt-5", 1.25, 10.00),
+    ("text-embedding-3-small", 0.02, 0.0),
+    ("text-embedding-3-large", 0.13, 0.0),
+    ("claude-3-haiku", 0.25, 1.25),
+    ("claude-3-5-haiku", 0.80, 4.00),
+    ("claude-haiku-4", 1.00, 5.00),
+    ("claude-3-5-sonnet", 3.00, 15.00),
+    ("claude-3-7-sonnet", 3.00, 15.00),
+    ("claude-sonnet-4", 3.00, 15.00),
+];
+
+/// USD per million tokens
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ModelPrice {
+    pub input: f64,
+    pub output: f64,
+}
+
+/// One provider call as recorded in the ledger
+#[derive(Debug, Clone)]
+pub struct AiCall {
+    pub provider: ProviderKind,
+    pub model: String,
+    pub task: Task,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    /// Token counts were estimated locally because the provider did not report them
+    pub estimated: bool,
+    pub image_count: usize,
+    /// Including retries
+    pub latency_ms: u64,
+    /// Not known for failed calls
+    pub retries: Option<u32>,
+    pub error: Option<String>,
+}
+
+/// Totals for one day ("2026-10-17") or month ("2026-10"), in local time
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct UsagePeriod {
+    pub period: String,
+    pub calls: u64,
+    pub failed: u64,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub image_count: u64,
+    pub cost_usd: f64,
+    /// Calls to models without a known price; not included in `cost_usd`
+    pub unpriced_calls: u64,
+    pub avg_latency_ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct UsageSummary {
+    /// Newest first
+    pub daily: Vec<UsagePeriod>,
+    pub monthly: Vec<UsagePeriod>,
+    pub month_to_date_usd: f64,
+    pub monthly_budget_usd: Option<f64>,
+}
+
+// Installed at startup; calls made without a ledger (e.g. in tests) are not recorded
+static LEDGER: OnceLock<Arc<Database>> = OnceLock::new();
+
+pub fn install(db: Arc<Database>) {
+    let _ = LEDGER.set(db);
+}
+
+/// Price of `model`: the longest matching prefix in the settings overrides, then the
+/// built-in table. Local providers are free. `None` if the price is unknown.
+pub fn price_for(settings: &Settings, provider: ProviderKind, model: &str) -> Option<ModelPrice> {
+    let model = model.to_lowercase();
+    let configured = settings
+        .model_prices
+        .iter()
+        .flatten()
+        .filter(|(prefix, _)| model.starts_with(&prefix.to_lowercase()))
+        .max_by_key(|(prefix, _)| prefix.len())
+        .map(|(_, price)| *price);
+    if configured.is_some() {
+        return configured;
+    }
+
+    match provider {
+        ProviderKind::Ollama | ProviderKind::Mock => Some(ModelPrice {
+            input: 0.0,
+            output: 0.0,
+        }),
+        _ => {
+            // Proxies often namespace models, e.g. "openai/gpt-4o-mini"
+            let model = model.rsplit('/').next().unwrap_or(&model);
+            PRICES
+                .iter()
+                .find(|(prefix, _, _)| model.starts_with(prefix))
+                .map(|&(_, input, output)| ModelPrice { input, output })
+        }
+    }
+}
+
+/// Estimated cost of a call; failed calls are not billed
+fn call_cost(price: ModelPrice, call: &AiCall) -> f64 {
+    if call.error.is_some() {
+        return 0.0;
+    }
+    (call.prompt_tokens as f64 * price.input + call.completion_tokens as f64 * price.output)
+        / 1_000_000.0
+}
+
+/// Midnight on the first day of the month `months_back` months before `now`
+fn month_start(now: DateTime<Local>, months_back: u32) -> i64 {
+    let months = now.year() * 12 + now.month0() as i32 - months_back as i32;
+    Local
+        .with_ymd_and_hms(
+            months.div_euclid(12),
+            months.rem_euclid(12) as u32 + 1,
+            1,
+            0,
+            0,
+            0,
+        )
+        .earliest()
+        .map(|start| start.timestamp_millis())
+        .unwrap_or(0)
+}
+
+/// Midnight `days_back` days before `now`
+fn day_start(now: DateTime<Local>, days_back: u32) -> i64 {
+    (now.date_naive() - Duration::days(days_back as i64))
+        .and_hms_opt(0, 0, 0)
+        .and_then(|midnight| midnight.and_local_timezone(Local).earliest())
+        .map(|start| start.timestamp_millis())
+        .unwrap_or(0)
+}
+
+/// Err once this month's estimated spend reaches the budget. Free calls are always allowed.
+fn within_budget(
+    db: &Database,
+    settings: &Settings,
+    price: Option<ModelPrice>,
+    now: DateTime<Local>,
+) -> Result<(), String> {
+    let Some(budget) = settings.monthly_budget_usd else {
+        return Ok(());
+    };
+    if matches!(price, Some(p) if p.input == 0.0 && p.output == 0.0) {
+        return Ok(());
+    }
+
+    let spent = db
+        .ai_cost_since(month_start(now, 0))
+        .map_err(|e| e.to_string())?;
+    if spent >= budget {
+        println!(
+            "[Usage] Monthly budget reached: ${:.4} of ${:.2}",
+            spent, budget
+        );
+        return Err(format!(
+            "Monthly AI budget of ${:.2} reached (${:.2} spent this month). Raise it in Settings to continue.",
+            budget, spent
+        ));
+    }
+    Ok(())
+}
+
+/// Refuse a call to `model` if the monthly budget is used up
+pub async fn check_budget(
+    settings: &Settings,
+    provider: ProviderKind,
+    model: &str,
+) -> Result<(), String> {
+    let Some(db) = LEDGER.get() else {
+        return Ok(());
+    };
+    if settings.monthly_budget_usd.is_none() {
+        return Ok(());
+    }
+    let settings = settings.clone();
+    let price = price_for(&settings, provider, model);
+    tokio::task::spawn_blocking(move || within_budget(db, &settings, price, Local::now()))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+/// Add a call to the ledger. Failures are logged; they never fail the AI call itself.
+pub async fn record(settings: &Settings, call: AiCall) {
+    let Some(db) = LEDGER.get() else {
+        return;
+    };
+    let cost = price_for(settings, call.provider, &call.model).map(|p| call_cost(p, &call));
+    println!(
+        "[Usage] {} {}: {} prompt + {} completion tokens{}, {}ms, cost {}",
+        call.task.purpose(),
+        call.model,
+        call.prompt_tokens,
+        call.completion_tokens,
+        if call.estimated { " (estimated)" } else { "" },
+        call.latency_ms,
+        cost.map(|c| format!("${:.6}", c))
+            .unwrap_or_else(|| "unknown".to_string())
+    );
+
+    let now = chrono::Utc::now().timestamp_millis();
+    let result = tokio::task::spawn_blocking(move || db.record_ai_call(&call, cost, now)).await;
+    match result {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => eprintln!("[Usage] Failed to record AI call: {}", e),
+        Err(e) => eprintln!("[Usage] Failed to record AI call: {}", e),
+    }
+}
+
+/// Daily totals for the last `days` days and monthly totals for the last `months` months
+pub async fn summary(
+    state: &AppState,
+    days: Option<u32>,
+    months: Option<u32>,
+) -> Result<UsageSummary, String> {
+    let (db, budget) = {
+        let storage = state.read().await;
+        (storage.db(), storage.settings.monthly_budget_usd)
+    };
+    let days = days.unwrap_or(DEFAULT_DAYS).max(1);
+    let months = months.unwrap_or(DEFAULT_MONTHS).max(1);
+
+    tokio::task::spawn_blocking(move || {
+        let now = Local::now();
+        Ok(UsageSummary {
+            daily: db.ai_usage_by_period("%Y-%m-%d", day_start(now, days - 1))?,
+            monthly: db.ai_usage_by_period("%Y-%m", month_start(now, months - 1))?,
+            month_to_date_usd: db.ai_cost_since(month_start(now, 0))?,
+            monthly_budget_usd: budget,
+        })
+    })
+    .await
+    .map_err(|e| e.to_string())?
+    .map_err(|e: rusqlite::Error| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn call(model: &str, prompt_tokens: u64, completion_tokens: u64) -> AiCall {
+        AiCall {
+            provider: ProviderKind::OpenAi,
+            model: model.to_string(),
+            task: Task::Suggestions,
+            prompt_tokens,
+            completion_tokens,
+            estimated: false,
+            image_count: 0,
+            latency_ms: 100,
+            retries: Some(0),
+            error: None,
+        }
+    }
+
+    fn local(year: i32, month: u32, day: u32, hour: u32) -> DateTime<Local> {
+        Local
+            .with_ymd_and_hms(year, month, day, hour, 0, 0)
+            .earliest()
+            .unwrap()
+    }
+
+    #[test]
+    fn prices_prefer_overrides_then_built_ins() {
+        let settings = Settings {
+            model_prices: Some(HashMap::from([
+                (
+                    "gpt-4o".to_string(),
+                    ModelPrice {
+                        input: 1.0,
+                        output: 2.0,
+                    },
+                ),
+                (
+                    "my-".to_string(),
+                    ModelPrice {
+                        input: 5.0,
+                        output: 5.0,
+                    },
+                ),
+            ])),
+            ..Settings::default()
+        };
+        let price = |kind, model| price_for(&settings, kind, model).map(|p| p.input);
+
+        // Overrides win over the more specific built-in "gpt-4o-mini"
+        assert_eq!(price(ProviderKind::OpenAi, "gpt-4o-mini"), Some(1.0));
+        assert_eq!(price(ProviderKind::Ollama, "my-model"), Some(5.0));
+        assert_eq!(
+            price(ProviderKind::OpenAi, "openai/gpt-4.1-mini"),
+            Some(0.40)
+        );
+        assert_eq!(price(ProviderKind::Ollama, "llama3.2"), Some(0.0));
+        assert_eq!(price(ProviderKind::OpenAi, "some-proxy-model"), None);
+    }
+
+    #[test]
+    fn failed_calls_cost_nothing() {
+        let price = ModelPrice {
+            input: 2.0,
+            output: 8.0,
+        };
+        let ok = call("gpt-4.1", 500_000, 100_000);
+        let failed = AiCall {
+            error: Some("API error (500)".to_string()),
+            ..ok.clone()
+        };
+
+        assert!((call_cost(price, &ok) - 1.8).abs() < 1e-9);
+        assert_eq!(call_cost(price, &failed), 0.0);
+    }
+
+    #[test]
+    fn aggregates_by_local_day_and_month() {
+        let db = Database::open_in_memory().unwrap();
+        let at = |t: DateTime<Local>| t.timestamp_millis();
+        db.record_ai_call(
+            &call("gpt-4o-mini", 100, 10),
+            Some(0.5),
+            at(local(2026, 9, 30, 23)),
+        )
+        .unwrap();
+        db.record_ai_call(
+            &call("gpt-4o-mini", 200, 20),
+            Some(0.25),
+            at(local(2026, 10, 1, 9)),
+        )
+        .unwrap();
+        let failed = AiCall {
+            error: Some("timed out".to_string()),
+            retries: None,
+            ..call("unknown-model", 300, 0)
+        };
+        db.record_ai_call(&failed, None, at(local(2026, 10, 1, 10)))
+            .unwrap();
+
+        let days = db.ai_usage_by_period("%Y-%m-%d", 0).unwrap();
+        let months = db.ai_usage_by_period("%Y-%m", 0).unwrap();
+
+        assert_eq!(days.len(), 2);
+        assert_eq!(days[0].period, "2026-10-01");
+        assert_eq!((days[0].calls, days[0].failed), (2, 1));
+        assert_eq!(days[0].prompt_tokens, 500);
+        assert_eq!(days[0].unpriced_calls, 1);
+        assert_eq!(
+            months.iter().map(|m| m.period.as_str()).collect::<Vec<_>>(),
+            ["2026-10", "2026-09"]
+        );
+        assert_eq!(months[0].cost_usd, 0.25);
+        let october = month_start(local(2026, 10, 17, 12), 0);
+        assert_eq!(db.ai_cost_since(october).unwrap(), 0.25);
+    }
+
+    #[test]
+    fn budget_blocks_paid_calls_once_reached() {
+        let db = Database::open_in_memory().unwrap();
+        let now = local(2026, 10, 17, 12);
+        let settings = Settings {
+            monthly_budget_usd: Some(1.0),
+            ..Settings::default()
+        };
+        let paid = price_for(&settings, ProviderKind::OpenAi, "gpt-4o-mini");
+        let free = price_for(&settings, ProviderKind::Ollama, "llama3.2");
+
+        // Last month's spend does not count
+        let september = local(2026, 9, 20, 12).timestamp_millis();
+        db.record_ai_call(&call("gpt-4o", 0, 0), Some(5.0), september)
+            .unwrap();
+        assert!(within_budget(&db, &settings, paid, now).is_ok());
+
+        db.record_ai_call(&call("gpt-4o", 0, 0), Some(1.0), now.timestamp_millis())
+            .unwrap();
+        let err = within_budget(&db, &settings, paid, now).unwrap_err();
+        assert!(err.contains("budget of $1.00"), "{}", err);
+        assert!(within_budget(&db, &settings, free, now).is_ok());
+        assert!(within_budget(&db, &Settings::default(), paid, now).is_ok());
+    }
+
+    #[test]
+    fn month_start_crosses_years() {
+        let january = local(2026, 1, 15, 12);
+        assert_eq!(
+            month_start(january, 1),
+            local(2025, 12, 1, 0).timestamp_millis()
+        );
+        assert_eq!(
+            month_start(january, 13),
+            local(2024, 12, 1, 0).timestamp_millis()
+        );
+        assert_eq!(
+            day_start(january, 15),
+            local(2025, 12, 31, 0).timestamp_millis()
+        );
+    }
+}
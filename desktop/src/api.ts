@@ -3,7 +3,27 @@
  */
 
 import { invoke } from "@tauri-apps/api/core";
-import type { TabRecord, Settings, DailyReport } from "./types";
+import type {
+  TabRecord,
+  Settings,
+  DailyReport,
+  JobInfo,
+  ReportDraft,
+  Granularity,
+  PeriodReport,
+  PrivacyPreview,
+  SearchQuery,
+  SearchResult,
+  SimilarTab,
+  Clustering,
+  DuplicateGroup,
+  SuggestionIssues,
+  UsageSummary,
+  PairingInfo,
+  ConnectedClient,
+  CloseOutcome,
+  QueuedCommand,
+} from "./types";
 
 export async function getTabs(): Promise<TabRecord[]> {
   return invoke("get_tabs");
@@ -13,6 +33,26 @@ export async function getClosedTabs(): Promise<TabRecord[]> {
   return invoke("get_closed_tabs");
 }
 
+export async function searchTabs(query: SearchQuery): Promise<SearchResult[]> {
+  return invoke("search_tabs", { query });
+}
+
+export async function refreshEmbeddings(): Promise<number> {
+  return invoke("refresh_embeddings");
+}
+
+export async function findSimilarTabs(tabId: number, limit?: number): Promise<SimilarTab[]> {
+  return invoke("find_similar_tabs", { tabId, limit });
+}
+
+export async function semanticSearch(query: string, limit?: number): Promise<SimilarTab[]> {
+  return invoke("semantic_search", { query, limit });
+}
+
+export async function clusterTabs(threshold?: number): Promise<Clustering> {
+  return invoke("cluster_tabs", { threshold });
+}
+
 export async function getSettings(): Promise<Settings> {
   return invoke("get_settings");
 }
@@ -21,24 +61,102 @@ export async function getReport(): Promise<DailyReport | null> {
   return invoke("get_report");
 }
 
+/** Dates are local calendar days formatted as YYYY-MM-DD */
+export async function getReportForDate(date: string): Promise<DailyReport | null> {
+  return invoke("get_report_for_date", { date });
+}
+
+export async function listReports(from?: string, to?: string): Promise<DailyReport[]> {
+  return invoke("list_reports", { from, to });
+}
+
+export async function deleteReport(date: string): Promise<boolean> {
+  return invoke("delete_report", { date });
+}
+
+export async function deleteReportsBefore(before: string): Promise<number> {
+  return invoke("delete_reports_before", { before });
+}
+
+export async function getTabsForDate(date: string): Promise<TabRecord[]> {
+  return invoke("get_tabs_for_date", { date });
+}
+
+export async function getClosedTabsForDate(date: string): Promise<TabRecord[]> {
+  return invoke("get_closed_tabs_for_date", { date });
+}
+
 export async function saveSettings(settings: Settings): Promise<void> {
   return invoke("save_settings", { settings });
 }
 
+/** `force` re-analyzes tabs even when a cached suggestion matches their content */
 export async function analyzeBatch(
-  limit: number
-): Promise<[TabRecord[], number]> {
-  return invoke("analyze_batch", { limit });
+  limit: number,
+  force = false
+): Promise<[TabRecord[], number, SuggestionIssues]> {
+  return invoke("analyze_batch", { limit, force });
+}
+
+export async function generateReport(date?: string): Promise<DailyReport> {
+  return invoke("generate_report", { date });
+}
+
+/** Progress arrives as `report-chunk` events; `resume` continues the saved draft */
+export async function generateReportStream(date?: string, resume = false): Promise<DailyReport> {
+  return invoke("generate_report_stream", { date, resume });
+}
+
+/** Rollup of `start` to `end` (YYYY-MM-DD, inclusive) from the archived daily data */
+export async function generatePeriodReport(
+  start: string,
+  end: string,
+  granularity: Granularity
+): Promise<PeriodReport> {
+  return invoke("generate_period_report", { start, end, granularity });
+}
+
+export async function listPeriodReports(granularity?: Granularity): Promise<PeriodReport[]> {
+  return invoke("list_period_reports", { granularity });
+}
+
+/** What an AI task would send with the saved privacy settings, without sending it */
+export async function previewAiRequest(
+  task: "suggestions" | "report" | "embeddings",
+  date?: string
+): Promise<PrivacyPreview> {
+  return invoke("preview_ai_request", { task, date });
+}
+
+export async function getReportDraft(date: string): Promise<ReportDraft | null> {
+  return invoke("get_report_draft", { date });
+}
+
+export async function listJobs(): Promise<JobInfo[]> {
+  return invoke("list_jobs");
 }
 
-export async function generateReport(): Promise<DailyReport> {
-  return invoke("generate_report");
+/** Results of a cancelled job are discarded; false if it had already finished */
+export async function cancelJob(jobId: number): Promise<boolean> {
+  return invoke("cancel_job", { jobId });
 }
 
-export async function closeTab(tabId: number): Promise<void> {
+/** Waits for the extension to close the tab; rejects if no extension confirms */
+export async function closeTab(tabId: number): Promise<CloseOutcome> {
   return invoke("close_tab", { tabId });
 }
 
+export async function findDuplicateTabs(): Promise<DuplicateGroup[]> {
+  return invoke("find_duplicate_tabs");
+}
+
+export async function mergeDuplicateTabs(
+  survivor: number,
+  duplicates: number[]
+): Promise<number> {
+  return invoke("merge_duplicate_tabs", { survivor, duplicates });
+}
+
 export async function markKeep(tabId: number): Promise<void> {
   return invoke("mark_keep", { tabId });
 }
@@ -63,6 +181,42 @@ export async function getStorageStats(): Promise<[number, number, number]> {
   return invoke("get_storage_stats");
 }
 
+export async function getAiUsage(days?: number, months?: number): Promise<UsageSummary> {
+  return invoke("get_ai_usage", { days, months });
+}
+
 export async function syncTabs(chromeTabIds: number[]): Promise<number> {
   return invoke("sync_tabs", { chromeTabIds });
 }
+
+export async function getPairing(): Promise<PairingInfo> {
+  return invoke("get_pairing");
+}
+
+export async function rotatePairingSecret(): Promise<PairingInfo> {
+  return invoke("rotate_pairing_secret");
+}
+
+export async function revokePairedClient(clientId: string): Promise<boolean> {
+  return invoke("revoke_paired_client", { clientId });
+}
+
+export async function createApiToken(name?: string): Promise<string> {
+  return invoke("create_api_token", { name: name || null });
+}
+
+export async function listClients(): Promise<ConnectedClient[]> {
+  return invoke("list_clients");
+}
+
+export async function listQueuedCommands(): Promise<QueuedCommand[]> {
+  return invoke("list_queued_commands");
+}
+
+export async function cancelQueuedCommand(commandId: number): Promise<boolean> {
+  return invoke("cancel_queued_command", { commandId });
+}
+
+export async function getServerToken(): Promise<string> {
+  return invoke("get_server_token");
+}
@@ -0,0 +1,26 @@
+/**
+ * Tabula Desktop - Running AI Jobs
+ */
+
+import type { JobInfo } from "../types";
+
+const JOB_LABELS: Record<JobInfo["kind"], string> = {
+  analyze: "Analyzing tabs",
+  report: "Writing report",
+};
+
+function renderJob(job: JobInfo): string {
+  const progress = job.total > 1 ? ` · ${job.done}/${job.total} batches` : "";
+  return `
+    <div class="job-item">
+      <span class="spinner"></span>
+      <span class="job-label">${JOB_LABELS[job.kind]}${progress}</span>
+      <button class="btn secondary cancel-job-btn" data-job-id="${job.id}">Cancel</button>
+    </div>
+  `;
+}
+
+/** Contents of the `#jobBar` element; empty when nothing is running */
+export function renderJobBar(jobs: JobInfo[]): string {
+  return jobs.map(renderJob).join("");
+}
@@ -14,6 +14,7 @@ import {
   getCategoryLabel,
   getCategoryClass,
 } from "../utils";
+import { hasPendingClose } from "../state";
 
 export function renderTabCard(tab: TabRecord): string {
   const suggestion = tab.suggestion;
@@ -61,6 +62,11 @@ export function renderTabCard(tab: TabRecord): string {
           }
         </div>
         <div class="tab-url" title="${escapeHtml(tab.url || "")}">${escapeHtml(tab.url || "")}</div>
+        ${
+          hasPendingClose(tab.id)
+            ? `<div class="tab-close-queued" title="Closes when its browser connects again">Close pending</div>`
+            : ""
+        }
         <div class="tab-meta">
           <span class="meta-item" title="Tab opened ${formatDateTime(tab.created_at)}">
             <svg width="12" height="12" viewBox="0 0 24 24" fill="none" stroke="currentColor" stroke-width="2">
@@ -5,7 +5,21 @@
 import { listen } from "@tauri-apps/api/event";
 import "./style.css";
 
-import type { ViewType, SortField, Settings, GroupMode } from "./types";
+import type {
+  ViewType,
+  SortField,
+  Settings,
+  GroupMode,
+  LlmProvider,
+  ModelPrice,
+  CapturePolicy,
+  DailyReport,
+  Granularity,
+  JobFinished,
+  JobInfo,
+  ReportChunk,
+  ReportStreamError,
+} from "./types";
 import * as state from "./state";
 import * as api from "./api";
 import { renderSidebar } from "./components/Sidebar";
@@ -14,6 +28,8 @@ import { renderStatsView } from "./views/StatsView";
 import { renderHistoryView } from "./views/HistoryView";
 import { renderReportView } from "./views/ReportView";
 import { renderSettingsView } from "./views/SettingsView";
+import { renderJobBar } from "./components/JobBar";
+import { formatReportContent, periodStartDate, todayDate } from "./utils";
 
 // ─────────────────────────────────────────────────────────────
 // Rendering
@@ -35,9 +51,21 @@ function renderContent(): string {
     case "history":
       return renderHistoryView(state.closedTabs);
     case "report":
-      return renderReportView(state.report);
+      return renderReportView(
+        state.report,
+        state.reportDraft,
+        state.reportStreaming,
+        state.periodReports
+      );
     case "settings":
-      return renderSettingsView(state.settings);
+      return renderSettingsView(
+        state.settings,
+        state.usage,
+        state.pairing,
+        state.clients,
+        state.queuedCommands,
+        (tabId) => state.tabs.find((t) => t.id === tabId)?.title ?? null
+      );
   }
 }
 
@@ -50,12 +78,18 @@ function renderApp(): void {
       <main class="content">
         ${renderContent()}
       </main>
+      <div id="jobBar" class="job-bar">${renderJobBar(state.jobs)}</div>
     </div>
   `;
 
   attachEventListeners();
 }
 
+function updateJobBar(): void {
+  const el = document.getElementById("jobBar");
+  if (el) el.innerHTML = renderJobBar(state.jobs);
+}
+
 function showStatus(message: string, isError = false): void {
   const el = document.getElementById("statusMessage");
   if (!el) return;
@@ -114,10 +148,24 @@ function attachEventListeners(): void {
       const btn = target.closest("button, [data-action]") as HTMLElement | null;
       if (!btn) return;
 
+      // Cancel a running AI job; its command then fails with "Cancelled"
+      if (btn.classList.contains("cancel-job-btn")) {
+        const jobId = Number(btn.dataset.jobId);
+        btn.setAttribute("disabled", "true");
+        try {
+          await api.cancelJob(jobId);
+        } catch (err) {
+          showStatus(`Error: ${err}`, true);
+        }
+        return;
+      }
+
       // Navigation
       if (btn.classList.contains("nav-item")) {
         const view = btn.dataset.view as ViewType;
         if (view) {
+          if (view === "settings")
+            await Promise.all([loadUsage(), loadPairing(), loadClients(), loadQueuedCommands()]);
           state.setCurrentView(view);
           state.resetPage();
           renderApp();
@@ -164,8 +212,17 @@ function attachEventListeners(): void {
         const tabId = parseInt(tabIdStr);
 
         if (action === "close") {
-          await api.closeTab(tabId);
-          await loadTabs();
+          try {
+            const outcome = await api.closeTab(tabId);
+            if (outcome === "already_gone") showStatus("Tab was already closed in the browser");
+            if (outcome === "queued") {
+              showStatus("Its browser is not connected; the tab will close when it reconnects");
+              await loadQueuedCommands();
+            }
+            await loadTabs();
+          } catch (err) {
+            showStatus(`Tab not closed: ${err}`, true);
+          }
         } else if (action === "keep") {
           await api.markKeep(tabId);
           await loadTabs();
@@ -182,9 +239,15 @@ function attachEventListeners(): void {
         showStatus("Analyzing unanalyzed tabs...");
 
         try {
-          const [tabs, count] = await api.analyzeBatch(batchSize);
+          // Shift-click ignores cached suggestions
+          const [tabs, count, issues] = await api.analyzeBatch(batchSize, e.shiftKey);
           state.setTabs(tabs);
-          showStatus(`Analyzed ${count} tabs!`);
+          const unanswered = issues.skipped.length + issues.invalid.length;
+          if (unanswered > 0) {
+            showStatus(`Analyzed ${count - unanswered} of ${count} tabs; ${unanswered} got no usable suggestion`, true);
+          } else {
+            showStatus(`Analyzed ${count} tabs!`);
+          }
           renderApp();
         } catch (err) {
           showStatus(`Error: ${err}`, true);
@@ -200,8 +263,8 @@ function attachEventListeners(): void {
         btn.innerHTML = '<span class="spinner"></span> Refreshing...';
 
         try {
+          // Resolves once the extension has recaptured everything
           await api.triggerRefresh();
-          await new Promise((resolve) => setTimeout(resolve, 2000));
           await loadTabs();
           showStatus("Tabs and screenshots refreshed!");
         } catch (err) {
@@ -271,21 +334,50 @@ function attachEventListeners(): void {
         return;
       }
 
-      // Generate report button
-      if (btn.id === "generateReportBtn") {
-        btn.setAttribute("disabled", "true");
-        btn.innerHTML = '<span class="spinner"></span> Generating...';
-        showStatus("Generating daily report...");
+      // Generate report button; resume continues an interrupted draft
+      if (btn.id === "generateReportBtn" || btn.id === "resumeReportBtn") {
+        const resume = btn.id === "resumeReportBtn";
+        state.setReportStreaming(true);
+        // Chunks rebuild the draft from scratch; a resumed stream starts with the saved text
+        state.setReportDraft({ date: todayDate(), content: "", updated_at: Date.now(), error: null });
+        renderApp();
 
         try {
-          const report = await api.generateReport();
-          state.setReport(report);
+          await api.generateReportStream(undefined, resume);
           showStatus("Report generated!");
-          renderApp();
         } catch (err) {
           showStatus(`Error: ${err}`, true);
+        }
+        return;
+      }
+
+      // Weekly or monthly rollup of the current period so far
+      if (btn.classList.contains("period-report-btn")) {
+        const granularity = btn.dataset.granularity as Granularity;
+        btn.setAttribute("disabled", "true");
+        showStatus(`Generating ${granularity === "week" ? "weekly" : "monthly"} report...`);
+        try {
+          await api.generatePeriodReport(periodStartDate(granularity), todayDate(), granularity);
+          await loadPeriodReports();
+          renderApp();
+          showStatus("Report generated!");
+        } catch (err) {
           btn.removeAttribute("disabled");
-          btn.innerHTML = "Generate Report";
+          showStatus(`Error: ${err}`, true);
+        }
+        return;
+      }
+
+      // Dry run of what a task would send
+      if (btn.classList.contains("preview-request-btn")) {
+        const task = btn.dataset.task as "suggestions" | "report" | "embeddings";
+        const output = document.getElementById("privacyPreview") as HTMLPreElement;
+        try {
+          const preview = await api.previewAiRequest(task);
+          output.textContent = JSON.stringify(preview, null, 2);
+          output.hidden = false;
+        } catch (err) {
+          showStatus(`Error: ${err}`, true);
         }
         return;
       }
@@ -295,21 +387,72 @@ function attachEventListeners(): void {
         const apiKey = (document.getElementById("apiKey") as HTMLInputElement).value.trim();
         const baseUrl = (document.getElementById("baseUrl") as HTMLInputElement).value.trim();
         const model = (document.getElementById("model") as HTMLInputElement).value.trim();
+        const embeddingModel = (document.getElementById("embeddingModel") as HTMLInputElement).value.trim();
+        const value = (id: string) => (document.getElementById(id) as HTMLInputElement | HTMLSelectElement).value.trim();
+        const provider = (id: string) => value(id) as LlmProvider;
         const userContext = (document.getElementById("userContext") as HTMLTextAreaElement).value.trim();
         const batchSizeStr = (document.getElementById("batchSize") as HTMLInputElement).value.trim();
         const batchSize = parseInt(batchSizeStr) || 30;
+        const concurrency = parseInt(value("analyzeConcurrency")) || 2;
+        const contextWindow = parseInt(value("suggestionContextWindow")) || undefined;
+        const reportContextWindow = parseInt(value("reportContextWindow")) || undefined;
+        const cacheTtl = parseInt(value("suggestionCacheTtl"));
+        const budget = parseFloat(value("monthlyBudget"));
+        const retention = parseInt(value("closedTabRetention"));
+        const patterns = (id: string) => {
+          const lines = value(id).split("\n").map((line) => line.trim()).filter(Boolean);
+          return lines.length ? lines : undefined;
+        };
+        let modelPrices: Record<string, ModelPrice> | undefined;
+        try {
+          const prices = value("modelPrices");
+          modelPrices = prices ? JSON.parse(prices) : undefined;
+        } catch (err) {
+          showStatus(`Invalid model prices: ${err}`, true);
+          return;
+        }
+        let capturePolicies: CapturePolicy[] | undefined;
+        try {
+          const policies = value("capturePolicies");
+          capturePolicies = policies ? JSON.parse(policies) : undefined;
+        } catch (err) {
+          showStatus(`Invalid capture policies: ${err}`, true);
+          return;
+        }
 
         const newSettings: Settings = {
           openai_api_key: apiKey || undefined,
           base_url: baseUrl || undefined,
           model: model || undefined,
+          embedding_model: embeddingModel || undefined,
+          anthropic_api_key: value("anthropicApiKey") || undefined,
+          anthropic_base_url: value("anthropicBaseUrl") || undefined,
+          ollama_base_url: value("ollamaBaseUrl") || undefined,
+          suggestion_provider: provider("suggestionProvider"),
+          suggestion_model: value("suggestionModel") || undefined,
+          report_provider: provider("reportProvider"),
+          report_model: value("reportModel") || undefined,
+          embedding_provider: provider("embeddingProvider"),
           user_context: userContext || undefined,
           analyze_batch_size: Math.max(1, Math.min(100, batchSize)),
+          analyze_concurrency: Math.max(1, Math.min(8, concurrency)),
+          suggestion_context_window: contextWindow,
+          report_context_window: reportContextWindow,
+          suggestion_cache_ttl_hours: cacheTtl >= 0 ? cacheTtl : undefined,
+          monthly_budget_usd: budget >= 0 ? budget : undefined,
+          model_prices: modelPrices,
+          privacy_blocklist: patterns("privacyBlocklist"),
+          privacy_title_only: patterns("privacyTitleOnly"),
+          privacy_no_screenshots: patterns("privacyNoScreenshots"),
+          redact_personal_data: value("redactPersonalData") !== "off",
+          capture_policies: capturePolicies,
+          closed_tab_retention_days: retention >= 1 ? retention : undefined,
         };
 
         try {
           await api.saveSettings(newSettings);
           state.setSettings(newSettings);
+          await loadUsage();
           showStatus("Settings saved!");
           renderApp();
         } catch (err) {
@@ -318,6 +461,61 @@ function attachEventListeners(): void {
         return;
       }
 
+      // Pairing
+      if (btn.id === "rotatePairingBtn") {
+        if (!confirm("Rotate the pairing secret? Every paired extension will have to pair again."))
+          return;
+        try {
+          state.setPairing(await api.rotatePairingSecret());
+          showStatus("Pairing secret rotated");
+          renderApp();
+        } catch (err) {
+          showStatus(`Error: ${err}`, true);
+        }
+        return;
+      }
+
+      if (btn.id === "createApiTokenBtn") {
+        const name = prompt("Name this token (e.g. the script using it):");
+        if (name === null) return;
+        try {
+          const token = await api.createApiToken(name.trim());
+          await loadPairing();
+          renderApp();
+          prompt("Copy the token now, it will not be shown again:", token);
+        } catch (err) {
+          showStatus(`Error: ${err}`, true);
+        }
+        return;
+      }
+
+      if (btn.classList.contains("cancel-command-btn")) {
+        const commandId = parseInt(btn.dataset.commandId ?? "");
+        if (isNaN(commandId)) return;
+        try {
+          await api.cancelQueuedCommand(commandId);
+          await loadQueuedCommands();
+          renderApp();
+        } catch (err) {
+          showStatus(`Error: ${err}`, true);
+        }
+        return;
+      }
+
+      if (btn.classList.contains("revoke-client-btn")) {
+        const clientId = btn.dataset.clientId;
+        if (!clientId) return;
+        try {
+          await api.revokePairedClient(clientId);
+          await loadPairing();
+          showStatus("Extension unpaired");
+          renderApp();
+        } catch (err) {
+          showStatus(`Error: ${err}`, true);
+        }
+        return;
+      }
+
       // Clear data button
       if (btn.id === "clearDataBtn") {
         if (!confirm("Are you sure you want to clear all data? This cannot be undone."))
@@ -337,6 +535,8 @@ function attachEventListeners(): void {
 
       // Cleanup old tabs button
       if (btn.id === "cleanupOldTabsBtn") {
+        if (!confirm("Remove closed tabs older than 7 days? Reports for those days can no longer be regenerated."))
+          return;
         btn.setAttribute("disabled", "true");
         btn.textContent = "Cleaning up...";
 
@@ -418,15 +618,114 @@ async function loadSettings(): Promise<void> {
   }
 }
 
+async function loadUsage(): Promise<void> {
+  try {
+    state.setUsage(await api.getAiUsage());
+  } catch (err) {
+    console.error("Failed to load AI usage:", err);
+  }
+}
+
+async function loadPairing(): Promise<void> {
+  try {
+    state.setPairing(await api.getPairing());
+  } catch (err) {
+    console.error("Failed to load pairing:", err);
+  }
+}
+
+async function loadClients(): Promise<void> {
+  try {
+    state.setClients(await api.listClients());
+  } catch (err) {
+    console.error("Failed to load connected browsers:", err);
+  }
+}
+
+async function loadQueuedCommands(): Promise<void> {
+  try {
+    state.setQueuedCommands(await api.listQueuedCommands());
+  } catch (err) {
+    console.error("Failed to load queued commands:", err);
+  }
+}
+
+async function loadServerToken(): Promise<void> {
+  try {
+    state.setServerToken(await api.getServerToken());
+  } catch (err) {
+    console.error("Failed to load server token:", err);
+  }
+}
+
 async function loadReport(): Promise<void> {
   try {
-    const report = await api.getReport();
+    const [report, draft] = await Promise.all([api.getReport(), api.getReportDraft(todayDate())]);
     state.setReport(report);
+    state.setReportDraft(draft);
   } catch (err) {
     console.error("Failed to load report:", err);
   }
 }
 
+async function loadPeriodReports(): Promise<void> {
+  try {
+    state.setPeriodReports(await api.listPeriodReports());
+  } catch (err) {
+    console.error("Failed to load rollups:", err);
+  }
+}
+
+async function listenForJobs(): Promise<void> {
+  await listen<JobInfo>("job-started", (event) => {
+    state.setJob(event.payload);
+    updateJobBar();
+  });
+
+  await listen<JobInfo>("job-progress", (event) => {
+    state.setJob(event.payload);
+    updateJobBar();
+  });
+
+  await listen<JobFinished>("job-finished", (event) => {
+    state.removeJob(event.payload.id);
+    updateJobBar();
+  });
+
+  // Jobs started before this window loaded
+  try {
+    state.setJobs(await api.listJobs());
+    updateJobBar();
+  } catch (err) {
+    console.error("Failed to load jobs:", err);
+  }
+}
+
+// Streamed report progress; chunks update the draft in place to avoid re-rendering the view
+async function listenForReports(): Promise<void> {
+  await listen<ReportChunk>("report-chunk", (event) => {
+    const draft = state.reportDraft;
+    if (!draft || draft.date !== event.payload.date) return;
+    draft.content += event.payload.text;
+    const el = document.getElementById("reportContent");
+    if (el) el.innerHTML = formatReportContent(draft.content);
+  });
+
+  await listen<DailyReport>("report-done", (event) => {
+    state.setReport(event.payload);
+    state.setReportDraft(null);
+    state.setReportStreaming(false);
+    if (state.currentView === "report") renderApp();
+  });
+
+  await listen<ReportStreamError>("report-error", (event) => {
+    const { date, error, partial } = event.payload;
+    state.setReportDraft(partial ? { date, content: partial, updated_at: Date.now(), error } : null);
+    state.setReportStreaming(false);
+    if (state.currentView === "report") renderApp();
+  });
+}
+
 // ─────────────────────────────────────────────────────────────
 // Initialization
 // ─────────────────────────────────────────────────────────────
@@ -435,7 +734,12 @@ async function init(): Promise<void> {
   // Load theme from localStorage first (before any rendering)
   loadTheme();
 
-  await Promise.all([loadTabs(), loadClosedTabs(), loadSettings(), loadReport()]);
+  // Screenshot URLs need the token, so load it before the first render
+  await Promise.all([loadServerToken(), loadQueuedCommands()]);
+  await Promise.all([loadTabs(), loadClosedTabs(), loadSettings(), loadReport(), loadPeriodReports()]);
+
+  await listenForReports();
+  await listenForJobs();
 
   await listen("tab-captured", () => {
     loadTabs();
@@ -444,6 +748,21 @@ async function init(): Promise<void> {
   await listen("tab-event", () => {
     loadTabs();
   });
+
+  await listen("pairing-changed", async () => {
+    await loadPairing();
+    if (state.currentView === "settings") renderApp();
+  });
+
+  await listen("command-queue-changed", async () => {
+    await loadQueuedCommands();
+    renderApp();
+  });
+
+  await listen("clients-changed", async () => {
+    await loadClients();
+    if (state.currentView === "settings") renderApp();
+  });
 }
 
 init();
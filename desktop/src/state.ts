@@ -6,10 +6,17 @@ import type {
   TabRecord,
   Settings,
   DailyReport,
+  JobInfo,
+  ReportDraft,
+  PeriodReport,
   ViewType,
   SortField,
   SortOrder,
   GroupMode,
+  UsageSummary,
+  PairingInfo,
+  ConnectedClient,
+  QueuedCommand,
 } from "./types";
 
 // Pagination constants
@@ -20,6 +27,16 @@ export let tabs: TabRecord[] = [];
 export let closedTabs: TabRecord[] = [];
 export let settings: Settings = {};
 export let report: DailyReport | null = null;
+export let reportDraft: ReportDraft | null = null;
+export let reportStreaming = false;
+export let periodReports: PeriodReport[] = [];
+export let jobs: JobInfo[] = [];
+export let usage: UsageSummary | null = null;
+export let pairing: PairingInfo | null = null;
+export let clients: ConnectedClient[] = [];
+export let queuedCommands: QueuedCommand[] = [];
+/** Lets the window load screenshots from the extension server */
+export let serverToken = "";
 export let currentView: ViewType = "tabs";
 export let sortField: SortField = "last_active";
 export let sortOrder: SortOrder = "desc";
@@ -45,6 +62,58 @@ export function setReport(newReport: DailyReport | null): void {
   report = newReport;
 }
 
+export function setReportDraft(newDraft: ReportDraft | null): void {
+  reportDraft = newDraft;
+}
+
+export function setReportStreaming(streaming: boolean): void {
+  reportStreaming = streaming;
+}
+
+export function setPeriodReports(newReports: PeriodReport[]): void {
+  periodReports = newReports;
+}
+
+/** Add or update a running job */
+export function setJob(job: JobInfo): void {
+  jobs = [...jobs.filter((j) => j.id !== job.id), job].sort((a, b) => a.id - b.id);
+}
+
+export function removeJob(id: number): void {
+  jobs = jobs.filter((j) => j.id !== id);
+}
+
+export function setJobs(newJobs: JobInfo[]): void {
+  jobs = newJobs;
+}
+
+export function setUsage(newUsage: UsageSummary | null): void {
+  usage = newUsage;
+}
+
+export function setPairing(newPairing: PairingInfo | null): void {
+  pairing = newPairing;
+}
+
+export function setClients(newClients: ConnectedClient[]): void {
+  clients = newClients;
+}
+
+export function setQueuedCommands(commands: QueuedCommand[]): void {
+  queuedCommands = commands;
+}
+
+/** Tabs whose close waits for their browser to connect */
+export function hasPendingClose(tabId: number): boolean {
+  return queuedCommands.some(
+    (c) => c.tab_id === tabId && c.status === "pending" && c.command.type === "close_tab"
+  );
+}
+
+export function setServerToken(token: string): void {
+  serverToken = token;
+}
+
 export function setCurrentView(view: ViewType): void {
   currentView = view;
 }
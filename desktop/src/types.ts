@@ -15,8 +15,38 @@ export interface TabSuggestion {
   scored_at: number;
 }
 
+/** Problems with an analysis response, returned by `analyze_batch` */
+export interface SuggestionIssues {
+  /** Tabs the model gave no suggestion for */
+  skipped: number[];
+  /** Tab ids in the response that were not requested */
+  unknown: number[];
+  /** Items still invalid after the automatic repair request */
+  invalid: { tab_id: number | null; error: string }[];
+  /** Items fixed by the repair request */
+  repaired: number;
+  /** Sub-batches that failed outright; their tabs are in `skipped` */
+  errors: string[];
+  /** Tabs not sent because they match the privacy blocklist */
+  withheld: number[];
+}
+
+/** How much of a tab is sent to AI providers */
+export type Exposure = "full" | "no_screenshot" | "title_only" | "withheld";
+
+/** Result of a dry run: the requests a task would send; images appear as `{ type, media_type, bytes }` */
+export interface PrivacyPreview {
+  exposure: Record<number, Exposure>;
+  requests: { model: string; messages: unknown[]; inputs: string[] }[];
+}
+
 export interface TabRecord {
+  /** Stable Tabula id; survives browser restarts */
   id: number;
+  /** Chrome's tab id in the current browser session */
+  chrome_id?: number;
+  session_id?: string;
+  tab_index?: number;
   window_id?: number;
   url?: string;
   title?: string;
@@ -38,6 +68,115 @@ export interface Settings {
   model?: string;
   user_context?: string;
   analyze_batch_size?: number;
+  analyze_concurrency?: number;
+  suggestion_context_window?: number;
+  report_context_window?: number;
+  /** Hours a cached suggestion is reused for an unchanged tab; 0 disables the cache */
+  suggestion_cache_ttl_hours?: number;
+  embedding_model?: string;
+  anthropic_api_key?: string;
+  anthropic_base_url?: string;
+  ollama_base_url?: string;
+  suggestion_provider?: LlmProvider;
+  suggestion_model?: string;
+  report_provider?: LlmProvider;
+  report_model?: string;
+  embedding_provider?: LlmProvider;
+  monthly_budget_usd?: number;
+  /** Closed tabs older than this are removed at startup; unset keeps them */
+  closed_tab_retention_days?: number;
+  /** USD per million tokens, keyed by model name prefix */
+  model_prices?: Record<string, ModelPrice>;
+  /** Domain or URL globs whose tabs are never sent to AI providers */
+  privacy_blocklist?: string[];
+  /** Globs whose tabs are sent as title and site only */
+  privacy_title_only?: string[];
+  /** Globs whose screenshots are never sent */
+  privacy_no_screenshots?: string[];
+  /** Mask emails, tokens, API keys, phone and card numbers in what is sent (default true) */
+  redact_personal_data?: boolean;
+  /** What the extension server stores per tab; the first matching policy wins */
+  capture_policies?: CapturePolicy[];
+}
+
+export type CaptureLevel = "nothing" | "metadata" | "description" | "full";
+
+export interface CapturePolicy {
+  /** Domain or URL globs; empty matches every URL */
+  patterns?: string[];
+  incognito?: boolean;
+  window_ids?: number[];
+  capture: CaptureLevel;
+}
+
+export interface ModelPrice {
+  input: number;
+  output: number;
+}
+
+/** AI usage for one local day ("2026-10-17") or month ("2026-10") */
+export interface UsagePeriod {
+  period: string;
+  calls: number;
+  failed: number;
+  prompt_tokens: number;
+  completion_tokens: number;
+  image_count: number;
+  cost_usd: number;
+  /** Calls to models without a known price, not counted in `cost_usd` */
+  unpriced_calls: number;
+  avg_latency_ms: number;
+}
+
+export interface UsageSummary {
+  daily: UsagePeriod[];
+  monthly: UsagePeriod[];
+  month_to_date_usd: number;
+  monthly_budget_usd: number | null;
+}
+
+export type LlmProvider = "openai" | "anthropic" | "ollama" | "mock";
+
+/** Whether a closed tab was still open in the browser, or waits for it to connect */
+export type CloseOutcome = "closed" | "already_gone" | "queued";
+
+export type QueuedCommandStatus = "pending" | "delivered" | "failed";
+
+/** A command that waited for its browser to connect */
+export interface QueuedCommand {
+  id: number;
+  command: { type: string; payload?: unknown };
+  tab_id: number | null;
+  status: QueuedCommandStatus;
+  error: string | null;
+  created_at: number;
+  expires_at: number;
+  updated_at: number;
+}
+
+/** A browser extension paired with the desktop server */
+export interface PairedClient {
+  id: string;
+  origin: string;
+  name: string | null;
+  paired_at: number;
+}
+
+export interface PairingInfo {
+  secret: string;
+  clients: PairedClient[];
+}
+
+/** An extension connected right now; browser details arrive shortly after it connects */
+export interface ConnectedClient {
+  id: number;
+  pairing_id: string | null;
+  browser: string | null;
+  browser_version: string | null;
+  profile: string | null;
+  extension_version: string | null;
+  session_id: string | null;
+  connected_at: number;
 }
 
 export interface DailyReport {
@@ -46,6 +185,100 @@ export interface DailyReport {
   generated_at: number;
 }
 
+export type Granularity = "week" | "month";
+
+/** A weekly or monthly rollup covering `start` to `end` (inclusive) */
+export interface PeriodReport {
+  granularity: Granularity;
+  start: string;
+  end: string;
+  content: string;
+  generated_at: number;
+}
+
+/** A report whose streamed generation has not finished; `error` is set once it failed */
+export interface ReportDraft {
+  date: string;
+  content: string;
+  updated_at: number;
+  error: string | null;
+}
+
+/** A running AI job; `done` of `total` batches have finished */
+export interface JobInfo {
+  id: number;
+  kind: "analyze" | "report";
+  done: number;
+  total: number;
+  started_at: number;
+}
+
+/** Payload of the `job-finished` event */
+export interface JobFinished {
+  id: number;
+  kind: JobInfo["kind"];
+  cancelled: boolean;
+}
+
+/** Payload of the `report-chunk` event */
+export interface ReportChunk {
+  date: string;
+  text: string;
+}
+
+/** Payload of the `report-error` event */
+export interface ReportStreamError {
+  date: string;
+  error: string;
+  partial: string;
+}
+
+export interface SearchQuery {
+  query?: string;
+  category?: string;
+  decision?: "close" | "keep";
+  domain?: string;
+  /** YYYY-MM-DD, inclusive */
+  from?: string;
+  /** YYYY-MM-DD, inclusive */
+  to?: string;
+  limit?: number;
+}
+
+export interface SearchResult {
+  tab: TabRecord;
+  score: number;
+  /** HTML-escaped, matches wrapped in <mark> */
+  title_highlight?: string;
+  /** HTML-escaped excerpt of the description or digest, matches wrapped in <mark> */
+  snippet?: string;
+}
+
+export interface SimilarTab {
+  tab: TabRecord;
+  /** Cosine similarity, -1 to 1 */
+  similarity: number;
+}
+
+export interface TabCluster {
+  label: string;
+  tab_ids: number[];
+}
+
+export interface Clustering {
+  clusters: TabCluster[];
+  unclustered: number[];
+}
+
+export interface DuplicateGroup {
+  reason: "same_url" | "similar_content";
+  /** Normalized URL shared by the group (same_url only) */
+  url?: string;
+  /** Recommended tab to keep */
+  survivor: number;
+  duplicates: number[];
+}
+
 export type SortField =
   | "last_active"
   | "created"
@@ -2,7 +2,16 @@
  * Tabula Desktop - Utility Functions
  */
 
-import type { TabRecord, SortField, SortOrder, TabStats, TabCategory, CategoryInfo } from "./types";
+import type {
+  TabRecord,
+  SortField,
+  SortOrder,
+  TabStats,
+  TabCategory,
+  CategoryInfo,
+  Granularity,
+} from "./types";
+import { serverToken } from "./state";
 
 export function formatDuration(ms: number): string {
   if (ms < 1000) return "0s";
@@ -31,6 +40,28 @@ export function formatDateTime(timestamp: number): string {
   });
 }
 
+/** A local date as YYYY-MM-DD */
+export function formatLocalDate(date: Date): string {
+  const pad = (n: number) => String(n).padStart(2, "0");
+  return `${date.getFullYear()}-${pad(date.getMonth() + 1)}-${pad(date.getDate())}`;
+}
+
+/** Today's local date as YYYY-MM-DD */
+export function todayDate(): string {
+  return formatLocalDate(new Date());
+}
+
+/** First day of the current week (Monday) or month, as YYYY-MM-DD */
+export function periodStartDate(granularity: Granularity): string {
+  const date = new Date();
+  if (granularity === "week") {
+    date.setDate(date.getDate() - ((date.getDay() + 6) % 7));
+  } else {
+    date.setDate(1);
+  }
+  return formatLocalDate(date);
+}
+
 export function formatAge(createdAt: number): string {
   const ms = Date.now() - createdAt;
   if (ms < 60000) return "just now";
@@ -54,7 +85,7 @@ export function getScreenshotUrl(
 ): string {
   const filename = screenshotPath.split("/").pop() || "";
   const cacheBuster = capturedAt || Date.now();
-  return `http://localhost:21890/screenshot/${encodeURIComponent(filename)}?t=${cacheBuster}`;
+  return `http://localhost:21890/screenshot/${encodeURIComponent(filename)}?t=${cacheBuster}&token=${encodeURIComponent(serverToken)}`;
 }
 
 export function getScreenshotFreshness(capturedAt: number): {
@@ -140,8 +171,23 @@ export function getStats(tabs: TabRecord[]) {
   return { total: openTabs.length, withScreenshots, analyzed, unanalyzed };
 }
 
+// Markdown table rows to one line of HTML, skipping the |---| separator
+function formatTable(block: string): string {
+  const rows = block
+    .trim()
+    .split("\n")
+    .filter((row) => !/^\|[\s|:-]+\|$/.test(row))
+    .map((row) => row.slice(1, -1).split("|").map((cell) => cell.trim()));
+  const [head, ...body] = rows;
+  const cells = (row: string[], tag: string) => row.map((cell) => `<${tag}>${cell}</${tag}>`).join("");
+  return `<table><thead><tr>${cells(head, "th")}</tr></thead><tbody>${body
+    .map((row) => `<tr>${cells(row, "td")}</tr>`)
+    .join("")}</tbody></table>\n`;
+}
+
 export function formatReportContent(content: string): string {
   return content
+    .replace(/(^\|.*\|$\n?)+/gm, formatTable)
     .replace(/^### (.+)$/gm, "<h3>$1</h3>")
     .replace(/^## (.+)$/gm, "<h2>$1</h2>")
     .replace(/^# (.+)$/gm, "<h1>$1</h1>")
@@ -2,10 +2,52 @@
  * Tabula Desktop - Report View
  */
 
-import type { DailyReport } from "../types";
-import { formatReportContent } from "../utils";
+import type { DailyReport, PeriodReport, ReportDraft } from "../types";
+import { escapeHtml, formatReportContent } from "../utils";
 
-export function renderReportView(report: DailyReport | null): string {
+function renderDraft(draft: ReportDraft, streaming: boolean): string {
+  const status = streaming
+    ? '<span class="spinner"></span> Writing report...'
+    : `Interrupted: ${escapeHtml(draft.error ?? "generation stopped")}`;
+  return `
+    <div class="report-header">
+      <span class="report-date">${draft.date}</span>
+      <span class="report-time">${status}</span>
+      ${streaming ? "" : '<button id="resumeReportBtn" class="btn secondary">Resume</button>'}
+    </div>
+    <div id="reportContent" class="report-content report-draft">${formatReportContent(draft.content)}</div>
+  `;
+}
+
+function renderPeriodReports(reports: PeriodReport[]): string {
+  if (reports.length === 0) return "";
+  return `
+    <div class="period-reports">
+      <h2>Rollups</h2>
+      ${reports
+        .map(
+          (report, i) => `
+        <details class="period-report" ${i === 0 ? "open" : ""}>
+          <summary>
+            <span class="report-date">${report.granularity === "week" ? "Week" : "Month"} of ${report.start} to ${report.end}</span>
+            <span class="report-time">Generated ${new Date(report.generated_at).toLocaleString()}</span>
+          </summary>
+          <div class="report-content">${formatReportContent(report.content)}</div>
+        </details>
+      `
+        )
+        .join("")}
+    </div>
+  `;
+}
+
+/** An unfinished `draft` (streaming or interrupted) is shown in place of the saved report */
+export function renderReportView(
+  report: DailyReport | null,
+  draft: ReportDraft | null = null,
+  streaming = false,
+  periodReports: PeriodReport[] = []
+): string {
   return `
     <div class="view-wrapper">
       <header class="view-header">
@@ -14,7 +56,7 @@ export function renderReportView(report: DailyReport | null): string {
           <p class="subtitle">AI-generated summary of your browsing activity</p>
         </div>
         <div class="actions">
-          <button id="generateReportBtn" class="btn primary">
+          <button id="generateReportBtn" class="btn primary" ${streaming ? "disabled" : ""}>
             <svg width="16" height="16" viewBox="0 0 24 24" fill="none" stroke="currentColor" stroke-width="2">
               <path d="M12 2a10 10 0 1 0 10 10"/>
               <path d="M12 12l4-4"/>
@@ -22,13 +64,17 @@ export function renderReportView(report: DailyReport | null): string {
             </svg>
             Generate Report
           </button>
+          <button class="btn secondary period-report-btn" data-granularity="week">This Week</button>
+          <button class="btn secondary period-report-btn" data-granularity="month">This Month</button>
         </div>
       </header>
       <div id="statusMessage" class="status-message"></div>
       <div class="scroll-area">
         <div class="report-container">
           ${
-            report
+            draft
+              ? renderDraft(draft, streaming)
+              : report
               ? `
             <div class="report-header">
               <span class="report-date">${report.date}</span>
@@ -43,6 +89,7 @@ export function renderReportView(report: DailyReport | null): string {
             </div>
           `
           }
+          ${renderPeriodReports(periodReports)}
         </div>
       </div>
     </div>
@@ -2,10 +2,154 @@
  * Tabula Desktop - Settings View
  */
 
-import type { Settings } from "../types";
+import type {
+  ConnectedClient,
+  LlmProvider,
+  PairingInfo,
+  QueuedCommand,
+  Settings,
+  UsageSummary,
+} from "../types";
 import { theme } from "../state";
+import { escapeHtml } from "../utils";
 
-export function renderSettingsView(settings: Settings): string {
+const PROVIDERS: { value: LlmProvider; label: string }[] = [
+  { value: "openai", label: "OpenAI" },
+  { value: "anthropic", label: "Anthropic" },
+  { value: "ollama", label: "Ollama" },
+  { value: "mock", label: "Mock (offline)" },
+];
+
+function renderProviderSelect(id: string, selected: LlmProvider | undefined, exclude: LlmProvider[] = []): string {
+  const options = PROVIDERS.filter((p) => !exclude.includes(p.value))
+    .map((p) => `<option value="${p.value}" ${(selected || "openai") === p.value ? "selected" : ""}>${p.label}</option>`)
+    .join("");
+  return `<select id="${id}">${options}</select>`;
+}
+
+function formatPatterns(patterns: string[] | undefined): string {
+  return (patterns ?? []).join("\n");
+}
+
+function formatUsd(amount: number): string {
+  return `$${amount.toFixed(amount < 1 ? 4 : 2)}`;
+}
+
+function renderUsage(usage: UsageSummary | null): string {
+  if (!usage) return "";
+  const budget = usage.monthly_budget_usd;
+  const days = usage.daily.slice(0, 7);
+  const rows = days
+    .map(
+      (d) => `
+        <div class="usage-row">
+          <span>${d.period}</span>
+          <span>${d.calls} calls${d.failed ? ` (${d.failed} failed)` : ""}</span>
+          <span>${(d.prompt_tokens + d.completion_tokens).toLocaleString()} tokens</span>
+          <span>${formatUsd(d.cost_usd)}${d.unpriced_calls ? "+" : ""}</span>
+        </div>`
+    )
+    .join("");
+  return `
+    <p class="section-desc">
+      This month: <strong>${formatUsd(usage.month_to_date_usd)}</strong>${budget != null ? ` of ${formatUsd(budget)} budget` : ""} (estimated).
+      ${days.some((d) => d.unpriced_calls) ? "+ marks days with calls to models without a known price." : ""}
+    </p>
+    ${rows ? `<div class="usage-table">${rows}</div>` : ""}
+  `;
+}
+
+// Origin the desktop records for API tokens, which are listed with paired extensions
+const API_TOKEN_ORIGIN = "tabula-api";
+
+function renderPairing(pairing: PairingInfo | null): string {
+  if (!pairing) return `<p class="section-desc">Pairing is unavailable.</p>`;
+  const clients = pairing.clients
+    .map(
+      (c) => `
+        <div class="pairing-row">
+          <span>${escapeHtml(c.name || (c.origin === API_TOKEN_ORIGIN ? "API token" : "Browser extension"))}</span>
+          <span class="pairing-origin">${c.origin === API_TOKEN_ORIGIN ? "read-only REST API" : escapeHtml(c.origin)}</span>
+          <span>${new Date(c.paired_at).toLocaleDateString()}</span>
+          <button class="btn secondary revoke-client-btn" data-client-id="${escapeHtml(c.id)}">Revoke</button>
+        </div>`
+    )
+    .join("");
+  return `
+    <div class="form-group">
+      <label>Pairing Secret</label>
+      <code class="pairing-secret">${escapeHtml(pairing.secret)}</code>
+      <span class="hint">Enter this in the extension popup to pair it. Only paired extensions can send tabs or receive commands</span>
+    </div>
+    ${clients ? `<div class="usage-table">${clients}</div>` : `<p class="section-desc">No extensions paired yet.</p>`}
+    <button class="btn secondary" id="createApiTokenBtn">Create API Token</button>
+    <span class="hint">For scripts reading tabs, reports and stats from the local REST API</span>
+    <button class="btn secondary" id="rotatePairingBtn">Rotate Secret</button>
+    <span class="hint">Creates a new secret and unpairs every extension; API tokens stay valid until revoked</span>
+  `;
+}
+
+function renderConnectedClients(clients: ConnectedClient[]): string {
+  if (clients.length === 0) {
+    return `<p class="section-desc">No browser is connected right now.</p>`;
+  }
+  const rows = clients
+    .map((c) => {
+      const browser = c.browser
+        ? `${c.browser}${c.browser_version ? ` ${c.browser_version.split(".")[0]}` : ""}`
+        : "Connecting…";
+      return `
+        <div class="client-row">
+          <span>${escapeHtml(browser)}</span>
+          <span>${escapeHtml(c.profile || "Unnamed profile")}</span>
+          <span>${c.extension_version ? `v${escapeHtml(c.extension_version)}` : ""}</span>
+          <span>since ${new Date(c.connected_at).toLocaleTimeString()}</span>
+        </div>`;
+    })
+    .join("");
+  return `<div class="usage-table">${rows}</div>`;
+}
+
+function describeCommand(command: QueuedCommand, tabTitle: (tabId: number) => string | null): string {
+  if (command.command.type === "close_tab" && command.tab_id !== null) {
+    return `Close “${tabTitle(command.tab_id) ?? `tab ${command.tab_id}`}”`;
+  }
+  return command.command.type;
+}
+
+function renderQueuedCommands(
+  commands: QueuedCommand[],
+  tabTitle: (tabId: number) => string | null
+): string {
+  if (commands.length === 0) {
+    return `<p class="section-desc">Nothing queued.</p>`;
+  }
+  const rows = commands
+    .map(
+      (c) => `
+        <div class="queue-row">
+          <span>${escapeHtml(describeCommand(c, tabTitle))}</span>
+          <span class="queue-status ${c.status}" title="${escapeHtml(c.error ?? "")}">${c.status}${c.error ? `: ${escapeHtml(c.error)}` : ""}</span>
+          <span>${new Date(c.status === "pending" ? c.created_at : c.updated_at).toLocaleString()}</span>
+          ${
+            c.status === "pending"
+              ? `<button class="btn secondary cancel-command-btn" data-command-id="${c.id}">Cancel</button>`
+              : "<span></span>"
+          }
+        </div>`
+    )
+    .join("");
+  return `<div class="usage-table">${rows}</div>`;
+}
+
+export function renderSettingsView(
+  settings: Settings,
+  usage: UsageSummary | null,
+  pairing: PairingInfo | null,
+  clients: ConnectedClient[],
+  queuedCommands: QueuedCommand[],
+  tabTitle: (tabId: number) => string | null
+): string {
   return `
     <div class="view-wrapper">
       <header class="view-header">
@@ -61,11 +205,138 @@ export function renderSettingsView(settings: Settings): string {
               <label for="model">Model</label>
               <input type="text" id="model" placeholder="gpt-4o-mini" value="${settings.model || ""}" />
             </div>
+            <div class="form-group">
+              <label for="embeddingModel">Embedding Model</label>
+              <input type="text" id="embeddingModel" placeholder="text-embedding-3-small" value="${settings.embedding_model || ""}" />
+              <span class="hint">Used for similar tabs and topic clustering</span>
+            </div>
             <div class="form-group">
               <label for="batchSize">Analyze Batch Size</label>
               <input type="number" id="batchSize" min="1" max="100" placeholder="30" value="${settings.analyze_batch_size || 30}" />
               <span class="hint">Number of tabs to analyze at once (1-100)</span>
             </div>
+            <div class="form-group">
+              <label for="analyzeConcurrency">Parallel Requests</label>
+              <input type="number" id="analyzeConcurrency" min="1" max="8" placeholder="2" value="${settings.analyze_concurrency || 2}" />
+              <span class="hint">Batches too large for the model's context are split; this many parts run at once (1-8)</span>
+            </div>
+            <div class="form-group">
+              <label for="suggestionCacheTtl">Suggestion Cache (hours)</label>
+              <input type="number" id="suggestionCacheTtl" min="0" placeholder="168" value="${settings.suggestion_cache_ttl_hours ?? 168}" />
+              <span class="hint">Unchanged tabs reuse their last suggestion for this long; 0 always asks the model. Shift-click Analyze to bypass.</span>
+            </div>
+          </div>
+
+          <div class="settings-section">
+            <h2>Providers</h2>
+            <p class="section-desc">Each task can use its own provider and model. Empty model fields use the provider's default (the Model above for OpenAI).</p>
+            <div class="form-group">
+              <label for="anthropicApiKey">Anthropic API Key</label>
+              <input type="password" id="anthropicApiKey" placeholder="sk-ant-..." value="${settings.anthropic_api_key || ""}" />
+            </div>
+            <div class="form-group">
+              <label for="anthropicBaseUrl">Anthropic Base URL</label>
+              <input type="text" id="anthropicBaseUrl" placeholder="https://api.anthropic.com/v1" value="${settings.anthropic_base_url || ""}" />
+            </div>
+            <div class="form-group">
+              <label for="ollamaBaseUrl">Ollama URL</label>
+              <input type="text" id="ollamaBaseUrl" placeholder="http://localhost:11434" value="${settings.ollama_base_url || ""}" />
+            </div>
+            <div class="form-group">
+              <label for="suggestionProvider">Tab Suggestions</label>
+              ${renderProviderSelect("suggestionProvider", settings.suggestion_provider)}
+              <input type="text" id="suggestionModel" placeholder="Default model" value="${settings.suggestion_model || ""}" />
+              <input type="number" id="suggestionContextWindow" min="0" placeholder="Context window (tokens), empty for built-in" value="${settings.suggestion_context_window || ""}" />
+            </div>
+            <div class="form-group">
+              <label for="reportProvider">Daily Report</label>
+              ${renderProviderSelect("reportProvider", settings.report_provider)}
+              <input type="text" id="reportModel" placeholder="Default model" value="${settings.report_model || ""}" />
+              <input type="number" id="reportContextWindow" min="0" placeholder="Context window (tokens), empty for built-in" value="${settings.report_context_window || ""}" />
+              <span class="hint">Days too large for one prompt are summarized in parts, then merged</span>
+            </div>
+            <div class="form-group">
+              <label for="embeddingProvider">Embeddings</label>
+              ${renderProviderSelect("embeddingProvider", settings.embedding_provider, ["anthropic"])}
+              <span class="hint">Model is set by Embedding Model above</span>
+            </div>
+          </div>
+
+          <div class="settings-section">
+            <h2>Usage & Budget</h2>
+            ${renderUsage(usage)}
+            <div class="form-group">
+              <label for="monthlyBudget">Monthly Budget (USD)</label>
+              <input type="number" id="monthlyBudget" min="0" step="0.01" placeholder="No limit" value="${settings.monthly_budget_usd ?? ""}" />
+              <span class="hint">Paid AI requests are refused once this month's estimated cost reaches the budget</span>
+            </div>
+            <div class="form-group">
+              <label for="modelPrices">Model Prices</label>
+              <textarea id="modelPrices" rows="3" placeholder='{"gpt-4o-mini": {"input": 0.15, "output": 0.6}}'>${settings.model_prices ? escapeHtml(JSON.stringify(settings.model_prices, null, 2)) : ""}</textarea>
+              <span class="hint">USD per million input/output tokens by model name prefix; overrides the built-in prices</span>
+            </div>
+          </div>
+
+          <div class="settings-section">
+            <h2>Privacy</h2>
+            <p class="section-desc">Control what is sent to AI providers. Patterns are domains (<code>*.bank.com</code> includes bank.com) or URLs (<code>wiki.corp.com/hr/*</code>), one per line.</p>
+            <div class="form-group">
+              <label for="privacyBlocklist">Never Send</label>
+              <textarea id="privacyBlocklist" rows="3" placeholder="*.mybank.com">${escapeHtml(formatPatterns(settings.privacy_blocklist))}</textarea>
+              <span class="hint">Matching tabs are left out of analysis, reports and embeddings</span>
+            </div>
+            <div class="form-group">
+              <label for="privacyTitleOnly">Send Title Only</label>
+              <textarea id="privacyTitleOnly" rows="3" placeholder="wiki.corp.com/*">${escapeHtml(formatPatterns(settings.privacy_title_only))}</textarea>
+              <span class="hint">Only the page title and site are sent; no URL path, content or screenshot</span>
+            </div>
+            <div class="form-group">
+              <label for="privacyNoScreenshots">Never Send Screenshots</label>
+              <textarea id="privacyNoScreenshots" rows="3" placeholder="mail.google.com">${escapeHtml(formatPatterns(settings.privacy_no_screenshots))}</textarea>
+            </div>
+            <div class="form-group">
+              <label for="redactPersonalData">Redact Personal Data</label>
+              <select id="redactPersonalData">
+                <option value="on" ${settings.redact_personal_data !== false ? "selected" : ""}>On</option>
+                <option value="off" ${settings.redact_personal_data === false ? "selected" : ""}>Off</option>
+              </select>
+              <span class="hint">Masks emails, tokens, API keys, phone and card numbers in everything sent</span>
+            </div>
+            <div class="form-group">
+              <label>Dry Run</label>
+              <div class="preview-actions">
+                <button class="btn secondary preview-request-btn" data-task="suggestions">Next Analysis</button>
+                <button class="btn secondary preview-request-btn" data-task="report">Today's Report</button>
+                <button class="btn secondary preview-request-btn" data-task="embeddings">Embeddings</button>
+              </div>
+              <span class="hint">Shows exactly what would be sent with the saved settings, without sending it</span>
+              <pre id="privacyPreview" class="privacy-preview" hidden></pre>
+            </div>
+          </div>
+
+          <div class="settings-section">
+            <h2>Browser Pairing</h2>
+            ${renderPairing(pairing)}
+            <div class="form-group">
+              <label>Connected Browsers</label>
+              ${renderConnectedClients(clients)}
+              <span class="hint">Commands such as closing a tab go to the browser the tab is open in. Name a profile in the extension popup</span>
+            </div>
+            <div class="form-group">
+              <label>Queued Commands</label>
+              ${renderQueuedCommands(queuedCommands, tabTitle)}
+              <span class="hint">Tabs closed while their browser is not connected close when it connects again, within a day</span>
+            </div>
+          </div>
+
+          <div class="settings-section">
+            <h2>Browser Capture</h2>
+            <p class="section-desc">Control what the extension records. The extension also skips capturing anything a policy drops.</p>
+            <div class="form-group">
+              <label for="capturePolicies">Capture Policies</label>
+              <textarea id="capturePolicies" rows="4" placeholder='[{"incognito": true, "capture": "nothing"}, {"patterns": ["*.mybank.com"], "capture": "metadata"}]'>${settings.capture_policies ? escapeHtml(JSON.stringify(settings.capture_policies, null, 2)) : ""}</textarea>
+              <span class="hint">What is stored from the browser: <code>full</code>, <code>description</code> (no screenshot), <code>metadata</code> (title and URL) or <code>nothing</code>. Match by <code>patterns</code>, <code>incognito</code> or <code>window_ids</code>; the first match wins</span>
+            </div>
           </div>
 
           <div class="settings-section">
@@ -99,13 +370,18 @@ Important projects: tabula, my-portfolio">${settings.user_context || ""}</textar
             </div>
             <div class="info-item">
               <strong>Reset</strong>
-              <p>Clears all AI suggestions so you can re-analyze all tabs fresh.</p>
+              <p>Clears all AI suggestions so you can re-analyze all tabs. Unchanged tabs get their cached suggestion back; shift-click Analyze to ask the AI again.</p>
             </div>
           </div>
 
           <div class="settings-section">
             <h2>Storage Management</h2>
             <p>Clean up old closed tab records to reduce memory usage.</p>
+            <div class="form-group">
+              <label for="closedTabRetention">Keep Closed Tabs (days)</label>
+              <input type="number" id="closedTabRetention" min="1" placeholder="Forever" value="${settings.closed_tab_retention_days ?? ""}" />
+              <span class="hint">Older closed tabs are removed at startup. Past reports and rollups can only be regenerated from tabs that are kept</span>
+            </div>
             <div class="storage-actions">
               <button id="cleanupOldTabsBtn" class="btn secondary">Clean Up Tabs Older Than 7 Days</button>
             </div>
@@ -156,7 +156,7 @@ function renderHeader(stats: ReturnType<typeof getStats>, batchSize: number): st
         <p class="subtitle">${stats.total} tabs total, ${stats.unanalyzed} pending analysis</p>
       </div>
       <div class="actions">
-        <button id="analyzeBatchBtn" class="btn primary" ${stats.unanalyzed === 0 ? "disabled" : ""}>
+        <button id="analyzeBatchBtn" class="btn primary" title="Shift-click to re-analyze tabs that have a cached suggestion" ${stats.unanalyzed === 0 ? "disabled" : ""}>
           <svg width="16" height="16" viewBox="0 0 24 24" fill="none" stroke="currentColor" stroke-width="2">
             <path d="M12 2a10 10 0 1 0 10 10"/>
             <path d="M12 12l4-4"/>
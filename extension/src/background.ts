@@ -11,6 +11,8 @@
 import { SYNC_INTERVAL_MS } from "./modules/config";
 import { getState } from "./modules/state";
 import { checkServerConnection } from "./modules/server";
+import { pairWithDesktop } from "./modules/auth";
+import { getProfileName, setProfileName } from "./modules/browser";
 import { syncActiveTime } from "./modules/timer";
 import { captureAndSendTab } from "./modules/screenshot";
 import {
@@ -20,7 +22,7 @@ import {
   handleRemoved,
   handleWindowFocusChanged,
 } from "./modules/handlers";
-import { connectWebSocket, ensureWebSocketConnected } from "./modules/websocket";
+import { connectWebSocket, ensureWebSocketConnected, sendHello } from "./modules/websocket";
 import { syncAllTabs } from "./modules/sync";
 
 // ─────────────────────────────────────────────────────────────
@@ -31,18 +33,39 @@ chrome.runtime.onMessage.addListener((message, _sender, sendResponse) => {
   if (!message?.type) return;
 
   if (message.type === "getStatus") {
-    Promise.all([getState(), checkServerConnection()]).then(
-      ([state, connected]) => {
+    Promise.all([getState(), checkServerConnection(), getProfileName()]).then(
+      ([state, server, profile]) => {
         sendResponse({
-          connected,
+          connected: server === "connected",
+          paired: server !== "unpaired",
           tabCount: Object.keys(state.tabs).length,
           activeTabId: state.activeTabId,
+          profile,
         });
       }
     );
     return true;
   }
 
+  if (message.type === "pair") {
+    pairWithDesktop(String(message.secret ?? "")).then(async (result) => {
+      if (result.ok) {
+        connectWebSocket();
+        await syncAllTabs().catch(console.error);
+      }
+      sendResponse(result);
+    });
+    return true;
+  }
+
+  if (message.type === "setProfile") {
+    // Re-introduce ourselves so the desktop app shows the new name
+    setProfileName(String(message.name ?? ""))
+      .then(sendHello)
+      .then(() => sendResponse({ ok: true }));
+    return true;
+  }
+
   if (message.type === "forceCapture") {
     // Force capture current tab immediately (no 3 second wait)
     getState().then(async (state) => {
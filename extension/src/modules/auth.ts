@@ -0,0 +1,64 @@
+/**
+ * Tabula Extension - Pairing with the Desktop App
+ *
+ * The desktop app only accepts requests carrying the token it issues when the
+ * pairing secret from its settings is entered here. The secret is not kept.
+ */
+
+import { SERVER_URL } from "./config";
+import { browserInfo } from "./browser";
+
+const TOKEN_KEY = "tabulaAuthToken";
+
+let tokenPromise: Promise<string | null> | null = null;
+
+export function getAuthToken(): Promise<string | null> {
+  if (!tokenPromise) {
+    tokenPromise = chrome.storage.local
+      .get(TOKEN_KEY)
+      .then((stored) => (stored[TOKEN_KEY] as string | undefined) ?? null);
+  }
+  return tokenPromise;
+}
+
+/**
+ * Forget the token, e.g. after the desktop app rejected it
+ */
+export async function clearAuthToken(): Promise<void> {
+  tokenPromise = Promise.resolve(null);
+  await chrome.storage.local.remove(TOKEN_KEY);
+}
+
+export async function authHeaders(): Promise<Record<string, string>> {
+  const token = await getAuthToken();
+  return token ? { Authorization: `Bearer ${token}` } : {};
+}
+
+/** Shown in the desktop app's list of paired extensions */
+function browserName(): string {
+  return `${browserInfo().browser} on ${navigator.platform}`;
+}
+
+/**
+ * Exchange the pairing secret for a token
+ */
+export async function pairWithDesktop(
+  secret: string
+): Promise<{ ok: boolean; error?: string }> {
+  try {
+    const response = await fetch(`${SERVER_URL}/pair`, {
+      method: "POST",
+      headers: { "Content-Type": "application/json" },
+      body: JSON.stringify({ secret: secret.trim(), name: browserName() }),
+    });
+    if (!response.ok) {
+      return { ok: false, error: (await response.text()) || "Pairing failed" };
+    }
+    const { token } = (await response.json()) as { token: string };
+    tokenPromise = Promise.resolve(token);
+    await chrome.storage.local.set({ [TOKEN_KEY]: token });
+    return { ok: true };
+  } catch {
+    return { ok: false, error: "Desktop app not running" };
+  }
+}
@@ -0,0 +1,43 @@
+/**
+ * Tabula Extension - Browser Identity
+ *
+ * Tells the desktop app which browser and profile this extension runs in, so it
+ * can list connected browsers and send each command to the one owning the tab.
+ * Profiles cannot be read from an extension, so the user names them in the popup.
+ */
+
+const PROFILE_KEY = "tabulaProfileName";
+
+export interface BrowserInfo {
+  browser: string;
+  version?: string;
+}
+
+export function browserInfo(): BrowserInfo {
+  const ua = navigator.userAgent;
+  const known: [string, RegExp][] = [
+    ["Edge", /Edg\/([\d.]+)/],
+    ["Opera", /OPR\/([\d.]+)/],
+    ["Firefox", /Firefox\/([\d.]+)/],
+    ["Chrome", /Chrome\/([\d.]+)/],
+  ];
+  for (const [browser, pattern] of known) {
+    const match = ua.match(pattern);
+    if (match) return { browser, version: match[1] };
+  }
+  return { browser: "Chrome" };
+}
+
+export async function getProfileName(): Promise<string | null> {
+  const stored = await chrome.storage.local.get(PROFILE_KEY);
+  return (stored[PROFILE_KEY] as string | undefined) || null;
+}
+
+export async function setProfileName(name: string): Promise<void> {
+  const trimmed = name.trim();
+  if (trimmed) {
+    await chrome.storage.local.set({ [PROFILE_KEY]: trimmed });
+  } else {
+    await chrome.storage.local.remove(PROFILE_KEY);
+  }
+}
@@ -7,3 +7,4 @@ export const WS_URL = "ws://localhost:21890/ws";
 export const SYNC_INTERVAL_MS = 10 * 1000; // Sync to storage every 10 seconds
 export const SCREENSHOT_DELAY_MS = 3000; // Wait 3 seconds before taking screenshot
 export const WS_RECONNECT_DELAY_MS = 5000; // Reconnect delay after connection loss
+export const PROTOCOL_VERSION = 1; // WebSocket protocol version we speak
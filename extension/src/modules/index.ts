@@ -5,7 +5,11 @@
 export * from "./types";
 export * from "./config";
 export * from "./state";
+export * from "./session";
+export * from "./auth";
+export * from "./browser";
 export * from "./server";
+export * from "./policies";
 export * from "./utils";
 export * from "./timer";
 export * from "./screenshot";
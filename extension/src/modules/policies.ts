@@ -0,0 +1,90 @@
+/**
+ * Tabula Extension - Capture Policies
+ *
+ * The desktop app decides how much of a tab it stores and sends its policies in
+ * the /health response. We keep a copy so tabs it would drop are never captured.
+ */
+
+import { CaptureLevel, CapturePolicy, TabData } from "./types";
+
+const POLICIES_KEY = "tabulaCapturePolicies";
+
+const LEVELS: CaptureLevel[] = ["nothing", "metadata", "description", "full"];
+
+let policiesPromise: Promise<CapturePolicy[]> | null = null;
+
+export async function setCapturePolicies(policies: CapturePolicy[]): Promise<void> {
+  policiesPromise = Promise.resolve(policies);
+  await chrome.storage.local.set({ [POLICIES_KEY]: policies });
+}
+
+function getCapturePolicies(): Promise<CapturePolicy[]> {
+  if (!policiesPromise) {
+    policiesPromise = chrome.storage.local
+      .get(POLICIES_KEY)
+      .then((stored) => (stored[POLICIES_KEY] as CapturePolicy[] | undefined) ?? []);
+  }
+  return policiesPromise;
+}
+
+function escapeRegExp(value: string): string {
+  return value.replace(/[.+^${}()|[\]\\]/g, "\\$&");
+}
+
+/** Same rules as the desktop app: `*.example.com` matches the domain and its subdomains;
+ * patterns with a `/` match host, path and query */
+function globMatches(pattern: string, url: URL | null): boolean {
+  let glob = pattern.trim().toLowerCase().replace(/^https?:\/\//, "");
+  if (!glob) return false;
+
+  const matchesPath = glob.includes("/");
+  let prefix = "";
+  if (!matchesPath && glob.startsWith("*.")) {
+    prefix = "(?:.*\\.)?";
+    glob = glob.slice(2);
+  }
+  const source = glob
+    .split("")
+    .map((c) => (c === "*" ? ".*" : c === "?" ? "." : escapeRegExp(c)))
+    .join("");
+  const regex = new RegExp(`^${prefix}${source}$`);
+
+  const host = url?.hostname ?? "";
+  return regex.test(matchesPath ? `${host}${url?.pathname ?? ""}${url?.search ?? ""}` : host);
+}
+
+function parseUrl(url: string | undefined): URL | null {
+  try {
+    return url ? new URL(url) : null;
+  } catch {
+    return null;
+  }
+}
+
+function policyMatches(policy: CapturePolicy, tab: TabData): boolean {
+  const url = parseUrl(tab.url);
+  const patterns = policy.patterns ?? [];
+  const windowIds = policy.window_ids ?? [];
+  return (
+    (patterns.length === 0 || patterns.some((p) => globMatches(p, url))) &&
+    (policy.incognito == null || policy.incognito === (tab.incognito ?? false)) &&
+    (windowIds.length === 0 ||
+      (tab.windowId !== undefined && windowIds.includes(tab.windowId)))
+  );
+}
+
+/**
+ * How much of a tab the desktop app keeps; the first matching policy wins
+ */
+export async function getCaptureLevel(tab: TabData): Promise<CaptureLevel> {
+  const policies = await getCapturePolicies();
+  return policies.find((p) => policyMatches(p, tab))?.capture ?? "full";
+}
+
+export function keepsDescription(level: CaptureLevel): boolean {
+  return LEVELS.indexOf(level) >= LEVELS.indexOf("description");
+}
+
+export function keepsScreenshot(level: CaptureLevel): boolean {
+  return level === "full";
+}
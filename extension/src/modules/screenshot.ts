@@ -6,6 +6,7 @@ import { CapturePayload, TabEvent } from "./types";
 import { SCREENSHOT_DELAY_MS } from "./config";
 import { getState, updateState } from "./state";
 import { sendToServer } from "./server";
+import { getCaptureLevel, keepsDescription, keepsScreenshot } from "./policies";
 import { now, getAccumulatedActiveMs } from "./utils";
 
 // Track when user activated each tab to verify they stayed long enough
@@ -122,25 +123,35 @@ export async function captureAndSendTab(
     }
   }
 
+  // The desktop app's capture policies say what it would keep
+  const level = await getCaptureLevel(tab);
+  if (level === "nothing") {
+    return false;
+  }
+
   // Extract description from content script
   let description: string | undefined;
-  try {
-    const response = await chrome.tabs.sendMessage(tabId, {
-      type: "extractContent",
-    });
-    description = response?.description;
-  } catch {
-    // Content script not available
+  if (keepsDescription(level)) {
+    try {
+      const response = await chrome.tabs.sendMessage(tabId, {
+        type: "extractContent",
+      });
+      description = response?.description;
+    } catch {
+      // Content script not available
+    }
   }
 
   // Capture screenshot
-  const screenshotBase64 = await captureScreenshotForTab(tabId);
+  const screenshotBase64 = keepsScreenshot(level)
+    ? await captureScreenshotForTab(tabId)
+    : null;
 
   // Send to server with accumulated time
   const payload: CapturePayload = {
     tab: {
       ...tab,
-      description: description ?? tab.description,
+      description: keepsDescription(level) ? description ?? tab.description : undefined,
       totalActiveMs: getAccumulatedActiveMs(tab, state),
       lastActiveAt: now(),
     },
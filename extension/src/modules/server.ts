@@ -3,17 +3,35 @@
  */
 
 import { SERVER_URL } from "./config";
+import { getSessionId } from "./session";
+import { setCapturePolicies } from "./policies";
+import { authHeaders, clearAuthToken, getAuthToken } from "./auth";
+import { HealthResponse } from "./types";
+
+export type ServerStatus = "connected" | "unpaired" | "offline";
 
 export async function sendToServer(
   endpoint: string,
   data: unknown
 ): Promise<boolean> {
+  // The desktop app rejects everything until we are paired
+  if (!(await getAuthToken())) return false;
+
   try {
     const response = await fetch(`${SERVER_URL}${endpoint}`, {
       method: "POST",
-      headers: { "Content-Type": "application/json" },
+      headers: {
+        "Content-Type": "application/json",
+        "X-Tabula-Session": await getSessionId(),
+        ...(await authHeaders()),
+      },
       body: JSON.stringify(data),
     });
+    if (response.status === 401) {
+      // Revoked, or the secret was rotated
+      console.log("[Tabula] Desktop app rejected our token, pair again");
+      await clearAuthToken();
+    }
     return response.ok;
   } catch (e) {
     console.log(`[Tabula] Failed to send to ${endpoint}:`, e);
@@ -21,11 +39,34 @@ export async function sendToServer(
   }
 }
 
-export async function checkServerConnection(): Promise<boolean> {
+/**
+ * Check the desktop app is up and accepts us, and pick up its current capture policies
+ */
+export async function checkServerConnection(): Promise<ServerStatus> {
+  if (!(await getAuthToken())) {
+    // Pairing is the only thing an unpaired extension can do; any answer means it is up
+    try {
+      await fetch(`${SERVER_URL}/health`, { method: "GET" });
+      return "unpaired";
+    } catch {
+      return "offline";
+    }
+  }
+
   try {
-    const response = await fetch(`${SERVER_URL}/health`, { method: "GET" });
-    return response.ok;
+    const response = await fetch(`${SERVER_URL}/health`, {
+      method: "GET",
+      headers: await authHeaders(),
+    });
+    if (response.status === 401) {
+      await clearAuthToken();
+      return "unpaired";
+    }
+    if (!response.ok) return "offline";
+    const health = (await response.json()) as HealthResponse;
+    await setCapturePolicies(health.capture_policies ?? []);
+    return "connected";
   } catch {
-    return false;
+    return "offline";
   }
 }
@@ -0,0 +1,32 @@
+/**
+ * Tabula Extension - Browser Session Identity
+ *
+ * Chrome reuses tab IDs after a restart, so every request carries an ID
+ * that lives for one browser session (chrome.storage.session is cleared on exit).
+ */
+
+const SESSION_KEY = "tabulaSessionId";
+
+let sessionIdPromise: Promise<string> | null = null;
+
+async function loadSessionId(): Promise<string> {
+  const stored = await chrome.storage.session.get(SESSION_KEY);
+  const existing = stored[SESSION_KEY];
+  if (typeof existing === "string" && existing) {
+    return existing;
+  }
+
+  const sessionId = crypto.randomUUID();
+  await chrome.storage.session.set({ [SESSION_KEY]: sessionId });
+  return sessionId;
+}
+
+export function getSessionId(): Promise<string> {
+  if (!sessionIdPromise) {
+    sessionIdPromise = loadSessionId().catch((e) => {
+      sessionIdPromise = null;
+      throw e;
+    });
+  }
+  return sessionIdPromise;
+}
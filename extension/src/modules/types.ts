@@ -5,6 +5,8 @@
 export interface TabData {
   id: number;
   windowId?: number;
+  /** Position of the tab within its window */
+  index?: number;
   url?: string;
   title?: string;
   favIconUrl?: string;
@@ -17,6 +19,25 @@ export interface TabData {
   lastScreenshotAt?: number;
   /** Rich description extracted from page meta/content (max 8000 words) */
   description?: string;
+  incognito?: boolean;
+}
+
+/** What the desktop app stores for a tab, from least to most */
+export type CaptureLevel = "nothing" | "metadata" | "description" | "full";
+
+/** Capture rule from the desktop app's settings; all given conditions must hold */
+export interface CapturePolicy {
+  /** Domain or URL globs; empty matches every URL */
+  patterns?: string[];
+  incognito?: boolean | null;
+  window_ids?: number[];
+  capture: CaptureLevel;
+}
+
+export interface HealthResponse {
+  status: string;
+  version: string;
+  capture_policies?: CapturePolicy[];
 }
 
 export interface StoredState {
@@ -38,6 +59,36 @@ export interface TabEvent {
   timestamp: number;
 }
 
+/** WebSocket message: a command from the desktop app, our reply with the same id, or our hello */
+export interface Envelope<T> {
+  version: number;
+  id: number;
+  type: T;
+  payload?: unknown;
+}
+
+export type CommandType = "refresh_all" | "close_tab" | "capture_policies_changed";
+
+/** Sent once connected, and again when the profile name changes */
+export interface Hello {
+  browser: string;
+  browser_version?: string;
+  profile?: string;
+  extension_version?: string;
+  session_id?: string;
+}
+
+export type ReplyErrorCode =
+  | "tab_not_found"
+  | "unknown_command"
+  | "unsupported_version"
+  | "failed";
+
+export interface ReplyError {
+  code: ReplyErrorCode;
+  message: string;
+}
+
 export const DEFAULT_STATE: StoredState = {
   tabs: {},
   activeTabId: null,
@@ -13,6 +13,7 @@ export function createTabData(tab: chrome.tabs.Tab, description?: string): TabDa
   return {
     id: tab.id ?? -1,
     windowId: tab.windowId,
+    index: tab.index,
     url: tab.url,
     title: tab.title,
     favIconUrl: tab.favIconUrl,
@@ -22,6 +23,7 @@ export function createTabData(tab: chrome.tabs.Tab, description?: string): TabDa
     isActive: false,
     discarded: tab.discarded,
     description,
+    incognito: tab.incognito,
   };
 }
 
@@ -36,7 +38,9 @@ export function updateTabFromChrome(
     title: chromeTab.title,
     favIconUrl: chromeTab.favIconUrl,
     windowId: chromeTab.windowId,
+    index: chromeTab.index,
     discarded: chromeTab.discarded,
+    incognito: chromeTab.incognito,
     // Preserve lastActiveAt from existing data
     lastActiveAt: existing.lastActiveAt,
     // Update description if provided, otherwise preserve existing
@@ -2,15 +2,20 @@
  * Tabula Extension - WebSocket Connection Management
  */
 
-import { CapturePayload, TabEvent } from "./types";
-import { WS_URL, WS_RECONNECT_DELAY_MS } from "./config";
+import { CapturePayload, CommandType, Envelope, Hello, ReplyError, TabEvent } from "./types";
+import { WS_URL, WS_RECONNECT_DELAY_MS, PROTOCOL_VERSION } from "./config";
 import { getState, updateState } from "./state";
-import { sendToServer } from "./server";
+import { sendToServer, checkServerConnection } from "./server";
+import { getAuthToken } from "./auth";
+import { browserInfo, getProfileName } from "./browser";
+import { getSessionId } from "./session";
+import { getCaptureLevel, keepsDescription, keepsScreenshot } from "./policies";
 import { now, createTabData, updateTabFromChrome, getAccumulatedActiveMs } from "./utils";
 import { captureScreenshotForTabInWindow } from "./screenshot";
 
 let ws: WebSocket | null = null;
 let wsReconnectTimeout: ReturnType<typeof setTimeout> | null = null;
+let wsConnecting = false;
 
 function scheduleReconnect(): void {
   if (wsReconnectTimeout) return;
@@ -58,11 +63,6 @@ async function refreshAllScreenshots(): Promise<void> {
     }
 
     try {
-      const screenshotBase64 = await captureScreenshotForTabInWindow(
-        activeTab.id,
-        win.id
-      );
-
       // Get or create tab data
       const state = await getState();
       let tab = state.tabs[String(activeTab.id)];
@@ -72,6 +72,17 @@ async function refreshAllScreenshots(): Promise<void> {
         tab = updateTabFromChrome(tab, activeTab);
       }
 
+      // Skip tabs the desktop app's capture policies drop
+      const level = await getCaptureLevel(tab);
+      if (level === "nothing") {
+        skippedCount++;
+        continue;
+      }
+
+      const screenshotBase64 = keepsScreenshot(level)
+        ? await captureScreenshotForTabInWindow(activeTab.id, win.id)
+        : null;
+
       await updateState((s) => ({
         ...s,
         tabs: { ...s.tabs, [String(activeTab.id!)]: tab! },
@@ -79,13 +90,15 @@ async function refreshAllScreenshots(): Promise<void> {
 
       // Get text content if possible
       let text: string | undefined;
-      try {
-        const response = await chrome.tabs.sendMessage(activeTab.id, {
-          type: "extractContent",
-        });
-        text = response?.text?.slice(0, 8000);
-      } catch {
-        // Content script not available
+      if (keepsDescription(level)) {
+        try {
+          const response = await chrome.tabs.sendMessage(activeTab.id, {
+            type: "extractContent",
+          });
+          text = response?.text?.slice(0, 8000);
+        } catch {
+          // Content script not available
+        }
       }
 
       // Send to server
@@ -104,8 +117,10 @@ async function refreshAllScreenshots(): Promise<void> {
         };
 
         await sendToServer("/capture", payload);
-        
-        if (screenshotBase64) {
+
+        if (!keepsScreenshot(level)) {
+          skippedCount++;
+        } else if (screenshotBase64) {
           capturedCount++;
           capturedTabIds.add(activeTab.id);
 
@@ -159,6 +174,8 @@ async function refreshAllScreenshots(): Promise<void> {
       tabs: { ...s.tabs, [String(chromeTab.id!)]: tab! },
     }));
 
+    if ((await getCaptureLevel(tab)) === "nothing") continue;
+
     // Send update event (no screenshot)
     const event: TabEvent = {
       type: "updated",
@@ -179,18 +196,96 @@ async function refreshAllScreenshots(): Promise<void> {
   );
 }
 
+class CommandError extends Error {
+  constructor(readonly reply: ReplyError) {
+    super(reply.message);
+  }
+}
+
+async function closeTab(tabId: number): Promise<void> {
+  try {
+    await chrome.tabs.get(tabId);
+  } catch {
+    throw new CommandError({ code: "tab_not_found", message: `No tab with id ${tabId}` });
+  }
+  await chrome.tabs.remove(tabId);
+}
+
+/**
+ * Run a command from the desktop app; throws a CommandError to reply with its code
+ */
+async function runCommand(command: Envelope<CommandType>): Promise<void> {
+  if (command.version > PROTOCOL_VERSION) {
+    throw new CommandError({
+      code: "unsupported_version",
+      message: `Extension speaks protocol v${PROTOCOL_VERSION}, got v${command.version}`,
+    });
+  }
+
+  switch (command.type) {
+    case "refresh_all":
+      await refreshAllScreenshots();
+      return;
+    case "capture_policies_changed":
+      await checkServerConnection();
+      return;
+    case "close_tab": {
+      const { tab_id } = command.payload as { tab_id: number };
+      await closeTab(tab_id);
+      return;
+    }
+    default:
+      throw new CommandError({
+        code: "unknown_command",
+        message: `Unknown command "${command.type}"`,
+      });
+  }
+}
+
+function reply(id: number, error?: ReplyError): void {
+  if (!ws || ws.readyState !== WebSocket.OPEN) return;
+  const message: Envelope<"ack" | "error"> = error
+    ? { version: PROTOCOL_VERSION, id, type: "error", payload: error }
+    : { version: PROTOCOL_VERSION, id, type: "ack" };
+  ws.send(JSON.stringify(message));
+}
+
+/**
+ * Introduce ourselves to the desktop app; the session id ties us to the tabs we sent
+ */
+export async function sendHello(): Promise<void> {
+  if (!ws || ws.readyState !== WebSocket.OPEN) return;
+  const { browser, version } = browserInfo();
+  const payload: Hello = {
+    browser,
+    browser_version: version,
+    profile: (await getProfileName()) ?? undefined,
+    extension_version: chrome.runtime.getManifest().version,
+    session_id: await getSessionId(),
+  };
+  const hello: Envelope<"hello"> = { version: PROTOCOL_VERSION, id: 0, type: "hello", payload };
+  ws.send(JSON.stringify(hello));
+}
+
 /**
  * Connect to the WebSocket server for receiving commands from desktop app
  */
-export function connectWebSocket(): void {
+export async function connectWebSocket(): Promise<void> {
   if (
-    ws &&
-    (ws.readyState === WebSocket.OPEN || ws.readyState === WebSocket.CONNECTING)
+    wsConnecting ||
+    (ws &&
+      (ws.readyState === WebSocket.OPEN || ws.readyState === WebSocket.CONNECTING))
   ) {
     return;
   }
 
-  ws = new WebSocket(WS_URL);
+  // Browsers cannot set headers on WebSocket requests, so the token goes in the URL
+  wsConnecting = true;
+  const token = await getAuthToken();
+  wsConnecting = false;
+  if (!token) return; // Connect once paired
+
+  ws = new WebSocket(`${WS_URL}?token=${encodeURIComponent(token)}`);
 
   ws.onopen = () => {
     console.log("[Tabula] WebSocket connected to desktop app");
@@ -199,33 +294,28 @@ export function connectWebSocket(): void {
       clearTimeout(wsReconnectTimeout);
       wsReconnectTimeout = null;
     }
+    void sendHello();
+    // Capture policies may have changed while we were disconnected
+    void checkServerConnection();
   };
 
   ws.onmessage = async (event) => {
-    const command = event.data;
+    let command: Envelope<CommandType>;
+    try {
+      command = JSON.parse(event.data);
+    } catch {
+      console.log("[Tabula] Ignoring malformed command:", event.data);
+      return;
+    }
 
-    if (command === "refresh_all") {
-      await refreshAllScreenshots();
-      // Send acknowledgment
-      if (ws && ws.readyState === WebSocket.OPEN) {
-        ws.send("refresh_all_done");
-      }
-    } else if (command.startsWith("close_tab:")) {
-      // Close a specific tab by ID
-      const tabIdStr = command.replace("close_tab:", "");
-      const tabId = parseInt(tabIdStr, 10);
-      if (!isNaN(tabId)) {
-        try {
-          await chrome.tabs.remove(tabId);
-          if (ws && ws.readyState === WebSocket.OPEN) {
-            ws.send(`close_tab_done:${tabId}`);
-          }
-        } catch {
-          if (ws && ws.readyState === WebSocket.OPEN) {
-            ws.send(`close_tab_error:${tabId}`);
-          }
-        }
-      }
+    try {
+      await runCommand(command);
+      reply(command.id);
+    } catch (e) {
+      reply(
+        command.id,
+        e instanceof CommandError ? e.reply : { code: "failed", message: String(e) }
+      );
     }
   };
 
@@ -4,6 +4,10 @@ const captureBtn = document.getElementById("captureBtn") as HTMLButtonElement;
 const syncBtn = document.getElementById("syncBtn") as HTMLButtonElement;
 const messageEl = document.getElementById("message") as HTMLDivElement;
 const hintEl = document.getElementById("hint") as HTMLDivElement;
+const pairingEl = document.getElementById("pairing") as HTMLDivElement;
+const secretInput = document.getElementById("secretInput") as HTMLInputElement;
+const pairBtn = document.getElementById("pairBtn") as HTMLButtonElement;
+const profileInput = document.getElementById("profileInput") as HTMLInputElement;
 
 function sendMessage<T>(message: Record<string, unknown>): Promise<T> {
   return new Promise((resolve) => {
@@ -22,11 +26,26 @@ function showMessage(text: string, isError = false): void {
 async function updateStatus(): Promise<void> {
   const status = await sendMessage<{
     connected: boolean;
+    paired: boolean;
     tabCount: number;
     activeTabId: number | null;
+    profile: string | null;
   }>({ type: "getStatus" });
 
-  if (status.connected) {
+  // Leave it alone while the user is typing
+  if (document.activeElement !== profileInput) {
+    profileInput.value = status.profile ?? "";
+  }
+
+  pairingEl.hidden = status.paired;
+
+  if (!status.paired) {
+    connectionStatus.textContent = "Not Paired";
+    connectionStatus.className = "status-value disconnected";
+    captureBtn.disabled = true;
+    syncBtn.disabled = true;
+    hintEl.textContent = "Copy the pairing secret from Settings → Browser Pairing in the desktop app.";
+  } else if (status.connected) {
     connectionStatus.textContent = "Connected";
     connectionStatus.className = "status-value connected";
     captureBtn.disabled = false;
@@ -81,6 +100,34 @@ syncBtn.addEventListener("click", async () => {
   await updateStatus();
 });
 
+pairBtn.addEventListener("click", async () => {
+  const secret = secretInput.value.trim();
+  if (!secret) return;
+  pairBtn.disabled = true;
+  pairBtn.textContent = "Pairing...";
+
+  const result = await sendMessage<{ ok: boolean; error?: string }>({
+    type: "pair",
+    secret,
+  });
+
+  if (result.ok) {
+    secretInput.value = "";
+    showMessage("Paired with the desktop app!");
+  } else {
+    showMessage(result.error ?? "Pairing failed", true);
+  }
+
+  pairBtn.disabled = false;
+  pairBtn.textContent = "Pair with Desktop App";
+  await updateStatus();
+});
+
+profileInput.addEventListener("change", async () => {
+  await sendMessage({ type: "setProfile", name: profileInput.value });
+  showMessage("Profile name saved");
+});
+
 // Initial status check
 updateStatus();
 
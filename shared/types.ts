@@ -12,6 +12,8 @@
 export interface TabData {
   id: number;
   windowId?: number;
+  /** Position of the tab within its window */
+  index?: number;
   url?: string;
   title?: string;
   favIconUrl?: string;